@@ -0,0 +1,7 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
+cargo build --workspace
+cargo clippy --workspace --all-targets -- -D warnings
+cargo test --workspace
+RUSTFLAGS="-D warnings" cargo check --no-default-features # no_std + alloc build
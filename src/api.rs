@@ -0,0 +1,533 @@
+//! High-level entry point for users that do not need the backend traits.
+//!
+//! [`Backend`] opens a device through the [`Registry`], applies common settings and
+//! wraps the allocate/compute/copy calls of [`backend::Backend`]:
+//!
+//! ```no_run
+//! # fn main() -> feml::error::Result<()> {
+//! let cpu = feml::Backend::cpu().with_threads(8).build()?;
+//! let gpu = feml::Backend::by_name("cuda:0").build()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::backend::{self, copy_tensor, BackendBuffer, BackendBufferUsage, BackendFeature};
+use crate::collections::HashMap;
+use crate::compute_graph::ComputeGraph;
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::memory;
+use crate::registry::Registry;
+use crate::rng::RngState;
+use crate::tensor::Tensor;
+use core::ops::{Deref, DerefMut};
+
+pub struct BackendBuilder {
+    name: Option<String>,
+    device: usize,
+    n_threads: Option<usize>,
+}
+
+impl BackendBuilder {
+    fn new(name: Option<String>, device: usize) -> Self {
+        Self { name, device, n_threads: None }
+    }
+
+    /// Compute threads, for backends that support setting them.
+    pub fn with_threads(mut self, n_threads: usize) -> Self {
+        self.n_threads = Some(n_threads);
+        self
+    }
+
+    pub fn build(self) -> Result<Backend> {
+        self.open().map_err(|e| e.context("in BackendBuilder::build"))
+    }
+
+    fn open(self) -> Result<Backend> {
+        let registry = Registry::discover()?;
+        registry.init_all()?;
+        let (name, mut inner) = match &self.name {
+            Some(name) => (name.clone(), registry.open_backend(name, self.device)?),
+            None => {
+                let name = registry.best().ok_or_else(|| Error::msg("no backend available"))?;
+                (name.name().to_string(), registry.open_best()?)
+            }
+        };
+
+        if let Some(n_threads) = self.n_threads {
+            let set_n_threads = registry.set_n_threads_fn(&name).ok_or_else(|| {
+                Error::msg(format!("backend {name} does not support setting the thread count"))
+            })?;
+            set_n_threads(inner.as_mut(), n_threads)?;
+        }
+        Ok(Backend { inner, device: self.device })
+    }
+}
+
+pub struct Backend {
+    inner: Box<dyn backend::Backend>,
+    device: usize,
+}
+
+impl Backend {
+    /// The CPU backend.
+    pub fn cpu() -> BackendBuilder {
+        BackendBuilder::new(Some("CPU".to_string()), 0)
+    }
+
+    /// A backend by name, optionally followed by a device index: `"cpu"`, `"cuda:0"`,
+    /// `"opencl:1"`. Names are matched case-insensitively; the device defaults to `0`. An
+    /// invalid index is reported by [`BackendBuilder::build`].
+    pub fn by_name(spec: &str) -> BackendBuilder {
+        match spec.rsplit_once(':') {
+            Some((name, index)) => match index.parse() {
+                Ok(device) => BackendBuilder::new(Some(name.to_string()), device),
+                Err(_) => BackendBuilder::new(Some(spec.to_string()), 0),
+            },
+            None => BackendBuilder::new(Some(spec.to_string()), 0),
+        }
+    }
+
+    /// The first available backend of CUDA, OpenCL and CPU.
+    pub fn best() -> BackendBuilder {
+        BackendBuilder::new(None, 0)
+    }
+
+    pub fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    pub fn device(&self) -> usize {
+        self.device
+    }
+
+    /// See [`backend::Backend::features`].
+    pub fn features(&self) -> Vec<BackendFeature> {
+        self.inner.features()
+    }
+
+    /// The wrapped backend, for everything this type does not cover.
+    pub fn inner(&self) -> &dyn backend::Backend {
+        self.inner.as_ref()
+    }
+
+    pub fn inner_mut(&mut self) -> &mut dyn backend::Backend {
+        self.inner.as_mut()
+    }
+
+    /// Allocates one buffer for `tensors` and binds them to it. The tensors must stay
+    /// bound only as long as the returned buffer is alive.
+    pub fn alloc(&self, tensors: &[Tensor]) -> Result<Box<dyn BackendBuffer>> {
+        let alignment = self.inner.alignment();
+        let mut offsets = Vec::with_capacity(tensors.len());
+        let mut size = 0;
+        for tensor in tensors {
+            offsets.push(size);
+            size += self.alloc_size(tensor);
+        }
+
+        let alloc = || {
+            let report = || {
+                let ctx = tensors.first()?.borrow().ctx().ok()?;
+                Some(ctx.memory_report())
+            };
+            let size = size.max(alignment);
+            let buffer =
+                memory::create_buffer(self.inner(), size, BackendBufferUsage::Any, report)?;
+            for (tensor, &offset) in tensors.iter().zip(&offsets) {
+                buffer.init_tensor(tensor.clone(), offset)?;
+            }
+            Ok(buffer)
+        };
+        alloc().map_err(|e: Error| e.context("in Backend::alloc"))
+    }
+
+    /// [`backend::Backend::alloc_size`] of `tensor`, kept a multiple of the alignment so
+    /// the tensor after it starts aligned.
+    fn alloc_size(&self, tensor: &Tensor) -> usize {
+        self.inner.alloc_size(tensor).next_multiple_of(self.inner.alignment())
+    }
+
+    /// Allocates one buffer for the unbound leafs and nodes of `graph`, in which a node's
+    /// memory is reused by later nodes once every node reading it has run. Leafs and the
+    /// graph's [outputs](ComputeGraph::add_output) are never reused, so only they can be
+    /// read after compute. The buffer is smaller than [`Backend::alloc`] of the same
+    /// tensors whenever intermediates die before the end of the graph.
+    pub fn alloc_graph(
+        &self,
+        ctx: &Context,
+        graph: &ComputeGraph,
+    ) -> Result<Box<dyn BackendBuffer>> {
+        let alloc = || {
+            let alignment = self.inner.alignment();
+            let (placed, size) = plan_graph(ctx, graph, |t| self.alloc_size(t))?;
+            let size = size.max(alignment);
+            let report = || Some(ctx.memory_report());
+            let buffer =
+                memory::create_buffer(self.inner(), size, BackendBufferUsage::Any, report)?;
+            for (tensor, offset) in placed {
+                buffer.init_tensor(tensor, offset)?;
+            }
+            Ok(buffer)
+        };
+        alloc().map_err(|e: Error| e.context("in Backend::alloc_graph"))
+    }
+
+    /// Bytes [`Backend::alloc_graph`] would allocate for `graph`, without allocating.
+    pub fn measure_graph(&self, ctx: &Context, graph: &ComputeGraph) -> Result<usize> {
+        let alignment = self.inner.alignment();
+        let (_, size) = plan_graph(ctx, graph, |t| self.alloc_size(t))
+            .map_err(|e| e.context("in Backend::measure_graph"))?;
+        Ok(size.max(alignment))
+    }
+
+    /// A [`GraphAllocator`] reusing one buffer for the graphs it allocates.
+    pub fn graph_allocator(&self) -> GraphAllocator<'_> {
+        GraphAllocator { backend: self, reserved: 0, buffer: None }
+    }
+
+    /// Computes `graph` and waits for it to finish.
+    pub fn compute(&self, ctx: &Context, graph: &mut ComputeGraph) -> Result<()> {
+        self.inner
+            .graph_compute(ctx, graph)
+            .and_then(|_| self.inner.synchronize())
+            .map_err(|e| e.context("in Backend::compute"))
+    }
+
+    /// Copies `src` into `dst`. The tensors may live on different backends.
+    pub fn transfer(&self, src: &Tensor, dst: &Tensor) -> Result<()> {
+        copy_tensor(src, dst).map_err(|e| e.context("in Backend::transfer"))
+    }
+
+    /// Writes host bytes to the start of `tensor`.
+    pub fn write(&self, tensor: &Tensor, data: &[u8]) -> Result<()> {
+        let mut data = data.to_vec();
+        let size = data.len();
+        self.inner
+            .write_async(tensor.clone(), &mut data, 0, size)
+            .and_then(|_| self.inner.synchronize())
+            .map_err(|e| e.context("in Backend::write"))
+    }
+
+    /// Writes the start of `src` to `tensor`. Memory [registered](Backend::register_host_memory)
+    /// with this backend is handed to it directly; anything else is staged through a copy,
+    /// like [`Backend::write`].
+    pub fn write_from(&self, tensor: &Tensor, src: &mut [u8]) -> Result<()> {
+        let size = tensor.nbytes();
+        if src.len() < size {
+            return Err(Error::msg(format!("{} bytes do not hold a tensor of {size}", src.len()))
+                .context("in Backend::write_from"));
+        }
+        if !self.inner.is_host_registered(src.as_ptr(), size) {
+            return self
+                .write(tensor, &src[..size])
+                .map_err(|e| e.context("in Backend::write_from"));
+        }
+        self.inner
+            .write_async(tensor.clone(), &mut src[..size], 0, size)
+            .and_then(|_| self.inner.synchronize())
+            .map_err(|e| e.context("in Backend::write_from"))
+    }
+
+    /// Reads the bytes of `tensor` into the start of `dst`. Memory
+    /// [registered](Backend::register_host_memory) with this backend is handed to it
+    /// directly; anything else is staged through a copy, like [`Backend::read`].
+    pub fn read_into(&self, tensor: &Tensor, dst: &mut [u8]) -> Result<()> {
+        let size = tensor.nbytes();
+        if dst.len() < size {
+            return Err(Error::msg(format!("{} bytes do not hold a tensor of {size}", dst.len()))
+                .context("in Backend::read_into"));
+        }
+        if !self.inner.is_host_registered(dst.as_ptr(), size) {
+            let staged = self.read(tensor).map_err(|e| e.context("in Backend::read_into"))?;
+            dst[..size].copy_from_slice(&staged);
+            return Ok(());
+        }
+        self.inner
+            .read_async(tensor.clone(), &mut dst[..size], 0, size)
+            .and_then(|_| self.inner.synchronize())
+            .map_err(|e| e.context("in Backend::read_into"))
+    }
+
+    /// Registers `data` with the backend for faster transfers, see
+    /// [`backend::Backend::register_host_memory`]; it is unregistered when the returned
+    /// guard is dropped. The CPU backend only records the range.
+    pub fn register_host_memory<'a>(
+        &self,
+        data: &'a mut [u8],
+    ) -> Result<RegisteredHostMemory<'a, '_>> {
+        // SAFETY: `data` is borrowed by the guard, which unregisters it when dropped.
+        unsafe { self.inner.register_host_memory(data.as_mut_ptr(), data.len()) }
+            .map_err(|e| e.context("in Backend::register_host_memory"))?;
+        Ok(RegisteredHostMemory { backend: self, data })
+    }
+
+    /// The current [random state](ComputeGraph::rng) of `graph`. Save it before a compute
+    /// and restore it with [`Backend::set_rng_state`] to replay the compute's draws.
+    pub fn rng_state(&self, ctx: &Context, graph: &ComputeGraph) -> Result<RngState> {
+        let tensor = rng_tensor(ctx, graph).map_err(|e| e.context("in Backend::rng_state"))?;
+        let bytes = self.read(&tensor).map_err(|e| e.context("in Backend::rng_state"))?;
+        RngState::from_bytes(&bytes)
+    }
+
+    pub fn set_rng_state(
+        &self,
+        ctx: &Context,
+        graph: &ComputeGraph,
+        state: RngState,
+    ) -> Result<()> {
+        let tensor = rng_tensor(ctx, graph).map_err(|e| e.context("in Backend::set_rng_state"))?;
+        self.write(&tensor, &state.to_bytes()).map_err(|e| e.context("in Backend::set_rng_state"))
+    }
+
+    /// Reads the bytes of `tensor` back to the host.
+    pub fn read(&self, tensor: &Tensor) -> Result<Vec<u8>> {
+        let mut data = vec![0; tensor.nbytes()];
+        let size = data.len();
+        self.inner
+            .read_async(tensor.clone(), &mut data, 0, size)
+            .and_then(|_| self.inner.synchronize())
+            .map_err(|e| e.context("in Backend::read"))?;
+        Ok(data)
+    }
+}
+
+fn rng_tensor(ctx: &Context, graph: &ComputeGraph) -> Result<Tensor> {
+    let id = graph.rng_id().ok_or_else(|| Error::msg("the graph has no rng state"))?;
+    ctx.get_tensor(id)
+}
+
+/// Host memory registered with [`Backend::register_host_memory`], unregistered on drop.
+pub struct RegisteredHostMemory<'a, 'b> {
+    backend: &'b Backend,
+    data: &'a mut [u8],
+}
+
+impl Deref for RegisteredHostMemory<'_, '_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.data
+    }
+}
+
+impl DerefMut for RegisteredHostMemory<'_, '_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.data
+    }
+}
+
+impl Drop for RegisteredHostMemory<'_, '_> {
+    fn drop(&mut self) {
+        let _ = self.backend.inner.unregister_host_memory(self.data.as_mut_ptr());
+    }
+}
+
+/// One buffer that is reused by every graph evaluation, like [`Backend::alloc_graph`]
+/// without an allocation per eval.
+///
+/// Allocation has two passes. The measure pass, [`GraphAllocator::reserve`], is run for
+/// every graph shape that will be evaluated, e.g. the prompt batch and single-token
+/// decode, and only records the largest size. The first [`GraphAllocator::alloc_graph`]
+/// then creates a buffer of that worst-case size, and later calls bind their graph into
+/// the same buffer, so the memory footprint stays fixed for the whole session.
+///
+/// ```no_run
+/// # fn main() -> feml::error::Result<()> {
+/// # let ctx = feml::context::Context::builder().build();
+/// # let mut prompt = feml::compute_graph::ComputeGraph::new();
+/// # let mut decode = feml::compute_graph::ComputeGraph::new();
+/// let backend = feml::Backend::cpu().build()?;
+/// let mut allocator = backend.graph_allocator();
+/// allocator.reserve(&ctx, &prompt)?;
+/// allocator.reserve(&ctx, &decode)?;
+///
+/// allocator.alloc_graph(&ctx, &prompt)?;
+/// backend.compute(&ctx, &mut prompt)?;
+/// for _ in 0..16 {
+///     allocator.alloc_graph(&ctx, &decode)?;
+///     backend.compute(&ctx, &mut decode)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct GraphAllocator<'b> {
+    backend: &'b Backend,
+    reserved: usize,
+    buffer: Option<Box<dyn BackendBuffer>>,
+}
+
+impl GraphAllocator<'_> {
+    /// Measures `graph` and raises the reserved size to what it needs. Returns the
+    /// reserved size.
+    pub fn reserve(&mut self, ctx: &Context, graph: &ComputeGraph) -> Result<usize> {
+        let size = self.backend.measure_graph(ctx, graph)?;
+        self.reserved = self.reserved.max(size);
+        Ok(self.reserved)
+    }
+
+    /// Bytes reserved by [`GraphAllocator::reserve`] or grown to by an allocation.
+    pub fn reserved(&self) -> usize {
+        self.reserved
+    }
+
+    /// Bytes of the current buffer, 0 before the first allocation.
+    pub fn buffer_size(&self) -> usize {
+        self.buffer.as_ref().map_or(0, |_| self.reserved)
+    }
+
+    /// Binds the unbound leafs and nodes of `graph` into the buffer as
+    /// [`Backend::alloc_graph`] does, after unbinding the previously allocated graph and
+    /// zeroing the buffer. A graph larger than reserved replaces the buffer by a larger one.
+    pub fn alloc_graph(&mut self, ctx: &Context, graph: &ComputeGraph) -> Result<()> {
+        let mut alloc = || {
+            if let Some(buffer) = &self.buffer {
+                buffer.reset()?;
+            }
+            let alignment = self.backend.inner().alignment();
+            let (placed, size) = plan_graph(ctx, graph, |t| self.backend.alloc_size(t))?;
+            if self.buffer.is_none() || size > self.reserved {
+                self.buffer = None;
+                self.reserved = self.reserved.max(size).max(alignment);
+                let report = || Some(ctx.memory_report());
+                let usage = BackendBufferUsage::Any;
+                let inner = self.backend.inner();
+                self.buffer = Some(memory::create_buffer(inner, self.reserved, usage, report)?);
+            }
+            let buffer = self.buffer.as_ref().expect("buffer was just created");
+            for (tensor, offset) in placed {
+                buffer.init_tensor(tensor, offset)?;
+            }
+            Ok(())
+        };
+        alloc().map_err(|e: Error| e.context("in GraphAllocator::alloc_graph"))
+    }
+}
+
+/// The tensor whose memory `tensor` shares, following views to their source.
+fn view_root(tensor: &Tensor) -> Tensor {
+    let mut root = tensor.clone();
+    loop {
+        let source = root.borrow().view_tensor.clone();
+        match source {
+            Some(source) => root = source,
+            None => return root,
+        }
+    }
+}
+
+/// Offsets of the unbound tensors of `graph` for [`Backend::alloc_graph`], views after their
+/// sources, and the size of the buffer. Each tensor takes `alloc_size` bytes, which keeps
+/// the following offsets aligned, and freed ranges are reused first fit.
+fn plan_graph(
+    ctx: &Context,
+    graph: &ComputeGraph,
+    alloc_size: impl Fn(&Tensor) -> usize,
+) -> Result<(Vec<(Tensor, usize)>, usize)> {
+    let unbound = |id| -> Result<Option<Tensor>> {
+        let tensor = ctx.get_tensor(id)?;
+        let bound = tensor.borrow().storage.is_some();
+        Ok((!bound).then_some(tensor))
+    };
+    let nodes = graph.nodes().iter().map(|&id| ctx.get_tensor(id)).collect::<Result<Vec<_>>>()?;
+
+    // The last node reading each tensor or a view of it; outputs are read after compute.
+    let mut last_use = HashMap::new();
+    for (step, node) in nodes.iter().enumerate() {
+        let srcs = node.src_tensor().into_iter().map(|id| ctx.get_tensor(id));
+        for tensor in srcs.chain([Ok(node.clone())]) {
+            last_use.insert(view_root(&tensor?).tensor_id(), step);
+        }
+    }
+    for &id in graph.outputs().iter() {
+        last_use.insert(view_root(&ctx.get_tensor(id)?).tensor_id(), usize::MAX);
+    }
+
+    let mut placed = Vec::new();
+    let mut size = 0;
+    for &id in graph.leafs().iter() {
+        if let Some(leaf) = unbound(id)? {
+            let nbytes = alloc_size(&leaf);
+            placed.push((leaf, size));
+            size += nbytes;
+        }
+    }
+
+    // Free ranges as (offset, size), sorted by offset.
+    let mut free: Vec<(usize, usize)> = Vec::new();
+    let mut owned: HashMap<_, (usize, usize)> = HashMap::new();
+    for (step, node) in nodes.iter().enumerate() {
+        if let Some(node) = unbound(node.tensor_id())? {
+            let is_view = node.borrow().view_tensor.is_some();
+            let offset = if is_view {
+                0
+            } else {
+                let nbytes = alloc_size(&node);
+                let offset = match free.iter().position(|&(_, len)| len >= nbytes) {
+                    Some(i) => {
+                        let (offset, len) = free[i];
+                        free[i] = (offset + nbytes, len - nbytes);
+                        offset
+                    }
+                    None => {
+                        size += nbytes;
+                        size - nbytes
+                    }
+                };
+                owned.insert(node.tensor_id(), (offset, nbytes));
+                offset
+            };
+            placed.push((node, offset));
+        }
+
+        let dead: Vec<_> = owned
+            .iter()
+            .filter(|(id, _)| last_use.get(*id) == Some(&step))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in dead {
+            free.push(owned.remove(&id).unwrap_or_default());
+        }
+        free.retain(|&(_, len)| len > 0);
+        free.sort_unstable();
+        free.dedup_by(|next, prev| {
+            let adjacent = prev.0 + prev.1 == next.0;
+            if adjacent {
+                prev.1 += next.1;
+            }
+            adjacent
+        });
+    }
+
+    Ok((placed, size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_type::{DataType, TensorOpType, TensorType};
+    use crate::shape;
+
+    #[test]
+    fn test_plan_graph_aligns_offsets() {
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut a = ctx.new_tensor(DataType::F32, &shape![5]).unwrap();
+        let b = ctx.new_tensor(DataType::F32, &shape![5]).unwrap();
+        for leaf in [&a, &b] {
+            leaf.set_op_type(TensorOpType::TensorNone);
+            leaf.set_tensor_type(TensorType::FlagParam);
+        }
+        let mut sum = a.add(b.clone()).unwrap();
+        let out = sum.mul(b).unwrap();
+        let graph = ComputeGraph::new();
+        graph.add_output(&ctx, &out).unwrap();
+
+        for alignment in [64, 256] {
+            let (placed, size) =
+                plan_graph(&ctx, &graph, |t| t.nbytes().next_multiple_of(alignment)).unwrap();
+            let offsets: Vec<usize> = placed.iter().map(|(_, offset)| *offset).collect();
+            assert!(offsets.iter().all(|offset| offset % alignment == 0), "{offsets:?}");
+            assert_eq!(size, 4 * alignment);
+        }
+    }
+}
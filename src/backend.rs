@@ -1,9 +1,17 @@
 use crate::compute_graph::ComputeGraph;
 use crate::context::Context;
-use crate::data_type::TensorOpType;
-use crate::error::Result;
-use crate::tensor::Tensor;
-use std::any::Any;
+use crate::data_type::{DataType, Element, TensorOpType};
+use crate::error::{Error, Result};
+#[cfg(feature = "std")]
+use crate::registry::BackendFunction;
+use crate::tensor::{Tensor, TensorId};
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::any::Any;
+use core::cell::{Ref, RefCell, RefMut};
+use core::sync::atomic::{AtomicU8, Ordering};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum BackendDeviceType {
@@ -34,6 +42,11 @@ pub struct BackendDeviceProps {
 #[derive(Default, Clone)]
 pub struct DeviceInfo {
     pub name: String,
+    /// Identity of the physical device that stays the same across runs and processes, e.g.
+    /// the UUID of a GPU, to key caches and configs per device with; see
+    /// [`Registry::find_device`](crate::registry::Registry::find_device). Empty if the
+    /// backend cannot tell.
+    pub id: String,
     pub description: String,
     pub memory: MemoryInfo,
     pub device_type: BackendDeviceType,
@@ -53,6 +66,153 @@ pub struct BackendCapabilities {
     pub events: bool,
 }
 
+/// Returns `true` to stop a running graph compute between two nodes.
+pub type AbortCallback = Box<dyn Fn() -> bool + Send + Sync>;
+
+/// Callbacks around graph execution, registered with [`Backend::set_observer`]. Node hooks
+/// see the tensor being computed, so an observer can log nodes, dump activations or gather
+/// statistics; like ggml's eval callback, the node tensor has been computed and can be read
+/// in `on_node_end`.
+pub trait GraphObserver: Send {
+    fn on_graph_begin(&mut self, _graph: &ComputeGraph) {}
+
+    fn on_graph_end(&mut self, _graph: &ComputeGraph) {}
+
+    fn on_node_begin(&mut self, _tensor: &Tensor) {}
+
+    /// Returns `false` to stop the graph compute after this node.
+    fn on_node_end(&mut self, _tensor: &Tensor) -> bool {
+        true
+    }
+}
+
+/// Span of one graph compute on `backend`, entered by the backends while they run the
+/// graph when the `tracing` feature is on.
+#[cfg(feature = "tracing")]
+pub(crate) fn graph_span(backend: &str, graph: &ComputeGraph) -> tracing::Span {
+    tracing::info_span!(
+        "graph_compute",
+        backend,
+        graph = graph.id().as_usize(),
+        nodes = graph.node_count()
+    )
+}
+
+/// Span of the compute of one node, a child of the [`graph_span`] of its graph.
+#[cfg(feature = "tracing")]
+pub(crate) fn node_span(tensor: &Tensor) -> tracing::Span {
+    tracing::debug_span!(
+        "node",
+        op = ?tensor.op_type(),
+        name = %tensor.name(),
+        shape = %tensor.shape(),
+        dtype = ?tensor.dtype(),
+        bytes = tensor.nbytes()
+    )
+}
+
+/// Host memory ranges registered with a backend, see [`Backend::register_host_memory`].
+#[derive(Debug, Default)]
+pub struct HostRanges {
+    /// `(start, len)` of every registered range.
+    ranges: RefCell<Vec<(usize, usize)>>,
+}
+
+impl HostRanges {
+    /// Records `len` bytes at `ptr`. Fails for an empty range or one overlapping a
+    /// registered range.
+    pub fn register(&self, ptr: *const u8, len: usize) -> Result<()> {
+        let start = ptr as usize;
+        let mut ranges = self.ranges.borrow_mut();
+        let overlaps = ranges.iter().any(|&(s, l)| start < s + l && s < start + len);
+        if len == 0 || overlaps {
+            return Err(Error::msg(format!(
+                "cannot register {len} bytes at {start:#x}, the range is empty or overlaps"
+            ))
+            .context("in HostRanges::register"));
+        }
+        ranges.push((start, len));
+        Ok(())
+    }
+
+    /// Forgets the range registered at `ptr`.
+    pub fn unregister(&self, ptr: *const u8) -> Result<()> {
+        let mut ranges = self.ranges.borrow_mut();
+        match ranges.iter().position(|&(start, _)| start == ptr as usize) {
+            Some(index) => {
+                ranges.swap_remove(index);
+                Ok(())
+            }
+            None => Err(Error::msg(format!("no host memory registered at {ptr:?}"))
+                .context("in HostRanges::unregister")),
+        }
+    }
+
+    /// Whether the `len` bytes at `ptr` lie in one registered range.
+    pub fn contains(&self, ptr: *const u8, len: usize) -> bool {
+        let start = ptr as usize;
+        self.ranges.borrow().iter().any(|&(s, l)| s <= start && start + len <= s + l)
+    }
+}
+
+/// Build or runtime feature a backend reports, e.g. `avx2 = 1`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackendFeature {
+    pub name: &'static str,
+    pub value: String,
+}
+
+/// `name=value`, e.g. `avx2=1`.
+impl core::fmt::Display for BackendFeature {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}={}", self.name, self.value)
+    }
+}
+
+/// How compute threads are placed on NUMA nodes, see [`numa_init`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum NumaStrategy {
+    #[default]
+    Disabled,
+    Distribute,
+    Isolate,
+    Numactl,
+    Mirror,
+}
+
+static NUMA_STRATEGY: AtomicU8 = AtomicU8::new(NumaStrategy::Disabled as u8);
+
+/// Records the NUMA strategy for backends that place threads themselves. Can only be set
+/// once per process.
+pub fn numa_init(strategy: NumaStrategy) -> Result<()> {
+    NUMA_STRATEGY
+        .compare_exchange(
+            NumaStrategy::Disabled as u8,
+            strategy as u8,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        )
+        .map(|_| ())
+        .or_else(|current| {
+            if current == strategy as u8 {
+                Ok(())
+            } else {
+                Err(Error::msg("NUMA strategy is already set").context("in numa_init"))
+            }
+        })
+}
+
+pub fn numa_strategy() -> NumaStrategy {
+    match NUMA_STRATEGY.load(Ordering::Acquire) {
+        1 => NumaStrategy::Distribute,
+        2 => NumaStrategy::Isolate,
+        3 => NumaStrategy::Numactl,
+        4 => NumaStrategy::Mirror,
+        _ => NumaStrategy::Disabled,
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum BackendBufferUsage {
     Any,
@@ -60,6 +220,17 @@ pub enum BackendBufferUsage {
     Compute,
 }
 
+/// Paging hint for the memory behind a tensor, see [`BackendBuffer::advise`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MemoryAdvice {
+    /// Ask the OS to start reading the pages in (`MADV_WILLNEED`).
+    WillNeed,
+    /// Fault every page in now by reading one byte per page.
+    Touch,
+    /// Release the pages; file-backed pages are read again on next use (`MADV_DONTNEED`).
+    DontNeed,
+}
+
 pub trait BackendBuffer {
     fn reset(&self) -> Result<()>;
 
@@ -75,11 +246,143 @@ pub trait BackendBuffer {
 
     fn usage(&self) -> Result<BackendBufferUsage>;
 
+    /// Returns whether the buffer lives in host memory that the CPU can address directly,
+    /// so tensors may point at host data such as a mapped file instead of copying it.
+    fn is_host(&self) -> bool {
+        false
+    }
+
+    /// Applies a paging hint to the bytes of `tensor`. Buffers that are not mmap-backed
+    /// ignore it.
+    fn advise(&self, _tensor: Tensor, _advice: MemoryAdvice) -> Result<()> {
+        Ok(())
+    }
+
+    /// All bytes of a host buffer. The memory starts at least [`HOST_BUFFER_ALIGNMENT`]
+    /// aligned and does not move while the buffer is alive.
+    fn host_memory(&self) -> Result<Ref<'_, [u8]>> {
+        Err(Error::msg("buffer is not in host memory"))
+    }
+
+    /// Mutable version of [`BackendBuffer::host_memory`].
+    fn host_memory_mut(&self) -> Result<RefMut<'_, [u8]>> {
+        Err(Error::msg("buffer is not in host memory"))
+    }
+
     fn as_any(&self) -> &dyn Any;
 
     fn as_any_mut(&mut self) -> &mut dyn Any;
 }
 
+/// Alignment of the start of [`BackendBuffer::host_memory`].
+pub const HOST_BUFFER_ALIGNMENT: usize = 64;
+
+/// Default [`Backend::alignment`]: a cache line, and enough for aligned AVX-512 loads.
+pub const TENSOR_ALIGNMENT: usize = 64;
+
+impl dyn BackendBuffer + '_ {
+    /// Host memory of the buffer as elements of type `T`. The buffer stays borrowed while
+    /// the slice is alive, so it cannot be written through the buffer in the meantime.
+    pub fn as_slice<T: Element>(&self) -> Result<Ref<'_, [T]>> {
+        let memory = self.host_memory().map_err(|e| e.context("in BackendBuffer::as_slice"))?;
+        let len = typed_len::<T>(&memory).map_err(|e| e.context("in BackendBuffer::as_slice"))?;
+        // SAFETY: `typed_len` checked alignment and size, and `T` has no invalid values.
+        Ok(Ref::map(memory, |bytes| unsafe {
+            core::slice::from_raw_parts(bytes.as_ptr().cast::<T>(), len)
+        }))
+    }
+
+    /// Mutable version of [`as_slice`](#method.as_slice).
+    pub fn as_mut_slice<T: Element>(&self) -> Result<RefMut<'_, [T]>> {
+        let memory =
+            self.host_memory_mut().map_err(|e| e.context("in BackendBuffer::as_mut_slice"))?;
+        let len =
+            typed_len::<T>(&memory).map_err(|e| e.context("in BackendBuffer::as_mut_slice"))?;
+        // SAFETY: as in `as_slice`; the `RefMut` makes the access exclusive.
+        Ok(RefMut::map(memory, |bytes| unsafe {
+            core::slice::from_raw_parts_mut(bytes.as_mut_ptr().cast::<T>(), len)
+        }))
+    }
+}
+
+/// Number of `T` in `bytes`, which must be aligned for `T` and a whole number of them.
+fn typed_len<T: Element>(bytes: &[u8]) -> Result<usize> {
+    let size = core::mem::size_of::<T>();
+    if bytes.as_ptr().align_offset(core::mem::align_of::<T>()) != 0 {
+        return Err(Error::msg(format!("memory is not aligned for {:?}", T::DTYPE)));
+    }
+    if !bytes.len().is_multiple_of(size) {
+        return Err(Error::msg(format!(
+            "buffer size {} is not a multiple of the {:?} size {size}",
+            bytes.len(),
+            T::DTYPE
+        )));
+    }
+    Ok(bytes.len() / size)
+}
+
+/// Copies `src` into `dst`, whichever buffers they live in. Buffers of the same type copy
+/// directly; anything else is staged through host memory.
+pub fn copy_tensor(src: &Tensor, dst: &Tensor) -> Result<()> {
+    let size = src.nbytes();
+    if size > dst.nbytes() {
+        return Err(Error::msg("source tensor is larger than destination tensor")
+            .context("in backend::copy_tensor"));
+    }
+
+    let src_storage = src.storage().map_err(|e| e.context("in backend::copy_tensor"))?;
+    let dst_storage = dst.storage().map_err(|e| e.context("in backend::copy_tensor"))?;
+    let src_buffer = src_storage.buffer();
+    let dst_buffer = dst_storage.buffer();
+
+    if Any::type_id(src_buffer.as_any()) == Any::type_id(dst_buffer.as_any()) {
+        dst_buffer.copy(src.clone(), dst.clone())
+    } else {
+        copy_via_host(src_buffer, src, dst_buffer, dst)
+    }
+}
+
+fn copy_via_host(
+    src_buffer: &dyn BackendBuffer,
+    src: &Tensor,
+    dst_buffer: &dyn BackendBuffer,
+    dst: &Tensor,
+) -> Result<()> {
+    let size = src.nbytes();
+    let mut staging = vec![0; size];
+    src_buffer
+        .read(src.clone(), &mut staging, 0, size)
+        .map_err(|e| e.context("in backend::copy_via_host"))?;
+    dst_buffer
+        .write(dst.clone(), &mut staging, 0, size)
+        .map_err(|e| e.context("in backend::copy_via_host"))
+}
+
+/// Synchronization point recorded on a [`BackendStream`].
+pub trait BackendEvent {
+    /// Returns whether the work enqueued before the last record has finished.
+    fn is_complete(&self) -> Result<bool>;
+
+    /// Blocks until the work enqueued before the last record has finished.
+    fn synchronize(&self) -> Result<()>;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// In-order work queue of a backend. Work on different streams of the same backend may
+/// overlap; [`BackendEvent`]s order it.
+pub trait BackendStream {
+    fn graph_compute(&self, ctx: &Context, graph: &mut ComputeGraph) -> Result<()>;
+
+    /// Records `event` after the work enqueued so far.
+    fn record_event(&self, event: &dyn BackendEvent) -> Result<()>;
+
+    /// Makes the work enqueued from now on wait until `event` is complete.
+    fn wait_event(&self, event: &dyn BackendEvent) -> Result<()>;
+
+    fn synchronize(&self) -> Result<()>;
+}
+
 pub trait Backend {
     fn name(&self) -> &str;
 
@@ -87,6 +390,97 @@ pub trait Backend {
 
     fn graph_compute(&self, ctx: &Context, graph: &mut ComputeGraph) -> Result<()>;
 
+    /// Computes a single graph node, as used by [`Eager`](crate::eager::Eager). Returns
+    /// `Ok(false)` if the backend can only compute whole graphs.
+    fn compute_node(&self, _ctx: &Context, _node: TensorId) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn new_stream(&self) -> Result<Box<dyn BackendStream + '_>> {
+        Err(Error::msg(format!("backend {} does not support streams", self.name()))
+            .context("in Backend::new_stream"))
+    }
+
+    fn new_event(&self) -> Result<Box<dyn BackendEvent>> {
+        Err(Error::msg(format!("backend {} does not support events", self.name()))
+            .context("in Backend::new_event"))
+    }
+
+    /// Features of this backend: what the build and the hardware offer and which code
+    /// paths the current settings select, e.g. `avx2=1` or `threads=16`, for logs and bug
+    /// reports. Empty by default.
+    fn features(&self) -> Vec<BackendFeature> {
+        Vec::new()
+    }
+
+    /// Data types the backend computes `op` on, see [`ops::supported_dtypes`]. Empty for
+    /// ops the backend does not implement.
+    ///
+    /// [`ops::supported_dtypes`]: crate::ops::supported_dtypes
+    fn supported_dtypes(&self, _op: TensorOpType) -> &'static [DataType] {
+        &[]
+    }
+
+    /// Whether the backend computes `op` with value operands of the types `dtypes`.
+    fn supports(&self, op: TensorOpType, dtypes: &[DataType]) -> bool {
+        let supported = self.supported_dtypes(op);
+        !supported.is_empty() && dtypes.iter().all(|dtype| supported.contains(dtype))
+    }
+
+    /// Rows per panel of the interleaved layout (see [`crate::repack`]) in which `op`
+    /// streams its `dtype` weights fastest. `None`, the default, for plain rows.
+    fn blk_size_interleave(&self, _op: TensorOpType, _dtype: DataType) -> Option<usize> {
+        None
+    }
+
+    /// Alignment, a power of two, of the offsets at which allocators such as
+    /// [`Backend::alloc`](crate::api::Backend::alloc) place tensors in the buffers of the
+    /// backend; the space of each tensor is padded to it as well.
+    fn alignment(&self) -> usize {
+        TENSOR_ALIGNMENT
+    }
+
+    /// Largest buffer [`Backend::create_buffer`] can allocate; larger requests fail before
+    /// reaching the backend. Unlimited by default.
+    fn max_buffer_size(&self) -> usize {
+        usize::MAX
+    }
+
+    /// Bytes the allocators reserve for `tensor` in a buffer of the backend: its size padded
+    /// to [`Backend::alignment`]. Backends that store tensors with extra padding override it.
+    fn alloc_size(&self, tensor: &Tensor) -> usize {
+        tensor.nbytes().next_multiple_of(self.alignment())
+    }
+
+    /// Registers `observer` for the following graph computes; `None` removes it.
+    fn set_observer(&mut self, _observer: Option<Box<dyn GraphObserver>>) -> Result<()> {
+        Err(Error::msg(format!("backend {} does not support observers", self.name()))
+            .context("in Backend::set_observer"))
+    }
+
+    /// Registers the `len` bytes at `ptr` for transfers. Backends that can page-lock host
+    /// memory do so, and then [`Backend::write_async`] and [`Backend::read_async`] move data
+    /// in a registered range without a staging copy. The default does nothing, so
+    /// [`Backend::is_host_registered`] stays false; the CPU backend, whose transfers are
+    /// plain copies anyway, only records the range.
+    ///
+    /// # Safety
+    ///
+    /// The memory must stay allocated until it is unregistered.
+    unsafe fn register_host_memory(&self, _ptr: *mut u8, _len: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// Unregisters the memory registered at `ptr`.
+    fn unregister_host_memory(&self, _ptr: *mut u8) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether the `len` bytes at `ptr` are registered host memory.
+    fn is_host_registered(&self, _ptr: *const u8, _len: usize) -> bool {
+        false
+    }
+
     fn write_async(
         &self,
         tensor: Tensor,
@@ -143,7 +537,42 @@ pub trait BackendRegister {
 
     fn init_devices(&self) -> Result<()>;
 
+    /// Looks up a backend-specific entry point by name, see
+    /// [`BackendFunction`](crate::registry::BackendFunction).
+    #[cfg(feature = "std")]
+    fn get_proc_address(&self, _name: &str) -> Option<BackendFunction> {
+        None
+    }
+
     fn as_any(&self) -> &dyn Any;
 
     fn as_any_mut(&mut self) -> &mut dyn Any;
 }
+
+#[cfg(all(test, feature = "cpu"))]
+mod tests {
+    use super::*;
+    use crate::cpu::backend::CpuBackend;
+    use crate::data_type::DataType;
+    use crate::shape;
+
+    #[test]
+    fn test_copy_via_host() {
+        let backend = CpuBackend::init().unwrap();
+        let src_buffer = backend.create_buffer(16, BackendBufferUsage::Any).unwrap();
+        let dst_buffer = backend.create_buffer(32, BackendBufferUsage::Any).unwrap();
+        let mut ctx = Context::builder().build();
+        let src = ctx.new_tensor(DataType::I32, &shape![4]).unwrap();
+        let dst = ctx.new_tensor(DataType::I32, &shape![4]).unwrap();
+        src_buffer.init_tensor(src.clone(), 0).unwrap();
+        dst_buffer.init_tensor(dst.clone(), 16).unwrap();
+
+        let mut bytes: Vec<u8> = (0..16).collect();
+        src_buffer.write(src.clone(), &mut bytes, 0, 16).unwrap();
+        copy_via_host(src_buffer.as_ref(), &src, dst_buffer.as_ref(), &dst).unwrap();
+
+        let mut copied = vec![0; 16];
+        dst_buffer.read(dst, &mut copied, 0, 16).unwrap();
+        assert_eq!(copied, bytes);
+    }
+}
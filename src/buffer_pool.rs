@@ -0,0 +1,160 @@
+//! Reuse of backend buffers across graph evaluations.
+//!
+//! Decoding evaluates a graph of the same shape once per token. Allocating its compute
+//! buffer every time costs an allocation and a free per token and fragments device memory.
+//! A [`BufferPool`] keeps released buffers of one backend and hands them back to later
+//! requests of the same usage and a similar size.
+
+use crate::backend::{Backend, BackendBuffer, BackendBufferUsage};
+use crate::error::Result;
+use crate::memory;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::ops::Deref;
+
+/// Counters of a [`BufferPool`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BufferPoolStats {
+    /// Requests served by a retained buffer.
+    pub hits: usize,
+    /// Requests that allocated a new buffer.
+    pub misses: usize,
+    pub cached_buffers: usize,
+    pub cached_bytes: usize,
+}
+
+struct Entry {
+    size: usize,
+    usage: BackendBufferUsage,
+    buffer: Box<dyn BackendBuffer>,
+}
+
+pub struct BufferPool<'a> {
+    backend: &'a dyn Backend,
+    max_buffers: usize,
+    /// Released buffers, least recently released first.
+    free: RefCell<Vec<Entry>>,
+    hits: Cell<usize>,
+    misses: Cell<usize>,
+}
+
+impl<'a> BufferPool<'a> {
+    /// Number of released buffers kept by default.
+    pub const DEFAULT_MAX_BUFFERS: usize = 4;
+
+    pub fn new(backend: &'a dyn Backend) -> Self {
+        Self {
+            backend,
+            max_buffers: Self::DEFAULT_MAX_BUFFERS,
+            free: RefCell::new(Vec::new()),
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+        }
+    }
+
+    /// Keeps at most `max_buffers` released buffers; the least recently released one is
+    /// freed first.
+    pub fn with_max_buffers(mut self, max_buffers: usize) -> Self {
+        self.max_buffers = max_buffers;
+        self
+    }
+
+    pub fn backend(&self) -> &'a dyn Backend {
+        self.backend
+    }
+
+    /// Returns a buffer of at least `size` bytes. A retained buffer of the same usage is
+    /// reused if it is no more than twice as large, so that a small request does not pin
+    /// a large buffer; otherwise a new one is created. The buffer returns to the pool when
+    /// the guard is dropped.
+    pub fn acquire(&self, size: usize, usage: BackendBufferUsage) -> Result<PooledBuffer<'_>> {
+        let reused = {
+            let mut free = self.free.borrow_mut();
+            free.iter()
+                .enumerate()
+                .filter(|(_, entry)| {
+                    entry.usage == usage && entry.size >= size && entry.size / 2 <= size
+                })
+                .min_by_key(|(_, entry)| entry.size)
+                .map(|(index, _)| index)
+                .map(|index| free.remove(index))
+        };
+
+        let entry = match reused {
+            Some(entry) => {
+                self.hits.set(self.hits.get() + 1);
+                entry
+            }
+            None => {
+                let mut created = self.backend.create_buffer(size, usage);
+                if created.is_err() {
+                    // The retained buffers are the cheapest memory to give back, before
+                    // the alloc failure hook gets its turn.
+                    self.clear();
+                    created = memory::create_buffer(self.backend, size, usage, || None);
+                }
+                let buffer = created.map_err(|e| e.context("in BufferPool::acquire"))?;
+                self.misses.set(self.misses.get() + 1);
+                Entry { size, usage, buffer }
+            }
+        };
+        Ok(PooledBuffer { pool: self, entry: Some(entry) })
+    }
+
+    /// Frees every retained buffer.
+    pub fn clear(&self) {
+        self.free.borrow_mut().clear();
+    }
+
+    pub fn stats(&self) -> BufferPoolStats {
+        let free = self.free.borrow();
+        BufferPoolStats {
+            hits: self.hits.get(),
+            misses: self.misses.get(),
+            cached_buffers: free.len(),
+            cached_bytes: free.iter().map(|entry| entry.size).sum(),
+        }
+    }
+
+    fn release(&self, entry: Entry) {
+        // Unbind the tensors of the previous graph; a buffer that cannot be reset is freed.
+        if self.max_buffers == 0 || entry.buffer.reset().is_err() {
+            return;
+        }
+        let mut free = self.free.borrow_mut();
+        if free.len() == self.max_buffers {
+            free.remove(0);
+        }
+        free.push(entry);
+    }
+}
+
+/// Buffer borrowed from a [`BufferPool`].
+pub struct PooledBuffer<'p> {
+    pool: &'p BufferPool<'p>,
+    entry: Option<Entry>,
+}
+
+impl PooledBuffer<'_> {
+    /// Size the buffer was created with, which may exceed the requested size.
+    pub fn size(&self) -> usize {
+        self.entry.as_ref().map_or(0, |entry| entry.size)
+    }
+}
+
+impl Deref for PooledBuffer<'_> {
+    type Target = dyn BackendBuffer;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.entry.as_ref().expect("pooled buffer is only taken on drop").buffer
+    }
+}
+
+impl Drop for PooledBuffer<'_> {
+    fn drop(&mut self) {
+        if let Some(entry) = self.entry.take() {
+            self.pool.release(entry);
+        }
+    }
+}
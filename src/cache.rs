@@ -0,0 +1,138 @@
+//! Opt-in on-disk cache for artifacts that are expensive to recompute at startup.
+//!
+//! Entries are grouped by [`CacheKind`] and device, and keyed by a caller-chosen string,
+//! usually a [`ComputeGraph::fingerprint`](crate::compute_graph::ComputeGraph::fingerprint).
+//! Nothing is cached unless a [`DiskCache`] is created, e.g. from the `FEML_CACHE_DIR`
+//! environment variable with [`DiskCache::from_env`].
+
+use crate::error::{Error, Result};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CacheKind {
+    /// Prepared compute plans.
+    Plan,
+    /// Autotuner results.
+    Profile,
+    /// Compiled kernels of GPU backends.
+    Shader,
+}
+
+impl CacheKind {
+    fn dir_name(self) -> &'static str {
+        match self {
+            CacheKind::Plan => "plans",
+            CacheKind::Profile => "profiles",
+            CacheKind::Shader => "shaders",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+impl DiskCache {
+    /// Environment variable naming the cache directory for [`DiskCache::from_env`].
+    pub const ENV_VAR: &'static str = "FEML_CACHE_DIR";
+
+    /// Opens the cache at `dir`, creating the directory if needed.
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| Error::from(e).context(format!("in DiskCache::new: {}", dir.display())))?;
+        Ok(Self { dir })
+    }
+
+    /// Opens the cache named by `FEML_CACHE_DIR`, or returns `None` if it is unset or empty.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var_os(Self::ENV_VAR) {
+            Some(dir) if !dir.is_empty() => Self::new(dir).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// File of the entry `key` for `device`, e.g. `"cpu"` or `"cuda:0"`. Characters that are
+    /// not safe in file names are replaced by `_`.
+    pub fn path(&self, kind: CacheKind, device: &str, key: &str) -> PathBuf {
+        self.dir.join(kind.dir_name()).join(sanitize(device)).join(sanitize(key))
+    }
+
+    /// Contents of an entry, or `None` if it was never stored.
+    pub fn load(&self, kind: CacheKind, device: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        match std::fs::read(self.path(kind, device, key)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Error::from(e).context("in DiskCache::load")),
+        }
+    }
+
+    /// Stores an entry. The file is written next to its final name and renamed, so a
+    /// concurrent reader sees either the old or the new contents.
+    pub fn store(&self, kind: CacheKind, device: &str, key: &str, data: &[u8]) -> Result<()> {
+        let path = self.path(kind, device, key);
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        let result = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&tmp, data))
+            .and_then(|_| std::fs::rename(&tmp, &path));
+        result.map_err(|e| Error::from(e).context("in DiskCache::store"))
+    }
+
+    /// Removes every entry of `kind`.
+    pub fn clear(&self, kind: CacheKind) -> Result<()> {
+        match std::fs::remove_dir_all(self.dir.join(kind.dir_name())) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(Error::from(e).context("in DiskCache::clear"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn sanitize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "-_.".contains(c) { c } else { '_' })
+        .collect();
+    match name.trim_start_matches('.') {
+        "" => "_".to_string(),
+        name => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_load_and_clear() {
+        let dir = std::env::temp_dir().join(format!("feml-cache-{}", std::process::id()));
+        let cache = DiskCache::new(&dir).unwrap();
+        assert_eq!(cache.load(CacheKind::Shader, "cuda:0", "kernel").unwrap(), None);
+
+        cache.store(CacheKind::Shader, "cuda:0", "kernel", b"ptx").unwrap();
+        cache.store(CacheKind::Plan, "cpu", "kernel", b"plan").unwrap();
+        assert_eq!(cache.load(CacheKind::Shader, "cuda:0", "kernel").unwrap().unwrap(), b"ptx");
+        assert_eq!(cache.load(CacheKind::Shader, "cuda:1", "kernel").unwrap(), None);
+        assert!(cache.path(CacheKind::Shader, "cuda:0", "kernel").ends_with("cuda_0/kernel"));
+
+        cache.clear(CacheKind::Shader).unwrap();
+        assert_eq!(cache.load(CacheKind::Shader, "cuda:0", "kernel").unwrap(), None);
+        assert_eq!(cache.load(CacheKind::Plan, "cpu", "kernel").unwrap().unwrap(), b"plan");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_sanitize_keeps_entries_inside_the_cache() {
+        assert_eq!(sanitize("../etc/passwd"), "_etc_passwd");
+        assert_eq!(sanitize(".."), "_");
+        assert_eq!(sanitize("0123abcd-4"), "0123abcd-4");
+    }
+}
@@ -0,0 +1,7 @@
+//! Maps and sets used by the core types. Without `std` there is no default hasher, so the
+//! ordered collections of `alloc` stand in; callers only rely on the API both share.
+
+#[cfg(not(feature = "std"))]
+pub(crate) use alloc::collections::{BTreeMap as HashMap, BTreeSet as HashSet};
+#[cfg(feature = "std")]
+pub(crate) use std::collections::{HashMap, HashSet};
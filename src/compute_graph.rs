@@ -1,19 +1,31 @@
+use crate::collections::{HashMap, HashSet};
 use crate::context::Context;
-use crate::data_type::TensorOpType;
 use crate::data_type::TensorType;
+use crate::data_type::{DataType, TensorOpType};
+use crate::defs::MAX_DIMS;
 use crate::error::{Error, Result};
-use crate::tensor::TensorId;
-use std::cell::{Ref, RefCell};
-use std::collections::{HashMap, HashSet};
-use std::fmt;
-use std::rc::Rc;
-
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+use crate::layout::Layout;
+use crate::memory::MemoryCategory;
+use crate::rng::RngState;
+use crate::shape::Shape;
+use crate::shape_inference::{matmul_shape, reduce_shape};
+use crate::tensor::{Tensor, TensorId};
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::{Ref, RefCell};
+use core::fmt;
+use core::fmt::Write as _;
+use core::panic::Location;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct GraphId(usize);
 
 impl GraphId {
     fn new() -> Self {
-        use std::sync::atomic;
+        use core::sync::atomic;
         static COUNTER: atomic::AtomicUsize = atomic::AtomicUsize::new(1);
         Self(COUNTER.fetch_add(1, atomic::Ordering::Relaxed))
     }
@@ -38,6 +50,12 @@ pub struct ComputeGraphInner {
     pub(crate) leafs: Vec<TensorId>,
     node_use_count: HashMap<TensorId, usize>,
     visited_nodes: HashSet<TensorId>,
+    outputs: Vec<TensorId>,
+    rng: Option<TensorId>,
+    /// Errors kept by [`ComputeGraph::build_node`] for [`ComputeGraph::finish`].
+    diagnostics: Vec<Error>,
+    /// Placeholders of failed nodes, and the nodes built on them.
+    poisoned: HashSet<TensorId>,
 }
 
 #[derive(Clone)]
@@ -54,6 +72,10 @@ impl Default for ComputeGraphInner {
             leafs: Vec::new(),
             node_use_count: HashMap::new(),
             visited_nodes: HashSet::new(),
+            outputs: Vec::new(),
+            rng: None,
+            diagnostics: Vec::new(),
+            poisoned: HashSet::new(),
         }
     }
 }
@@ -72,6 +94,9 @@ impl ComputeGraph {
         inner.leafs.clear();
         inner.node_use_count.clear();
         inner.visited_nodes.clear();
+        inner.outputs.clear();
+        inner.diagnostics.clear();
+        inner.poisoned.clear();
     }
 
     pub fn id(&self) -> GraphId {
@@ -106,6 +131,169 @@ impl ComputeGraph {
         self.0.borrow().visited_nodes.contains(&id)
     }
 
+    /// Marks `tensor` as an output of the graph, adding it and its parents to the graph
+    /// first. Outputs keep their memory until the graph is cleared: allocation plans such
+    /// as [`Backend::alloc_graph`](crate::api::Backend::alloc_graph) never reuse it for
+    /// other nodes, so they can be read after compute.
+    pub fn add_output(&self, context: &Context, tensor: &Tensor) -> Result<()> {
+        let id = tensor.tensor_id();
+        self.build_forward(context, id, true)
+            .map_err(|e| e.context("in ComputeGraph::add_output"))?;
+        let mut inner = self.0.borrow_mut();
+        if !inner.outputs.contains(&id) {
+            inner.outputs.push(id);
+        }
+        Ok(())
+    }
+
+    /// Runs `op`, which builds the node `name` from `inputs`, without aborting graph
+    /// construction at the first mistake. An error of `op` is kept for
+    /// [`ComputeGraph::finish`], with the node name and the input shapes as context, and a
+    /// placeholder with the dtype and shape of the first input stands in for the node, so
+    /// the rest of the model can still be built and checked. Nodes built on placeholders
+    /// are placeholders too, and their errors are dropped as consequences of the first one.
+    /// The error is returned right away only if there is no input to stand in for. With
+    /// [provenance](crate::context::ContextBuilder::provenance) on, the kept error also
+    /// names the line that called this method.
+    ///
+    /// ```
+    /// # use feml::{compute_graph::ComputeGraph, context::Context, data_type::DataType, shape};
+    /// # fn main() -> feml::error::Result<()> {
+    /// let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+    /// let x = ctx.new_tensor(DataType::F32, &shape![4, 2])?;
+    /// let w = ctx.new_tensor(DataType::F32, &shape![3, 5])?;
+    /// let b = ctx.new_tensor(DataType::F32, &shape![3])?;
+    /// let graph = ComputeGraph::new();
+    /// let h = graph.build_node("up", &[&w, &x], || w.clone().mul_mat(x.clone()))?;
+    /// let y = graph.build_node("bias", &[&h, &b], || h.clone().add(b.clone()))?;
+    /// let z = graph.build_node("scale", &[&x, &b], || x.clone().mul(b.clone()))?;
+    /// // the mismatched product and scale, but not the bias added to the failed product
+    /// assert_eq!(graph.finish(&ctx, &[&y, &z]).unwrap_err().len(), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    pub fn build_node<F>(&self, name: &str, inputs: &[&Tensor], op: F) -> Result<Tensor>
+    where
+        F: FnOnce() -> Result<Tensor>,
+    {
+        let poisoned = {
+            let inner = self.0.borrow();
+            inputs.iter().any(|input| inner.poisoned.contains(&input.tensor_id()))
+        };
+        let error = match op() {
+            Ok(tensor) => {
+                tensor.set_name(name);
+                if poisoned {
+                    self.0.borrow_mut().poisoned.insert(tensor.tensor_id());
+                }
+                return Ok(tensor);
+            }
+            Err(error) => error,
+        };
+
+        let Some(&like) = inputs.first() else {
+            return Err(error.context(format!("in node {name}")));
+        };
+        if !poisoned {
+            let shapes: Vec<String> = inputs
+                .iter()
+                .map(|input| format!("{} {:?} {}", input.name(), input.dtype(), input.shape()))
+                .collect();
+            let mut node = format!("in node {name} of [{}]", shapes.join(", "));
+            if like.ctx().is_ok_and(|ctx| ctx.borrow().provenance) {
+                node.push_str(&format!(" built at {}", Location::caller()));
+            }
+            self.0.borrow_mut().diagnostics.push(error.context(node));
+        }
+        let placeholder = like
+            .ctx()
+            .and_then(|mut ctx| ctx.dup_tensor(like.clone()))
+            .map_err(|e| e.context("in ComputeGraph::build_node"))?;
+        placeholder.set_name(name);
+        self.0.borrow_mut().poisoned.insert(placeholder.tensor_id());
+        Ok(placeholder)
+    }
+
+    /// Ends construction with [`ComputeGraph::build_node`]: all errors kept since the graph
+    /// was created or cleared, or if there are none, the errors of adding `outputs` (see
+    /// [`ComputeGraph::add_output`]). The kept errors are handed out once.
+    pub fn finish(
+        &self,
+        context: &Context,
+        outputs: &[&Tensor],
+    ) -> core::result::Result<(), Vec<Error>> {
+        let diagnostics = core::mem::take(&mut self.0.borrow_mut().diagnostics);
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+        let errors: Vec<Error> = outputs
+            .iter()
+            .filter_map(|output| self.add_output(context, output).err())
+            .map(|e| e.context("in ComputeGraph::finish"))
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// The outputs in the order they were added.
+    pub fn outputs(&self) -> Ref<'_, [TensorId]> {
+        Ref::map(self.0.borrow(), |inner| inner.outputs.as_slice())
+    }
+
+    pub fn is_output(&self, id: TensorId) -> bool {
+        self.0.borrow().outputs.contains(&id)
+    }
+
+    /// The output `id`. Fails if `id` is not an output of this graph.
+    pub fn output(&self, context: &Context, id: TensorId) -> Result<Tensor> {
+        if !self.is_output(id) {
+            return Err(Error::msg(format!("tensor {} is not a graph output", id.as_usize()))
+                .context("in ComputeGraph::output"));
+        }
+        context.get_tensor(id).map_err(|e| e.context("in ComputeGraph::output"))
+    }
+
+    /// The first output named `name`.
+    pub fn output_by_name(&self, context: &Context, name: &str) -> Result<Tensor> {
+        for &id in self.outputs().iter() {
+            let tensor =
+                context.get_tensor(id).map_err(|e| e.context("in ComputeGraph::output_by_name"))?;
+            if tensor.name() == name {
+                return Ok(tensor);
+            }
+        }
+        Err(Error::msg(format!("no graph output named {name:?}"))
+            .context("in ComputeGraph::output_by_name"))
+    }
+
+    /// The random state of the graph, a `U8` tensor of [`RngState::NBYTES`] that stochastic
+    /// ops such as [`Tensor::dropout`] take as a source. It is created on the first call and
+    /// kept by [`ComputeGraph::clear`], so a graph rebuilt every step draws from one state.
+    /// Set it with [`Backend::set_rng_state`](crate::api::Backend::set_rng_state) once it
+    /// is allocated.
+    pub fn rng(&self, context: &mut Context) -> Result<Tensor> {
+        if let Some(id) = self.rng_id() {
+            return context.get_tensor(id).map_err(|e| e.context("in ComputeGraph::rng"));
+        }
+        let tensor = context
+            .new_tensor(DataType::U8, &Shape::new(&[RngState::NBYTES]))
+            .map_err(|e| e.context("in ComputeGraph::rng"))?;
+        tensor.set_op_type(TensorOpType::TensorNone);
+        tensor.set_tensor_type(TensorType::FlagParam);
+        tensor.set_name("rng_state").set_memory_category(MemoryCategory::Other);
+        self.0.borrow_mut().rng = Some(tensor.tensor_id());
+        Ok(tensor)
+    }
+
+    /// The id of the [random state](ComputeGraph::rng), if it was created.
+    pub fn rng_id(&self) -> Option<TensorId> {
+        self.0.borrow().rng
+    }
+
     pub fn visit_parents(&self, context: &Context, input: TensorId) -> Result<()> {
         if self.0.borrow().visited_nodes.contains(&input) {
             return Ok(());
@@ -162,6 +350,361 @@ impl ComputeGraph {
 
         Ok(())
     }
+
+    /// Hash of the graph's structure: the op, data type, shape, strides and view offset of
+    /// every leaf and node, and which of them each node reads. Graphs built the same way in
+    /// different contexts or processes have the same fingerprint, so it can key cached
+    /// artifacts such as compute plans. Op parameters are not included.
+    pub fn fingerprint(&self, context: &Context) -> Result<u64> {
+        let leafs = self.leafs().to_vec();
+        let nodes = self.nodes().to_vec();
+        let position = |id: &TensorId| {
+            let leaf = leafs.iter().position(|leaf| leaf == id).map(|i| (0u8, i));
+            leaf.or_else(|| nodes.iter().position(|node| node == id).map(|i| (1u8, i)))
+        };
+
+        let mut hasher = Fnv1a::new();
+        hasher.write(&[leafs.len(), nodes.len()]);
+        for id in leafs.iter().chain(&nodes) {
+            let tensor = context.get_tensor(*id).map_err(|e| e.context("in fingerprint"))?;
+            hasher.write_str(&format!("{:?}/{:?}", tensor.op_type(), tensor.dtype()));
+            let shape = *tensor.shape();
+            hasher.write(&shape.dims[..shape.rank]);
+            hasher.write(&tensor.stride());
+            hasher.write(&[tensor.view_offset()]);
+            for src in tensor.src_tensor() {
+                match position(&src) {
+                    Some((kind, index)) => hasher.write(&[kind as usize, index]),
+                    None => hasher.write(&[usize::MAX]),
+                }
+            }
+        }
+
+        Ok(hasher.finish())
+    }
+
+    /// Sets dimension `axis` of every `(input, axis)` pair to `len` and infers the shapes of
+    /// the nodes again, so one graph serves e.g. every prompt length up to the one it was
+    /// built and allocated for. The graph keeps its topology, so a CPU compute plan only
+    /// needs a `plan_update`.
+    ///
+    /// A bound tensor must fit in the memory it was allocated, so `len` may not exceed the
+    /// length it was built with. Nodes with ops whose shape cannot be inferred again, such
+    /// as views with explicit shapes and reshapes, must not depend on the inputs.
+    pub fn bind_dim(
+        &self,
+        context: &Context,
+        inputs: &[(Tensor, usize)],
+        len: usize,
+    ) -> Result<()> {
+        let mut changed = HashSet::new();
+        for (input, axis) in inputs {
+            if *axis >= MAX_DIMS || len == 0 {
+                return Err(Error::msg(format!("cannot bind dimension {axis} to {len}"))
+                    .context("in ComputeGraph::bind_dim"));
+            }
+            let shape = input.shape().with_dim(*axis, len);
+            resize(input, shape).map_err(|e| e.context("in ComputeGraph::bind_dim"))?;
+            changed.insert(input.tensor_id());
+        }
+
+        for &id in self.nodes().iter() {
+            let node =
+                context.get_tensor(id).map_err(|e| e.context("in ComputeGraph::bind_dim"))?;
+            let src_ids = node.src_tensor();
+            if !src_ids.iter().any(|src| changed.contains(src)) {
+                continue;
+            }
+            let srcs = src_ids
+                .iter()
+                .map(|src| context.get_tensor(*src))
+                .collect::<Result<Vec<_>>>()
+                .map_err(|e| e.context("in ComputeGraph::bind_dim"))?;
+            let shape = infer_shape(&node, &srcs).ok_or_else(|| {
+                Error::msg(format!(
+                    "cannot infer the shape of {:?} {:?}",
+                    node.op_type(),
+                    node.name()
+                ))
+                .context("in ComputeGraph::bind_dim")
+            })?;
+            if node.op_type() == TensorOpType::TensorOpTranspose {
+                let mut stride = srcs[0].borrow().layout.stride;
+                stride.swap(0, 1);
+                let mut inner = node.borrow_mut();
+                inner.layout.shape = shape;
+                inner.layout.stride = stride;
+            } else {
+                resize(&node, shape).map_err(|e| e.context("in ComputeGraph::bind_dim"))?;
+            }
+            changed.insert(id);
+        }
+
+        Ok(())
+    }
+}
+
+impl ComputeGraph {
+    /// The graph as an aligned text table, one row per node in compute order followed by
+    /// one per leaf. Nodes are numbered `n0, n1, ..` and leafs `l0, l1, ..`; the `src`
+    /// column refers to these numbers and the flags are `p` for a parameter leaf, `o` for
+    /// an [output](ComputeGraph::add_output) and `v` for a view.
+    ///
+    /// ```text
+    ///  #   op      shape  dtype  src    flags  name
+    ///  n0  MulMat  3x2    F32    l0 l1  o      h
+    ///  l0  None    4x3    F32           p      w
+    ///  l1  None    4x2    F32           p
+    /// 1 nodes, 2 leafs
+    /// ```
+    pub fn table(&self, context: &Context) -> Result<String> {
+        let table = || {
+            let labels: HashMap<TensorId, String> = self
+                .nodes()
+                .iter()
+                .enumerate()
+                .map(|(i, id)| (*id, format!("n{i}")))
+                .chain(self.leafs().iter().enumerate().map(|(i, id)| (*id, format!("l{i}"))))
+                .collect();
+
+            let header = ["#", "op", "shape", "dtype", "src", "flags", "name"].map(String::from);
+            let mut rows = vec![header];
+            for &id in self.nodes().iter().chain(self.leafs().iter()) {
+                let tensor = context.get_tensor(id)?;
+                let op = format!("{:?}", tensor.op_type());
+                let op = op.strip_prefix("TensorOp").or(op.strip_prefix("Tensor")).unwrap_or(&op);
+                let src: Vec<&str> = tensor
+                    .src_tensor()
+                    .iter()
+                    .map(|src| labels.get(src).map_or("?", String::as_str))
+                    .collect();
+                let mut flags = String::new();
+                if tensor.tensor_type() == TensorType::FlagParam {
+                    flags.push('p');
+                }
+                if self.is_output(id) {
+                    flags.push('o');
+                }
+                if tensor.borrow().view_tensor.is_some() {
+                    flags.push('v');
+                }
+                rows.push([
+                    labels[&id].clone(),
+                    op.to_string(),
+                    tensor.shape().to_string(),
+                    format!("{:?}", tensor.dtype()),
+                    src.join(" "),
+                    flags,
+                    tensor.name(),
+                ]);
+            }
+
+            let mut widths = [0; 7];
+            for row in &rows {
+                for (width, cell) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(cell.chars().count());
+                }
+            }
+            let mut out = String::new();
+            for row in &rows {
+                let mut line = String::new();
+                for (cell, width) in row.iter().zip(widths) {
+                    let _ = write!(line, " {cell:<width$} ");
+                }
+                out.push_str(line.trim_end());
+                out.push('\n');
+            }
+            let _ = writeln!(out, "{} nodes, {} leafs", self.node_count(), self.leaf_count());
+            Ok(out)
+        };
+        table().map_err(|e: Error| e.context("in ComputeGraph::table"))
+    }
+
+    /// Prints [`ComputeGraph::table`] to stdout.
+    #[cfg(feature = "std")]
+    pub fn print(&self, context: &Context) -> Result<()> {
+        print!("{}", self.table(context)?);
+        Ok(())
+    }
+}
+
+/// Gives the dense `tensor` a new shape that fits in its memory, if it is bound.
+fn resize(tensor: &Tensor, shape: Shape) -> Result<()> {
+    let stride = Layout::contiguous_stride(&shape, tensor.dtype());
+    let nbytes = Layout::new(shape, stride, 0).nbytes(tensor.dtype());
+    let mut inner = tensor.borrow_mut();
+    if inner.view_tensor.is_some() {
+        return Err(Error::msg(format!("view {:?} cannot be resized", inner.name)));
+    }
+    let allocated = inner.storage.as_ref().map_or(usize::MAX, |storage| storage.size());
+    if nbytes > allocated {
+        return Err(Error::msg(format!(
+            "{:?} of shape {shape} needs {nbytes} bytes, {allocated} are allocated",
+            inner.name
+        )));
+    }
+    inner.layout.shape = shape;
+    inner.layout.stride = stride;
+    Ok(())
+}
+
+/// Shape of `node` computed from the shapes of `srcs`, for [`ComputeGraph::bind_dim`].
+/// Returns `None` for ops whose shape depends on parameters that are not kept.
+fn infer_shape(node: &Tensor, srcs: &[Tensor]) -> Option<Shape> {
+    let src = |i: usize| srcs.get(i).map(|src| *src.shape());
+    let rank = node.shape().rank;
+    match node.op_type() {
+        TensorOpType::TensorOpAdd
+        | TensorOpType::TensorOpSub
+        | TensorOpType::TensorOpMul
+        | TensorOpType::TensorOpDiv
+        | TensorOpType::TensorOpMapUnary
+        | TensorOpType::TensorOpGelu
+        | TensorOpType::TensorOpRelu
+        | TensorOpType::TensorOpLeakyRelu
+        | TensorOpType::TensorOpSilu
+        | TensorOpType::TensorOpSwiGlu
+        | TensorOpType::TensorOpMapBinary
+        | TensorOpType::TensorOpCont
+        | TensorOpType::TensorOpDiagMaskInf
+        | TensorOpType::TensorOpSoftMax
+        | TensorOpType::TensorOpNorm
+        | TensorOpType::TensorOpRmsNorm
+        | TensorOpType::TensorOpDropout
+        | TensorOpType::TensorOpGroupNorm => src(0),
+        TensorOpType::TensorOpMulMat => matmul_shape(&src(0)?, &src(1)?).ok(),
+        TensorOpType::TensorOpAttention => Some(Shape::new(&src(0)?.dims[..rank])),
+        TensorOpType::TensorOpSumRows | TensorOpType::TensorOpMean => {
+            reduce_shape(&src(0)?, &[0]).ok()
+        }
+        TensorOpType::TensorOpTranspose => {
+            let mut shape = src(0)?;
+            shape.dims.swap(0, 1);
+            shape.rank = rank;
+            Some(shape)
+        }
+        _ => None,
+    }
+}
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher` its output is fixed, so fingerprints can be
+/// persisted.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write(&mut self, values: &[usize]) {
+        self.write_bytes(&(values.len() as u64).to_le_bytes());
+        for value in values {
+            self.write_bytes(&(*value as u64).to_le_bytes());
+        }
+    }
+
+    fn write_str(&mut self, value: &str) {
+        self.write(&[value.len()]);
+        self.write_bytes(value.as_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Declares the tensors of a model and the graph computing it in near-math notation.
+///
+/// The body is a list of statements that expand, in place, to the usual construction calls
+/// on the context `ctx`, so every declared name is a [`Tensor`](crate::tensor::Tensor)
+/// binding in the enclosing scope and errors propagate with `?`:
+///
+/// - `input x: F32[64, 8];` and `weight w: F32[64, 32];` create named leaf tensors with
+///   the given [`DataType`](crate::data_type::DataType) variant and dimensions, innermost
+///   first.
+/// - `let y = w @ x;` is a matrix product ([`Tensor::mul_mat`](crate::tensor::Tensor::mul_mat))
+///   and `let y = a * b;` an element-wise product.
+/// - `let y = op(a, b; p, q);` calls any tensor op as `a.op(b, p, q)`: the names before the
+///   `;` are tensor sources, the expressions after it are further parameters.
+/// - `output y, z => graph;` builds the forward graph of the outputs as `graph` and marks
+///   them as its outputs ([`ComputeGraph::add_output`]).
+///
+/// Shapes of the results are inferred by the ops, and every result is named after its
+/// binding.
+///
+/// ```
+/// # fn main() -> feml::error::Result<()> {
+/// use feml::context::Context;
+///
+/// let mut ctx = Context::builder().build();
+/// feml::feml_graph! { ctx;
+///     input x: F32[4, 2];
+///     weight w: F32[4, 3];
+///     let h = w @ x;
+///     let y = diag_mask_inf(h; 0);
+///     output y => graph;
+/// }
+/// assert_eq!(h.shape().dims[..2], [3, 2]);
+/// assert_eq!(graph.node_count(), 2);
+/// assert_eq!(graph.output_by_name(&ctx, "y")?.tensor_id(), y.tensor_id());
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! feml_graph {
+    ($ctx:ident; $($body:tt)*) => {
+        $crate::feml_graph!(@stmt $ctx; $($body)*);
+    };
+
+    (@stmt $ctx:ident;) => {};
+
+    (@stmt $ctx:ident; input $name:ident : $dtype:ident [$($dim:expr),+ $(,)?]; $($rest:tt)*) => {
+        $crate::feml_graph!(@leaf $ctx, $name, $dtype, [$($dim),+]);
+        $crate::feml_graph!(@stmt $ctx; $($rest)*);
+    };
+
+    (@stmt $ctx:ident; weight $name:ident : $dtype:ident [$($dim:expr),+ $(,)?]; $($rest:tt)*) => {
+        $crate::feml_graph!(@leaf $ctx, $name, $dtype, [$($dim),+]);
+        $crate::feml_graph!(@stmt $ctx; $($rest)*);
+    };
+
+    (@stmt $ctx:ident; let $name:ident = $a:ident @ $b:ident; $($rest:tt)*) => {
+        $crate::feml_graph!(@stmt $ctx; let $name = mul_mat($a, $b); $($rest)*);
+    };
+
+    (@stmt $ctx:ident; let $name:ident = $a:ident * $b:ident; $($rest:tt)*) => {
+        $crate::feml_graph!(@stmt $ctx; let $name = mul($a, $b); $($rest)*);
+    };
+
+    (@stmt $ctx:ident;
+        let $name:ident = $op:ident($recv:ident $(, $src:ident)* $(; $($param:expr),+ $(,)?)?);
+        $($rest:tt)*
+    ) => {
+        let $name = $recv.clone().$op($($src.clone(),)* $($($param),+)?)?;
+        $name.set_name(stringify!($name));
+        $crate::feml_graph!(@stmt $ctx; $($rest)*);
+    };
+
+    (@stmt $ctx:ident; output $($out:ident),+ => $graph:ident; $($rest:tt)*) => {
+        #[allow(unused_mut)]
+        let mut $graph = $crate::compute_graph::ComputeGraph::new();
+        $($graph.add_output(&$ctx, &$out)?;)+
+        $crate::feml_graph!(@stmt $ctx; $($rest)*);
+    };
+
+    (@leaf $ctx:ident, $name:ident, $dtype:ident, [$($dim:expr),+]) => {
+        let shape = $crate::shape::Shape::new(&[$($dim),+]);
+        let $name = $ctx.new_tensor($crate::data_type::DataType::$dtype, &shape)?;
+        $name.set_name(stringify!($name));
+        $name.set_tensor_type($crate::data_type::TensorType::FlagParam);
+        $name.set_op_type($crate::data_type::TensorOpType::TensorNone);
+    };
 }
 
 #[cfg(test)]
@@ -336,4 +879,103 @@ mod tests {
         assert_eq!(graph.node_count(), node_count);
         assert_eq!(&*graph.nodes(), &[input]);
     }
+
+    #[test]
+    fn test_fingerprint_depends_on_structure_not_ids() {
+        let build = |dims: [usize; 2]| {
+            let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+            let mut a = ctx.new_tensor(DataType::F32, &Shape::new(&dims)).unwrap();
+            let b = ctx.new_tensor(DataType::F32, &Shape::new(&dims)).unwrap();
+            mark_as_param_leaf(&a);
+            mark_as_param_leaf(&b);
+            let out = a.mul(b).unwrap();
+            let graph = ComputeGraph::new();
+            graph.build_forward(&ctx, out.tensor_id(), false).unwrap();
+            graph.fingerprint(&ctx).unwrap()
+        };
+
+        assert_eq!(build([2, 3]), build([2, 3]));
+        assert_ne!(build([2, 3]), build([3, 2]));
+        assert_ne!(ComputeGraph::new().fingerprint(&Context::builder().build()).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_add_output_records_outputs_once() {
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut a = new_test_tensor(&mut ctx);
+        let b = new_test_tensor(&mut ctx);
+        mark_as_param_leaf(&a);
+        mark_as_param_leaf(&b);
+        let prod = a.mul(b.clone()).unwrap();
+        prod.set_name("prod");
+
+        let graph = ComputeGraph::new();
+        graph.add_output(&ctx, &prod).unwrap();
+        graph.add_output(&ctx, &b).unwrap();
+        graph.add_output(&ctx, &prod).unwrap();
+        assert_eq!(&*graph.outputs(), &[prod.tensor_id(), b.tensor_id()]);
+        assert_eq!(graph.node_count(), 1);
+        assert_eq!(graph.leaf_count(), 2);
+        assert!(graph.is_output(b.tensor_id()) && !graph.is_output(a.tensor_id()));
+
+        assert_eq!(graph.output_by_name(&ctx, "prod").unwrap().tensor_id(), prod.tensor_id());
+        assert!(graph.output_by_name(&ctx, "missing").is_err());
+        assert!(graph.output(&ctx, a.tensor_id()).is_err());
+        graph.clear();
+        assert!(graph.outputs().is_empty());
+    }
+
+    #[test]
+    fn test_table_lists_nodes_then_leafs() {
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut w = ctx.new_tensor(DataType::F32, &shape![4, 3]).unwrap();
+        let x = ctx.new_tensor(DataType::F32, &shape![4, 2]).unwrap();
+        mark_as_param_leaf(&w);
+        mark_as_param_leaf(&x);
+        w.set_name("w");
+        let h = w.mul_mat(x).unwrap();
+        h.set_name("h");
+        let graph = ComputeGraph::new();
+        graph.add_output(&ctx, &h).unwrap();
+
+        let table = graph.table(&ctx).unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], " #   op      shape  dtype  src    flags  name");
+        assert_eq!(lines[1], " n0  MulMat  3x2    F32    l0 l1  o      h");
+        assert_eq!(lines[2], " l0  None    4x3    F32           p      w");
+        assert_eq!(lines[4], "1 nodes, 2 leafs");
+    }
+
+    #[test]
+    fn test_build_node_collects_errors_until_finish() {
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let w = ctx.new_tensor(DataType::F32, &shape![3, 5]).unwrap();
+        let x = ctx.new_tensor(DataType::F32, &shape![4, 2]).unwrap();
+        let b = ctx.new_tensor(DataType::F32, &shape![3]).unwrap();
+        for tensor in [&w, &x, &b] {
+            mark_as_param_leaf(tensor);
+        }
+        w.set_name("w");
+        x.set_name("x");
+
+        let graph = ComputeGraph::new();
+        let up = graph.build_node("up", &[&w, &x], || w.clone().mul_mat(x.clone())).unwrap();
+        assert_eq!((up.name(), *up.shape()), ("up".into(), shape![3, 5]));
+        let bias = graph.build_node("bias", &[&up, &b], || up.clone().add(b.clone())).unwrap();
+        let ok = graph.build_node("ok", &[&w, &b], || w.clone().add(b.clone())).unwrap();
+        assert_eq!(ok.op_type(), TensorOpType::TensorOpAdd);
+        assert!(graph.build_node("none", &[], || Err(Error::msg("no inputs"))).is_err());
+
+        let errors = graph.finish(&ctx, &[&bias, &ok]).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        let message = errors[0].to_string();
+        assert!(message.contains("mul_mat shapes 3x5 and 4x2"), "{message}");
+        assert!(message.contains("in node up of [w F32 3x5, x F32 4x2]"), "{message}");
+        assert!(graph.outputs().is_empty());
+
+        graph.clear();
+        graph.finish(&ctx, &[&ok]).unwrap();
+        assert_eq!(&*graph.outputs(), &[ok.tensor_id()]);
+    }
 }
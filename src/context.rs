@@ -4,22 +4,24 @@
 //! including memory management through object pools and table-based storage
 //! for tensors and compute graphs.
 
+use crate::collections::HashMap;
 use crate::compute_graph::{ComputeGraph, ComputeGraphInner, GraphId};
-use crate::data_type::{get_block_size, get_type_size, DataType};
+use crate::data_type::{get_block_size, get_type_size, DataType, TensorOpType};
 use crate::defs::MAX_DIMS;
 use crate::error::Result;
 use crate::error::{Error, ErrorKind};
 use crate::object_pool::ObjectPool;
 use crate::shape::Shape;
 use crate::tensor::{Tensor, TensorId, TensorInner};
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::rc::Rc;
+use alloc::format;
+use alloc::rc::Rc;
+use core::cell::RefCell;
 
 #[derive(Debug, Clone)]
 pub struct ContextConfig {
     pub tensor_pool_capacity: usize,
     pub graph_pool_cacacity: usize,
+    pub provenance: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -44,6 +46,14 @@ impl ContextBuilder {
         self
     }
 
+    /// Records on every op node the file and line of the code that built it (see
+    /// [`Tensor::origin`](crate::tensor::Tensor::origin)), for error messages that point at
+    /// the model code rather than a node number. Off by default.
+    pub fn provenance(mut self, enabled: bool) -> Self {
+        self.config.provenance = enabled;
+        self
+    }
+
     pub fn build(self) -> Context {
         Context::with_config(self.config)
     }
@@ -51,7 +61,7 @@ impl ContextBuilder {
 
 impl Default for ContextConfig {
     fn default() -> Self {
-        Self { tensor_pool_capacity: 1024, graph_pool_cacacity: 0 }
+        Self { tensor_pool_capacity: 1024, graph_pool_cacacity: 0, provenance: false }
     }
 }
 
@@ -67,6 +77,8 @@ pub struct ContextInner {
     pub tensor_tables: HashMap<TensorId, Tensor>,
     /// Hash table mapping graph IDs to compute graph objects.
     pub graph_tables: HashMap<GraphId, ComputeGraph>,
+    /// Whether op builders record where they were called, see [`ContextBuilder::provenance`].
+    pub provenance: bool,
 }
 
 /// Public context wrapper providing thread-safe access to the internal context.
@@ -82,7 +94,7 @@ impl AsRef<Context> for Context {
     }
 }
 
-impl std::ops::Deref for Context {
+impl core::ops::Deref for Context {
     type Target = RefCell<ContextInner>;
 
     fn deref(&self) -> &Self::Target {
@@ -107,6 +119,7 @@ impl ContextInner {
             ),
             tensor_tables: HashMap::new(),
             graph_tables: HashMap::new(),
+            provenance: config.provenance,
         })
         .into()
     }
@@ -126,12 +139,28 @@ impl Context {
         }
 
         // Validate data type: check if supported for tensor creation
-        if !matches!(dtype, DataType::F32 | DataType::I32 | DataType::U8) {
+        if !matches!(
+            dtype,
+            DataType::F32
+                | DataType::F16
+                | DataType::I32
+                | DataType::U8
+                | DataType::I8
+                | DataType::Custom(_)
+        ) {
             return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
                 dtype,
                 op: "tensor creation",
             }));
         }
+        if !shape.dim(0).is_multiple_of(get_block_size(dtype)) {
+            return Err(Error::msg(format!(
+                "rows of {dtype:?} hold whole blocks of {} elements, not {}",
+                get_block_size(dtype),
+                shape.dim(0)
+            ))
+            .context("in new_tensor_impl"));
+        }
 
         let mut tensor_inner = self.borrow_mut().tensor_pool.get();
 
@@ -172,12 +201,12 @@ impl Context {
             );
         }
         tensor_inner.layout.stride[1] =
-            tensor_inner.layout.stride[0] * (tensor_inner.layout.stride[0] / block_size);
+            tensor_inner.layout.stride[0] * (tensor_inner.layout.shape.dim(0) / block_size);
 
         // Calculate remaining strides with overflow protection
         for i in 2..MAX_DIMS {
             let next_stride = tensor_inner.layout.stride[i - 1]
-                .checked_mul(tensor_inner.layout.shape[i - 1])
+                .checked_mul(tensor_inner.layout.shape.dim(i - 1))
                 .ok_or_else(|| {
                     Error::msg("stride calculation overflow").context("in stride calculation")
                 })?;
@@ -228,6 +257,40 @@ impl Context {
         Ok(tensor)
     }
 
+    /// Makes `tensor` of another context, e.g. one holding model weights, usable by the graphs
+    /// of this one without copying its data. Returns a leaf of this context that views the
+    /// data of `tensor`, which must already be bound to a buffer. The view keeps that buffer
+    /// alive, so the other context may be dropped first. It is
+    /// [copy-on-write](Tensor::set_copy_on_write), so in-place ops leave the data alone.
+    pub fn share_tensor(&mut self, tensor: &Tensor) -> Result<Tensor> {
+        if self.contain_tensor(tensor.tensor_id()) {
+            return Ok(tensor.clone());
+        }
+        let storage = tensor.borrow().storage.clone().ok_or_else(|| {
+            Error::msg(format!("shared tensor {:?} is not bound to a buffer", tensor.name()))
+                .context("in Context::share_tensor")
+        })?;
+        let source = tensor.borrow().view_tensor.clone().unwrap_or_else(|| tensor.clone());
+
+        let shared = self
+            .new_tensor_impl(tensor.dtype(), &tensor.shape(), None)
+            .map_err(|e| e.context("in Context::share_tensor"))?;
+        {
+            let mut inner = shared.borrow_mut();
+            let src = tensor.borrow();
+            inner.name = src.name.clone();
+            inner.layout.stride = src.layout.stride;
+            inner.tensor_type = src.tensor_type;
+            inner.op_type = TensorOpType::TensorNone;
+            inner.memory_category = Some(tensor.memory_category());
+            inner.view_offset = src.view_offset;
+            inner.view_tensor = Some(source);
+            inner.storage = Some(storage);
+            inner.copy_on_write = true;
+        }
+        Ok(shared)
+    }
+
     /// Creates a new tensor by duplicating the shape and data type of an existing tensor.
     /// This method is a convenience wrapper around `new_tensor` that extracts the necessary
     /// information from the source tensor.
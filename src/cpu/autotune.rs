@@ -0,0 +1,382 @@
+//! Thread-count and GEMM tile-size calibration for the CPU backend.
+//!
+//! More threads are not always faster: on hybrid CPUs the efficiency cores can hold back
+//! every kernel, and small machines pay for the synchronization. [`ThreadProfile::calibrate`]
+//! times a matrix multiplication at several thread counts and keeps the fastest; the result
+//! can be stored on disk so only the first run pays for it, see
+//! [`CpuBackend::autotune_threads`]. [`GemmProfile`] does the same for the tile sizes of the
+//! matrix multiplication kernel, whose best values depend on the cache hierarchy.
+
+use super::backend::CpuBackend;
+use crate::backend::{Backend, BackendBufferUsage};
+use crate::cache::{CacheKind, DiskCache};
+use crate::compute_graph::ComputeGraph;
+use crate::context::Context;
+use crate::data_type::{DataType, TensorOpType, TensorType};
+use crate::error::{Error, Result};
+use crate::shape;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Side of the square matrices multiplied while calibrating.
+const BENCH_SIZE: usize = 128;
+const BENCH_RUNS: usize = 3;
+
+/// Best thread count measured on a machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadProfile {
+    /// Identifies the machine the profile was measured on, see [`machine_id`].
+    pub machine: String,
+    pub n_threads: usize,
+}
+
+impl ThreadProfile {
+    /// Times `backend` at every thread count in `candidates` and picks the fastest. The
+    /// backend's own thread count is restored afterwards.
+    pub fn calibrate(backend: &mut CpuBackend, candidates: &[usize]) -> Result<Self> {
+        if candidates.is_empty() || candidates.contains(&0) {
+            return Err(Error::msg("thread candidates must be non-empty and non-zero")
+                .context("in ThreadProfile::calibrate"));
+        }
+
+        let (ctx, mut graph) = bench_graph(backend, BENCH_SIZE)?;
+        let original = backend.n_threads();
+        let mut best = (Duration::MAX, candidates[0]);
+        for &n_threads in candidates {
+            backend.set_n_threads(n_threads)?;
+            let elapsed = time_graph(backend, &ctx, &mut graph)?;
+            if elapsed < best.0 {
+                best = (elapsed, n_threads);
+            }
+        }
+        backend.set_n_threads(original)?;
+
+        Ok(Self { machine: machine_id(), n_threads: best.1 })
+    }
+
+    /// Reads a profile written by [`ThreadProfile::save`]. Returns `None` if the file does
+    /// not exist or was measured on another machine.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let Some(entries) = read_profile(path).map_err(|e| e.context("in ThreadProfile::load"))?
+        else {
+            return Ok(None);
+        };
+
+        let n_threads = parse_entry(&entries, "n_threads", path)?;
+        Ok(Some(Self { machine: machine_id(), n_threads }))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_profile(path, &self.machine, &[("n_threads", self.n_threads)])
+            .map_err(|e| e.context("in ThreadProfile::save"))
+    }
+}
+
+/// Tile sizes of the CPU matrix multiplication kernel, in elements. A `kc x mc` tile of the
+/// first source is reused by blocks of `nc` output rows, which are also the unit of work
+/// handed to the threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GemmBlocking {
+    pub mc: usize,
+    pub kc: usize,
+    pub nc: usize,
+}
+
+impl Default for GemmBlocking {
+    fn default() -> Self {
+        Self { mc: 64, kc: 256, nc: 32 }
+    }
+}
+
+impl GemmBlocking {
+    pub fn validate(&self) -> Result<()> {
+        if self.mc == 0 || self.kc == 0 || self.nc == 0 {
+            return Err(Error::msg(format!("gemm tile sizes must be non-zero: {self:?}")));
+        }
+        Ok(())
+    }
+
+    /// Tile sizes swept by [`CpuBackend::autotune_gemm`].
+    pub fn candidates() -> Vec<Self> {
+        let mut candidates = Vec::new();
+        for mc in [32, 64, 128] {
+            for kc in [128, 256, 512] {
+                for nc in [8, 32, 128] {
+                    candidates.push(Self { mc, kc, nc });
+                }
+            }
+        }
+        candidates
+    }
+}
+
+/// Best GEMM tile sizes measured on a machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GemmProfile {
+    /// Identifies the machine the profile was measured on, see [`machine_id`].
+    pub machine: String,
+    pub blocking: GemmBlocking,
+}
+
+impl GemmProfile {
+    /// Times the matrix multiplication kernel of `backend` with every tile size in
+    /// `candidates`, at the backend's current thread count, and picks the fastest. The
+    /// backend's own tile sizes are restored afterwards.
+    pub fn calibrate(backend: &mut CpuBackend, candidates: &[GemmBlocking]) -> Result<Self> {
+        if candidates.is_empty() {
+            return Err(Error::msg("gemm candidates must be non-empty")
+                .context("in GemmProfile::calibrate"));
+        }
+
+        let size = candidates.iter().map(|blocking| blocking.kc).max().unwrap_or(0);
+        let (ctx, mut graph) = bench_graph(backend, size.max(BENCH_SIZE))?;
+        let original = backend.gemm_blocking();
+        let mut best = (Duration::MAX, candidates[0]);
+        for &blocking in candidates {
+            backend.set_gemm_blocking(blocking)?;
+            let elapsed = time_graph(backend, &ctx, &mut graph)?;
+            if elapsed < best.0 {
+                best = (elapsed, blocking);
+            }
+        }
+        backend.set_gemm_blocking(original)?;
+
+        Ok(Self { machine: machine_id(), blocking: best.1 })
+    }
+
+    /// Reads a profile written by [`GemmProfile::save`]. Returns `None` if the file does
+    /// not exist or was measured on another machine.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let Some(entries) = read_profile(path).map_err(|e| e.context("in GemmProfile::load"))?
+        else {
+            return Ok(None);
+        };
+
+        let blocking = GemmBlocking {
+            mc: parse_entry(&entries, "mc", path)?,
+            kc: parse_entry(&entries, "kc", path)?,
+            nc: parse_entry(&entries, "nc", path)?,
+        };
+        Ok(Some(Self { machine: machine_id(), blocking }))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let GemmBlocking { mc, kc, nc } = self.blocking;
+        write_profile(path, &self.machine, &[("mc", mc), ("kc", kc), ("nc", nc)])
+            .map_err(|e| e.context("in GemmProfile::save"))
+    }
+}
+
+/// Architecture and number of hardware threads of this machine.
+pub fn machine_id() -> String {
+    let n_cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+    format!("{}-{}-{}", std::env::consts::OS, std::env::consts::ARCH, n_cpus)
+}
+
+/// Thread counts worth trying here: the powers of two below the hardware threads and the
+/// hardware threads themselves.
+pub fn default_candidates() -> Vec<usize> {
+    let n_cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut candidates: Vec<usize> =
+        std::iter::successors(Some(1), |n| Some(n * 2)).take_while(|&n| n < n_cpus).collect();
+    candidates.push(n_cpus);
+    candidates
+}
+
+/// Reads the `key=value` lines of a profile. `None` if the file does not exist or its
+/// `machine` is not this one.
+fn read_profile(path: &Path) -> Result<Option<HashMap<String, String>>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let entries: HashMap<String, String> = text
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    match entries.get("machine") {
+        Some(machine) if *machine == machine_id() => Ok(Some(entries)),
+        Some(_) => Ok(None),
+        None => Err(Error::msg(format!("profile {} has no machine", path.display()))),
+    }
+}
+
+fn parse_entry(entries: &HashMap<String, String>, key: &str, path: &Path) -> Result<usize> {
+    entries
+        .get(key)
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|&value| value > 0)
+        .ok_or_else(|| Error::msg(format!("profile {} has no valid {key}", path.display())))
+}
+
+fn write_profile(path: &Path, machine: &str, entries: &[(&str, usize)]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let mut text = format!("machine={machine}\n");
+    for (key, value) in entries {
+        text.push_str(&format!("{key}={value}\n"));
+    }
+    Ok(std::fs::write(path, text)?)
+}
+
+/// Multiplies two `size x size` matrices.
+fn bench_graph(backend: &CpuBackend, size: usize) -> Result<(Context, ComputeGraph)> {
+    let mut ctx = Context::builder().tensor_pool_capacity(4).build();
+    let mut lhs = ctx.new_tensor(DataType::F32, &shape![size, size])?;
+    let rhs = ctx.new_tensor(DataType::F32, &shape![size, size])?;
+    for tensor in [&lhs, &rhs] {
+        tensor.set_tensor_type(TensorType::FlagParam);
+        tensor.set_op_type(TensorOpType::TensorNone);
+    }
+    let product = lhs.mul_mat(rhs.clone())?;
+
+    let nbytes = lhs.nbytes();
+    let buffer = backend.create_buffer(3 * nbytes, BackendBufferUsage::Compute)?;
+    for (i, tensor) in [&lhs, &rhs, &product].into_iter().enumerate() {
+        buffer.init_tensor(tensor.clone(), i * nbytes)?;
+    }
+    for tensor in [lhs, rhs] {
+        buffer.fill(tensor, 0x3c, 0, nbytes)?;
+    }
+
+    let graph = ComputeGraph::new();
+    graph.build_forward(&ctx, product.tensor_id(), false)?;
+    Ok((ctx, graph))
+}
+
+/// Fastest of [`BENCH_RUNS`] runs of `graph`, after one warm-up run.
+fn time_graph(backend: &CpuBackend, ctx: &Context, graph: &mut ComputeGraph) -> Result<Duration> {
+    backend.graph_compute(ctx, graph)?;
+
+    let mut fastest = Duration::MAX;
+    for _ in 0..BENCH_RUNS {
+        let start = Instant::now();
+        backend.graph_compute(ctx, graph)?;
+        fastest = fastest.min(start.elapsed());
+    }
+    Ok(fastest)
+}
+
+impl CpuBackend {
+    /// Sets the thread count from the profile at `profile`, calibrating and writing it
+    /// first if it is missing or was measured on another machine. Without a path the
+    /// backend is calibrated every time. Returns the thread count in use.
+    pub fn autotune_threads(&mut self, profile: Option<&Path>) -> Result<usize> {
+        let cached = match profile {
+            Some(path) => ThreadProfile::load(path)?,
+            None => None,
+        };
+        let tuned = match cached {
+            Some(tuned) => tuned,
+            None => {
+                let tuned = ThreadProfile::calibrate(self, &default_candidates())?;
+                if let Some(path) = profile {
+                    tuned.save(path)?;
+                }
+                tuned
+            }
+        };
+
+        self.set_n_threads(tuned.n_threads)?;
+        Ok(tuned.n_threads)
+    }
+
+    /// Like [`CpuBackend::autotune_threads`] for the GEMM tile sizes, sweeping
+    /// [`GemmBlocking::candidates`]. The best tiles depend on the thread count, so tune that
+    /// first.
+    pub fn autotune_gemm(&mut self, profile: Option<&Path>) -> Result<GemmBlocking> {
+        let cached = match profile {
+            Some(path) => GemmProfile::load(path)?,
+            None => None,
+        };
+        let tuned = match cached {
+            Some(tuned) => tuned,
+            None => {
+                let tuned = GemmProfile::calibrate(self, &GemmBlocking::candidates())?;
+                if let Some(path) = profile {
+                    tuned.save(path)?;
+                }
+                tuned
+            }
+        };
+
+        self.set_gemm_blocking(tuned.blocking)?;
+        Ok(tuned.blocking)
+    }
+
+    /// Tunes the thread count and then the GEMM tile sizes, keeping both profiles in
+    /// `cache` if given.
+    pub fn autotune(&mut self, cache: Option<&DiskCache>) -> Result<(usize, GemmBlocking)> {
+        let device = self.name().to_string();
+        let profile = |name| cache.map(|cache| cache.path(CacheKind::Profile, &device, name));
+        let n_threads = self.autotune_threads(profile("threads").as_deref())?;
+        let blocking = self.autotune_gemm(profile("gemm").as_deref())?;
+        Ok((n_threads, blocking))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("feml-autotune-{name}-{}", std::process::id()))
+    }
+
+    #[test]
+    fn test_calibrate_picks_a_candidate() {
+        let mut backend = CpuBackend::init().unwrap();
+        let profile = ThreadProfile::calibrate(&mut backend, &[1, 2]).unwrap();
+        assert!([1, 2].contains(&profile.n_threads));
+        assert_eq!(profile.machine, machine_id());
+        assert_eq!(backend.n_threads(), 1);
+        assert!(ThreadProfile::calibrate(&mut backend, &[]).is_err());
+    }
+
+    #[test]
+    fn test_profile_round_trip() {
+        let dir = temp_dir("threads");
+        let path = dir.join("cpu-threads");
+        assert_eq!(ThreadProfile::load(&path).unwrap(), None);
+
+        let profile = ThreadProfile { machine: machine_id(), n_threads: 3 };
+        profile.save(&path).unwrap();
+        assert_eq!(ThreadProfile::load(&path).unwrap(), Some(profile));
+
+        let mut backend = CpuBackend::init().unwrap();
+        assert_eq!(backend.autotune_threads(Some(&path)).unwrap(), 3);
+
+        ThreadProfile { machine: "elsewhere".into(), n_threads: 3 }.save(&path).unwrap();
+        assert_eq!(ThreadProfile::load(&path).unwrap(), None);
+
+        std::fs::write(&path, "n_threads=2\n").unwrap();
+        assert!(ThreadProfile::load(&path).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_gemm_calibrate_and_round_trip() {
+        let mut backend = CpuBackend::init().unwrap();
+        backend.set_n_threads(2).unwrap();
+        let candidates =
+            [GemmBlocking { mc: 16, kc: 32, nc: 4 }, GemmBlocking { mc: 128, kc: 64, nc: 64 }];
+        let profile = GemmProfile::calibrate(&mut backend, &candidates).unwrap();
+        assert!(candidates.contains(&profile.blocking));
+        assert_eq!(backend.gemm_blocking(), GemmBlocking::default());
+        assert!(backend.set_gemm_blocking(GemmBlocking { mc: 0, kc: 1, nc: 1 }).is_err());
+
+        let dir = temp_dir("gemm");
+        let path = dir.join("cpu-gemm");
+        profile.save(&path).unwrap();
+        assert_eq!(GemmProfile::load(&path).unwrap(), Some(profile.clone()));
+        assert_eq!(backend.autotune_gemm(Some(&path)).unwrap(), profile.blocking);
+        assert_eq!(backend.gemm_blocking(), profile.blocking);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
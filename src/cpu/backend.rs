@@ -1,19 +1,48 @@
+use super::autotune::GemmBlocking;
 use super::backend_buffers::CpuBackendBuffer;
 use super::backend_context::CpuBackendContext;
 use super::backend_device::CpuBackendDevice;
 use super::backend_register::CpuBackendRegister;
-use crate::backend::{Backend, BackendBuffer, BackendBufferUsage};
+use super::backend_stream::{CpuBackendEvent, CpuBackendStream};
+use super::compute_plan::ComputePlan;
+use super::kernels::{self, Isa};
+use super::ops::common::RowPartition;
+use super::ops::f16::native_dot_f16;
+use super::ops::gemm_q8::dot_q8_kernel;
+use super::ops::mul_mat::PANEL_ROWS;
+use crate::backend::{
+    numa_strategy, AbortCallback, Backend, BackendBuffer, BackendBufferUsage, BackendEvent,
+    BackendFeature, BackendStream, GraphObserver, NumaStrategy,
+};
+use crate::cache::{CacheKind, DiskCache};
 use crate::compute_graph::ComputeGraph;
 use crate::context::Context;
-use crate::data_type::{DataType, TensorOpType};
-use crate::error::{Error, ErrorKind, Result};
-use crate::tensor::Tensor;
+use crate::data_type::{custom_data_type, DataType, TensorOpType};
+use crate::error::{Error, Result};
+use crate::tensor::{Tensor, TensorId};
+use crate::threadpool::ThreadPool;
 use std::any::Any;
+use std::sync::Arc;
+
+/// Smallest size per pool thread of the buffers [`CpuBackend::set_first_touch`] touches.
+pub const FIRST_TOUCH_BYTES_PER_THREAD: usize = 1 << 20;
+
+/// How the kernels compute on F16 operands.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Precision {
+    /// Widen F16 values to F32 and compute in F32.
+    #[default]
+    F32,
+    /// Compute in F16 on CPUs with F16 arithmetic (AVX-512 FP16, ARM FP16), see
+    /// [`CpuBackend::native_f16`], and as with `F32` elsewhere. Much faster where it applies,
+    /// but every product and sum is rounded to F16. Covers matrix products of two F16
+    /// sources.
+    NativeF16,
+}
 
 pub struct CpuBackend {
     #[allow(dead_code)]
     device: CpuBackendDevice,
-    #[allow(dead_code)]
     context: CpuBackendContext,
 }
 
@@ -26,15 +55,98 @@ impl Backend for CpuBackend {
         Ok(())
     }
 
+    /// Host allocations cannot exceed `isize::MAX` bytes.
+    fn max_buffer_size(&self) -> usize {
+        isize::MAX as usize
+    }
+
     fn graph_compute(&self, ctx: &Context, graph: &mut ComputeGraph) -> Result<()> {
-        for node in graph.nodes().iter() {
-            let tensor = ctx.get_tensor(*node)?;
-            self.compute_forward(ctx, &tensor)?;
+        #[cfg(feature = "tracing")]
+        let _span = crate::backend::graph_span(self.name(), graph).entered();
+        let nodes = graph.nodes().to_vec();
+        self.run_nodes(
+            graph,
+            &nodes,
+            |id| ctx.get_tensor(*id),
+            |_, tensor| self.compute_forward(ctx, tensor),
+        )
+    }
+
+    fn compute_node(&self, ctx: &Context, node: TensorId) -> Result<bool> {
+        let tensor = ctx.get_tensor(node)?;
+        #[cfg(feature = "tracing")]
+        let _node_span = crate::backend::node_span(&tensor).entered();
+        self.compute_forward(ctx, &tensor)?;
+        Ok(true)
+    }
+
+    fn new_stream(&self) -> Result<Box<dyn BackendStream + '_>> {
+        Ok(Box::new(CpuBackendStream::new(self)))
+    }
+
+    fn new_event(&self) -> Result<Box<dyn BackendEvent>> {
+        Ok(Box::new(CpuBackendEvent))
+    }
+
+    fn supported_dtypes(&self, op: TensorOpType) -> &'static [DataType] {
+        crate::ops::supported_dtypes(op)
+    }
+
+    /// Registered data types are supported as the weights, the first operand, of `mul_mat`.
+    fn supports(&self, op: TensorOpType, dtypes: &[DataType]) -> bool {
+        let supported = self.supported_dtypes(op);
+        let custom_weights = |i: usize, dtype: DataType| {
+            i == 0 && op == TensorOpType::TensorOpMulMat && custom_data_type(dtype).is_some()
+        };
+        let mut dtypes = dtypes.iter().enumerate();
+        !supported.is_empty()
+            && dtypes.all(|(i, &dtype)| supported.contains(&dtype) || custom_weights(i, dtype))
+    }
+
+    /// The CPU features of the registry, plus the kernels and settings in use.
+    fn features(&self) -> Vec<BackendFeature> {
+        let mut features = super::backend_register::features();
+        let mut push = |name, value: String| features.push(BackendFeature { name, value });
+        push("threads", self.n_threads().to_string());
+        push("reference_kernels", u8::from(self.reference_kernels()).to_string());
+        push("isa", Isa::detect().name().to_string());
+        let blocking = self.gemm_blocking();
+        push("gemm_blocking", format!("{}x{}x{}", blocking.mc, blocking.kc, blocking.nc));
+        push("dot_q8", dot_q8_kernel().0.to_string());
+        let precision = match self.precision() {
+            Precision::F32 => "f32",
+            Precision::NativeF16 => "native_f16",
+        };
+        push("precision", precision.to_string());
+        push("native_f16", self.native_f16().unwrap_or("none").to_string());
+        push("first_touch", u8::from(self.first_touch()).to_string());
+        features
+    }
+
+    fn blk_size_interleave(&self, op: TensorOpType, dtype: DataType) -> Option<usize> {
+        match (op, dtype) {
+            (TensorOpType::TensorOpMulMat, DataType::F32 | DataType::F16) => Some(PANEL_ROWS),
+            _ => None,
         }
+    }
 
+    fn set_observer(&mut self, observer: Option<Box<dyn GraphObserver>>) -> Result<()> {
+        self.context.set_observer(observer);
         Ok(())
     }
 
+    unsafe fn register_host_memory(&self, ptr: *mut u8, len: usize) -> Result<()> {
+        self.context.host_ranges().register(ptr, len)
+    }
+
+    fn unregister_host_memory(&self, ptr: *mut u8) -> Result<()> {
+        self.context.host_ranges().unregister(ptr)
+    }
+
+    fn is_host_registered(&self, ptr: *const u8, len: usize) -> bool {
+        self.context.host_ranges().contains(ptr, len)
+    }
+
     fn write_async(
         &self,
         tensor: Tensor,
@@ -42,12 +154,12 @@ impl Backend for CpuBackend {
         offset: usize,
         size: usize,
     ) -> Result<()> {
-        // let buffer = {
-        //     let storage = tensor.storage()?;
-        //     storage.as_cpu().ok_or_else(|| Error::msg("tensor storage is not CPU"))?.clone()
-        // };
-        // buffer.write(tensor, data, offset, size)
-        todo!()
+        // CPU memory is written in place, so there is nothing to wait for.
+        let buffer = {
+            let storage = tensor.storage()?;
+            storage.as_cpu().ok_or_else(|| Error::msg("tensor storage is not CPU"))?.clone()
+        };
+        buffer.write(tensor, data, offset, size)
     }
 
     fn read_async(
@@ -57,21 +169,19 @@ impl Backend for CpuBackend {
         offset: usize,
         size: usize,
     ) -> Result<()> {
-        // let buffer = {
-        //     let storage = tensor.storage()?;
-        //     storage.as_cpu().ok_or_else(|| Error::msg("tensor storage is not CPU"))?.clone()
-        // };
-        // buffer.read(tensor, data, offset, size)
-        todo!()
+        let buffer = {
+            let storage = tensor.storage()?;
+            storage.as_cpu().ok_or_else(|| Error::msg("tensor storage is not CPU"))?.clone()
+        };
+        buffer.read(tensor, data, offset, size)
     }
 
     fn copy_async(&self, src: Tensor, dst: Tensor) -> Result<()> {
-        // let buffer = {
-        //     let storage = src.storage()?;
-        //     storage.as_cpu().ok_or_else(|| Error::msg("source tensor storage is not CPU"))?.clone()
-        // };
-        // buffer.copy(src, dst)
-        todo!()
+        let buffer = {
+            let storage = src.storage()?;
+            storage.as_cpu().ok_or_else(|| Error::msg("source tensor storage is not CPU"))?.clone()
+        };
+        buffer.copy(src, dst)
     }
 
     fn create_buffer(
@@ -79,7 +189,15 @@ impl Backend for CpuBackend {
         size: usize,
         usage: BackendBufferUsage,
     ) -> Result<Box<dyn BackendBuffer>> {
-        Ok(Box::new(CpuBackendBuffer::new(size, usage)))
+        let buffer = CpuBackendBuffer::new(size, usage)?;
+        let pool = self.threadpool();
+        if self.first_touch()
+            && usage != BackendBufferUsage::Weights
+            && size >= pool.n_threads() * FIRST_TOUCH_BYTES_PER_THREAD
+        {
+            buffer.first_touch(pool).map_err(|e| e.context("in CpuBackend::create_buffer"))?;
+        }
+        Ok(Box::new(buffer))
     }
 
     fn as_any(&self) -> &dyn Any {
@@ -92,8 +210,15 @@ impl Backend for CpuBackend {
 }
 
 impl CpuBackend {
+    /// Environment variable that makes new backends start with reference kernels when set
+    /// to anything but `0`, see [`CpuBackend::set_reference_kernels`].
+    pub const REFERENCE_KERNELS_ENV_VAR: &'static str = "FEML_CPU_REFERENCE_KERNELS";
+
     pub fn new(device: CpuBackendDevice) -> Self {
-        let context = CpuBackendContext::new();
+        let mut context = CpuBackendContext::new();
+        let reference = std::env::var_os(Self::REFERENCE_KERNELS_ENV_VAR);
+        context.set_reference(reference.is_some_and(|value| !value.is_empty() && value != "0"));
+        context.set_first_touch(numa_strategy() == NumaStrategy::Distribute);
         Self { device, context }
     }
 
@@ -103,161 +228,266 @@ impl CpuBackend {
         Ok(Self::new(device))
     }
 
-    fn compute_forward(&self, ctx: &Context, tensor: &Tensor) -> Result<()> {
-        let src_tensor = tensor.src_tensor();
-        match tensor.op_type() {
-            TensorOpType::TensorOpMul => {
-                if src_tensor.len() < 2 {
-                    return Err(Error::msg("mul tensor requires two source tensors")
-                        .context("in CpuBackend::compute_forward"));
-                }
-
-                let src0 = ctx.get_tensor(src_tensor[0])?;
-                let src1 = ctx.get_tensor(src_tensor[1])?;
-                self.mul(&src0, &src1, tensor)
-            }
-            _ => {
-                let op_name = format!("{:?}", tensor.op_type());
-                Err(Error::new(ErrorKind::UnsupportedBackendOp {
-                    backend: "cpu",
-                    op: "compute_forward",
-                })
-                .context(format!("unsupported op type: {}", op_name))
-                .context("in CpuBackend::compute_forward"))
+    /// Creates a buffer backed by a private mapping of `path`. Pages are read on first use
+    /// and can be steered with [`Tensor::advise`].
+    #[cfg(feature = "mmap")]
+    pub fn buffer_from_file(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        usage: BackendBufferUsage,
+    ) -> Result<Box<dyn BackendBuffer>> {
+        let buffer = CpuBackendBuffer::from_file(path.as_ref(), usage)
+            .map_err(|e| e.context("in CpuBackend::buffer_from_file"))?;
+        Ok(Box::new(buffer))
+    }
+
+    /// Prepares `graph` for repeated execution with [`CpuBackend::plan_compute`].
+    pub fn plan_create(&self, ctx: &Context, graph: &ComputeGraph) -> Result<ComputePlan> {
+        ComputePlan::new(ctx, graph, self.max_isa(), self.n_threads())
+    }
+
+    /// Like [`CpuBackend::plan_create`], but reuses the kernels stored in `cache` for a
+    /// graph with the same fingerprint and instruction sets, and stores them on a miss.
+    pub fn plan_create_cached(
+        &self,
+        ctx: &Context,
+        graph: &ComputeGraph,
+        cache: &DiskCache,
+    ) -> Result<ComputePlan> {
+        let key = format!("{:016x}-{}", graph.fingerprint(ctx)?, self.max_isa().name());
+        if let Some(data) = cache.load(CacheKind::Plan, self.name(), &key)? {
+            // A stale or truncated entry is rebuilt below.
+            let plan = ComputePlan::decode(ctx, graph, self.max_isa(), self.n_threads(), &data);
+            if let Ok(plan) = plan {
+                return Ok(plan);
             }
         }
+
+        let plan = self.plan_create(ctx, graph)?;
+        cache.store(CacheKind::Plan, self.name(), &key, &plan.encode())?;
+        Ok(plan)
     }
 
-    fn mul(&self, src0: &Tensor, src1: &Tensor, dst: &Tensor) -> Result<()> {
-        if src0.dtype() != DataType::F32
-            || src1.dtype() != DataType::F32
-            || dst.dtype() != DataType::F32
-        {
-            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
-                dtype: dst.dtype(),
-                op: "cpu mul",
-            }));
-        }
+    /// Points `plan` at `graph`, a rebuild of the planned graph with the same topology.
+    pub fn plan_update(&self, plan: &mut ComputePlan, graph: &ComputeGraph) -> Result<()> {
+        plan.update(graph)
+    }
+
+    /// Runs the kernels of `plan` in its work buffer, with the same abort checks and
+    /// observer hooks as [`Backend::graph_compute`].
+    pub fn plan_compute(&self, plan: &ComputePlan) -> Result<()> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("plan_compute", backend = self.name()).entered();
+        let mut work = plan.work().try_borrow_mut().map_err(|_| {
+            Error::msg("plan is already computing").context("in CpuBackend::plan_compute")
+        })?;
+        std::mem::swap(&mut *self.context.work(), &mut *work);
+        let result = self.run_nodes(
+            plan.graph(),
+            plan.nodes(),
+            |node| Ok(node.tensor.clone()),
+            |node, tensor| {
+                self.context.set_partition(Some(node.partition));
+                let result = (node.kernel.kernel)(self, &node.srcs, tensor);
+                self.context.set_partition(None);
+                result.map_err(|e| e.context(format!("in {}", tensor.describe())))
+            },
+        );
+        std::mem::swap(&mut *self.context.work(), &mut *work);
+        result
+    }
 
-        let src0_data = self.read_tensor_bytes(src0)?;
-        let src1_data = self.read_tensor_bytes(src1)?;
-        let mut dst_data = vec![0; dst.nbytes()];
+    /// Number of threads the kernels are spread over.
+    pub fn n_threads(&self) -> usize {
+        self.context.n_threads()
+    }
 
-        let src1_shape = *src1.shape();
-        let dst_shape = *dst.shape();
+    /// Sets the size of the backend's own worker pool. Ignored while a shared pool is set
+    /// with [`CpuBackend::set_threadpool`].
+    pub fn set_n_threads(&mut self, n_threads: usize) -> Result<()> {
+        self.context.set_n_threads(n_threads).map_err(|e| e.context("in CpuBackend::set_n_threads"))
+    }
 
-        let src0_stride = {
-            let stride = src0.stride();
-            [stride[0], stride[1], stride[2], stride[3]]
-        };
-        let src1_stride = {
-            let stride = src1.stride();
-            [stride[0], stride[1], stride[2], stride[3]]
-        };
-        let dst_stride = {
-            let stride = dst.stride();
-            [stride[0], stride[1], stride[2], stride[3]]
-        };
+    pub fn set_abort_callback(&mut self, abort_fn: Option<AbortCallback>) {
+        self.context.set_abort_fn(abort_fn);
+    }
 
-        let ne0 = dim(&dst_shape, 0);
-        let ne1 = dim(&dst_shape, 1);
-        let ne2 = dim(&dst_shape, 2);
-        let ne3 = dim(&dst_shape, 3);
-
-        let ne10 = dim(&src1_shape, 0);
-        let ne11 = dim(&src1_shape, 1);
-        let ne12 = dim(&src1_shape, 2);
-        let ne13 = dim(&src1_shape, 3);
-
-        for i3 in 0..ne3 {
-            let i13 = i3 % ne13;
-            for i2 in 0..ne2 {
-                let i12 = i2 % ne12;
-                for i1 in 0..ne1 {
-                    let i11 = i1 % ne11;
-                    for i0 in 0..ne0 {
-                        let i10 = i0 % ne10;
-                        let src0_offset = byte_offset(&src0_stride, i0, i1, i2, i3)?;
-                        let src1_offset = byte_offset(&src1_stride, i10, i11, i12, i13)?;
-                        let dst_offset = byte_offset(&dst_stride, i0, i1, i2, i3)?;
-
-                        let value = read_f32(&src0_data, src0_offset, "src0")?
-                            * read_f32(&src1_data, src1_offset, "src1")?;
-                        write_f32(&mut dst_data, dst_offset, value, "dst")?;
-                    }
-                }
-            }
+    /// Runs the kernels on `threadpool`, which other backends may share, instead of the
+    /// backend's own pool. `None` goes back to the own pool.
+    pub fn set_threadpool(&mut self, threadpool: Option<Arc<ThreadPool>>) {
+        self.context.set_threadpool(threadpool);
+    }
+
+    /// Tile sizes of the matrix multiplication kernel.
+    pub fn gemm_blocking(&self) -> GemmBlocking {
+        self.context.gemm()
+    }
+
+    pub fn set_gemm_blocking(&mut self, blocking: GemmBlocking) -> Result<()> {
+        blocking.validate().map_err(|e| e.context("in CpuBackend::set_gemm_blocking"))?;
+        self.context.set_gemm(blocking);
+        Ok(())
+    }
+
+    /// Whether the kernels run their reference implementations.
+    pub fn reference_kernels(&self) -> bool {
+        self.context.reference()
+    }
+
+    /// Forces the straightforward reference implementation of every kernel, run on the
+    /// calling thread: matrix multiplication skips its cache blocking and no op is split
+    /// over threads. Results of the fast paths can then be compared against it on real
+    /// inputs.
+    pub fn set_reference_kernels(&mut self, enabled: bool) {
+        self.context.set_reference(enabled);
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.context.precision()
+    }
+
+    /// Selects how F16 operands are computed, see [`Precision`]. Reference mode always
+    /// computes in F32.
+    pub fn set_precision(&mut self, precision: Precision) {
+        self.context.set_precision(precision);
+    }
+
+    /// Whether new compute buffers are first touched by the worker threads.
+    pub fn first_touch(&self) -> bool {
+        self.context.first_touch()
+    }
+
+    /// Makes [`Backend::create_buffer`] touch the pages of new buffers, other than weight
+    /// buffers, from the threads of the backend's pool, split the way the kernels split
+    /// their rows. On NUMA systems that place pages on first touch, the pages of a
+    /// compute buffer then sit near the threads that mostly work on them. Buffers with
+    /// less than [`FIRST_TOUCH_BYTES_PER_THREAD`] bytes per thread are not worth waking
+    /// the workers for and are skipped. On by default under the NUMA strategy
+    /// [`NumaStrategy::Distribute`], see [`numa_init`](crate::backend::numa_init).
+    pub fn set_first_touch(&mut self, enabled: bool) {
+        self.context.set_first_touch(enabled);
+    }
+
+    /// The F16 arithmetic [`Precision::NativeF16`] uses on this CPU, e.g. `avx512fp16`,
+    /// if it has any.
+    pub fn native_f16(&self) -> Option<&'static str> {
+        native_dot_f16().map(|(name, _)| name)
+    }
+
+    pub(crate) fn threadpool(&self) -> &ThreadPool {
+        self.context.threadpool()
+    }
+
+    /// Runs `f` on `len` zeroed F32s of scratch. Under [`CpuBackend::plan_compute`] that is
+    /// the plan's work buffer, already sized by
+    /// [`work_size_for`](super::compute_plan::work_size_for); graph computes grow the
+    /// backend's own buffer as needed.
+    pub(crate) fn with_work<R>(&self, len: usize, f: impl FnOnce(&mut [f32]) -> R) -> R {
+        let mut work = self.context.work();
+        if work.len() < len {
+            work.resize(len, 0.0);
         }
+        let work = &mut work[..len];
+        work.fill(0.0);
+        f(work)
+    }
 
-        self.write_tensor_bytes(dst, &mut dst_data)
+    /// Split of `n_rows` rows of `row_bytes` bytes over the kernel threads: the partition
+    /// the plan stored for the node being computed if it splits these rows, else a new one.
+    pub(crate) fn row_partition(&self, n_rows: usize, row_bytes: usize) -> RowPartition {
+        let planned = self.context.partition().filter(|p| p.splits(n_rows, row_bytes));
+        planned.unwrap_or_else(|| RowPartition::new(n_rows, row_bytes, self.n_threads()))
     }
 
-    fn read_tensor_bytes(&self, tensor: &Tensor) -> Result<Vec<u8>> {
-        let mut data = vec![0; tensor.nbytes()];
-        let buffer = {
-            let storage = tensor.storage()?;
-            storage
-                .as_cpu()
-                .ok_or_else(|| Error::msg("tensor storage is not CPU"))?
-                .clone()
-        };
-        let size = data.len();
-        buffer.read(tensor.clone(), &mut data, 0, size)?;
-        Ok(data)
+    fn check_abort(&self) -> Result<()> {
+        if self.context.aborted() {
+            return Err(Error::msg("graph compute aborted").context("in CpuBackend"));
+        }
+        Ok(())
     }
 
-    fn write_tensor_bytes(&self, tensor: &Tensor, data: &mut [u8]) -> Result<()> {
-        let buffer = {
-            let storage = tensor.storage()?;
-            storage
-                .as_cpu()
-                .ok_or_else(|| Error::msg("tensor storage is not CPU"))?
-                .clone()
-        };
-        buffer.write(tensor.clone(), data, 0, data.len())
+    /// Highest instruction set the kernels may use: the baseline in reference mode.
+    fn max_isa(&self) -> Isa {
+        if self.reference_kernels() {
+            Isa::Scalar
+        } else {
+            Isa::Avx512
+        }
     }
-}
 
-fn dim(shape: &crate::shape::Shape, index: usize) -> usize {
-    if index < shape.rank {
-        shape.dims[index]
-    } else {
-        1
+    fn compute_forward(&self, ctx: &Context, tensor: &Tensor) -> Result<()> {
+        let srcs = tensor.src_tensor().into_iter().map(|id| ctx.get_tensor(id));
+        let srcs = srcs.collect::<Result<Vec<_>>>()?;
+        kernels::compute(self, &srcs, tensor, self.max_isa())
+            .map_err(|e| e.context(format!("in {}", tensor.describe())))
+    }
+
+    /// Computes `nodes` of `graph` in order: `tensor` gives the tensor of a node and
+    /// `compute` runs its kernel. Checks for aborts before each node and calls the observer
+    /// hooks around the nodes and the graph.
+    fn run_nodes<N>(
+        &self,
+        graph: &ComputeGraph,
+        nodes: &[N],
+        tensor: impl Fn(&N) -> Result<Tensor>,
+        compute: impl Fn(&N, &Tensor) -> Result<()>,
+    ) -> Result<()> {
+        let mut observer = self.context.observer();
+        if let Some(observer) = observer.as_mut() {
+            observer.on_graph_begin(graph);
+        }
+        for node in nodes {
+            self.check_abort()?;
+            let tensor = tensor(node)?;
+            #[cfg(feature = "tracing")]
+            let _node_span = crate::backend::node_span(&tensor).entered();
+            let Some(observer) = observer.as_mut() else {
+                compute(node, &tensor)?;
+                continue;
+            };
+            observer.on_node_begin(&tensor);
+            compute(node, &tensor)?;
+            if !observer.on_node_end(&tensor) {
+                return Err(
+                    Error::msg("graph compute stopped by observer").context("in CpuBackend")
+                );
+            }
+        }
+        if let Some(observer) = observer.as_mut() {
+            observer.on_graph_end(graph);
+        }
+
+        Ok(())
     }
 }
 
-fn byte_offset(stride: &[usize; 4], i0: usize, i1: usize, i2: usize, i3: usize) -> Result<usize> {
-    i0.checked_mul(stride[0])
-        .and_then(|offset| i1.checked_mul(stride[1]).and_then(|delta| offset.checked_add(delta)))
-        .and_then(|offset| i2.checked_mul(stride[2]).and_then(|delta| offset.checked_add(delta)))
-        .and_then(|offset| i3.checked_mul(stride[3]).and_then(|delta| offset.checked_add(delta)))
-        .ok_or_else(|| Error::msg("tensor byte offset overflow"))
+fn cpu_backend(backend: &mut dyn Backend) -> Result<&mut CpuBackend> {
+    backend
+        .as_any_mut()
+        .downcast_mut::<CpuBackend>()
+        .ok_or_else(|| Error::msg("backend is not a CPU backend"))
 }
 
-fn read_f32(data: &[u8], offset: usize, name: &'static str) -> Result<f32> {
-    let end = offset.checked_add(4).ok_or_else(|| Error::msg("f32 offset overflow"))?;
-    let bytes = data
-        .get(offset..end)
-        .ok_or_else(|| {
-            Error::msg(format!(
-                "{name} f32 read is out of bounds: offset={offset}, len={}",
-                data.len()
-            ))
-        })?;
-    Ok(f32::from_ne_bytes(bytes.try_into().unwrap()))
+/// Sets the thread count of a CPU backend opened through the
+/// [`Registry`](crate::registry::Registry).
+pub fn set_n_threads(backend: &mut dyn Backend, n_threads: usize) -> Result<()> {
+    cpu_backend(backend).and_then(|cpu| cpu.set_n_threads(n_threads))
 }
 
-fn write_f32(data: &mut [u8], offset: usize, value: f32, name: &'static str) -> Result<()> {
-    let end = offset.checked_add(4).ok_or_else(|| Error::msg("f32 offset overflow"))?;
-    let len = data.len();
-    let dst = data
-        .get_mut(offset..end)
-        .ok_or_else(|| {
-            Error::msg(format!(
-                "{name} f32 write is out of bounds: offset={offset}, len={}",
-                len
-            ))
-        })?;
-    dst.copy_from_slice(&value.to_ne_bytes());
+/// Sets the shared thread pool of a CPU backend opened through the
+/// [`Registry`](crate::registry::Registry).
+pub fn set_threadpool(
+    backend: &mut dyn Backend,
+    threadpool: Option<Arc<ThreadPool>>,
+) -> Result<()> {
+    cpu_backend(backend)?.set_threadpool(threadpool);
+    Ok(())
+}
+
+pub(super) fn set_abort_callback(
+    backend: &mut dyn Backend,
+    abort_fn: Option<AbortCallback>,
+) -> Result<()> {
+    cpu_backend(backend)?.set_abort_callback(abort_fn);
     Ok(())
 }
@@ -1,38 +1,175 @@
-use crate::backend::{BackendBuffer, BackendBufferUsage};
-use crate::error::{Error, Result};
+use super::ops::common::RowPartition;
+use crate::backend::{BackendBuffer, BackendBufferUsage, MemoryAdvice, HOST_BUFFER_ALIGNMENT};
+use crate::error::{Error, ErrorKind, Result};
 use crate::storage::TensorStorage;
-use crate::tensor::Tensor;
+use crate::tensor::{Tensor, TensorInner};
+use crate::threadpool::ThreadPool;
 use std::any::Any;
-use std::cell::RefCell;
-use std::ops::Range;
-use std::rc::Rc;
+use std::cell::{Ref, RefCell, RefMut};
+use std::ops::{Deref, DerefMut, Range};
+#[cfg(feature = "mmap")]
+use std::path::Path;
+use std::rc::{Rc, Weak};
+
+const PAGE_SIZE: usize = 4096;
+
+/// Unit of heap memory, so that the memory is [`HOST_BUFFER_ALIGNMENT`] aligned.
+#[derive(Clone, Copy)]
+#[repr(C, align(64))]
+struct AlignedBlock([u8; HOST_BUFFER_ALIGNMENT]);
+
+const _: () = assert!(std::mem::align_of::<AlignedBlock>() == HOST_BUFFER_ALIGNMENT);
+
+/// Host memory behind a CPU buffer.
+enum HostMemory {
+    /// `len` bytes at the start of the blocks. The blocks are never reallocated.
+    Heap { blocks: Vec<AlignedBlock>, len: usize },
+    /// Private copy-on-write mapping of a file: writes never reach the file.
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::MmapMut),
+}
+
+impl Deref for HostMemory {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            // SAFETY: the blocks are `len` or more contiguous, initialized bytes.
+            Self::Heap { blocks, len } => unsafe {
+                std::slice::from_raw_parts(blocks.as_ptr().cast(), *len)
+            },
+            #[cfg(feature = "mmap")]
+            Self::Mapped(map) => map,
+        }
+    }
+}
+
+impl DerefMut for HostMemory {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self {
+            // SAFETY: as in `deref`, and `&mut self` makes the access exclusive.
+            Self::Heap { blocks, len } => unsafe {
+                std::slice::from_raw_parts_mut(blocks.as_mut_ptr().cast(), *len)
+            },
+            #[cfg(feature = "mmap")]
+            Self::Mapped(map) => map,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct CpuBackendBuffer {
-    buffers: Rc<RefCell<Vec<u8>>>,
+    buffers: Rc<RefCell<HostMemory>>,
+    /// Tensors bound by `init_tensor`, unbound again by `reset`.
+    tensors: Rc<RefCell<Vec<Weak<RefCell<TensorInner>>>>>,
     usage: BackendBufferUsage,
 }
 
 impl CpuBackendBuffer {
-    pub(super) fn new(size: usize, usage: BackendBufferUsage) -> Self {
-        Self { buffers: Rc::new(RefCell::new(vec![0; size])), usage }
+    /// Fails instead of aborting if the memory cannot be allocated. The memory is zeroed
+    /// by the allocator, so large buffers get fresh pages from the OS that are only placed
+    /// once touched, see [`CpuBackendBuffer::first_touch`].
+    pub(super) fn new(size: usize, usage: BackendBufferUsage) -> Result<Self> {
+        let n_blocks = size.div_ceil(HOST_BUFFER_ALIGNMENT);
+        let failed = || Error::new(ErrorKind::AllocationFailed { backend: "cpu", size });
+        let blocks = match n_blocks {
+            0 => Vec::new(),
+            _ => {
+                let layout =
+                    std::alloc::Layout::array::<AlignedBlock>(n_blocks).map_err(|_| failed())?;
+                // SAFETY: the layout has a non-zero size.
+                let ptr = unsafe { std::alloc::alloc_zeroed(layout) };
+                if ptr.is_null() {
+                    return Err(failed());
+                }
+                // SAFETY: `ptr` was allocated by the global allocator with the layout of
+                // `n_blocks` blocks, which are initialized since all-zero bytes are a valid
+                // block.
+                unsafe { Vec::from_raw_parts(ptr.cast(), n_blocks, n_blocks) }
+            }
+        };
+        Ok(Self::with_memory(HostMemory::Heap { blocks, len: size }, usage))
+    }
+
+    /// Maps `path` privately, so the buffer starts out with the file contents and pages are
+    /// read on first use.
+    #[cfg(feature = "mmap")]
+    pub(super) fn from_file(path: &Path, usage: BackendBufferUsage) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: the mapping is private, so later changes to the file by other processes are
+        // the only way its contents can change under us; weights files are not rewritten
+        // while loaded.
+        let map = unsafe { memmap2::MmapOptions::new().map_copy(&file)? };
+        Ok(Self::with_memory(HostMemory::Mapped(map), usage))
+    }
+
+    fn with_memory(memory: HostMemory, usage: BackendBufferUsage) -> Self {
+        Self {
+            buffers: Rc::new(RefCell::new(memory)),
+            tensors: Rc::new(RefCell::new(Vec::new())),
+            usage,
+        }
     }
 
     fn len(&self) -> usize {
         self.buffers.borrow().len()
     }
 
+    /// Writes the first byte of every page of heap memory from the threads of `pool`,
+    /// splitting the pages like the kernels split their output rows (see [`RowPartition`]).
+    /// An OS that places pages on the node of the thread touching them first then spreads
+    /// the buffer over the nodes of the threads that work on it, without NUMA calls. The
+    /// bytes written are zeros, so the contents do not change; mapped files are left alone.
+    pub(super) fn first_touch(&self, pool: &ThreadPool) -> Result<()> {
+        let mut memory = self.buffers.borrow_mut();
+        if !matches!(*memory, HostMemory::Heap { .. }) {
+            return Ok(());
+        }
+        let n_pages = memory.len().div_ceil(PAGE_SIZE);
+        let partition = RowPartition::new(n_pages, PAGE_SIZE, pool.n_threads());
+        let base = memory.as_mut_ptr() as usize;
+        pool.run(partition.n_chunks(), &|chunk| {
+            for page in partition.chunk(chunk) {
+                // SAFETY: the page starts within the memory, which stays mutably borrowed
+                // for the run, and every page belongs to exactly one chunk.
+                unsafe { std::ptr::write_volatile((base as *mut u8).add(page * PAGE_SIZE), 0) };
+            }
+            Ok(())
+        })
+    }
+
+    /// Start of the memory, for handing tensors to foreign code without copying. The memory
+    /// is never reallocated, so the pointer is valid while the buffer, or a tensor bound to
+    /// it, is alive.
+    #[cfg(feature = "python")]
+    pub(crate) fn host_ptr(&self) -> *mut u8 {
+        self.buffers.borrow_mut().as_mut_ptr()
+    }
+
     fn checked_range(&self, start: usize, size: usize) -> Result<Range<usize>> {
         let end = start.checked_add(size).ok_or_else(|| Error::msg("offset + size overflow"))?;
 
         if end > self.len() {
-            return Err(Error::msg("offset + size > buffer size"));
+            return Err(Error::msg(format!(
+                "offset + size ({start} + {size}) > buffer size ({})",
+                self.len()
+            )));
         }
 
         Ok(start..end)
     }
 
+    /// Range of `size` bytes at `offset` into the data of `tensor`, which must lie within
+    /// both the tensor and the buffer.
     fn tensor_range(&self, tensor: &Tensor, offset: usize, size: usize) -> Result<Range<usize>> {
+        let end = offset.checked_add(size).ok_or_else(|| Error::msg("offset + size overflow"))?;
+        if end > tensor.nbytes() {
+            return Err(Error::msg(format!(
+                "offset + size ({offset} + {size}) > tensor size ({})",
+                tensor.nbytes()
+            )));
+        }
+
         let storage = tensor.storage()?;
 
         if !matches!(*storage, TensorStorage::Cpu { .. }) {
@@ -50,8 +187,34 @@ impl CpuBackendBuffer {
 }
 
 impl BackendBuffer for CpuBackendBuffer {
+    /// Unbinds every tensor bound by `init_tensor` and reverts the memory: heap buffers are
+    /// zeroed and mapped buffers go back to the file contents.
     fn reset(&self) -> Result<()> {
-        self.buffers.borrow_mut().fill(0);
+        for inner in self.tensors.borrow_mut().drain(..) {
+            if let Some(inner) = inner.upgrade() {
+                inner.borrow_mut().storage = None;
+            }
+        }
+
+        let mut memory = self.buffers.borrow_mut();
+        match &mut *memory {
+            HostMemory::Heap { blocks, .. } => {
+                blocks.fill(AlignedBlock([0; HOST_BUFFER_ALIGNMENT]))
+            }
+            #[cfg(all(feature = "mmap", unix))]
+            HostMemory::Mapped(map) => {
+                // SAFETY: the mutable borrow guarantees no slice into the mapping is alive.
+                // Dropped pages of the private mapping are read from the file again.
+                unsafe { map.unchecked_advise(memmap2::UncheckedAdvice::DontNeed) }
+                    .map_err(|e| Error::from(e).context("in CpuBackendBuffer::reset"))?;
+            }
+            #[cfg(all(feature = "mmap", not(unix)))]
+            HostMemory::Mapped(_) => {
+                return Err(Error::msg("mapped buffers can only be reset on unix")
+                    .context("in CpuBackendBuffer::reset"));
+            }
+        }
+
         Ok(())
     }
 
@@ -60,6 +223,13 @@ impl BackendBuffer for CpuBackendBuffer {
         match view_tensor_opt {
             Some(view_tensor) => {
                 let view_storage = view_tensor.storage()?.clone();
+                let same_buffer = view_storage
+                    .as_cpu()
+                    .is_some_and(|buffer| Rc::ptr_eq(&buffer.buffers, &self.buffers));
+                if !same_buffer {
+                    return Err(Error::msg("view source is bound to another buffer")
+                        .context("in CpuBackendBuffer::init_tensor"));
+                }
                 tensor.set_storage(Some(view_storage))?;
             }
             None => {
@@ -70,12 +240,15 @@ impl BackendBuffer for CpuBackendBuffer {
             }
         }
 
+        let mut tensors = self.tensors.borrow_mut();
+        tensors.retain(|inner| inner.strong_count() > 0);
+        tensors.push(Rc::downgrade(&tensor.0));
         Ok(())
     }
 
     fn fill(&self, tensor: Tensor, value: u8, offset: usize, size: usize) -> Result<()> {
         let range = self.tensor_range(&tensor, offset, size)?;
-        self.buffers.borrow_mut()[range].fill(value);
+        self.host_memory_mut()?[range].fill(value);
         Ok(())
     }
 
@@ -85,7 +258,7 @@ impl BackendBuffer for CpuBackendBuffer {
         }
 
         let range = self.tensor_range(&tensor, offset, size)?;
-        self.buffers.borrow_mut()[range].copy_from_slice(&data[..size]);
+        self.host_memory_mut()?[range].copy_from_slice(&data[..size]);
         Ok(())
     }
 
@@ -95,7 +268,7 @@ impl BackendBuffer for CpuBackendBuffer {
         }
 
         let range = self.tensor_range(&tensor, offset, size)?;
-        data[..size].copy_from_slice(&self.buffers.borrow()[range]);
+        data[..size].copy_from_slice(&self.host_memory()?[range]);
         Ok(())
     }
 
@@ -139,6 +312,68 @@ impl BackendBuffer for CpuBackendBuffer {
         Ok(self.usage)
     }
 
+    fn is_host(&self) -> bool {
+        true
+    }
+
+    fn advise(&self, tensor: Tensor, advice: MemoryAdvice) -> Result<()> {
+        let range = self.tensor_range(&tensor, 0, tensor.nbytes())?;
+        let memory = self.buffers.borrow_mut();
+        match (&*memory, advice) {
+            (_, MemoryAdvice::Touch) => {
+                let mut sum = 0u8;
+                for i in range.clone().step_by(PAGE_SIZE).chain(range.end.checked_sub(1)) {
+                    sum = sum.wrapping_add(memory[i]);
+                }
+                std::hint::black_box(sum);
+                Ok(())
+            }
+            (HostMemory::Heap { .. }, _) => Ok(()),
+            #[cfg(all(feature = "mmap", unix))]
+            (HostMemory::Mapped(map), MemoryAdvice::WillNeed) => map
+                .advise_range(memmap2::Advice::WillNeed, range.start, range.len())
+                .map_err(|e| Error::from(e).context("in CpuBackendBuffer::advise")),
+            #[cfg(all(feature = "mmap", unix))]
+            (HostMemory::Mapped(map), MemoryAdvice::DontNeed) => {
+                // The mapping starts at file offset 0, so only pages lying wholly inside the
+                // tensor are dropped; the partial pages at either end may hold neighbouring
+                // tensors whose writes must survive.
+                let start = range.start.next_multiple_of(PAGE_SIZE);
+                let end = range.end / PAGE_SIZE * PAGE_SIZE;
+                if start >= end {
+                    return Ok(());
+                }
+                // SAFETY: the mutable borrow guarantees no slice into the mapping is alive.
+                // Dropped pages of the private mapping are read from the file again, which
+                // discards writes made to them since the file was mapped.
+                unsafe {
+                    map.unchecked_advise_range(
+                        memmap2::UncheckedAdvice::DontNeed,
+                        start,
+                        end - start,
+                    )
+                }
+                .map_err(|e| Error::from(e).context("in CpuBackendBuffer::advise"))
+            }
+            #[cfg(all(feature = "mmap", not(unix)))]
+            (HostMemory::Mapped(_), _) => Ok(()),
+        }
+    }
+
+    fn host_memory(&self) -> Result<Ref<'_, [u8]>> {
+        let memory = self
+            .buffers
+            .try_borrow()
+            .map_err(|_| Error::msg("buffer memory is borrowed mutably"))?;
+        Ok(Ref::map(memory, |memory| &**memory))
+    }
+
+    fn host_memory_mut(&self) -> Result<RefMut<'_, [u8]>> {
+        let memory =
+            self.buffers.try_borrow_mut().map_err(|_| Error::msg("buffer memory is borrowed"))?;
+        Ok(RefMut::map(memory, |memory| &mut **memory))
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -1,11 +1,135 @@
+use super::autotune::GemmBlocking;
+use super::backend::Precision;
+use super::ops::common::RowPartition;
+use crate::backend::{AbortCallback, GraphObserver, HostRanges};
+use crate::error::Result;
+use crate::threadpool::{ThreadPool, ThreadPoolParams};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
 pub(super) struct CpuBackendContext {
-    n_threads: usize,
-    data: Vec<u8>,
-    abort_fn: Option<Box<dyn Fn() -> bool + Send + Sync>>,
+    /// Scratch the kernels stage values in; a plan lends its own while it runs.
+    work: Mutex<Vec<f32>>,
+    /// Row partition a plan stored for the node being computed.
+    partition: Mutex<Option<RowPartition>>,
+    abort_fn: Option<AbortCallback>,
+    observer: Mutex<Option<Box<dyn GraphObserver>>>,
+    /// Pool with the backend's own thread count, used unless a shared pool is set.
+    own_pool: ThreadPool,
+    threadpool: Option<Arc<ThreadPool>>,
+    gemm: GemmBlocking,
+    /// Whether kernels take their reference path on `sequential_pool`.
+    reference: bool,
+    sequential_pool: ThreadPool,
+    host_ranges: HostRanges,
+    precision: Precision,
+    first_touch: bool,
 }
 
 impl CpuBackendContext {
     pub fn new() -> Self {
-        Self { n_threads: 1, data: Vec::new(), abort_fn: None }
+        Self {
+            work: Mutex::default(),
+            partition: Mutex::default(),
+            abort_fn: None,
+            observer: Mutex::new(None),
+            own_pool: ThreadPool::sequential(),
+            threadpool: None,
+            gemm: GemmBlocking::default(),
+            reference: false,
+            sequential_pool: ThreadPool::sequential(),
+            host_ranges: HostRanges::default(),
+            precision: Precision::default(),
+            first_touch: false,
+        }
+    }
+
+    /// Pool the kernels run on: a sequential one in reference mode, else the shared one if
+    /// set, else the backend's own.
+    pub fn threadpool(&self) -> &ThreadPool {
+        if self.reference {
+            return &self.sequential_pool;
+        }
+        self.threadpool.as_deref().unwrap_or(&self.own_pool)
+    }
+
+    pub fn host_ranges(&self) -> &HostRanges {
+        &self.host_ranges
+    }
+
+    pub fn n_threads(&self) -> usize {
+        self.threadpool().n_threads()
+    }
+
+    /// Resizes the backend's own pool. A shared pool set with `set_threadpool` keeps its
+    /// size and stays in use.
+    pub fn set_n_threads(&mut self, n_threads: usize) -> Result<()> {
+        if n_threads != self.own_pool.n_threads() {
+            self.own_pool = ThreadPool::new(ThreadPoolParams::new(n_threads))?;
+        }
+        Ok(())
+    }
+
+    pub fn set_abort_fn(&mut self, abort_fn: Option<AbortCallback>) {
+        self.abort_fn = abort_fn;
+    }
+
+    pub fn set_observer(&mut self, observer: Option<Box<dyn GraphObserver>>) {
+        *self.observer.get_mut().unwrap_or_else(PoisonError::into_inner) = observer;
+    }
+
+    pub fn observer(&self) -> MutexGuard<'_, Option<Box<dyn GraphObserver>>> {
+        self.observer.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn work(&self) -> MutexGuard<'_, Vec<f32>> {
+        self.work.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn partition(&self) -> Option<RowPartition> {
+        *self.partition.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn set_partition(&self, partition: Option<RowPartition>) {
+        *self.partition.lock().unwrap_or_else(PoisonError::into_inner) = partition;
+    }
+
+    pub fn set_threadpool(&mut self, threadpool: Option<Arc<ThreadPool>>) {
+        self.threadpool = threadpool;
+    }
+
+    pub fn gemm(&self) -> GemmBlocking {
+        self.gemm
+    }
+
+    pub fn set_gemm(&mut self, gemm: GemmBlocking) {
+        self.gemm = gemm;
+    }
+
+    pub fn reference(&self) -> bool {
+        self.reference
+    }
+
+    pub fn set_reference(&mut self, reference: bool) {
+        self.reference = reference;
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    pub fn set_precision(&mut self, precision: Precision) {
+        self.precision = precision;
+    }
+
+    pub fn first_touch(&self) -> bool {
+        self.first_touch
+    }
+
+    pub fn set_first_touch(&mut self, first_touch: bool) {
+        self.first_touch = first_touch;
+    }
+
+    pub fn aborted(&self) -> bool {
+        self.abort_fn.as_ref().is_some_and(|abort| abort())
     }
 }
@@ -1,3 +1,4 @@
+use super::autotune::machine_id;
 use super::backend::CpuBackend;
 use crate::backend::{
     Backend, BackendBuffer, BackendCapabilities, BackendDevice, BackendDeviceType, DeviceInfo,
@@ -23,10 +24,11 @@ impl BackendDevice for CpuBackendDevice {
     fn info(&self) -> Result<DeviceInfo> {
         Ok(DeviceInfo {
             name: "cpu".to_string(),
+            id: format!("cpu-{}", machine_id()),
             description: self.description.clone(),
             memory: MemoryInfo { total: 0, free: 0 },
             device_type: BackendDeviceType::Cpu,
-            caps: BackendCapabilities { async_compute: false, host_buffer: false, events: false },
+            caps: BackendCapabilities { async_compute: false, host_buffer: false, events: true },
         })
     }
 
@@ -35,10 +37,7 @@ impl BackendDevice for CpuBackendDevice {
     }
 
     fn supports_op(&self, op_type: TensorOpType) -> Result<bool> {
-        match op_type {
-            TensorOpType::TensorOpMul => Ok(true),
-            _ => Ok(false),
-        }
+        Ok(!crate::ops::supported_dtypes(op_type).is_empty())
     }
 
     fn offload_op(&self, _tensor: Tensor) -> Result<bool> {
@@ -1,6 +1,8 @@
+use super::backend::{set_abort_callback, set_n_threads, set_threadpool};
 use super::backend_device::CpuBackendDevice;
-use crate::backend::{BackendDevice, BackendRegister};
+use crate::backend::{numa_init, BackendDevice, BackendFeature, BackendRegister};
 use crate::error::{Error, ErrorKind, Result};
+use crate::registry::BackendFunction;
 use std::any::Any;
 use std::sync::OnceLock;
 
@@ -31,6 +33,17 @@ impl BackendRegister for CpuBackendRegister {
         Ok(())
     }
 
+    fn get_proc_address(&self, name: &str) -> Option<BackendFunction> {
+        match name {
+            "set_n_threads" => Some(BackendFunction::SetNThreads(set_n_threads)),
+            "set_abort_callback" => Some(BackendFunction::SetAbortCallback(set_abort_callback)),
+            "set_threadpool" => Some(BackendFunction::SetThreadpool(set_threadpool)),
+            "get_features" => Some(BackendFunction::GetFeatures(features)),
+            "numa_init" => Some(BackendFunction::NumaInit(numa_init)),
+            _ => None,
+        }
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -78,3 +91,40 @@ impl CpuBackendRegister {
         Ok(cpu_devices)
     }
 }
+
+pub(super) fn features() -> Vec<BackendFeature> {
+    let mut features = Vec::new();
+    let mut push = |name: &'static str, enabled: bool| {
+        features.push(BackendFeature { name, value: u8::from(enabled).to_string() });
+    };
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        push("sse3", std::arch::is_x86_feature_detected!("sse3"));
+        push("avx", std::arch::is_x86_feature_detected!("avx"));
+        push("avx2", std::arch::is_x86_feature_detected!("avx2"));
+        push("fma", std::arch::is_x86_feature_detected!("fma"));
+        push("f16c", std::arch::is_x86_feature_detected!("f16c"));
+        push("avx512f", std::arch::is_x86_feature_detected!("avx512f"));
+        push("avx512vnni", std::arch::is_x86_feature_detected!("avx512vnni"));
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        push("neon", std::arch::is_aarch64_feature_detected!("neon"));
+        push("fp16", std::arch::is_aarch64_feature_detected!("fp16"));
+        push("dotprod", std::arch::is_aarch64_feature_detected!("dotprod"));
+    }
+    push("mmap", cfg!(feature = "mmap"));
+
+    features
+}
+
+/// Registration hook of the CPU backend, listed by [`Registry::discover`] when the
+/// backend's feature is enabled.
+///
+/// [`Registry::discover`]: crate::registry::Registry::discover
+pub fn register() -> Result<Box<dyn BackendRegister>> {
+    let reg = CpuBackendRegister::new();
+    reg.probe_devices()?;
+    Ok(Box::new(reg))
+}
@@ -0,0 +1,59 @@
+use super::backend::CpuBackend;
+use crate::backend::{Backend, BackendEvent, BackendStream};
+use crate::compute_graph::ComputeGraph;
+use crate::context::Context;
+use crate::error::{Error, Result};
+use std::any::Any;
+
+/// CPU stream. The CPU backend computes graphs synchronously, so work is finished by the
+/// time it has been enqueued and every recorded event is complete.
+pub(crate) struct CpuBackendStream<'a> {
+    backend: &'a CpuBackend,
+}
+
+impl<'a> CpuBackendStream<'a> {
+    pub(super) fn new(backend: &'a CpuBackend) -> Self {
+        Self { backend }
+    }
+}
+
+impl BackendStream for CpuBackendStream<'_> {
+    fn graph_compute(&self, ctx: &Context, graph: &mut ComputeGraph) -> Result<()> {
+        self.backend.graph_compute(ctx, graph)
+    }
+
+    fn record_event(&self, event: &dyn BackendEvent) -> Result<()> {
+        cpu_event(event).map(|_| ()).map_err(|e| e.context("in CpuBackendStream::record_event"))
+    }
+
+    fn wait_event(&self, event: &dyn BackendEvent) -> Result<()> {
+        cpu_event(event).map(|_| ()).map_err(|e| e.context("in CpuBackendStream::wait_event"))
+    }
+
+    fn synchronize(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub(crate) struct CpuBackendEvent;
+
+impl BackendEvent for CpuBackendEvent {
+    fn is_complete(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn synchronize(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+fn cpu_event(event: &dyn BackendEvent) -> Result<&CpuBackendEvent> {
+    event
+        .as_any()
+        .downcast_ref::<CpuBackendEvent>()
+        .ok_or_else(|| Error::msg("event was not created by the CPU backend"))
+}
@@ -0,0 +1,269 @@
+use super::kernels::{find_named_kernel, resolve, Isa, KernelEntry};
+use super::ops::common::{dims, RowPartition};
+use crate::compute_graph::{ComputeGraph, GraphId};
+use crate::context::Context;
+use crate::data_type::TensorOpType;
+use crate::error::{Error, Result};
+use crate::tensor::Tensor;
+use std::cell::RefCell;
+
+/// Graph prepared for repeated execution on the CPU backend.
+///
+/// Creating a plan resolves every node once: its sources, the kernel computing it and how
+/// its output rows are split over the backend threads. Computing the plan again skips the
+/// lookups, the kernel selection and the partitioning of
+/// [`Backend::graph_compute`](crate::backend::Backend::graph_compute) and only runs the
+/// kernels. Partitions are made for the thread count of the backend when the plan is
+/// created. Kernels are picked for the instruction sets the backend allows when the plan
+/// is created or updated. The plan also owns the scratch the kernels stage their values
+/// in, sized for its largest node with [`work_size_for`]. A plan stays valid while the
+/// graph keeps its topology; see
+/// [`CpuBackend::plan_update`](super::backend::CpuBackend::plan_update).
+pub struct ComputePlan {
+    ctx: Context,
+    graph: ComputeGraph,
+    max_isa: Isa,
+    n_threads: usize,
+    nodes: Vec<PlanNode>,
+    work: RefCell<Vec<f32>>,
+}
+
+pub(super) struct PlanNode {
+    pub(super) tensor: Tensor,
+    pub(super) srcs: Vec<Tensor>,
+    pub(super) kernel: &'static KernelEntry,
+    /// Split of the output rows over the backend threads, see [`output_partition`].
+    pub(super) partition: RowPartition,
+    /// Scratch bytes the kernel takes, see [`work_size_for`].
+    pub(super) work_size: usize,
+}
+
+impl ComputePlan {
+    pub(super) fn new(
+        ctx: &Context,
+        graph: &ComputeGraph,
+        max_isa: Isa,
+        n_threads: usize,
+    ) -> Result<Self> {
+        let mut plan = Self::empty(ctx, graph, max_isa, n_threads);
+        plan.nodes = plan.plan_nodes(graph).map_err(|e| e.context("in ComputePlan::new"))?;
+        plan.size_work();
+        Ok(plan)
+    }
+
+    fn empty(ctx: &Context, graph: &ComputeGraph, max_isa: Isa, n_threads: usize) -> Self {
+        Self {
+            ctx: ctx.clone(),
+            graph: graph.clone(),
+            max_isa,
+            n_threads,
+            nodes: Vec::new(),
+            work: RefCell::default(),
+        }
+    }
+
+    /// Grows the work buffer to the largest scratch of a node.
+    fn size_work(&mut self) {
+        let size = self.nodes.iter().map(|node| node.work_size).max().unwrap_or(0);
+        let work = self.work.get_mut();
+        work.resize(work.len().max(size.div_ceil(size_of::<f32>())), 0.0);
+    }
+
+    /// Rebuilds a plan for `graph` with the kernels of [`ComputePlan::encode`]. Fails if
+    /// `data` was encoded for another graph or names a kernel this CPU cannot run.
+    pub(super) fn decode(
+        ctx: &Context,
+        graph: &ComputeGraph,
+        max_isa: Isa,
+        n_threads: usize,
+        data: &[u8],
+    ) -> Result<Self> {
+        let malformed = || Error::msg("malformed cached plan").context("in ComputePlan::decode");
+        let text = std::str::from_utf8(data).map_err(|_| malformed())?;
+        let ids = graph.nodes();
+        if text.lines().count() != ids.len() {
+            return Err(malformed());
+        }
+
+        let mut nodes = Vec::with_capacity(ids.len());
+        for (id, line) in ids.iter().zip(text.lines()) {
+            let tensor = ctx.get_tensor(*id).map_err(|e| e.context("in ComputePlan::decode"))?;
+            let srcs = sources(ctx, &tensor)?;
+            let (name, isa) = line.split_once(' ').ok_or_else(malformed)?;
+            let dtype = srcs.first().map(Tensor::dtype);
+            let kernel =
+                find_named_kernel(tensor.op_type(), dtype, name, isa).ok_or_else(malformed)?;
+            if kernel.isa > max_isa || srcs.len() < kernel.n_srcs {
+                return Err(malformed());
+            }
+            let partition = output_partition(&tensor, n_threads);
+            let work_size = work_size_for(&tensor, n_threads)?;
+            nodes.push(PlanNode { tensor, srcs, kernel, partition, work_size });
+        }
+
+        let mut plan = Self::empty(ctx, graph, max_isa, n_threads);
+        plan.nodes = nodes;
+        plan.size_work();
+        Ok(plan)
+    }
+
+    /// Kernel of every node, one `name isa` line each.
+    pub(super) fn encode(&self) -> Vec<u8> {
+        let lines = self
+            .nodes
+            .iter()
+            .map(|node| format!("{} {}\n", node.kernel.name, node.kernel.isa.name()));
+        lines.collect::<String>().into_bytes()
+    }
+
+    /// Re-targets the plan at `graph`, which must have the same ops in the same order as
+    /// the planned graph. Shapes may differ.
+    pub(super) fn update(&mut self, graph: &ComputeGraph) -> Result<()> {
+        let ids = graph.nodes();
+        let same_topology = ids.len() == self.nodes.len()
+            && ids.iter().zip(&self.nodes).all(|(id, node)| {
+                self.ctx.get_tensor(*id).is_ok_and(|tensor| tensor.op_type() == node.kernel.op)
+            });
+        if !same_topology {
+            return Err(Error::msg("graph topology changed, create a new plan")
+                .context("in ComputePlan::update"));
+        }
+
+        self.nodes = self.plan_nodes(graph).map_err(|e| e.context("in ComputePlan::update"))?;
+        self.size_work();
+        self.graph = graph.clone();
+        Ok(())
+    }
+
+    fn plan_nodes(&self, graph: &ComputeGraph) -> Result<Vec<PlanNode>> {
+        let ids = graph.nodes();
+        let mut nodes = Vec::with_capacity(ids.len());
+        for id in ids.iter() {
+            let tensor = self.ctx.get_tensor(*id)?;
+            let srcs = sources(&self.ctx, &tensor)?;
+            let kernel = resolve(&srcs, &tensor, self.max_isa)
+                .map_err(|e| e.context(format!("in {}", tensor.describe())))?;
+            let partition = output_partition(&tensor, self.n_threads);
+            let work_size = work_size_for(&tensor, self.n_threads)?;
+            nodes.push(PlanNode { tensor, srcs, kernel, partition, work_size });
+        }
+        Ok(nodes)
+    }
+
+    pub(super) fn graph(&self) -> &ComputeGraph {
+        &self.graph
+    }
+
+    pub(super) fn nodes(&self) -> &[PlanNode] {
+        &self.nodes
+    }
+
+    pub(super) fn work(&self) -> &RefCell<Vec<f32>> {
+        &self.work
+    }
+
+    /// Bytes of the scratch the plan holds for its kernels.
+    pub fn work_size(&self) -> usize {
+        self.work.borrow().len() * size_of::<f32>()
+    }
+
+    /// Id of the graph the plan was created for or last updated to.
+    pub fn graph_id(&self) -> GraphId {
+        self.graph.id()
+    }
+
+    pub fn n_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Name and instruction set of the kernel computing the node at `index`.
+    pub fn kernel(&self, index: usize) -> Option<(&'static str, Isa)> {
+        self.nodes.get(index).map(|node| (node.kernel.name, node.kernel.isa))
+    }
+}
+
+fn sources(ctx: &Context, tensor: &Tensor) -> Result<Vec<Tensor>> {
+    tensor.src_tensor().into_iter().map(|id| ctx.get_tensor(id)).collect()
+}
+
+/// Scratch bytes the kernel of `node` takes from the work buffer when computed over
+/// `n_threads` threads (see `CpuBackend::with_work`): the F32 accumulators of the matrix
+/// products and attention, and for attention a row of scores per chunk of output rows.
+pub fn work_size_for(node: &Tensor, n_threads: usize) -> Result<usize> {
+    let n_f32 = match node.op_type() {
+        TensorOpType::TensorOpMulMat | TensorOpType::TensorOpMulMatRowwise => n_elements(node),
+        TensorOpType::TensorOpAttention => {
+            let ctx = node.ctx().map_err(|e| e.context("in work_size_for"))?;
+            let k = node.src_tensor().get(1).map(|&id| ctx.get_tensor(id)).transpose()?;
+            let n_kv = k.map_or(0, |k| dims(&k)[1]);
+            let n_chunks = output_partition(node, n_threads).n_chunks().max(1);
+            n_elements(node) + n_chunks * n_kv
+        }
+        _ => 0,
+    };
+    Ok(n_f32 * size_of::<f32>())
+}
+
+fn n_elements(tensor: &Tensor) -> usize {
+    dims(tensor).iter().product()
+}
+
+/// Split of the output rows of `node` over `n_threads` threads, as the kernels computing
+/// one output row at a time make it.
+pub(super) fn output_partition(node: &Tensor, n_threads: usize) -> RowPartition {
+    let [ne0, ne1, ne2, ne3] = dims(node);
+    RowPartition::new(ne1 * ne2 * ne3, ne0 * size_of::<f32>(), n_threads)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_type::{DataType, TensorType};
+    use crate::shape;
+
+    #[test]
+    fn test_work_size_for() {
+        let mut ctx = Context::builder().build();
+        let mut a = ctx.new_tensor(DataType::F32, &shape![4, 3]).unwrap();
+        let b = ctx.new_tensor(DataType::F16, &shape![4, 2]).unwrap();
+        let product = a.mul_mat(b).unwrap();
+        assert_eq!(work_size_for(&product, 4).unwrap(), 6 * 4);
+
+        let transposed = a.transpose().unwrap();
+        assert_eq!(work_size_for(&transposed, 4).unwrap(), 0);
+        assert_eq!(work_size_for(&transposed.clone().cont().unwrap(), 4).unwrap(), 0);
+
+        let mut q = ctx.new_tensor(DataType::F32, &shape![8, 2, 2]).unwrap();
+        let k = ctx.new_tensor(DataType::F32, &shape![8, 5, 1]).unwrap();
+        let v = ctx.new_tensor(DataType::F32, &shape![8, 5, 1]).unwrap();
+        let out = q.attention(k, v, None, 1.0).unwrap();
+        assert_eq!(work_size_for(&out, 1).unwrap(), (32 + 5) * 4);
+        let n_chunks = output_partition(&out, 3).n_chunks();
+        assert!(n_chunks > 1);
+        assert_eq!(work_size_for(&out, 3).unwrap(), (32 + n_chunks * 5) * 4);
+    }
+
+    #[test]
+    fn test_plan_stores_partitions_and_work_sizes() {
+        let mut ctx = Context::builder().build();
+        let mut a = ctx.new_tensor(DataType::F32, &shape![64, 48]).unwrap();
+        let b = ctx.new_tensor(DataType::F32, &shape![64, 40]).unwrap();
+        for leaf in [&a, &b] {
+            leaf.set_tensor_type(TensorType::FlagParam);
+            leaf.set_op_type(TensorOpType::TensorNone);
+        }
+        let product = a.mul_mat(b).unwrap();
+        let graph = ComputeGraph::new();
+        graph.build_forward(&ctx, product.tensor_id(), false).unwrap();
+
+        let mut plan = ComputePlan::new(&ctx, &graph, Isa::Scalar, 4).unwrap();
+        let node = &plan.nodes()[0];
+        assert_eq!(node.partition, output_partition(&product, 4));
+        assert!(node.partition.splits(40, 48 * 4) && node.partition.n_chunks() > 1);
+        assert_eq!(node.work_size, 48 * 40 * 4);
+        assert_eq!(plan.work_size(), node.work_size);
+
+        plan.update(&graph).unwrap();
+        assert_eq!(plan.nodes()[0].partition, output_partition(&product, 4));
+    }
+}
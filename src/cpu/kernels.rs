@@ -0,0 +1,344 @@
+//! Kernel table of the CPU backend.
+//!
+//! Every kernel is an entry of [`KERNELS`]: the op it computes, the types of the operands
+//! it accepts, the instruction set it needs and the function computing it. A node is
+//! computed by the entry for its op and first source type with the highest instruction set
+//! the CPU has, so a new data type or SIMD variant of a kernel is one more entry. The int8
+//! and native F16 matrix products have [`Isa::Avx512`] and [`Isa::Neon`] entries using the
+//! VNNI or SDOT and the F16 dot products; these need extensions beyond the instruction set,
+//! so the entries fall back to the portable dot product on CPUs without them. Reference
+//! mode only picks [`Isa::Scalar`] entries.
+
+use super::backend::CpuBackend;
+use super::ops::acc::{acc, set};
+use super::ops::attention::attention;
+use super::ops::binary::binary;
+use super::ops::cont::cont;
+use super::ops::conv::{conv_1d, conv_transpose_1d, conv_transpose_2d};
+use super::ops::diag_mask_inf::diag_mask_inf;
+use super::ops::dropout::dropout;
+use super::ops::gelu::gelu;
+use super::ops::gemm_q8::dot_q8_kernel;
+use super::ops::get_rows_back::get_rows_back;
+use super::ops::group_norm::{group_norm, group_norm_back};
+use super::ops::im2col_back::im2col_back;
+use super::ops::map::{map_binary, map_unary};
+use super::ops::mul_mat::{mul_mat, mul_mat_native_f16, mul_mat_rowwise};
+use super::ops::norm::{layer_norm, rms_norm};
+use super::ops::out_prod::out_prod;
+use super::ops::relu::relu;
+use super::ops::repeat::{repeat, repeat_back};
+use super::ops::rope::rope_store_kv;
+use super::ops::silu::{silu, swiglu};
+use super::ops::soft_max::soft_max;
+use super::ops::soft_max_back::soft_max_back;
+use super::ops::sum_rows::sum_rows;
+use super::ops::upscale::upscale;
+use crate::data_type::{custom_data_type, DataType, TensorOpType};
+use crate::error::{Error, ErrorKind, Result};
+use crate::quant::dot_q8;
+use crate::tensor::Tensor;
+use std::sync::OnceLock;
+
+/// Instruction set a kernel needs, from the portable baseline up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Isa {
+    /// Plain Rust, auto-vectorized for the build target.
+    Scalar,
+    /// aarch64 Advanced SIMD.
+    Neon,
+    /// x86-64 AVX2 with FMA.
+    Avx2,
+    /// x86-64 AVX-512 F.
+    Avx512,
+}
+
+impl Isa {
+    /// The highest instruction set of this CPU.
+    pub fn detect() -> Self {
+        static ISA: OnceLock<Isa> = OnceLock::new();
+        *ISA.get_or_init(|| {
+            #[cfg(target_arch = "x86_64")]
+            {
+                if std::arch::is_x86_feature_detected!("avx512f") {
+                    return Isa::Avx512;
+                }
+                if std::arch::is_x86_feature_detected!("avx2")
+                    && std::arch::is_x86_feature_detected!("fma")
+                {
+                    return Isa::Avx2;
+                }
+            }
+            #[cfg(target_arch = "aarch64")]
+            if std::arch::is_aarch64_feature_detected!("neon") {
+                return Isa::Neon;
+            }
+            Isa::Scalar
+        })
+    }
+
+    /// Whether kernels needing `self` run on this CPU.
+    pub fn is_available(self) -> bool {
+        match self {
+            Isa::Scalar => true,
+            Isa::Neon => Isa::detect() == Isa::Neon,
+            Isa::Avx2 => matches!(Isa::detect(), Isa::Avx2 | Isa::Avx512),
+            Isa::Avx512 => Isa::detect() == Isa::Avx512,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Isa::Scalar => "scalar",
+            Isa::Neon => "neon",
+            Isa::Avx2 => "avx2",
+            Isa::Avx512 => "avx512",
+        }
+    }
+}
+
+/// Computes a node from its sources, in graph order.
+pub(crate) type Kernel = fn(&CpuBackend, &[Tensor], &Tensor) -> Result<()>;
+
+pub(crate) struct KernelEntry {
+    pub(crate) op: TensorOpType,
+    /// Name of the op in errors.
+    pub(crate) name: &'static str,
+    /// Types of the value operands the kernel computes, i.e. the sources other than index
+    /// tensors; empty accepts any type. Kernels are looked up by the type of the first
+    /// source, and [`supported_dtypes`](crate::ops::supported_dtypes) reports the types
+    /// of the [`Isa::Scalar`] entries.
+    pub(crate) dtypes: &'static [DataType],
+    pub(crate) isa: Isa,
+    /// Sources the kernel needs at least, and what they are in errors.
+    pub(crate) n_srcs: usize,
+    pub(crate) srcs: &'static str,
+    pub(crate) kernel: Kernel,
+}
+
+const FLOAT: &[DataType] = &[DataType::F32, DataType::F16];
+
+/// Int8 weights with F32 or F16 inputs and scales.
+const ROWWISE: &[DataType] = &[DataType::I8, DataType::F32, DataType::F16];
+
+const TWO_SOURCES: &str = "two source tensors";
+
+const ONE_SOURCE: &str = "a source tensor";
+
+const ROWWISE_SOURCES: &str = "weights, input and scales sources";
+
+/// Views share the storage of their source, there is nothing to compute.
+fn view(_: &CpuBackend, _: &[Tensor], _: &Tensor) -> Result<()> {
+    Ok(())
+}
+
+macro_rules! kernels {
+    ($($op:ident $name:literal $dtypes:expr, $isa:ident, $srcs:expr => $kernel:expr;)*) => {
+        &[$(KernelEntry {
+            op: TensorOpType::$op,
+            name: $name,
+            dtypes: $dtypes,
+            isa: Isa::$isa,
+            n_srcs: $srcs.0,
+            srcs: $srcs.1,
+            kernel: $kernel,
+        },)*]
+    };
+}
+
+pub(crate) static KERNELS: &[KernelEntry] = kernels! {
+    TensorOpAdd "add" FLOAT, Scalar, (2, TWO_SOURCES) => |b, s, d| binary(b, &s[0], &s[1], d);
+    TensorOpSub "sub" FLOAT, Scalar, (2, TWO_SOURCES) => |b, s, d| binary(b, &s[0], &s[1], d);
+    TensorOpMul "mul" FLOAT, Scalar, (2, TWO_SOURCES) => |b, s, d| binary(b, &s[0], &s[1], d);
+    TensorOpDiv "div" FLOAT, Scalar, (2, TWO_SOURCES) => |b, s, d| binary(b, &s[0], &s[1], d);
+    TensorOpAcc "acc" FLOAT, Scalar, (2, TWO_SOURCES) => |_, s, d| acc(&s[0], &s[1], d);
+    TensorOpSet "set" FLOAT, Scalar, (2, TWO_SOURCES) => |_, s, d| set(&s[0], &s[1], d);
+    TensorOpOutProd "out_prod" FLOAT, Scalar, (2, TWO_SOURCES)
+        => |b, s, d| out_prod(b, &s[0], &s[1], d);
+    TensorOpDiagMaskInf "diag_mask_inf" FLOAT, Scalar, (1, ONE_SOURCE)
+        => |b, s, d| diag_mask_inf(b, &s[0], d);
+    TensorOpDropout "dropout" &[DataType::F32], Scalar, (2, "a source and an rng state")
+        => |_, s, d| dropout(&s[0], &s[1], d);
+    TensorOpRopeStoreKv "rope_store_kv" FLOAT, Scalar,
+        (4, "keys, values, positions and a value cache")
+        => |_, s, d| rope_store_kv(&s[0], &s[1], &s[2], &s[3], d);
+    TensorOpNorm "layer_norm" FLOAT, Scalar, (3, "a source, a weight and a bias")
+        => |b, s, d| layer_norm(b, &s[0], &s[1], &s[2], d);
+    TensorOpRmsNorm "rms_norm" FLOAT, Scalar, (1, ONE_SOURCE) => |b, s, d| rms_norm(b, &s[0], d);
+    TensorOpGroupNorm "group_norm" FLOAT, Scalar, (1, ONE_SOURCE)
+        => |b, s, d| group_norm(b, &s[0], d);
+    TensorOpGroupNormBack "group_norm_back" FLOAT, Scalar, (2, TWO_SOURCES)
+        => |b, s, d| group_norm_back(b, &s[0], &s[1], d);
+    TensorOpConv1d "conv_1d" FLOAT, Scalar, (2, TWO_SOURCES)
+        => |b, s, d| conv_1d(b, &s[0], &s[1], d);
+    TensorOpConvTranspose1d "conv_transpose_1d" FLOAT, Scalar, (2, TWO_SOURCES)
+        => |b, s, d| conv_transpose_1d(b, &s[0], &s[1], d);
+    TensorOpConvTranspose2d "conv_transpose_2d" FLOAT, Scalar, (2, TWO_SOURCES)
+        => |b, s, d| conv_transpose_2d(b, &s[0], &s[1], d);
+    TensorOpUpscale "upscale" FLOAT, Scalar, (1, ONE_SOURCE) => |b, s, d| upscale(b, &s[0], d);
+    TensorOpMulMat "mul_mat" FLOAT, Scalar, (2, TWO_SOURCES)
+        => |b, s, d| mul_mat(b, &s[0], &s[1], d);
+    TensorOpMulMat "mul_mat" &[DataType::F16], Avx512, (2, TWO_SOURCES)
+        => |b, s, d| mul_mat_native_f16(b, &s[0], &s[1], d);
+    TensorOpMulMat "mul_mat" &[DataType::F16], Neon, (2, TWO_SOURCES)
+        => |b, s, d| mul_mat_native_f16(b, &s[0], &s[1], d);
+    TensorOpMulMatRowwise "mul_mat_rowwise" ROWWISE, Scalar, (3, ROWWISE_SOURCES)
+        => |b, s, d| mul_mat_rowwise(b, &s[0], &s[1], &s[2], d, dot_q8);
+    TensorOpMulMatRowwise "mul_mat_rowwise" &[DataType::I8], Avx512, (3, ROWWISE_SOURCES)
+        => |b, s, d| mul_mat_rowwise(b, &s[0], &s[1], &s[2], d, dot_q8_kernel().1);
+    TensorOpMulMatRowwise "mul_mat_rowwise" &[DataType::I8], Neon, (3, ROWWISE_SOURCES)
+        => |b, s, d| mul_mat_rowwise(b, &s[0], &s[1], &s[2], d, dot_q8_kernel().1);
+    TensorOpView "view" &[], Scalar, (0, "") => view;
+    TensorOpTranspose "transpose" &[], Scalar, (0, "") => view;
+    TensorOpPermute "permute" &[], Scalar, (0, "") => view;
+    TensorOpReshape "reshape" &[], Scalar, (0, "") => view;
+    TensorOpCont "cont" &[], Scalar, (1, ONE_SOURCE) => |_, s, d| cont(&s[0], d);
+    TensorOpAttention "attention" FLOAT, Scalar, (3, "q, k and v source tensors")
+        => |b, s, d| attention(b, &s[0], &s[1], &s[2], s.get(3), d);
+    TensorOpMapUnary "map_unary" FLOAT, Scalar, (1, ONE_SOURCE)
+        => |b, s, d| map_unary(b, &s[0], d);
+    TensorOpGelu "gelu" FLOAT, Scalar, (1, ONE_SOURCE) => |b, s, d| gelu(b, &s[0], d);
+    TensorOpRelu "relu" FLOAT, Scalar, (1, ONE_SOURCE) => |b, s, d| relu(b, &s[0], d);
+    TensorOpLeakyRelu "leaky_relu" FLOAT, Scalar, (1, ONE_SOURCE) => |b, s, d| relu(b, &s[0], d);
+    TensorOpSilu "silu" FLOAT, Scalar, (1, ONE_SOURCE) => |b, s, d| silu(b, &s[0], d);
+    TensorOpSwiGlu "swiglu" FLOAT, Scalar, (2, TWO_SOURCES)
+        => |b, s, d| swiglu(b, &s[0], &s[1], d);
+    TensorOpMapBinary "map_binary" FLOAT, Scalar, (2, TWO_SOURCES)
+        => |b, s, d| map_binary(b, &s[0], &s[1], d);
+    TensorOpSoftMax "soft_max" FLOAT, Scalar, (1, ONE_SOURCE) => |b, s, d| soft_max(b, &s[0], d);
+    TensorOpSoftMaxBack "soft_max_back" FLOAT, Scalar, (2, TWO_SOURCES)
+        => |b, s, d| soft_max_back(b, &s[0], &s[1], d);
+    TensorOpIm2ColBack "im2col_back" FLOAT, Scalar, (2, TWO_SOURCES)
+        => |b, s, d| im2col_back(b, &s[0], &s[1], d);
+    TensorOpSumRows "sum_rows" FLOAT, Scalar, (1, ONE_SOURCE) => |b, s, d| sum_rows(b, &s[0], d);
+    TensorOpMean "mean" FLOAT, Scalar, (1, ONE_SOURCE) => |b, s, d| sum_rows(b, &s[0], d);
+    TensorOpRepeat "repeat" FLOAT, Scalar, (1, ONE_SOURCE) => |_, s, d| repeat(&s[0], d);
+    TensorOpRepeatBack "repeat_back" FLOAT, Scalar, (1, ONE_SOURCE)
+        => |_, s, d| repeat_back(&s[0], d);
+    TensorOpGetRowsBack "get_rows_back" FLOAT, Scalar, (2, TWO_SOURCES)
+        => |_, s, d| get_rows_back(&s[0], &s[1], d);
+};
+
+/// The entry computing `op` on a first source of type `dtype` with the highest instruction
+/// set up to `max_isa` this CPU has.
+pub(crate) fn find_kernel(
+    op: TensorOpType,
+    dtype: Option<DataType>,
+    max_isa: Isa,
+) -> Option<&'static KernelEntry> {
+    KERNELS
+        .iter()
+        .filter(|entry| entry.op == op && entry.isa <= max_isa && entry.isa.is_available())
+        .filter(|entry| match dtype {
+            Some(dtype) => entry.dtypes.is_empty() || entry.dtypes.contains(&dtype),
+            None => true,
+        })
+        .max_by_key(|entry| entry.isa)
+}
+
+/// The entry of [`KERNELS`] with `name` and `isa` that computes `op` on a first source of
+/// type `dtype`, if this CPU has the instruction set.
+pub(crate) fn find_named_kernel(
+    op: TensorOpType,
+    dtype: Option<DataType>,
+    name: &str,
+    isa: &str,
+) -> Option<&'static KernelEntry> {
+    KERNELS.iter().find(|entry| {
+        entry.op == op
+            && entry.name == name
+            && entry.isa.name() == isa
+            && entry.isa.is_available()
+            && dtype.is_none_or(|dtype| entry.dtypes.is_empty() || entry.dtypes.contains(&dtype))
+    })
+}
+
+/// The entry [`find_kernel`] picks for `tensor`, checked against its sources.
+pub(crate) fn resolve(
+    srcs: &[Tensor],
+    tensor: &Tensor,
+    max_isa: Isa,
+) -> Result<&'static KernelEntry> {
+    let op = tensor.op_type();
+    let dtype = srcs.first().map(Tensor::dtype);
+    // No entry lists a registered data type; the kernels taking them check for them.
+    let lookup = dtype.filter(|&dtype| custom_data_type(dtype).is_none());
+    let Some(entry) = find_kernel(op, lookup, max_isa) else {
+        if let Some(dtype) = dtype.filter(|_| KERNELS.iter().any(|entry| entry.op == op)) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype,
+                op: "cpu compute_forward",
+            })
+            .context(format!("no kernel for {op:?}"))
+            .context("in CpuBackend::compute_forward"));
+        }
+        return Err(Error::new(ErrorKind::UnsupportedBackendOp {
+            backend: "cpu",
+            op: "compute_forward",
+        })
+        .context(format!("unsupported op type: {op:?}"))
+        .context("in CpuBackend::compute_forward"));
+    };
+    if srcs.len() < entry.n_srcs {
+        return Err(Error::msg(format!("{} tensor requires {}", entry.name, entry.srcs))
+            .context("in CpuBackend::compute_forward"));
+    }
+    Ok(entry)
+}
+
+/// Computes `tensor` with the kernel [`find_kernel`] picks for it.
+pub(crate) fn compute(
+    backend: &CpuBackend,
+    srcs: &[Tensor],
+    tensor: &Tensor,
+    max_isa: Isa,
+) -> Result<()> {
+    (resolve(srcs, tensor, max_isa)?.kernel)(backend, srcs, tensor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::supported_dtypes;
+
+    #[test]
+    fn test_kernel_table_matches_op_dtypes() {
+        // supported_dtypes reads the scalar entries, which the SIMD entries may only narrow
+        for entry in KERNELS {
+            let supported = supported_dtypes(entry.op);
+            assert!(!supported.is_empty(), "{} has no scalar kernel", entry.name);
+            assert!(entry.dtypes.iter().all(|dtype| supported.contains(dtype)), "{}", entry.name);
+        }
+        assert_eq!(supported_dtypes(TensorOpType::TensorOpDropout), [DataType::F32]);
+        assert!(supported_dtypes(TensorOpType::TensorOpMulMatRowwise).contains(&DataType::F16));
+
+        let mul = find_kernel(TensorOpType::TensorOpMul, Some(DataType::F16), Isa::Avx512);
+        assert_eq!(mul.map(|entry| entry.name), Some("mul"));
+        let int_mul = find_kernel(TensorOpType::TensorOpMul, Some(DataType::I32), Isa::Avx512);
+        assert!(int_mul.is_none());
+        let cont = find_kernel(TensorOpType::TensorOpCont, Some(DataType::I64), Isa::Scalar);
+        assert_eq!(cont.map(|entry| entry.isa), Some(Isa::Scalar));
+        assert!(Isa::Scalar.is_available() && Isa::detect().is_available());
+    }
+
+    #[test]
+    fn test_find_kernel_picks_simd_dot_products() {
+        let simd = match Isa::detect() {
+            isa @ (Isa::Avx512 | Isa::Neon) => isa,
+            _ => Isa::Scalar,
+        };
+        let lookups = [
+            (TensorOpType::TensorOpMulMatRowwise, DataType::I8),
+            (TensorOpType::TensorOpMulMat, DataType::F16),
+        ];
+        for (op, dtype) in lookups {
+            let entry = find_kernel(op, Some(dtype), Isa::Avx512).unwrap();
+            assert_eq!(entry.isa, simd, "{}", entry.name);
+            // Reference mode caps the instruction set at the baseline.
+            let entry = find_kernel(op, Some(dtype), Isa::Scalar).unwrap();
+            assert_eq!(entry.isa, Isa::Scalar, "{}", entry.name);
+        }
+        let f32 = find_kernel(TensorOpType::TensorOpMulMat, Some(DataType::F32), Isa::Avx512);
+        assert_eq!(f32.map(|entry| entry.isa), Some(Isa::Scalar));
+    }
+}
@@ -1,5 +1,10 @@
+pub mod autotune;
 pub mod backend;
 pub(crate) mod backend_buffers;
 pub(crate) mod backend_context;
 pub mod backend_device;
 pub mod backend_register;
+pub(crate) mod backend_stream;
+pub mod compute_plan;
+pub mod kernels;
+pub(super) mod ops;
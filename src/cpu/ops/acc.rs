@@ -0,0 +1,68 @@
+use super::common::{
+    byte_offset, dims, for_each_index, load, read_tensor_bytes, store, strides, write_tensor_bytes,
+};
+use crate::data_type::DataType;
+use crate::error::{Error, ErrorKind, Result};
+use crate::ops::OpParams;
+use crate::tensor::Tensor;
+
+/// Copies `src0` into `dst` and adds `src1` into the strided region of `dst`
+/// described by the node's `OpParams::Acc`.
+pub(crate) fn acc(src0: &Tensor, src1: &Tensor, dst: &Tensor) -> Result<()> {
+    write_view(src0, src1, dst, true, "cpu acc")
+}
+
+/// Copies `src0` into `dst` and overwrites the strided region of `dst` described by the
+/// node's `OpParams::Acc` with `src1`.
+pub(crate) fn set(src0: &Tensor, src1: &Tensor, dst: &Tensor) -> Result<()> {
+    write_view(src0, src1, dst, false, "cpu set")
+}
+
+fn write_view(
+    src0: &Tensor,
+    src1: &Tensor,
+    dst: &Tensor,
+    add: bool,
+    op: &'static str,
+) -> Result<()> {
+    let dtype = dst.dtype();
+    if !matches!(dtype, DataType::F32 | DataType::F16)
+        || src0.dtype() != dtype
+        || src1.dtype() != dtype
+    {
+        return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp { dtype, op }));
+    }
+
+    let (nb1, nb2, nb3, offset) = match dst.op_params() {
+        Some(OpParams::Acc { nb1, nb2, nb3, offset }) => (nb1, nb2, nb3, offset),
+        _ => return Err(Error::msg("node is missing its op params").context(format!("in {op}"))),
+    };
+
+    let src0_data = read_tensor_bytes(src0)?;
+    let src1_data = read_tensor_bytes(src1)?;
+    let mut dst_data = vec![0; dst.nbytes()];
+
+    let src0_stride = strides(src0);
+    let src1_stride = strides(src1);
+    let dst_stride = strides(dst);
+
+    for_each_index(dims(dst), |i0, i1, i2, i3| {
+        let value = load(&src0_data, byte_offset(&src0_stride, i0, i1, i2, i3)?, dtype, "src0")?;
+        store(&mut dst_data, byte_offset(&dst_stride, i0, i1, i2, i3)?, value, dtype, "dst")
+    })?;
+
+    let view_stride = [dst_stride[0], nb1, nb2, nb3];
+    for_each_index(dims(src1), |i0, i1, i2, i3| {
+        let dst_offset = byte_offset(&view_stride, i0, i1, i2, i3)?
+            .checked_add(offset)
+            .ok_or_else(|| Error::msg("view offset overflow"))?;
+        let mut value =
+            load(&src1_data, byte_offset(&src1_stride, i0, i1, i2, i3)?, dtype, "src1")?;
+        if add {
+            value += load(&dst_data, dst_offset, dtype, "dst")?;
+        }
+        store(&mut dst_data, dst_offset, value, dtype, "dst")
+    })?;
+
+    write_tensor_bytes(dst, &mut dst_data)
+}
@@ -0,0 +1,119 @@
+use super::common::{dims, parallel_chunks, read_tensor_f32, write_tensor_f32};
+use crate::cpu::backend::CpuBackend;
+use crate::data_type::DataType;
+use crate::error::{Error, ErrorKind, Result};
+use crate::ops::{alibi_slopes, OpParams};
+use crate::tensor::Tensor;
+use std::sync::{Mutex, PoisonError};
+
+/// dst[:, i, h, b] = sum_j softmax_j(scale * q[:, i, h, b] . k[:, j, h', b] + bias) v[:, j, h', b]
+///
+/// with `bias = mask[j, i, h] - slope(h) * |n_kv - n_q + i - j|`, the second term only for
+/// a nonzero ALiBi `max_bias`. With a sliding window, keys `window` or more positions
+/// before the query position `n_kv - n_q + i` are left out.
+///
+/// `h' = h / (H / H_kv)`, so a group of query heads shares one K/V head without copying
+/// it. Every `(i, h, b)` output row is computed on the backend threads, with the scores of
+/// the row in the work buffer.
+pub(crate) fn attention(
+    backend: &CpuBackend,
+    q: &Tensor,
+    k: &Tensor,
+    v: &Tensor,
+    mask: Option<&Tensor>,
+    dst: &Tensor,
+) -> Result<()> {
+    for tensor in [q, k, v, dst].into_iter().chain(mask) {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op: "cpu attention",
+            }));
+        }
+    }
+
+    let params = match dst.op_params() {
+        Some(OpParams::Attention(params)) => params,
+        _ => return Err(Error::msg("attention tensor is missing its parameters")),
+    };
+    let (scale, max_bias) = (params.scale, params.max_bias);
+    let window = params.window.unwrap_or(usize::MAX);
+
+    let [d, n_q, n_head, n_batch] = dims(q);
+    let [_, n_kv, n_head_kv, _] = dims(k);
+    if dims(dst) != dims(q) || dims(v) != dims(k) || n_head_kv == 0 || n_head % n_head_kv != 0 {
+        return Err(Error::msg("attention shapes do not match"));
+    }
+
+    let q = read_tensor_f32(q)?;
+    let k = read_tensor_f32(k)?;
+    let v = read_tensor_f32(v)?;
+    let mask_heads = mask.map_or(1, |mask| dims(mask)[2]);
+    let mask = mask.map(read_tensor_f32).transpose()?;
+    let group = n_head / n_head_kv;
+    let slopes = if max_bias > 0.0 { alibi_slopes(n_head, max_bias) } else { Vec::new() };
+
+    let n_rows = n_q * n_head * n_batch;
+    let partition = backend.row_partition(n_rows, d * size_of::<f32>());
+    let n_chunks = partition.n_chunks().max(1);
+    backend.with_work(d * n_rows + n_chunks * n_kv, |work| {
+        let (out, scores) = work.split_at_mut(d * n_rows);
+        if n_kv == 0 {
+            // without keys every row stays zero
+            return write_tensor_f32(dst, out);
+        }
+        // One row of scores per chunk, a chunk runs on one thread at a time.
+        let scores: Vec<Mutex<&mut [f32]>> =
+            scores.chunks_exact_mut(n_kv).map(Mutex::new).collect();
+        let compute_row = |row: usize, dst_row: &mut [f32], scores: &mut [f32]| {
+            let i = row % n_q;
+            let h = (row / n_q) % n_head;
+            let b = row / (n_q * n_head);
+            let query = &q[row * d..][..d];
+            let kv_start = (b * n_head_kv + h / group) * n_kv * d;
+            let keys = &k[kv_start..][..n_kv * d];
+            let values = &v[kv_start..][..n_kv * d];
+            let mask = mask.as_ref().map(|mask| &mask[(h % mask_heads) * n_q * n_kv..]);
+            let slope = slopes.get(h).copied();
+            let position = (n_kv + i).saturating_sub(n_q);
+
+            for ((j, key), score) in keys.chunks_exact(d).enumerate().zip(scores.iter_mut()) {
+                *score = if position >= j.saturating_add(window) {
+                    f32::NEG_INFINITY
+                } else {
+                    let dot: f32 = query.iter().zip(key).map(|(q, k)| q * k).sum();
+                    let alibi = slope.map_or(0.0, |slope| -slope * position.abs_diff(j) as f32);
+                    scale * dot + mask.map_or(0.0, |mask| mask[i * n_kv + j]) + alibi
+                };
+            }
+
+            let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            if max == f32::NEG_INFINITY {
+                // every key is masked out, the row stays zero
+                return;
+            }
+            let mut sum = 0.0;
+            for score in scores.iter_mut() {
+                *score = (*score - max).exp();
+                sum += *score;
+            }
+
+            for (weight, value) in scores.iter().zip(values.chunks_exact(d)) {
+                for (acc, value) in dst_row.iter_mut().zip(value) {
+                    *acc += weight / sum * value;
+                }
+            }
+        };
+
+        parallel_chunks(backend.threadpool(), out, d, partition, |rows, dst| {
+            let chunk = rows.start / partition.rows_per_chunk();
+            let mut scores = scores[chunk].lock().unwrap_or_else(PoisonError::into_inner);
+            for (row, dst_row) in rows.zip(dst.chunks_mut(d)) {
+                compute_row(row, dst_row, &mut scores[..n_kv]);
+            }
+            Ok(())
+        })?;
+
+        write_tensor_f32(dst, out)
+    })
+}
@@ -0,0 +1,61 @@
+use super::common::{dims, parallel_rows, read_tensor_f32, write_tensor_f32};
+use crate::cpu::backend::CpuBackend;
+use crate::data_type::{DataType, TensorOpType};
+use crate::error::{Error, ErrorKind, Result};
+use crate::tensor::Tensor;
+
+/// dst = src0 op src1, element-wise for the add, sub, mul and div ops of `dst`.
+///
+/// `src1` is repeated along every dimension it is smaller in, so each of its dimensions
+/// must divide the one of `src0`, e.g. a row of biases added to every row of a matrix.
+/// The rows of `dst` are split across the backend threads.
+pub(crate) fn binary(
+    backend: &CpuBackend,
+    src0: &Tensor,
+    src1: &Tensor,
+    dst: &Tensor,
+) -> Result<()> {
+    let (f, name): (fn(f32, f32) -> f32, _) = match dst.op_type() {
+        TensorOpType::TensorOpAdd => (|a, b| a + b, "cpu add"),
+        TensorOpType::TensorOpSub => (|a, b| a - b, "cpu sub"),
+        TensorOpType::TensorOpMul => (|a, b| a * b, "cpu mul"),
+        TensorOpType::TensorOpDiv => (|a, b| a / b, "cpu div"),
+        op => return Err(Error::msg(format!("{op:?} is not an element-wise binary op"))),
+    };
+    for tensor in [src0, src1, dst] {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op: name,
+            }));
+        }
+    }
+
+    let [ne0, ne1, ne2, ne3] = dims(dst);
+    let [ne10, ne11, ne12, ne13] = dims(src1);
+    if dims(src0) != dims(dst) {
+        return Err(Error::msg(format!("{name} destination shape does not match src0")));
+    }
+    if [ne10, ne11, ne12, ne13].contains(&0)
+        || ne0 % ne10 != 0
+        || ne1 % ne11 != 0
+        || ne2 % ne12 != 0
+        || ne3 % ne13 != 0
+    {
+        return Err(Error::msg(format!("{name} cannot repeat src1 to the shape of src0")));
+    }
+
+    let mut values = read_tensor_f32(src0)?;
+    let rhs = read_tensor_f32(src1)?;
+    parallel_rows(backend, &mut values, ne0, |row, dst_row| {
+        let (i1, i2, i3) = (row % ne1, row / ne1 % ne2, row / (ne1 * ne2));
+        let rhs_row = ((i3 % ne13) * ne12 + i2 % ne12) * ne11 + i1 % ne11;
+        let rhs = &rhs[rhs_row * ne10..][..ne10];
+        for (value, rhs) in dst_row.iter_mut().zip(rhs.iter().cycle()) {
+            *value = f(*value, *rhs);
+        }
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &values)
+}
@@ -0,0 +1,334 @@
+use crate::backend::BackendBuffer;
+use crate::cpu::backend::CpuBackend;
+use crate::data_type::{from_f32, get_type_size, to_f32, to_i32, DataType};
+use crate::error::{Error, Result};
+use crate::tensor::Tensor;
+use crate::threadpool::ThreadPool;
+use std::ops::Range;
+use std::sync::{Mutex, PoisonError};
+
+pub(crate) fn dims(tensor: &Tensor) -> [usize; 4] {
+    let shape = tensor.shape();
+    [shape.dim(0), shape.dim(1), shape.dim(2), shape.dim(3)]
+}
+
+pub(crate) fn strides(tensor: &Tensor) -> [usize; 4] {
+    let stride = tensor.stride();
+    [stride[0], stride[1], stride[2], stride[3]]
+}
+
+pub(crate) fn byte_offset(
+    stride: &[usize; 4],
+    i0: usize,
+    i1: usize,
+    i2: usize,
+    i3: usize,
+) -> Result<usize> {
+    i0.checked_mul(stride[0])
+        .and_then(|offset| i1.checked_mul(stride[1]).and_then(|delta| offset.checked_add(delta)))
+        .and_then(|offset| i2.checked_mul(stride[2]).and_then(|delta| offset.checked_add(delta)))
+        .and_then(|offset| i3.checked_mul(stride[3]).and_then(|delta| offset.checked_add(delta)))
+        .ok_or_else(|| Error::msg("tensor byte offset overflow"))
+}
+
+/// Calls `f` for every index of a tensor with extents `ne`, innermost dimension first.
+pub(crate) fn for_each_index<F>(ne: [usize; 4], mut f: F) -> Result<()>
+where
+    F: FnMut(usize, usize, usize, usize) -> Result<()>,
+{
+    for i3 in 0..ne[3] {
+        for i2 in 0..ne[2] {
+            for i1 in 0..ne[1] {
+                for i0 in 0..ne[0] {
+                    f(i0, i1, i2, i3)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads one element of `dtype` at `offset` and widens it to `f32`.
+pub(crate) fn load(data: &[u8], offset: usize, dtype: DataType, name: &'static str) -> Result<f32> {
+    let bytes = data.get(offset..).filter(|bytes| bytes.len() >= get_type_size(dtype));
+    let bytes = bytes.ok_or_else(|| {
+        Error::msg(format!("{name} read is out of bounds: offset={offset}, len={}", data.len()))
+    })?;
+    to_f32(dtype, bytes)
+}
+
+/// Narrows `value` to `dtype` and stores it at `offset`.
+pub(crate) fn store(
+    data: &mut [u8],
+    offset: usize,
+    value: f32,
+    dtype: DataType,
+    name: &'static str,
+) -> Result<()> {
+    let len = data.len();
+    let bytes = data.get_mut(offset..).filter(|bytes| bytes.len() >= get_type_size(dtype));
+    let bytes = bytes.ok_or_else(|| {
+        Error::msg(format!("{name} write is out of bounds: offset={offset}, len={len}"))
+    })?;
+    from_f32(dtype, value, bytes)
+}
+
+pub(crate) fn read_tensor_bytes(tensor: &Tensor) -> Result<Vec<u8>> {
+    let mut data = vec![0; tensor.nbytes()];
+    let buffer = {
+        let storage = tensor.storage()?;
+        storage.as_cpu().ok_or_else(|| Error::msg("tensor storage is not CPU"))?.clone()
+    };
+    let size = data.len();
+    buffer.read(tensor.clone(), &mut data, 0, size)?;
+    Ok(data)
+}
+
+pub(crate) fn write_tensor_bytes(tensor: &Tensor, data: &mut [u8]) -> Result<()> {
+    let buffer = {
+        let storage = tensor.storage()?;
+        storage.as_cpu().ok_or_else(|| Error::msg("tensor storage is not CPU"))?.clone()
+    };
+    buffer.write(tensor.clone(), data, 0, data.len())
+}
+
+/// Gathers the elements of `data` (laid out like `tensor`) into a contiguous `f32` vector,
+/// innermost dimension first.
+pub(crate) fn gather_f32(tensor: &Tensor, data: &[u8]) -> Result<Vec<f32>> {
+    let dtype = tensor.dtype();
+    let stride = strides(tensor);
+    let ne = dims(tensor);
+    let mut values = Vec::with_capacity(ne.iter().product());
+    for_each_index(ne, |i0, i1, i2, i3| {
+        values.push(load(data, byte_offset(&stride, i0, i1, i2, i3)?, dtype, "gather")?);
+        Ok(())
+    })?;
+    Ok(values)
+}
+
+/// Inverse of [`gather_f32`]: scatters contiguous `values` into `data` laid out like `tensor`.
+pub(crate) fn scatter_f32(tensor: &Tensor, values: &[f32], data: &mut [u8]) -> Result<()> {
+    let dtype = tensor.dtype();
+    let stride = strides(tensor);
+    let mut values = values.iter();
+    for_each_index(dims(tensor), |i0, i1, i2, i3| {
+        let value = values.next().ok_or_else(|| Error::msg("scatter source is too short"))?;
+        store(data, byte_offset(&stride, i0, i1, i2, i3)?, *value, dtype, "scatter")
+    })
+}
+
+pub(crate) fn read_tensor_f32(tensor: &Tensor) -> Result<Vec<f32>> {
+    gather_f32(tensor, &read_tensor_bytes(tensor)?)
+}
+
+pub(crate) fn read_tensor_i32(tensor: &Tensor) -> Result<Vec<i32>> {
+    let data = read_tensor_bytes(tensor)?;
+    let dtype = tensor.dtype();
+    let stride = strides(tensor);
+    let size = get_type_size(dtype);
+    let mut values = Vec::with_capacity(dims(tensor).iter().product());
+    for_each_index(dims(tensor), |i0, i1, i2, i3| {
+        let offset = byte_offset(&stride, i0, i1, i2, i3)?;
+        let bytes = data.get(offset..offset + size).ok_or_else(|| {
+            Error::msg(format!("i32 read is out of bounds: offset={offset}, len={}", data.len()))
+        })?;
+        values.push(to_i32(dtype, bytes)?);
+        Ok(())
+    })?;
+    Ok(values)
+}
+
+pub(crate) fn write_tensor_f32(tensor: &Tensor, values: &[f32]) -> Result<()> {
+    let mut data = vec![0; tensor.nbytes()];
+    scatter_f32(tensor, values, &mut data)?;
+    write_tensor_bytes(tensor, &mut data)
+}
+
+/// Bytes per cache line; chunk boundaries are kept on multiples of it so two threads never
+/// write the same line.
+const CACHE_LINE: usize = 64;
+
+/// Chunks handed out per thread. The pool's threads claim chunks as they go, so extra chunks
+/// let fast threads take over the tail of slow ones.
+const CHUNKS_PER_THREAD: usize = 4;
+
+/// Division of `n_rows` output rows into chunks of whole rows for the threads of a pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RowPartition {
+    n_rows: usize,
+    rows_per_chunk: usize,
+    /// Smallest number of rows whose bytes fill whole cache lines.
+    step: usize,
+}
+
+impl RowPartition {
+    /// Splits `n_rows` rows of `row_bytes` bytes for `n_threads` threads. Chunks start at a
+    /// cache-line boundary when the rows do.
+    pub(crate) fn new(n_rows: usize, row_bytes: usize, n_threads: usize) -> Self {
+        let step = Self::step(row_bytes);
+        if n_threads <= 1 || n_rows <= 1 {
+            return Self { n_rows, rows_per_chunk: n_rows.max(1), step };
+        }
+
+        let target = n_rows.div_ceil(n_threads * CHUNKS_PER_THREAD);
+        let rows_per_chunk = target.next_multiple_of(step).min(n_rows);
+        Self { n_rows, rows_per_chunk, step }
+    }
+
+    fn step(row_bytes: usize) -> usize {
+        match row_bytes {
+            0 => 1,
+            _ => CACHE_LINE / gcd(row_bytes, CACHE_LINE),
+        }
+    }
+
+    /// Whether the partition splits `n_rows` rows of `row_bytes` bytes.
+    pub(crate) fn splits(&self, n_rows: usize, row_bytes: usize) -> bool {
+        self.n_rows == n_rows && self.step == Self::step(row_bytes)
+    }
+
+    /// Caps the chunks at `max_rows` rows, rounded up to keep them on cache lines.
+    pub(crate) fn max_rows(self, max_rows: usize) -> Self {
+        let cap = max_rows.max(1).next_multiple_of(self.step);
+        Self { rows_per_chunk: self.rows_per_chunk.min(cap), ..self }
+    }
+
+    pub(crate) fn n_chunks(&self) -> usize {
+        self.n_rows.div_ceil(self.rows_per_chunk)
+    }
+
+    pub(crate) fn rows_per_chunk(&self) -> usize {
+        self.rows_per_chunk
+    }
+
+    pub(crate) fn chunk(&self, index: usize) -> Range<usize> {
+        let start = (index * self.rows_per_chunk).min(self.n_rows);
+        start..(start + self.rows_per_chunk).min(self.n_rows)
+    }
+}
+
+fn gcd(mut a: usize, mut b: usize) -> usize {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Splits `dst` into rows of `row_len` elements and runs `f(row_index, row)` for each of
+/// them, spreading chunks of rows over the kernel threads of `backend` as
+/// [`CpuBackend::row_partition`] splits them.
+pub(crate) fn parallel_rows<F>(
+    backend: &CpuBackend,
+    dst: &mut [f32],
+    row_len: usize,
+    f: F,
+) -> Result<()>
+where
+    F: Fn(usize, &mut [f32]) -> Result<()> + Sync,
+{
+    if row_len == 0 {
+        return Ok(());
+    }
+
+    let n_rows = dst.len() / row_len;
+    let partition = backend.row_partition(n_rows, row_len * size_of::<f32>());
+    parallel_chunks(backend.threadpool(), dst, row_len, partition, |rows, values| {
+        for (row, values) in rows.zip(values.chunks_mut(row_len)) {
+            f(row, values)?;
+        }
+        Ok(())
+    })
+}
+
+/// Runs `f(rows, values)` for every chunk of `partition`, where `values` are the rows of
+/// `row_len` elements of `dst` in that chunk. For kernels that work on blocks of rows.
+pub(crate) fn parallel_chunks<F>(
+    pool: &ThreadPool,
+    dst: &mut [f32],
+    row_len: usize,
+    partition: RowPartition,
+    f: F,
+) -> Result<()>
+where
+    F: Fn(Range<usize>, &mut [f32]) -> Result<()> + Sync,
+{
+    if row_len == 0 {
+        return Ok(());
+    }
+
+    if partition.n_chunks() <= 1 {
+        return f(partition.chunk(0), dst);
+    }
+
+    let chunks: Vec<Mutex<&mut [f32]>> =
+        dst.chunks_mut(partition.rows_per_chunk() * row_len).map(Mutex::new).collect();
+    pool.run(chunks.len(), &|chunk| {
+        let mut values = chunks[chunk].lock().unwrap_or_else(PoisonError::into_inner);
+        f(partition.chunk(chunk), &mut values)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parallel_rows_visits_every_row_once() {
+        for n_threads in [1, 3, 8] {
+            let mut backend = CpuBackend::init().unwrap();
+            backend.set_n_threads(n_threads).unwrap();
+            let mut values = vec![0.0f32; 7 * 5];
+            parallel_rows(&backend, &mut values, 5, |row, dst| {
+                dst.iter_mut().for_each(|value| *value += row as f32);
+                Ok(())
+            })
+            .unwrap();
+
+            for (row, chunk) in values.chunks(5).enumerate() {
+                assert!(chunk.iter().all(|&value| value == row as f32));
+            }
+        }
+    }
+
+    #[test]
+    fn test_parallel_rows_propagates_errors() {
+        let mut backend = CpuBackend::init().unwrap();
+        backend.set_n_threads(4).unwrap();
+        let mut values = vec![0.0f32; 16];
+        let result = parallel_rows(&backend, &mut values, 4, |row, _| {
+            if row == 2 {
+                Err(Error::msg("row failed"))
+            } else {
+                Ok(())
+            }
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_row_partition_covers_rows_on_cache_lines() {
+        for (n_rows, row_bytes, n_threads) in [(1000, 4, 8), (37, 12, 3), (5, 64, 16), (9, 0, 2)] {
+            let partition = RowPartition::new(n_rows, row_bytes, n_threads);
+            let mut next = 0;
+            for chunk in 0..partition.n_chunks() {
+                let rows = partition.chunk(chunk);
+                assert_eq!(rows.start, next);
+                assert!(!rows.is_empty());
+                assert!((rows.start * row_bytes).is_multiple_of(CACHE_LINE));
+                next = rows.end;
+            }
+            assert_eq!(next, n_rows);
+        }
+
+        assert!(RowPartition::new(1000, 4, 8).n_chunks() > 8);
+        assert_eq!(RowPartition::new(1000, 4, 1).n_chunks(), 1);
+
+        assert!(RowPartition::new(1000, 4, 8).splits(1000, 36));
+        assert!(!RowPartition::new(1000, 4, 8).splits(1000, 32));
+        assert!(!RowPartition::new(1000, 4, 8).splits(999, 4));
+
+        let capped = RowPartition::new(1000, 4, 1).max_rows(40);
+        assert_eq!(capped.rows_per_chunk(), 48);
+        assert_eq!(capped.chunk(capped.n_chunks() - 1), 960..1000);
+    }
+}
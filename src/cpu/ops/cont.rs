@@ -0,0 +1,29 @@
+use super::common::{
+    byte_offset, dims, for_each_index, read_tensor_bytes, strides, write_tensor_bytes,
+};
+use crate::error::{Error, Result};
+use crate::tensor::Tensor;
+
+/// Copies the elements of `src0` through its byte strides into the contiguous `dst`.
+///
+/// Elements are moved as raw bytes, so every dtype is supported.
+pub(crate) fn cont(src0: &Tensor, dst: &Tensor) -> Result<()> {
+    if src0.dtype() != dst.dtype() || dims(src0) != dims(dst) {
+        return Err(Error::msg("cont destination does not match its source"));
+    }
+
+    let src = read_tensor_bytes(src0)?;
+    let stride = strides(src0);
+    let size = src0.element_size();
+    let mut out = Vec::with_capacity(dst.nbytes());
+    for_each_index(dims(src0), |i0, i1, i2, i3| {
+        let offset = byte_offset(&stride, i0, i1, i2, i3)?;
+        let element = src.get(offset..offset + size).ok_or_else(|| {
+            Error::msg(format!("cont read is out of bounds: offset={offset}, len={}", src.len()))
+        })?;
+        out.extend_from_slice(element);
+        Ok(())
+    })?;
+
+    write_tensor_bytes(dst, &mut out)
+}
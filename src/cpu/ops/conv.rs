@@ -0,0 +1,157 @@
+use super::common::{dims, parallel_rows, read_tensor_f32, write_tensor_f32};
+use crate::cpu::backend::CpuBackend;
+use crate::data_type::DataType;
+use crate::error::{Error, ErrorKind, Result};
+use crate::ops::OpParams;
+use crate::tensor::{Im2ColParams, Tensor};
+
+/// Validates the dtypes of a convolution and returns its parameters.
+fn conv_params(
+    src0: &Tensor,
+    src1: &Tensor,
+    dst: &Tensor,
+    op: &'static str,
+) -> Result<Im2ColParams> {
+    for tensor in [src0, src1] {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op,
+            }));
+        }
+    }
+    if dst.dtype() != DataType::F32 {
+        return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp { dtype: dst.dtype(), op }));
+    }
+
+    match dst.op_params() {
+        Some(OpParams::Im2Col(params)) => Ok(params),
+        _ => Err(Error::msg(format!("{op} tensor is missing its convolution parameters"))),
+    }
+}
+
+/// `pos * stride + k * dilation - padding` along `axis`, if it lands inside `0..len`.
+///
+/// This maps an output position to the input it reads for a convolution, and an input
+/// position to the output it writes for a transposed one.
+fn tap(pos: usize, k: usize, axis: usize, params: &Im2ColParams, len: usize) -> Option<usize> {
+    (pos * params.stride[axis] + k * params.dilation[axis])
+        .checked_sub(params.padding[axis])
+        .filter(|&i| i < len)
+}
+
+/// dst[ol, oc, n] = sum_{ic, k} src0[k, ic, oc] * src1[ol * s + k * d - p, ic, n]
+///
+/// Every `(oc, n)` output row is computed independently on the backend threads.
+pub(crate) fn conv_1d(
+    backend: &CpuBackend,
+    src0: &Tensor,
+    src1: &Tensor,
+    dst: &Tensor,
+) -> Result<()> {
+    let params = conv_params(src0, src1, dst, "cpu conv_1d")?;
+    let kernel = read_tensor_f32(src0)?;
+    let input = read_tensor_f32(src1)?;
+    let mut out = vec![0.0f32; dims(dst).iter().product()];
+    let [k_len, n_ic, _, _] = dims(src0);
+    let [in_len, _, _, _] = dims(src1);
+    let [out_len, n_oc, _, _] = dims(dst);
+
+    parallel_rows(backend, &mut out, out_len, |row, dst_row| {
+        let (oc, n) = (row % n_oc, row / n_oc);
+        for ic in 0..n_ic {
+            let weights = &kernel[(oc * n_ic + ic) * k_len..][..k_len];
+            let src = &input[(n * n_ic + ic) * in_len..][..in_len];
+            for (o, acc) in dst_row.iter_mut().enumerate() {
+                for (k, weight) in weights.iter().enumerate() {
+                    if let Some(i) = tap(o, k, 0, &params, in_len) {
+                        *acc += weight * src[i];
+                    }
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &out)
+}
+
+/// dst[i * s + k * d - p, oc, n] += src0[k, oc, ic] * src1[i, ic, n]
+///
+/// Every `(oc, n)` output row is accumulated independently on the backend threads.
+pub(crate) fn conv_transpose_1d(
+    backend: &CpuBackend,
+    src0: &Tensor,
+    src1: &Tensor,
+    dst: &Tensor,
+) -> Result<()> {
+    let params = conv_params(src0, src1, dst, "cpu conv_transpose_1d")?;
+    let kernel = read_tensor_f32(src0)?;
+    let input = read_tensor_f32(src1)?;
+    let mut out = vec![0.0f32; dims(dst).iter().product()];
+    let [k_len, n_oc, n_ic, _] = dims(src0);
+    let [in_len, _, _, _] = dims(src1);
+    let [out_len, _, _, _] = dims(dst);
+
+    parallel_rows(backend, &mut out, out_len, |row, dst_row| {
+        let (oc, n) = (row % n_oc, row / n_oc);
+        for ic in 0..n_ic {
+            let weights = &kernel[(ic * n_oc + oc) * k_len..][..k_len];
+            let src = &input[(n * n_ic + ic) * in_len..][..in_len];
+            for (i, value) in src.iter().enumerate() {
+                for (k, weight) in weights.iter().enumerate() {
+                    if let Some(o) = tap(i, k, 0, &params, out_len) {
+                        dst_row[o] += weight * value;
+                    }
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &out)
+}
+
+/// dst[x * s0 + kx - p0, y * s1 + ky - p1, oc, n] += src0[kx, ky, oc, ic] * src1[x, y, ic, n]
+///
+/// Every `(oc, n)` output plane is accumulated independently on the backend threads.
+pub(crate) fn conv_transpose_2d(
+    backend: &CpuBackend,
+    src0: &Tensor,
+    src1: &Tensor,
+    dst: &Tensor,
+) -> Result<()> {
+    let params = conv_params(src0, src1, dst, "cpu conv_transpose_2d")?;
+    let kernel = read_tensor_f32(src0)?;
+    let input = read_tensor_f32(src1)?;
+    let mut out = vec![0.0f32; dims(dst).iter().product()];
+    let [kw, kh, n_oc, n_ic] = dims(src0);
+    let [iw, ih, _, _] = dims(src1);
+    let [ow, oh, _, _] = dims(dst);
+
+    parallel_rows(backend, &mut out, ow * oh, |plane, dst_plane| {
+        let (oc, n) = (plane % n_oc, plane / n_oc);
+        for ic in 0..n_ic {
+            let weights = &kernel[(ic * n_oc + oc) * kw * kh..][..kw * kh];
+            let src = &input[(n * n_ic + ic) * iw * ih..][..iw * ih];
+            for y in 0..ih {
+                for ky in 0..kh {
+                    let Some(oy) = tap(y, ky, 1, &params, oh) else {
+                        continue;
+                    };
+                    for x in 0..iw {
+                        let value = src[y * iw + x];
+                        for kx in 0..kw {
+                            if let Some(ox) = tap(x, kx, 0, &params, ow) {
+                                dst_plane[oy * ow + ox] += weights[ky * kw + kx] * value;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &out)
+}
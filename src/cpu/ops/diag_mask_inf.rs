@@ -0,0 +1,37 @@
+use super::common::{dims, parallel_rows, read_tensor_f32, write_tensor_f32};
+use crate::cpu::backend::CpuBackend;
+use crate::data_type::DataType;
+use crate::error::{Error, ErrorKind, Result};
+use crate::ops::OpParams;
+use crate::tensor::Tensor;
+
+/// dst[i0, i1, ...] = -inf if i0 > n_past + i1, src0[i0, i1, ...] otherwise.
+pub(crate) fn diag_mask_inf(backend: &CpuBackend, src0: &Tensor, dst: &Tensor) -> Result<()> {
+    for tensor in [src0, dst] {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op: "cpu diag_mask_inf",
+            }));
+        }
+    }
+
+    let n_past = match dst.op_params() {
+        Some(OpParams::DiagMask { n_past }) => n_past,
+        _ => return Err(Error::msg("diag_mask_inf tensor is missing its n_past parameter")),
+    };
+
+    let ne = dims(dst);
+    if dims(src0) != ne {
+        return Err(Error::msg("diag_mask_inf destination shape does not match its source"));
+    }
+
+    let mut values = read_tensor_f32(src0)?;
+    parallel_rows(backend, &mut values, ne[0], |row, dst_row| {
+        let i1 = row % ne[1];
+        dst_row.iter_mut().skip(n_past + i1 + 1).for_each(|value| *value = f32::NEG_INFINITY);
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &values)
+}
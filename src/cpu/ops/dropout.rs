@@ -0,0 +1,40 @@
+use super::common::{
+    dims, read_tensor_bytes, read_tensor_f32, write_tensor_bytes, write_tensor_f32,
+};
+use crate::data_type::DataType;
+use crate::error::{Error, ErrorKind, Result};
+use crate::ops::OpParams;
+use crate::rng::RngState;
+use crate::tensor::Tensor;
+
+/// dst = src0 * (u >= p) / (1 - p) with u uniform from the state in `rng`, which is then
+/// advanced in place so the next draw differs.
+pub(crate) fn dropout(src0: &Tensor, rng: &Tensor, dst: &Tensor) -> Result<()> {
+    for tensor in [src0, dst] {
+        if tensor.dtype() != DataType::F32 {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op: "cpu dropout",
+            }));
+        }
+    }
+
+    let p = match dst.op_params() {
+        Some(OpParams::Dropout { p }) => p,
+        _ => return Err(Error::msg("dropout tensor is missing its probability parameter")),
+    };
+    if dims(src0) != dims(dst) {
+        return Err(Error::msg("dropout destination shape does not match its source"));
+    }
+
+    let mut state = RngState::from_bytes(&read_tensor_bytes(rng)?)?;
+    let scale = 1.0 / (1.0 - p);
+    let mut values = read_tensor_f32(src0)?;
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = if state.uniform(i as u64) < p { 0.0 } else { *value * scale };
+    }
+    write_tensor_f32(dst, &values)?;
+
+    state.advance();
+    write_tensor_bytes(rng, &mut state.to_bytes())
+}
@@ -0,0 +1,192 @@
+use super::common::{
+    byte_offset, dims, for_each_index, read_tensor_bytes, strides, write_tensor_bytes,
+};
+use crate::data_type::f16_to_f32;
+use crate::error::{Error, Result};
+use crate::tensor::Tensor;
+use std::sync::OnceLock;
+
+/// Dot product of two rows of F16 bits of the same length, accumulated in F16.
+pub(crate) type DotF16 = fn(&[u16], &[u16]) -> f32;
+
+/// The F16 dot product of this CPU and its name, if it has F16 arithmetic: `avx512fp16` on
+/// x86-64 with AVX-512 FP16 and VL, `fp16` on aarch64.
+pub(crate) fn native_dot_f16() -> Option<(&'static str, DotF16)> {
+    static KERNEL: OnceLock<Option<(&'static str, DotF16)>> = OnceLock::new();
+    *KERNEL.get_or_init(|| {
+        #[cfg(target_arch = "x86_64")]
+        if x86::has_avx512fp16() {
+            return Some(("avx512fp16", x86::dot_f16 as DotF16));
+        }
+        #[cfg(target_arch = "aarch64")]
+        if std::arch::is_aarch64_feature_detected!("fp16") {
+            return Some(("fp16", aarch64::dot_f16 as DotF16));
+        }
+        None
+    })
+}
+
+/// The F16 elements of `tensor` as raw bits, innermost dimension first.
+pub(crate) fn gather_f16_bits(tensor: &Tensor) -> Result<Vec<u16>> {
+    let data = read_tensor_bytes(tensor)?;
+    let stride = strides(tensor);
+    let mut values = Vec::with_capacity(dims(tensor).iter().product());
+    for_each_index(dims(tensor), |i0, i1, i2, i3| {
+        let offset = byte_offset(&stride, i0, i1, i2, i3)?;
+        let bytes = data.get(offset..offset + 2).ok_or_else(|| {
+            Error::msg(format!("f16 read is out of bounds: offset={offset}, len={}", data.len()))
+        })?;
+        values.push(u16::from_ne_bytes([bytes[0], bytes[1]]));
+        Ok(())
+    })?;
+    Ok(values)
+}
+
+/// Inverse of [`gather_f16_bits`]: writes `values`, innermost dimension first, to `tensor`.
+pub(crate) fn scatter_f16_bits(tensor: &Tensor, values: &[u16]) -> Result<()> {
+    let mut data = vec![0; tensor.nbytes()];
+    let len = data.len();
+    let stride = strides(tensor);
+    let mut values = values.iter();
+    for_each_index(dims(tensor), |i0, i1, i2, i3| {
+        let offset = byte_offset(&stride, i0, i1, i2, i3)?;
+        let value = values.next().ok_or_else(|| Error::msg("f16 scatter source is too short"))?;
+        let bytes = data.get_mut(offset..offset + 2).ok_or_else(|| {
+            Error::msg(format!("f16 write is out of bounds: offset={offset}, len={len}"))
+        })?;
+        bytes.copy_from_slice(&value.to_ne_bytes());
+        Ok(())
+    })?;
+    write_tensor_bytes(tensor, &mut data)
+}
+
+/// Sums the F16 `lanes` of a vector accumulator and the `tail` products the vector loop
+/// left over, the latter in F32.
+fn reduce(lanes: &[u16], a_tail: &[u16], b_tail: &[u16]) -> f32 {
+    let tail: f32 = a_tail.iter().zip(b_tail).map(|(&a, &b)| f16_to_f32(a) * f16_to_f32(b)).sum();
+    lanes.iter().map(|&lane| f16_to_f32(lane)).sum::<f32>() + tail
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use core::arch::asm;
+    use core::arch::x86_64::__cpuid_count;
+
+    const BLOCK: usize = 16;
+
+    /// AVX-512 FP16 with 256-bit vectors. The feature is read from CPUID leaf 7, EDX bit
+    /// 23; AVX-512 F being detected means the OS saves the vector state.
+    pub(super) fn has_avx512fp16() -> bool {
+        if !std::arch::is_x86_feature_detected!("avx512f")
+            || !std::arch::is_x86_feature_detected!("avx512vl")
+        {
+            return false;
+        }
+        // SAFETY: CPUID leaf 7 exists on every CPU with AVX-512. The intrinsic is only an
+        // unsafe fn on older toolchains, such as the pinned one.
+        #[allow(unused_unsafe)]
+        let leaf = unsafe { __cpuid_count(7, 0) };
+        leaf.edx & (1 << 23) != 0
+    }
+
+    pub(super) fn dot_f16(a: &[u16], b: &[u16]) -> f32 {
+        let len = a.len().min(b.len());
+        let blocks = len / BLOCK;
+        let mut lanes = [0u16; BLOCK];
+        if blocks > 0 {
+            // SAFETY: only selected by `native_dot_f16` when the CPU has AVX-512 FP16 and
+            // VL, and both rows hold `blocks * BLOCK` values.
+            unsafe { dot_blocks(a.as_ptr(), b.as_ptr(), blocks, &mut lanes) };
+        }
+        super::reduce(&lanes, &a[blocks * BLOCK..len], &b[blocks * BLOCK..len])
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn dot_blocks(a: *const u16, b: *const u16, blocks: usize, lanes: &mut [u16; BLOCK]) {
+        // SAFETY: the caller guarantees the reads and the instructions.
+        unsafe {
+            asm!(
+                "vpxor ymm0, ymm0, ymm0",
+                "2:",
+                "vmovdqu ymm1, [{a}]",
+                "vfmadd231ph ymm0, ymm1, [{b}]",
+                "add {a}, 32",
+                "add {b}, 32",
+                "dec {n}",
+                "jnz 2b",
+                "vmovdqu [{lanes}], ymm0",
+                a = inout(reg) a => _,
+                b = inout(reg) b => _,
+                n = inout(reg) blocks => _,
+                lanes = in(reg) lanes.as_mut_ptr(),
+                out("ymm0") _, out("ymm1") _,
+                options(nostack),
+            );
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64 {
+    use core::arch::asm;
+
+    const BLOCK: usize = 8;
+
+    pub(super) fn dot_f16(a: &[u16], b: &[u16]) -> f32 {
+        let len = a.len().min(b.len());
+        let blocks = len / BLOCK;
+        let mut lanes = [0u16; BLOCK];
+        if blocks > 0 {
+            // SAFETY: only selected by `native_dot_f16` when the CPU has F16 arithmetic, and
+            // both rows hold `blocks * BLOCK` values.
+            unsafe { dot_blocks(a.as_ptr(), b.as_ptr(), blocks, &mut lanes) };
+        }
+        super::reduce(&lanes, &a[blocks * BLOCK..len], &b[blocks * BLOCK..len])
+    }
+
+    #[target_feature(enable = "fp16")]
+    unsafe fn dot_blocks(a: *const u16, b: *const u16, blocks: usize, lanes: &mut [u16; BLOCK]) {
+        // SAFETY: the caller guarantees the reads and the instructions.
+        unsafe {
+            asm!(
+                "movi {acc:v}.8h, #0",
+                "2:",
+                "ldr {x:q}, [{a}], #16",
+                "ldr {y:q}, [{b}], #16",
+                "fmla {acc:v}.8h, {x:v}.8h, {y:v}.8h",
+                "subs {n}, {n}, #1",
+                "b.ne 2b",
+                "str {acc:q}, [{lanes}]",
+                a = inout(reg) a => _,
+                b = inout(reg) b => _,
+                n = inout(reg) blocks => _,
+                lanes = in(reg) lanes.as_mut_ptr(),
+                acc = out(vreg) _,
+                x = out(vreg) _,
+                y = out(vreg) _,
+                options(nostack),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_type::f32_to_f16;
+
+    #[test]
+    fn test_native_dot_f16() {
+        let Some((name, dot)) = native_dot_f16() else {
+            return;
+        };
+        for len in [0, 1, 7, 8, 16, 17, 40, 100] {
+            let a: Vec<f32> = (0..len).map(|i| ((i * 7 % 11) as f32 - 5.0) / 4.0).collect();
+            let b: Vec<f32> = (0..len).map(|i| ((i * 3 % 5) as f32 - 2.0) / 2.0).collect();
+            let exact: f32 = a.iter().zip(&b).map(|(a, b)| a * b).sum();
+            let bits = |values: &[f32]| values.iter().map(|&v| f32_to_f16(v)).collect::<Vec<_>>();
+            // the values are exact in F16 and the sums stay small, so only the order differs
+            assert_eq!(dot(&bits(&a), &bits(&b)), exact, "{name} with {len} values");
+        }
+    }
+}
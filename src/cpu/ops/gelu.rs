@@ -0,0 +1,86 @@
+use super::common::{dims, parallel_rows, read_tensor_f32, write_tensor_f32};
+use super::f16::{gather_f16_bits, scatter_f16_bits};
+use crate::cpu::backend::CpuBackend;
+use crate::data_type::{f16_to_f32, f32_to_f16, DataType};
+use crate::error::{Error, ErrorKind, Result};
+use crate::ops::OpParams;
+use crate::tensor::{GeluMode, Tensor};
+use std::sync::OnceLock;
+
+const SQRT_2_OVER_PI: f32 = 0.797_884_6;
+
+const GELU_COEF_A: f32 = 0.044_715;
+
+fn gelu_tanh(x: f32) -> f32 {
+    0.5 * x * (1.0 + (SQRT_2_OVER_PI * x * (1.0 + GELU_COEF_A * x * x)).tanh())
+}
+
+fn gelu_exact(x: f32) -> f32 {
+    let x = f64::from(x);
+    (0.5 * x * (1.0 + erf(x * core::f64::consts::FRAC_1_SQRT_2))) as f32
+}
+
+/// Abramowitz and Stegun 7.1.26, within 1.5e-7 of erf, below what F32 results resolve.
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let poly = t
+        * (0.254_829_592
+            + t * (-0.284_496_736
+                + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    (1.0 - poly * (-x * x).exp()).copysign(x)
+}
+
+/// GELU of every F16 value, indexed by its bits, so F16 tensors take one lookup per element.
+/// Built on first use for each mode.
+fn f16_table(mode: GeluMode) -> &'static [u16] {
+    static TANH: OnceLock<Box<[u16]>> = OnceLock::new();
+    static EXACT: OnceLock<Box<[u16]>> = OnceLock::new();
+    let (table, f): (_, fn(f32) -> f32) = match mode {
+        GeluMode::Tanh => (&TANH, gelu_tanh),
+        GeluMode::Exact => (&EXACT, gelu_exact),
+    };
+    table.get_or_init(|| (0..=u16::MAX).map(|bits| f32_to_f16(f(f16_to_f32(bits)))).collect())
+}
+
+/// dst = gelu(src0), element-wise, with the formula of the op's [`GeluMode`].
+///
+/// When both tensors are F16 the values go through a table of all F16 results instead,
+/// which rounds the same as computing in F32 and storing F16. Reference mode always
+/// computes in F32, split by rows across the backend threads.
+pub(crate) fn gelu(backend: &CpuBackend, src0: &Tensor, dst: &Tensor) -> Result<()> {
+    for tensor in [src0, dst] {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op: "cpu gelu",
+            }));
+        }
+    }
+    let mode = match dst.op_params() {
+        Some(OpParams::Gelu { mode }) => mode,
+        _ => return Err(Error::msg("gelu tensor is missing its mode")),
+    };
+    if dims(src0) != dims(dst) {
+        return Err(Error::msg("gelu destination shape does not match its source"));
+    }
+
+    let f16 = src0.dtype() == DataType::F16 && dst.dtype() == DataType::F16;
+    if f16 && !backend.reference_kernels() {
+        let table = f16_table(mode);
+        let mut values = gather_f16_bits(src0)?;
+        values.iter_mut().for_each(|value| *value = table[usize::from(*value)]);
+        return scatter_f16_bits(dst, &values);
+    }
+
+    let f = match mode {
+        GeluMode::Tanh => gelu_tanh,
+        GeluMode::Exact => gelu_exact,
+    };
+    let mut values = read_tensor_f32(src0)?;
+    parallel_rows(backend, &mut values, dims(dst)[0], |_, row| {
+        row.iter_mut().for_each(|value| *value = f(*value));
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &values)
+}
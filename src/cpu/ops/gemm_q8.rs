@@ -0,0 +1,161 @@
+use crate::quant::dot_q8;
+use std::sync::OnceLock;
+
+/// Dot product of two int8 rows of the same length, accumulated exactly in `i32`.
+pub(crate) type DotQ8 = fn(&[i8], &[i8]) -> i32;
+
+/// The fastest [`DotQ8`] of this CPU and its name: `avx512vnni` on x86-64 with AVX-512 VNNI
+/// and VL, `dotprod` on aarch64 with SDOT, else `scalar`.
+pub(crate) fn dot_q8_kernel() -> (&'static str, DotQ8) {
+    static KERNEL: OnceLock<(&'static str, DotQ8)> = OnceLock::new();
+    *KERNEL.get_or_init(|| {
+        #[cfg(target_arch = "x86_64")]
+        if std::arch::is_x86_feature_detected!("avx512vnni")
+            && std::arch::is_x86_feature_detected!("avx512vl")
+        {
+            return ("avx512vnni", x86::dot_q8_vnni);
+        }
+        #[cfg(target_arch = "aarch64")]
+        if std::arch::is_aarch64_feature_detected!("dotprod") {
+            return ("dotprod", aarch64::dot_q8_sdot);
+        }
+        ("scalar", dot_q8)
+    })
+}
+
+/// int8 GEMM with `dot`: `c[j * m + i] = a_i . b_j` for the `m` rows of `a` and the `n`
+/// rows of `b`, all `k` long.
+pub(crate) fn gemm_q8(dot: DotQ8, a: &[i8], b: &[i8], k: usize, c: &mut [i32]) {
+    let m = a.len() / k.max(1);
+    for (c_row, b_row) in c.chunks_exact_mut(m.max(1)).zip(b.chunks_exact(k.max(1))) {
+        for (c, a_row) in c_row.iter_mut().zip(a.chunks_exact(k.max(1))) {
+            *c = dot(a_row, b_row);
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use crate::quant::dot_q8;
+    use core::arch::asm;
+
+    const BLOCK: usize = 32;
+
+    pub(super) fn dot_q8_vnni(a: &[i8], b: &[i8]) -> i32 {
+        let len = a.len().min(b.len());
+        let blocks = len / BLOCK;
+        let tail = dot_q8(&a[blocks * BLOCK..len], &b[blocks * BLOCK..len]);
+        if blocks == 0 {
+            return tail;
+        }
+        // SAFETY: only selected by `dot_q8_kernel` when the CPU has AVX-512 VNNI and VL, and
+        // both rows hold `blocks * BLOCK` bytes.
+        unsafe { dot_blocks(a.as_ptr(), b.as_ptr(), blocks) + tail }
+    }
+
+    /// `vpdpbusd` multiplies unsigned by signed bytes, so `a` is offset by 128 and
+    /// `128 * sum(b)` subtracted again: `a . b = (a + 128) . b - 128 . b`.
+    #[target_feature(enable = "avx2")]
+    unsafe fn dot_blocks(a: *const i8, b: *const i8, blocks: usize) -> i32 {
+        let mut lanes = [0i32; 8];
+        // SAFETY: the caller guarantees the reads and the instructions.
+        unsafe {
+            asm!(
+                "vpxor ymm0, ymm0, ymm0",
+                "vpxor ymm3, ymm3, ymm3",
+                "mov {t:e}, 0x80808080",
+                "vmovd xmm4, {t:e}",
+                "vpbroadcastd ymm4, xmm4",
+                "2:",
+                "vmovdqu ymm1, [{a}]",
+                "vmovdqu ymm2, [{b}]",
+                "vpxor ymm1, ymm1, ymm4",
+                "vpdpbusd ymm0, ymm1, ymm2",
+                "vpdpbusd ymm3, ymm4, ymm2",
+                "add {a}, 32",
+                "add {b}, 32",
+                "dec {n}",
+                "jnz 2b",
+                "vpsubd ymm0, ymm0, ymm3",
+                "vmovdqu [{lanes}], ymm0",
+                a = inout(reg) a => _,
+                b = inout(reg) b => _,
+                n = inout(reg) blocks => _,
+                t = out(reg) _,
+                lanes = in(reg) lanes.as_mut_ptr(),
+                out("ymm0") _, out("ymm1") _, out("ymm2") _, out("ymm3") _, out("ymm4") _,
+                options(nostack),
+            );
+        }
+        lanes.iter().sum()
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64 {
+    use crate::quant::dot_q8;
+    use core::arch::asm;
+
+    const BLOCK: usize = 16;
+
+    pub(super) fn dot_q8_sdot(a: &[i8], b: &[i8]) -> i32 {
+        let len = a.len().min(b.len());
+        let blocks = len / BLOCK;
+        let tail = dot_q8(&a[blocks * BLOCK..len], &b[blocks * BLOCK..len]);
+        if blocks == 0 {
+            return tail;
+        }
+        // SAFETY: only selected by `dot_q8_kernel` when the CPU has the dot product
+        // extension, and both rows hold `blocks * BLOCK` bytes.
+        unsafe { dot_blocks(a.as_ptr(), b.as_ptr(), blocks) + tail }
+    }
+
+    #[target_feature(enable = "dotprod")]
+    unsafe fn dot_blocks(a: *const i8, b: *const i8, blocks: usize) -> i32 {
+        let sum: i32;
+        // SAFETY: the caller guarantees the reads and the instructions.
+        unsafe {
+            asm!(
+                "movi {acc:v}.4s, #0",
+                "2:",
+                "ldr {x:q}, [{a}], #16",
+                "ldr {y:q}, [{b}], #16",
+                "sdot {acc:v}.4s, {x:v}.16b, {y:v}.16b",
+                "subs {n}, {n}, #1",
+                "b.ne 2b",
+                "addv {acc:s}, {acc:v}.4s",
+                "fmov {sum:w}, {acc:s}",
+                a = inout(reg) a => _,
+                b = inout(reg) b => _,
+                n = inout(reg) blocks => _,
+                acc = out(vreg) _,
+                x = out(vreg) _,
+                y = out(vreg) _,
+                sum = out(reg) sum,
+                options(nostack),
+            );
+        }
+        sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dot_q8_kernel_matches_scalar() {
+        let (name, dot) = dot_q8_kernel();
+        for len in [0, 1, 15, 16, 31, 32, 33, 64, 100, 257] {
+            let a: Vec<i8> = (0..len).map(|i| (i * 37 % 256) as u8 as i8).collect();
+            let b: Vec<i8> = (0..len).map(|i| (i * 91 % 256 + 128) as u8 as i8).collect();
+            assert_eq!(dot(&a, &b), dot_q8(&a, &b), "{name} with {len} values");
+        }
+        let extremes = [-128i8; 64];
+        assert_eq!(dot(&extremes, &[127; 64]), -128 * 127 * 64);
+
+        let mut c = [0; 9];
+        gemm_q8(dot, &[1, 2, 3, 4, 5, 6], &[1, 0, -1, 1, 1, 1], 2, &mut c);
+        assert_eq!(c, [1, 3, 5, 1, 1, 1, 3, 7, 11]);
+    }
+}
@@ -0,0 +1,38 @@
+use super::common::{dims, read_tensor_f32, read_tensor_i32, write_tensor_f32};
+use crate::data_type::DataType;
+use crate::error::{Error, ErrorKind, Result};
+use crate::tensor::Tensor;
+
+/// dst[:, rows[i]] += src0[:, i]
+///
+/// Repeated indices accumulate, so this runs on a single thread.
+pub(crate) fn get_rows_back(src0: &Tensor, src1: &Tensor, dst: &Tensor) -> Result<()> {
+    for tensor in [src0, dst] {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op: "cpu get_rows_back",
+            }));
+        }
+    }
+
+    let [ne0, n_rows, _, _] = dims(dst);
+    let grad = read_tensor_f32(src0)?;
+    let rows = read_tensor_i32(src1)?;
+    if dims(src0)[0] != ne0 || grad.len() != rows.len() * ne0 {
+        return Err(Error::msg("get_rows_back gradient does not match its row indices"));
+    }
+
+    let mut out = vec![0.0f32; ne0 * n_rows];
+    for (i, &row) in rows.iter().enumerate() {
+        let row = usize::try_from(row).ok().filter(|&row| row < n_rows).ok_or_else(|| {
+            Error::msg(format!("get_rows_back row index {row} is out of range 0..{n_rows}"))
+        })?;
+        let src = &grad[i * ne0..(i + 1) * ne0];
+        for (acc, value) in out[row * ne0..(row + 1) * ne0].iter_mut().zip(src) {
+            *acc += value;
+        }
+    }
+
+    write_tensor_f32(dst, &out)
+}
@@ -0,0 +1,96 @@
+use super::common::{dims, parallel_rows, read_tensor_f32, write_tensor_f32};
+use crate::cpu::backend::CpuBackend;
+use crate::data_type::DataType;
+use crate::error::{Error, ErrorKind, Result};
+use crate::ops::OpParams;
+use crate::tensor::Tensor;
+
+fn check_dtypes(tensors: &[&Tensor], op: &'static str) -> Result<()> {
+    for tensor in tensors {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op,
+            }));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the number of contiguous elements in one channel group of `dst` and `eps`.
+fn group_len(dst: &Tensor) -> Result<(usize, f32)> {
+    let (n_groups, eps) = match dst.op_params() {
+        Some(OpParams::GroupNorm { n_groups, eps }) => (n_groups, eps),
+        _ => return Err(Error::msg("group_norm tensor is missing its group parameters")),
+    };
+
+    let [ne0, ne1, ne2, _] = dims(dst);
+    if n_groups == 0 || ne2 % n_groups != 0 {
+        return Err(Error::msg(format!("{ne2} channels cannot be split into {n_groups} groups")));
+    }
+    Ok((ne0 * ne1 * (ne2 / n_groups), eps))
+}
+
+/// Mean and `1 / sqrt(var + eps)` of one group.
+fn moments(x: &[f32], eps: f32) -> (f32, f32) {
+    let n = x.len() as f32;
+    let mean = x.iter().sum::<f32>() / n;
+    let var = x.iter().map(|value| (value - mean) * (value - mean)).sum::<f32>() / n;
+    (mean, 1.0 / (var + eps).sqrt())
+}
+
+/// dst = (x - mean) / sqrt(var + eps) within every channel group of every batch.
+///
+/// Channel groups are contiguous, so each one is handled as a single row of the
+/// thread partition.
+pub(crate) fn group_norm(backend: &CpuBackend, src0: &Tensor, dst: &Tensor) -> Result<()> {
+    check_dtypes(&[src0, dst], "cpu group_norm")?;
+    if dims(src0) != dims(dst) {
+        return Err(Error::msg("group_norm destination shape does not match its source"));
+    }
+
+    let (len, eps) = group_len(dst)?;
+    let mut values = read_tensor_f32(src0)?;
+    parallel_rows(backend, &mut values, len, |_, group| {
+        let (mean, scale) = moments(group, eps);
+        group.iter_mut().for_each(|value| *value = (*value - mean) * scale);
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &values)
+}
+
+/// dx = scale * (dy - mean(dy) - y * mean(dy * y)) within every channel group, where
+/// `y` is the normalized input, `src0` is `dy` and `src1` the forward input `x`.
+pub(crate) fn group_norm_back(
+    backend: &CpuBackend,
+    src0: &Tensor,
+    src1: &Tensor,
+    dst: &Tensor,
+) -> Result<()> {
+    check_dtypes(&[src0, src1, dst], "cpu group_norm_back")?;
+    if dims(src0) != dims(dst) || dims(src1) != dims(dst) {
+        return Err(Error::msg("group_norm_back shapes do not match"));
+    }
+
+    let (len, eps) = group_len(dst)?;
+    let dy = read_tensor_f32(src0)?;
+    let x = read_tensor_f32(src1)?;
+    let mut out = vec![0.0f32; dy.len()];
+    parallel_rows(backend, &mut out, len, |group, dx| {
+        let dy = &dy[group * len..(group + 1) * len];
+        let x = &x[group * len..(group + 1) * len];
+        let (mean, scale) = moments(x, eps);
+
+        let n = len as f32;
+        let dy_mean = dy.iter().sum::<f32>() / n;
+        let dy_y_mean = dy.iter().zip(x).map(|(dy, x)| dy * (x - mean) * scale).sum::<f32>() / n;
+        for ((dx, dy), x) in dx.iter_mut().zip(dy).zip(x) {
+            let y = (x - mean) * scale;
+            *dx = scale * (dy - dy_mean - y * dy_y_mean);
+        }
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &out)
+}
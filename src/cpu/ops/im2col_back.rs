@@ -0,0 +1,71 @@
+use super::common::{dims, parallel_rows, read_tensor_f32, write_tensor_f32};
+use crate::cpu::backend::CpuBackend;
+use crate::data_type::DataType;
+use crate::error::{Error, ErrorKind, Result};
+use crate::ops::OpParams;
+use crate::tensor::Tensor;
+
+/// Folds the im2col gradient `src0` back onto the input gradient `dst`.
+///
+/// `src1` is the kernel and only provides its spatial extent. Every `(channel, batch)`
+/// plane of `dst` is summed independently, so the planes are split across the threads.
+pub(crate) fn im2col_back(
+    backend: &CpuBackend,
+    src0: &Tensor,
+    src1: &Tensor,
+    dst: &Tensor,
+) -> Result<()> {
+    if !matches!(src0.dtype(), DataType::F32 | DataType::F16) || dst.dtype() != DataType::F32 {
+        let dtype = if dst.dtype() != DataType::F32 { dst.dtype() } else { src0.dtype() };
+        return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+            dtype,
+            op: "cpu im2col_back",
+        }));
+    }
+
+    let params = match dst.op_params() {
+        Some(OpParams::Im2Col(params)) => params,
+        _ => return Err(Error::msg("im2col_back tensor is missing its im2col parameters")),
+    };
+
+    let kernel = dims(src1);
+    let input = dims(dst);
+    let (kw, kh) = if params.is_2d { (kernel[0], kernel[1]) } else { (kernel[0], 1) };
+    let [iw, ih, ic, n] = if params.is_2d { input } else { [input[0], 1, input[1], input[2]] };
+    let ow = params.output_len(0, iw, kw)?;
+    let oh = if params.is_2d { params.output_len(1, ih, kh)? } else { 1 };
+
+    let cols = read_tensor_f32(src0)?;
+    let col_len = ic * kh * kw;
+    if cols.len() != col_len * ow * oh * n {
+        return Err(Error::msg("im2col_back gradient does not match the input shape"));
+    }
+
+    let [s0, s1] = params.stride;
+    let [p0, p1] = params.padding;
+    let [d0, d1] = params.dilation;
+    let mut out = vec![0.0f32; iw * ih * ic * n];
+    parallel_rows(backend, &mut out, iw * ih, |plane, dst_plane| {
+        let (c, batch) = (plane % ic, plane / ic);
+        for oy in 0..oh {
+            for ky in 0..kh {
+                let Some(iy) = (oy * s1 + ky * d1).checked_sub(p1).filter(|&iy| iy < ih) else {
+                    continue;
+                };
+                for ox in 0..ow {
+                    let col_start = ((batch * oh + oy) * ow + ox) * col_len + (c * kh + ky) * kw;
+                    for kx in 0..kw {
+                        let Some(ix) = (ox * s0 + kx * d0).checked_sub(p0).filter(|&ix| ix < iw)
+                        else {
+                            continue;
+                        };
+                        dst_plane[iy * iw + ix] += cols[col_start + kx];
+                    }
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &out)
+}
@@ -0,0 +1,67 @@
+use super::common::{dims, parallel_rows, read_tensor_f32, write_tensor_f32};
+use crate::cpu::backend::CpuBackend;
+use crate::data_type::DataType;
+use crate::error::{Error, ErrorKind, Result};
+use crate::ops::OpParams;
+use crate::tensor::Tensor;
+
+fn check_dtypes(tensors: &[&Tensor], op: &'static str) -> Result<()> {
+    for tensor in tensors {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op,
+            }));
+        }
+    }
+    Ok(())
+}
+
+/// dst = f(src0), element-wise, with rows split across the backend threads.
+pub(crate) fn map_unary(backend: &CpuBackend, src0: &Tensor, dst: &Tensor) -> Result<()> {
+    check_dtypes(&[src0, dst], "cpu map_unary")?;
+    let f = match dst.op_params() {
+        Some(OpParams::MapUnary(f)) => f,
+        _ => return Err(Error::msg("map_unary tensor is missing its closure")),
+    };
+    if dims(src0) != dims(dst) {
+        return Err(Error::msg("map_unary destination shape does not match its source"));
+    }
+
+    let mut values = read_tensor_f32(src0)?;
+    parallel_rows(backend, &mut values, dims(dst)[0], |_, row| {
+        row.iter_mut().for_each(|value| *value = f(*value));
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &values)
+}
+
+/// dst = f(src0, src1), element-wise, with rows split across the backend threads.
+pub(crate) fn map_binary(
+    backend: &CpuBackend,
+    src0: &Tensor,
+    src1: &Tensor,
+    dst: &Tensor,
+) -> Result<()> {
+    check_dtypes(&[src0, src1, dst], "cpu map_binary")?;
+    let f = match dst.op_params() {
+        Some(OpParams::MapBinary(f)) => f,
+        _ => return Err(Error::msg("map_binary tensor is missing its closure")),
+    };
+    if dims(src0) != dims(dst) || dims(src1) != dims(dst) {
+        return Err(Error::msg("map_binary shapes do not match"));
+    }
+
+    let mut values = read_tensor_f32(src0)?;
+    let rhs = read_tensor_f32(src1)?;
+    let ne0 = dims(dst)[0];
+    parallel_rows(backend, &mut values, ne0, |row, dst_row| {
+        for (value, rhs) in dst_row.iter_mut().zip(&rhs[row * ne0..]) {
+            *value = f(*value, *rhs);
+        }
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &values)
+}
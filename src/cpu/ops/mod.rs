@@ -0,0 +1,26 @@
+pub(super) mod acc;
+pub(super) mod attention;
+pub(super) mod binary;
+pub(super) mod common;
+pub(super) mod cont;
+pub(super) mod conv;
+pub(super) mod diag_mask_inf;
+pub(super) mod dropout;
+pub(super) mod f16;
+pub(super) mod gelu;
+pub(super) mod gemm_q8;
+pub(super) mod get_rows_back;
+pub(super) mod group_norm;
+pub(super) mod im2col_back;
+pub(super) mod map;
+pub(super) mod mul_mat;
+pub(super) mod norm;
+pub(super) mod out_prod;
+pub(super) mod relu;
+pub(super) mod repeat;
+pub(super) mod rope;
+pub(super) mod silu;
+pub(super) mod soft_max;
+pub(super) mod soft_max_back;
+pub(super) mod sum_rows;
+pub(super) mod upscale;
@@ -0,0 +1,387 @@
+use super::common::{
+    byte_offset, dims, parallel_chunks, parallel_rows, read_tensor_bytes, read_tensor_f32,
+    read_tensor_i32, strides, write_tensor_f32,
+};
+use super::f16::{gather_f16_bits, native_dot_f16, DotF16};
+use super::gemm_q8::{gemm_q8, DotQ8};
+use crate::cpu::backend::{CpuBackend, Precision};
+use crate::data_type::{
+    custom_data_type, f16_to_f32, get_row_size, get_type_size, CustomDataType, DataType,
+};
+use crate::error::{Error, ErrorKind, Result};
+use crate::ops::OpParams;
+use crate::quant::{quantize_rows_q8, Q8_MAX};
+use crate::tensor::Tensor;
+use std::ops::Range;
+
+/// Rows per panel of the interleaved weights [`mul_mat`] streams, see [`crate::repack`]:
+/// eight F32 accumulators fill one AVX register.
+pub(crate) const PANEL_ROWS: usize = 8;
+
+/// dst[m, n, i2, i3] = sum_k src0[k, m, i2', i3'] * src1[k, n, i2, i3]
+///
+/// `src0` is broadcast along the batch dimensions of `src1`. Both sources are read in place
+/// through their byte strides, so transposed and other strided views need neither a `cont`
+/// node in the graph nor a contiguous copy. The output rows `(n, i2, i3)` are split into
+/// blocks of at most `nc` rows for the backend threads, and every block is accumulated over
+/// `kc x mc` tiles of `src0` (see [`GemmBlocking`](crate::cpu::autotune::GemmBlocking)) so
+/// the tile stays in cache while the rows of the block reuse it. Reference mode computes
+/// every output element with a single dot product instead. An interleaved `src0` (see
+/// [`Tensor::interleaved_rows`]) is streamed panel by panel in either mode. Weights of a
+/// registered data type go through [`mul_mat_custom`].
+pub(crate) fn mul_mat(
+    backend: &CpuBackend,
+    src0: &Tensor,
+    src1: &Tensor,
+    dst: &Tensor,
+) -> Result<()> {
+    check_mul_mat(src0, src1, dst)?;
+    if let Some(custom) = custom_data_type(src0.dtype()) {
+        return mul_mat_custom(backend, src0, src1, dst, custom);
+    }
+    if let Some(panel) = src0.interleaved_rows() {
+        return mul_mat_interleaved(backend, src0, src1, dst, panel);
+    }
+
+    let [ne00, _, ne02, ne03] = dims(src0);
+    let [_, _, ne12, ne13] = dims(src1);
+    let [ne0, ne1, ne2, ne3] = dims(dst);
+    let a = StridedOperand::new(src0)?;
+    let b = StridedOperand::new(src1)?;
+    backend.with_work(ne0 * ne1 * ne2 * ne3, |out| {
+        let (r2, r3) = (ne12 / ne02, ne13 / ne03);
+        let k = ne00;
+        // byte offsets of the `src0` matrix and the `src1` row an output row reads
+        let offsets_of = |row: usize| {
+            let n = row % ne1;
+            let i2 = (row / ne1) % ne2;
+            let i3 = row / (ne1 * ne2);
+            let lhs = (i2 / r2) * a.nb[2] + (i3 / r3) * a.nb[3];
+            (lhs, n * b.nb[1] + i2 * b.nb[2] + i3 * b.nb[3])
+        };
+
+        if backend.reference_kernels() {
+            for (row, dst_row) in out.chunks_mut(ne0).enumerate() {
+                let (lhs, rhs) = offsets_of(row);
+                for (m, value) in dst_row.iter_mut().enumerate() {
+                    *value = a.dot(lhs + m * a.nb[1], &b, rhs, 0..k);
+                }
+            }
+            return write_tensor_f32(dst, out);
+        }
+
+        let blocking = backend.gemm_blocking();
+        let pool = backend.threadpool();
+        let partition =
+            backend.row_partition(ne1 * ne2 * ne3, ne0 * size_of::<f32>()).max_rows(blocking.nc);
+        parallel_chunks(pool, out, ne0, partition, |rows, dst| {
+            for k0 in (0..k).step_by(blocking.kc) {
+                let ks = k0..(k0 + blocking.kc).min(k);
+                for m0 in (0..ne0).step_by(blocking.mc) {
+                    let ms = m0..(m0 + blocking.mc).min(ne0);
+                    for (row, dst_row) in rows.clone().zip(dst.chunks_mut(ne0)) {
+                        let (lhs, rhs) = offsets_of(row);
+                        for m in ms.clone() {
+                            dst_row[m] += a.dot(lhs + m * a.nb[1], &b, rhs, ks.clone());
+                        }
+                    }
+                }
+            }
+            Ok(())
+        })?;
+
+        write_tensor_f32(dst, out)
+    })
+}
+
+/// An F32 or F16 source of [`mul_mat`] read in place: its bytes as laid out in its buffer,
+/// indexed through its byte strides `nb`.
+struct StridedOperand {
+    data: Vec<u8>,
+    nb: [usize; 4],
+    load: fn(&[u8]) -> f32,
+}
+
+impl StridedOperand {
+    fn new(tensor: &Tensor) -> Result<Self> {
+        let load: fn(&[u8]) -> f32 = match tensor.dtype() {
+            DataType::F32 => |bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            DataType::F16 => |bytes| f16_to_f32(u16::from_ne_bytes([bytes[0], bytes[1]])),
+            dtype => {
+                return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                    dtype,
+                    op: "cpu mul_mat",
+                }))
+            }
+        };
+        let data = read_tensor_bytes(tensor)?;
+        let nb = strides(tensor);
+        // the last element bounds every offset the inner loops compute
+        if let [Some(i0), Some(i1), Some(i2), Some(i3)] = dims(tensor).map(|ne| ne.checked_sub(1)) {
+            let end = byte_offset(&nb, i0, i1, i2, i3)? + get_type_size(tensor.dtype());
+            if end > data.len() {
+                return Err(Error::msg(format!(
+                    "mul_mat source is out of bounds: end={end}, len={}",
+                    data.len()
+                )));
+            }
+        }
+        Ok(Self { data, nb, load })
+    }
+
+    /// Dot product over `ks` of the row at byte offset `row` with the row at `other_row` of
+    /// `other`.
+    fn dot(&self, row: usize, other: &Self, other_row: usize, ks: Range<usize>) -> f32 {
+        ks.map(|k| {
+            let lhs = (self.load)(&self.data[row + k * self.nb[0]..]);
+            lhs * (other.load)(&other.data[other_row + k * other.nb[0]..])
+        })
+        .sum()
+    }
+}
+
+/// Checks the types and shapes of the sources and destination of [`mul_mat`].
+fn check_mul_mat(src0: &Tensor, src1: &Tensor, dst: &Tensor) -> Result<()> {
+    let custom = custom_data_type(src0.dtype()).is_some();
+    let floats: &[&Tensor] = if custom { &[src1, dst] } else { &[src0, src1, dst] };
+    for &tensor in floats {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op: "cpu mul_mat",
+            }));
+        }
+    }
+
+    let [ne00, ne01, ne02, ne03] = dims(src0);
+    let [ne10, ne11, ne12, ne13] = dims(src1);
+    let [ne0, ne1, ne2, ne3] = dims(dst);
+    if ne00 != ne10 || ne0 != ne01 || ne1 != ne11 || ne2 != ne12 || ne3 != ne13 {
+        return Err(Error::msg("mul_mat destination shape does not match its sources"));
+    }
+    if ne12 % ne02 != 0 || ne13 % ne03 != 0 {
+        return Err(Error::msg("mul_mat batch dimensions cannot be broadcast"));
+    }
+    Ok(())
+}
+
+/// [`mul_mat`] of two F16 sources with the F16 dot product of the CPU, see
+/// [`native_dot_f16`], under [`Precision::NativeF16`]. Anything else, and CPUs without F16
+/// arithmetic, go through [`mul_mat`].
+pub(crate) fn mul_mat_native_f16(
+    backend: &CpuBackend,
+    src0: &Tensor,
+    src1: &Tensor,
+    dst: &Tensor,
+) -> Result<()> {
+    let native = native_dot_f16().filter(|_| {
+        backend.precision() == Precision::NativeF16
+            && src0.dtype() == DataType::F16
+            && src1.dtype() == DataType::F16
+            && src0.interleaved_rows().is_none()
+    });
+    match native {
+        Some((_, dot)) => {
+            check_mul_mat(src0, src1, dst)?;
+            mul_mat_f16(backend, src0, src1, dst, dot)
+        }
+        None => mul_mat(backend, src0, src1, dst),
+    }
+}
+
+/// [`mul_mat`] of a `src0` interleaved in panels of `panel` rows: every output row
+/// accumulates `panel` values at a time, reading each panel once with unit stride.
+fn mul_mat_interleaved(
+    backend: &CpuBackend,
+    src0: &Tensor,
+    src1: &Tensor,
+    dst: &Tensor,
+    panel: usize,
+) -> Result<()> {
+    let [k, ne01, ne02, ne03] = dims(src0);
+    let [ne0, ne1, ne2, ne3] = dims(dst);
+    if panel == 0 || ne01 % panel != 0 || !src0.is_contiguous() {
+        return Err(Error::msg(format!(
+            "mul_mat reads interleaved weights only contiguous and in whole panels of {panel} \
+             rows, got {ne01} rows"
+        )));
+    }
+    let (r2, r3) = (ne2 / ne02, ne3 / ne03);
+    // the weights are contiguous, so they are read in memory order, panel by panel
+    let a = read_tensor_f32(src0)?;
+    let b = read_tensor_f32(src1)?;
+
+    backend.with_work(ne0 * ne1 * ne2 * ne3, |out| {
+        parallel_rows(backend, out, ne0, |row, dst_row| {
+            let i2 = (row / ne1) % ne2;
+            let i3 = row / (ne1 * ne2);
+            let lhs = &a[((i3 / r3) * ne02 + i2 / r2) * ne01 * k..][..ne01 * k];
+            let rhs = &b[row * k..][..k];
+            for (acc, lhs) in dst_row.chunks_exact_mut(panel).zip(lhs.chunks_exact(panel * k)) {
+                for (x, column) in rhs.iter().zip(lhs.chunks_exact(panel)) {
+                    for (acc, w) in acc.iter_mut().zip(column) {
+                        *acc += x * w;
+                    }
+                }
+            }
+            Ok(())
+        })?;
+
+        write_tensor_f32(dst, out)
+    })
+}
+
+/// [`mul_mat`] of contiguous weights of a registered data type, one
+/// [`vec_dot`](CustomDataType::vec_dot) per output element. Types without one, and
+/// reference mode, convert every weight row with `to_f32` and take an F32 dot product.
+fn mul_mat_custom(
+    backend: &CpuBackend,
+    src0: &Tensor,
+    src1: &Tensor,
+    dst: &Tensor,
+    custom: &CustomDataType,
+) -> Result<()> {
+    let [k, ne01, ne02, ne03] = dims(src0);
+    let [ne0, ne1, ne2, ne3] = dims(dst);
+    if !src0.is_contiguous() {
+        return Err(Error::msg(format!("mul_mat reads {} weights only contiguous", custom.name)));
+    }
+    let row_size = get_row_size(src0.dtype(), k)?;
+    let vec_dot = custom.vec_dot.filter(|_| !backend.reference_kernels());
+    let (r2, r3) = (ne2 / ne02, ne3 / ne03);
+    let a = read_tensor_bytes(src0)?;
+    let b = read_tensor_f32(src1)?;
+
+    backend.with_work(ne0 * ne1 * ne2 * ne3, |out| {
+        parallel_rows(backend, out, ne0, |row, dst_row| {
+            let i2 = (row / ne1) % ne2;
+            let i3 = row / (ne1 * ne2);
+            let lhs = &a[((i3 / r3) * ne02 + i2 / r2) * ne01 * row_size..][..ne01 * row_size];
+            let rhs = &b[row * k..][..k];
+            let mut weights = vec![0.0f32; if vec_dot.is_some() { 0 } else { k }];
+            for (value, lhs) in dst_row.iter_mut().zip(lhs.chunks_exact(row_size)) {
+                *value = match vec_dot {
+                    Some(dot) => dot(lhs, rhs),
+                    None => {
+                        (custom.to_f32)(lhs, &mut weights);
+                        weights.iter().zip(rhs).map(|(a, b)| a * b).sum()
+                    }
+                };
+            }
+            Ok(())
+        })?;
+
+        write_tensor_f32(dst, out)
+    })
+}
+
+/// [`mul_mat`] of two F16 sources in F16 arithmetic, one `dot` per output element.
+fn mul_mat_f16(
+    backend: &CpuBackend,
+    src0: &Tensor,
+    src1: &Tensor,
+    dst: &Tensor,
+    dot: DotF16,
+) -> Result<()> {
+    let [k, ne01, ne02, ne03] = dims(src0);
+    let [ne0, ne1, ne2, ne3] = dims(dst);
+    let (r2, r3) = (ne2 / ne02, ne3 / ne03);
+    let a = gather_f16_bits(src0)?;
+    let b = gather_f16_bits(src1)?;
+
+    backend.with_work(ne0 * ne1 * ne2 * ne3, |out| {
+        parallel_rows(backend, out, ne0, |row, dst_row| {
+            let i2 = (row / ne1) % ne2;
+            let i3 = row / (ne1 * ne2);
+            let lhs = &a[((i3 / r3) * ne02 + i2 / r2) * ne01 * k..][..ne01 * k];
+            let rhs = &b[row * k..][..k];
+            for (value, lhs) in dst_row.iter_mut().zip(lhs.chunks_exact(k)) {
+                *value = dot(lhs, rhs);
+            }
+            Ok(())
+        })?;
+
+        write_tensor_f32(dst, out)
+    })
+}
+
+/// dst[m, n, i2, i3] = sw[m, i2', i3'] * sx[n, i2, i3] * sum_k w[k, m, i2', i3'] * x[k, n, i2, i3]
+///
+/// with the I8 weights `w = src0` and their row scales `sw`, and `x`, `sx` the rows of
+/// `src1` quantized by [`quantize_rows_q8`]. Batches broadcast like [`mul_mat`]; every
+/// output row is one int8 GEMM with `dot` on the backend threads, see [`gemm_q8`]. An I8
+/// `dst` is requantized with the scale of its `OpParams::Requantize`.
+pub(crate) fn mul_mat_rowwise(
+    backend: &CpuBackend,
+    src0: &Tensor,
+    src1: &Tensor,
+    scales: &Tensor,
+    dst: &Tensor,
+    dot: DotQ8,
+) -> Result<()> {
+    if src0.dtype() != DataType::I8 {
+        return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+            dtype: src0.dtype(),
+            op: "cpu mul_mat_rowwise weights",
+        }));
+    }
+    for tensor in [src1, scales] {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op: "cpu mul_mat_rowwise",
+            }));
+        }
+    }
+    let out_scale = match (dst.dtype(), dst.op_params()) {
+        (DataType::F32 | DataType::F16, _) => None,
+        (DataType::I8, Some(OpParams::Requantize { out_scale })) => Some(out_scale),
+        (dtype, _) => {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype,
+                op: "cpu mul_mat_rowwise output",
+            }));
+        }
+    };
+
+    let [k, ne01, ne02, ne03] = dims(src0);
+    let [ne10, ne11, ne12, ne13] = dims(src1);
+    let [ne0, ne1, ne2, ne3] = dims(dst);
+    if k != ne10 || ne0 != ne01 || ne1 != ne11 || ne2 != ne12 || ne3 != ne13 {
+        return Err(Error::msg("mul_mat_rowwise destination shape does not match its sources"));
+    }
+    if ne12 % ne02 != 0 || ne13 % ne03 != 0 {
+        return Err(Error::msg("mul_mat_rowwise batch dimensions cannot be broadcast"));
+    }
+    if dims(scales) != [ne01, ne02, ne03, 1] {
+        return Err(Error::msg("mul_mat_rowwise needs one scale per weight row"));
+    }
+
+    let weights: Vec<i8> = read_tensor_i32(src0)?.into_iter().map(|q| q as i8).collect();
+    let weight_scales = read_tensor_f32(scales)?;
+    let x = quantize_rows_q8(&read_tensor_f32(src1)?, k)?;
+
+    let (r2, r3) = (ne12 / ne02, ne13 / ne03);
+    backend.with_work(ne0 * ne1 * ne2 * ne3, |out| {
+        parallel_rows(backend, out, ne0, |row, dst_row| {
+            let i2 = (row / ne1) % ne2;
+            let i3 = row / (ne1 * ne2);
+            let matrix = (i3 / r3) * ne02 + i2 / r2;
+            let lhs = &weights[matrix * ne01 * k..][..ne01 * k];
+            let lhs_scales = &weight_scales[matrix * ne01..][..ne01];
+            let rhs = &x.values[row * k..][..k];
+            let rhs_scale = x.scales[row];
+            let mut acc = vec![0; ne0];
+            gemm_q8(dot, lhs, rhs, k, &mut acc);
+            for ((value, acc), lhs_scale) in dst_row.iter_mut().zip(acc).zip(lhs_scales) {
+                *value = lhs_scale * rhs_scale * acc as f32;
+                if let Some(out_scale) = out_scale {
+                    *value = (*value / out_scale).round().clamp(-Q8_MAX, Q8_MAX);
+                }
+            }
+            Ok(())
+        })?;
+
+        write_tensor_f32(dst, out)
+    })
+}
@@ -0,0 +1,112 @@
+use super::common::{dims, parallel_rows, read_tensor_f32, write_tensor_f32};
+use crate::cpu::backend::CpuBackend;
+use crate::data_type::DataType;
+use crate::error::{Error, ErrorKind, Result};
+use crate::ops::OpParams;
+use crate::tensor::Tensor;
+
+/// Independent accumulators of the row sums, so the compiler can keep them in one vector
+/// register instead of adding every element to a single scalar in order.
+const LANES: usize = 8;
+
+fn check_norm(src0: &Tensor, dst: &Tensor, op: &'static str) -> Result<f32> {
+    for tensor in [src0, dst] {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op,
+            }));
+        }
+    }
+    if dims(src0) != dims(dst) {
+        return Err(Error::msg(format!("{op} destination shape does not match its source")));
+    }
+    match dst.op_params() {
+        Some(OpParams::Norm { eps }) => Ok(eps),
+        _ => Err(Error::msg(format!("{op} tensor is missing its epsilon"))),
+    }
+}
+
+/// Sum of `f` of the elements of `x`, accumulated in [`LANES`] lanes.
+fn lane_sum(x: &[f32], f: impl Fn(f32) -> f32) -> f32 {
+    let mut lanes = [0.0f32; LANES];
+    let chunks = x.chunks_exact(LANES);
+    let tail: f32 = chunks.remainder().iter().map(|&value| f(value)).sum();
+    for chunk in chunks {
+        for (lane, &value) in lanes.iter_mut().zip(chunk) {
+            *lane += f(value);
+        }
+    }
+    lanes.iter().sum::<f32>() + tail
+}
+
+/// dst = (x - mean(x)) / sqrt(var(x) + eps) * weight + bias for every row along
+/// dimension 0, where `weight` and `bias` are rows as long as those of `src0`.
+///
+/// Reference mode sums the elements of a row in order instead of in [`LANES`] lanes.
+pub(crate) fn layer_norm(
+    backend: &CpuBackend,
+    src0: &Tensor,
+    weight: &Tensor,
+    bias: &Tensor,
+    dst: &Tensor,
+) -> Result<()> {
+    let eps = check_norm(src0, dst, "cpu layer_norm")?;
+    let ne0 = dims(dst)[0];
+    for tensor in [weight, bias] {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op: "cpu layer_norm",
+            }));
+        }
+        if dims(tensor) != [ne0, 1, 1, 1] {
+            return Err(Error::msg("layer_norm weight and bias must be single rows"));
+        }
+    }
+    let reference = backend.reference_kernels();
+
+    let weight = read_tensor_f32(weight)?;
+    let bias = read_tensor_f32(bias)?;
+    let mut values = read_tensor_f32(src0)?;
+    parallel_rows(backend, &mut values, ne0, |_, row| {
+        let n = ne0 as f32;
+        let mean = if reference { row.iter().sum() } else { lane_sum(row, |value| value) } / n;
+        let square = |value: f32| (value - mean) * (value - mean);
+        let var = if reference {
+            row.iter().map(|&value| square(value)).sum()
+        } else {
+            lane_sum(row, square)
+        } / n;
+        let scale = 1.0 / (var + eps).sqrt();
+        for ((value, weight), bias) in row.iter_mut().zip(&weight).zip(&bias) {
+            *value = (*value - mean) * scale * weight + bias;
+        }
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &values)
+}
+
+/// dst = x / sqrt(mean(x^2) + eps) for every row along dimension 0.
+///
+/// Reference mode sums the squares of a row in order instead of in [`LANES`] lanes.
+pub(crate) fn rms_norm(backend: &CpuBackend, src0: &Tensor, dst: &Tensor) -> Result<()> {
+    let eps = check_norm(src0, dst, "cpu rms_norm")?;
+    let reference = backend.reference_kernels();
+
+    let mut values = read_tensor_f32(src0)?;
+    let ne0 = dims(dst)[0];
+    parallel_rows(backend, &mut values, ne0, |_, row| {
+        let sum = if reference {
+            row.iter().map(|value| value * value).sum()
+        } else {
+            lane_sum(row, |value| value * value)
+        };
+        let scale = 1.0 / (sum / ne0 as f32 + eps).sqrt();
+        row.iter_mut().for_each(|value| *value *= scale);
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &values)
+}
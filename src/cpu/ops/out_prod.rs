@@ -0,0 +1,55 @@
+use super::common::{dims, parallel_rows, read_tensor_f32, write_tensor_f32};
+use crate::cpu::backend::CpuBackend;
+use crate::data_type::DataType;
+use crate::error::{Error, ErrorKind, Result};
+use crate::tensor::Tensor;
+
+/// dst[i0, i1, i2, i3] = sum_k src0[i0, k, i2', i3'] * src1[i1, k, i2, i3]
+///
+/// The rows of `dst` are accumulated over `k` and split across the backend threads.
+/// `src0` is broadcast along dimensions 2 and 3.
+pub(crate) fn out_prod(
+    backend: &CpuBackend,
+    src0: &Tensor,
+    src1: &Tensor,
+    dst: &Tensor,
+) -> Result<()> {
+    for tensor in [src0, src1, dst] {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op: "cpu out_prod",
+            }));
+        }
+    }
+
+    let [ne00, ne01, ne02, ne03] = dims(src0);
+    let [ne10, _, ne12, ne13] = dims(src1);
+    let [ne0, ne1, ne2, ne3] = dims(dst);
+    if ne0 != ne00 || ne1 != ne10 || ne2 != ne12 || ne3 != ne13 {
+        return Err(Error::msg("out_prod destination shape does not match its sources"));
+    }
+
+    let a = read_tensor_f32(src0)?;
+    let b = read_tensor_f32(src1)?;
+    let mut out = vec![0.0f32; ne0 * ne1 * ne2 * ne3];
+
+    let (r2, r3) = (ne2 / ne02, ne3 / ne03);
+    parallel_rows(backend, &mut out, ne0, |row, dst_row| {
+        let i1 = row % ne1;
+        let i2 = (row / ne1) % ne2;
+        let i3 = row / (ne1 * ne2);
+        let (i02, i03) = (i2 / r2, i3 / r3);
+
+        for k in 0..ne01 {
+            let rhs = b[((i3 * ne12 + i2) * ne01 + k) * ne10 + i1];
+            let lhs_start = ((i03 * ne02 + i02) * ne01 + k) * ne00;
+            for (acc, lhs) in dst_row.iter_mut().zip(&a[lhs_start..lhs_start + ne00]) {
+                *acc += lhs * rhs;
+            }
+        }
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &out)
+}
@@ -0,0 +1,43 @@
+use super::common::{dims, parallel_rows, read_tensor_f32, write_tensor_f32};
+use crate::cpu::backend::CpuBackend;
+use crate::data_type::{DataType, TensorOpType};
+use crate::error::{Error, ErrorKind, Result};
+use crate::ops::OpParams;
+use crate::tensor::Tensor;
+
+/// dst = relu(src0), or leaky_relu(src0) with the slope of the op params, element-wise.
+/// The rows of `dst` are split across the backend threads.
+pub(crate) fn relu(backend: &CpuBackend, src0: &Tensor, dst: &Tensor) -> Result<()> {
+    let (slope, name) = match (dst.op_type(), dst.op_params()) {
+        (TensorOpType::TensorOpRelu, _) => (0.0, "cpu relu"),
+        (TensorOpType::TensorOpLeakyRelu, Some(OpParams::LeakyRelu { slope })) => {
+            (slope, "cpu leaky_relu")
+        }
+        (TensorOpType::TensorOpLeakyRelu, _) => {
+            return Err(Error::msg("leaky_relu tensor is missing its slope"))
+        }
+        (op, _) => return Err(Error::msg(format!("{op:?} is not a relu op"))),
+    };
+    for tensor in [src0, dst] {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op: name,
+            }));
+        }
+    }
+    if dims(src0) != dims(dst) {
+        return Err(Error::msg(format!("{name} destination shape does not match its source")));
+    }
+
+    let mut values = read_tensor_f32(src0)?;
+    parallel_rows(backend, &mut values, dims(dst)[0], |_, row| {
+        for value in row.iter_mut().filter(|value| **value < 0.0) {
+            // Relu stores +0.0 rather than the -0.0 of scaling by a zero slope.
+            *value = if slope == 0.0 { 0.0 } else { *value * slope };
+        }
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &values)
+}
@@ -0,0 +1,73 @@
+use super::common::{dims, read_tensor_f32, write_tensor_f32};
+use crate::data_type::DataType;
+use crate::error::{Error, ErrorKind, Result};
+use crate::tensor::Tensor;
+
+/// dst[i] = src0[i % ne(src0)] along every dimension.
+pub(crate) fn repeat(src0: &Tensor, dst: &Tensor) -> Result<()> {
+    check_dtypes(src0, dst, "cpu repeat")?;
+    let (ne, dst_ne) = (dims(src0), dims(dst));
+    check_tiling(ne, dst_ne)?;
+
+    let src = read_tensor_f32(src0)?;
+    let mut out = Vec::with_capacity(dst_ne.iter().product());
+    for i3 in 0..dst_ne[3] {
+        for i2 in 0..dst_ne[2] {
+            for i1 in 0..dst_ne[1] {
+                let row = flat_index(ne, 0, i1 % ne[1], i2 % ne[2], i3 % ne[3]);
+                let row = &src[row..row + ne[0]];
+                out.extend((0..dst_ne[0]).map(|i0| row[i0 % ne[0]]));
+            }
+        }
+    }
+
+    write_tensor_f32(dst, &out)
+}
+
+/// dst[j] = sum of src0[i] over all i with i % ne(dst) == j along every dimension.
+pub(crate) fn repeat_back(src0: &Tensor, dst: &Tensor) -> Result<()> {
+    check_dtypes(src0, dst, "cpu repeat_back")?;
+    let (ne, dst_ne) = (dims(src0), dims(dst));
+    check_tiling(dst_ne, ne)?;
+
+    let grad = read_tensor_f32(src0)?;
+    let mut out = vec![0.0f32; dst_ne.iter().product()];
+    let mut values = grad.iter();
+    for i3 in 0..ne[3] {
+        for i2 in 0..ne[2] {
+            for i1 in 0..ne[1] {
+                let row = flat_index(dst_ne, 0, i1 % dst_ne[1], i2 % dst_ne[2], i3 % dst_ne[3]);
+                for (i0, value) in values.by_ref().take(ne[0]).enumerate() {
+                    out[row + i0 % dst_ne[0]] += value;
+                }
+            }
+        }
+    }
+
+    write_tensor_f32(dst, &out)
+}
+
+fn check_dtypes(src0: &Tensor, dst: &Tensor, op: &'static str) -> Result<()> {
+    for tensor in [src0, dst] {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op,
+            }));
+        }
+    }
+    Ok(())
+}
+
+fn check_tiling(small: [usize; 4], large: [usize; 4]) -> Result<()> {
+    if small.iter().zip(&large).any(|(&small, &large)| small == 0 || large % small != 0) {
+        return Err(Error::msg(format!(
+            "repeat of {small:?} into {large:?} is not a whole tiling"
+        )));
+    }
+    Ok(())
+}
+
+fn flat_index(ne: [usize; 4], i0: usize, i1: usize, i2: usize, i3: usize) -> usize {
+    ((i3 * ne[2] + i2) * ne[1] + i1) * ne[0] + i0
+}
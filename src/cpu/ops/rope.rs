@@ -0,0 +1,82 @@
+use super::common::{dims, read_tensor_f32, read_tensor_i32, store};
+use crate::data_type::{get_type_size, DataType};
+use crate::error::{Error, ErrorKind, Result};
+use crate::model_meta::RopeParams;
+use crate::ops::OpParams;
+use crate::tensor::Tensor;
+use core::iter::repeat_n;
+
+/// Rotates the pairs `(x[2i], x[2i + 1])` of the first `n_dims` values of `row` by
+/// `position * freq_scale * freq_base^(-2i / n_dims)`.
+pub(crate) fn rope_row(row: &mut [f32], position: f32, rope: &RopeParams) {
+    let n_dims = rope.n_dims.unwrap_or(row.len()).min(row.len());
+    for (i, pair) in row[..n_dims].chunks_exact_mut(2).enumerate() {
+        let theta =
+            position * rope.freq_scale * rope.freq_base.powf(-2.0 * i as f32 / n_dims as f32);
+        let (sin, cos) = theta.sin_cos();
+        let (x0, x1) = (pair[0], pair[1]);
+        pair[0] = x0 * cos - x1 * sin;
+        pair[1] = x0 * sin + x1 * cos;
+    }
+}
+
+/// Writes the rotated keys `k` into `dst`, a view of the key cache, and the values `v` into
+/// `cache_v`, one head row per token at slot `positions[t] % n_ctx`. Only the written rows
+/// are touched.
+pub(crate) fn rope_store_kv(
+    k: &Tensor,
+    v: &Tensor,
+    positions: &Tensor,
+    cache_v: &Tensor,
+    dst: &Tensor,
+) -> Result<()> {
+    for tensor in [k, v, cache_v, dst] {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op: "cpu rope_store_kv",
+            }));
+        }
+    }
+
+    let rope = match dst.op_params() {
+        Some(OpParams::Rope(rope)) => rope,
+        _ => return Err(Error::msg("rope_store_kv tensor is missing its rope parameters")),
+    };
+
+    let [head_dim, n_kv_heads, n_tokens, _] = dims(k);
+    let n_ctx = dims(dst)[1];
+    let positions = read_tensor_i32(positions)?;
+    if positions.len() != n_tokens || positions.iter().any(|&position| position < 0) {
+        return Err(Error::msg("rope_store_kv positions must be one non-negative per token"));
+    }
+
+    let mut keys = read_tensor_f32(k)?;
+    // every head of a token shares its position
+    let head_positions = positions.iter().flat_map(|&position| repeat_n(position, n_kv_heads));
+    for (row, position) in keys.chunks_exact_mut(head_dim).zip(head_positions) {
+        rope_row(row, position as f32, &rope);
+    }
+    let values = read_tensor_f32(v)?;
+
+    for (cache, data) in [(dst, &keys), (cache_v, &values)] {
+        let dtype = cache.dtype();
+        let stride = cache.stride();
+        let size = head_dim * get_type_size(dtype);
+        if stride[0] != get_type_size(dtype) {
+            return Err(Error::msg("rope_store_kv caches must have contiguous rows"));
+        }
+        let storage = cache.storage()?;
+        let mut bytes = vec![0; size];
+        for (index, row) in data.chunks_exact(head_dim).enumerate() {
+            let (head, token) = (index % n_kv_heads, index / n_kv_heads);
+            for (i, &value) in row.iter().enumerate() {
+                store(&mut bytes, i * get_type_size(dtype), value, dtype, "rope_store_kv")?;
+            }
+            let slot = positions[token] as usize % n_ctx;
+            let offset = slot * stride[1] + head * stride[2];
+            storage.buffer().write(cache.clone(), &mut bytes, offset, size)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use super::common::{dims, parallel_rows, read_tensor_f32, write_tensor_f32};
+use crate::cpu::backend::CpuBackend;
+use crate::data_type::DataType;
+use crate::error::{Error, ErrorKind, Result};
+use crate::tensor::Tensor;
+
+fn check_dtypes(tensors: &[&Tensor], op: &'static str) -> Result<()> {
+    for tensor in tensors {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op,
+            }));
+        }
+    }
+    Ok(())
+}
+
+/// `x * sigmoid(x)`.
+fn silu_f32(x: f32) -> f32 {
+    x / (1.0 + (-x).exp())
+}
+
+/// dst = silu(src0), element-wise, with rows split across the backend threads.
+pub(crate) fn silu(backend: &CpuBackend, src0: &Tensor, dst: &Tensor) -> Result<()> {
+    check_dtypes(&[src0, dst], "cpu silu")?;
+    if dims(src0) != dims(dst) {
+        return Err(Error::msg("silu destination shape does not match its source"));
+    }
+
+    let mut values = read_tensor_f32(src0)?;
+    parallel_rows(backend, &mut values, dims(dst)[0], |_, row| {
+        row.iter_mut().for_each(|value| *value = silu_f32(*value));
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &values)
+}
+
+/// dst = silu(src0) * src1, element-wise: the gate and the product of a SwiGLU layer
+/// without storing the gate in between.
+pub(crate) fn swiglu(
+    backend: &CpuBackend,
+    src0: &Tensor,
+    src1: &Tensor,
+    dst: &Tensor,
+) -> Result<()> {
+    check_dtypes(&[src0, src1, dst], "cpu swiglu")?;
+    if dims(src0) != dims(dst) || dims(src1) != dims(dst) {
+        return Err(Error::msg("swiglu shapes do not match"));
+    }
+
+    let mut values = read_tensor_f32(src0)?;
+    let up = read_tensor_f32(src1)?;
+    let ne0 = dims(dst)[0];
+    parallel_rows(backend, &mut values, ne0, |row, dst_row| {
+        for (value, up) in dst_row.iter_mut().zip(&up[row * ne0..]) {
+            *value = silu_f32(*value) * up;
+        }
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &values)
+}
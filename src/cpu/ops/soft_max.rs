@@ -0,0 +1,46 @@
+use super::common::{dims, parallel_rows, read_tensor_f32, write_tensor_f32};
+use crate::cpu::backend::CpuBackend;
+use crate::data_type::DataType;
+use crate::error::{Error, ErrorKind, Result};
+use crate::tensor::Tensor;
+
+/// y = exp(x - max(x)) / sum(exp(x - max(x))) for every row along dimension 0.
+///
+/// Subtracting the row maximum keeps every exponent at most zero, so large inputs do not
+/// overflow. Rows whose elements are all `-inf`, e.g. masked out entirely by
+/// [`Tensor::diag_mask_inf`], come out as zeros, as in the attention kernel.
+pub(crate) fn soft_max(backend: &CpuBackend, src0: &Tensor, dst: &Tensor) -> Result<()> {
+    for tensor in [src0, dst] {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op: "cpu soft_max",
+            }));
+        }
+    }
+    if dims(src0) != dims(dst) {
+        return Err(Error::msg("soft_max destination shape does not match src0"));
+    }
+
+    let mut values = read_tensor_f32(src0)?;
+    let ne0 = dims(dst)[0];
+    parallel_rows(backend, &mut values, ne0, |_, row| {
+        let max = row.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        if max == f32::NEG_INFINITY {
+            row.fill(0.0);
+            return Ok(());
+        }
+        let mut sum = 0.0;
+        for value in row.iter_mut() {
+            *value = (*value - max).exp();
+            sum += *value;
+        }
+        let inv = 1.0 / sum;
+        for value in row.iter_mut() {
+            *value *= inv;
+        }
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &values)
+}
@@ -0,0 +1,45 @@
+use super::common::{dims, parallel_rows, read_tensor_f32, write_tensor_f32};
+use crate::cpu::backend::CpuBackend;
+use crate::data_type::DataType;
+use crate::error::{Error, ErrorKind, Result};
+use crate::tensor::Tensor;
+
+/// dx = y * (dy - dot(dy, y)) for every row along dimension 0,
+/// where `src0` is `dy` and `src1` the softmax output `y`.
+pub(crate) fn soft_max_back(
+    backend: &CpuBackend,
+    src0: &Tensor,
+    src1: &Tensor,
+    dst: &Tensor,
+) -> Result<()> {
+    for tensor in [src0, src1, dst] {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op: "cpu soft_max_back",
+            }));
+        }
+    }
+
+    let ne = dims(dst);
+    if dims(src0) != ne || dims(src1) != ne {
+        return Err(Error::msg("soft_max_back shapes do not match"));
+    }
+
+    let dy = read_tensor_f32(src0)?;
+    let y = read_tensor_f32(src1)?;
+    let mut out = vec![0.0f32; dy.len()];
+
+    let ne0 = ne[0];
+    parallel_rows(backend, &mut out, ne0, |row, dst_row| {
+        let dy = &dy[row * ne0..(row + 1) * ne0];
+        let y = &y[row * ne0..(row + 1) * ne0];
+        let dot: f32 = dy.iter().zip(y).map(|(dy, y)| dy * y).sum();
+        for ((dx, dy), y) in dst_row.iter_mut().zip(dy).zip(y) {
+            *dx = y * (dy - dot);
+        }
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &out)
+}
@@ -0,0 +1,62 @@
+use super::common::{dims, parallel_rows, read_tensor_f32, write_tensor_f32};
+use crate::cpu::backend::CpuBackend;
+use crate::data_type::{DataType, TensorOpType};
+use crate::error::{Error, ErrorKind, Result};
+use crate::tensor::Tensor;
+
+/// dst[0, i1, ...] = sum of src0[:, i1, ...], divided by the row length for `mean`.
+pub(crate) fn sum_rows(backend: &CpuBackend, src0: &Tensor, dst: &Tensor) -> Result<()> {
+    for tensor in [src0, dst] {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op: "cpu sum_rows",
+            }));
+        }
+    }
+
+    let ne = dims(src0);
+    if dims(dst) != [1, ne[1], ne[2], ne[3]] {
+        return Err(Error::msg("sum_rows destination is not one column of its source"));
+    }
+    let scale = match dst.op_type() {
+        TensorOpType::TensorOpMean => 1.0 / ne[0] as f32,
+        _ => 1.0,
+    };
+
+    let values = read_tensor_f32(src0)?;
+    let mut out = vec![0.0f32; ne[1] * ne[2] * ne[3]];
+    parallel_rows(backend, &mut out, 1, |row, dst| {
+        dst[0] = sum(&values[row * ne[0]..(row + 1) * ne[0]]) * scale;
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &out)
+}
+
+/// Sum over independent lanes, which the compiler turns into vector adds.
+fn sum(values: &[f32]) -> f32 {
+    const LANES: usize = 8;
+    let mut lanes = [0.0f32; LANES];
+    let chunks = values.chunks_exact(LANES);
+    let tail: f32 = chunks.remainder().iter().sum();
+    for chunk in chunks {
+        for (lane, value) in lanes.iter_mut().zip(chunk) {
+            *lane += value;
+        }
+    }
+    lanes.iter().sum::<f32>() + tail
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sum_matches_sequential_sum() {
+        for len in [0, 1, 7, 8, 9, 100] {
+            let values: Vec<f32> = (0..len).map(|i| i as f32).collect();
+            assert_eq!(sum(&values), values.iter().sum::<f32>());
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use super::common::{dims, parallel_rows, read_tensor_f32, write_tensor_f32};
+use crate::cpu::backend::CpuBackend;
+use crate::data_type::DataType;
+use crate::error::{Error, ErrorKind, Result};
+use crate::ops::OpParams;
+use crate::tensor::{Tensor, UpscaleMode};
+
+/// Source coordinate sampled by output coordinate `o` under half-pixel centers.
+fn source_coord(o: usize, scale: f32) -> f32 {
+    (o as f32 + 0.5) / scale - 0.5
+}
+
+/// Indices of the two neighbours of `coord` along an axis of length `len` and the
+/// weight of the second one.
+fn neighbours(coord: f32, len: usize) -> (usize, usize, f32) {
+    let coord = coord.clamp(0.0, (len - 1) as f32);
+    let lo = coord.floor() as usize;
+    (lo, (lo + 1).min(len - 1), coord - lo as f32)
+}
+
+/// Resizes the width and height of `src0` into `dst`. Dimensions 2 and 3 must match.
+///
+/// Every output row is computed independently on the backend threads.
+pub(crate) fn upscale(backend: &CpuBackend, src0: &Tensor, dst: &Tensor) -> Result<()> {
+    for tensor in [src0, dst] {
+        if !matches!(tensor.dtype(), DataType::F32 | DataType::F16) {
+            return Err(Error::new(ErrorKind::UnsupportedDataTypeForOp {
+                dtype: tensor.dtype(),
+                op: "cpu upscale",
+            }));
+        }
+    }
+
+    let mode = match dst.op_params() {
+        Some(OpParams::Upscale { mode }) => mode,
+        _ => return Err(Error::msg("upscale tensor is missing its interpolation mode")),
+    };
+
+    let [iw, ih, ne2, ne3] = dims(src0);
+    let [ow, oh, _, _] = dims(dst);
+    if dims(dst)[2..] != [ne2, ne3] {
+        return Err(Error::msg("upscale can only resize the first two dimensions"));
+    }
+
+    let (sx, sy) = (ow as f32 / iw as f32, oh as f32 / ih as f32);
+    let src = read_tensor_f32(src0)?;
+    let mut out = vec![0.0f32; ow * oh * ne2 * ne3];
+    parallel_rows(backend, &mut out, ow, |row, dst_row| {
+        let (oy, plane) = (row % oh, row / oh);
+        let plane = &src[plane * iw * ih..][..iw * ih];
+        match mode {
+            UpscaleMode::Nearest => {
+                let y = ((oy as f32 / sy) as usize).min(ih - 1);
+                for (ox, value) in dst_row.iter_mut().enumerate() {
+                    let x = ((ox as f32 / sx) as usize).min(iw - 1);
+                    *value = plane[y * iw + x];
+                }
+            }
+            UpscaleMode::Bilinear => {
+                let (y0, y1, wy) = neighbours(source_coord(oy, sy), ih);
+                for (ox, value) in dst_row.iter_mut().enumerate() {
+                    let (x0, x1, wx) = neighbours(source_coord(ox, sx), iw);
+                    let top = plane[y0 * iw + x0] * (1.0 - wx) + plane[y0 * iw + x1] * wx;
+                    let bottom = plane[y1 * iw + x0] * (1.0 - wx) + plane[y1 * iw + x1] * wx;
+                    *value = top * (1.0 - wy) + bottom * wy;
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    write_tensor_f32(dst, &out)
+}
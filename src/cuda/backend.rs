@@ -6,7 +6,7 @@ use crate::backend::{Backend, BackendBuffer, BackendRegister};
 use crate::compute_graph::ComputeGraph;
 use crate::context::Context;
 use crate::cuda::kernels::mul::mul;
-use crate::data_type::TensorOpType;
+use crate::data_type::{DataType, TensorOpType};
 use crate::error::{Error, ErrorKind, Result};
 use crate::tensor::Tensor;
 use cuda_core::DeviceBuffer;
@@ -14,6 +14,10 @@ use std::any::Any;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+/// Tensors start 256 bytes apart, the alignment of `cudaMalloc`, so that kernels may read
+/// them with the widest loads.
+const GPU_TENSOR_ALIGNMENT: usize = 256;
+
 pub(crate) struct CudaBackend {
     pub(super) backend_ctx: Rc<RefCell<CudaBackendContext>>,
 }
@@ -29,9 +33,24 @@ impl Backend for CudaBackend {
         Ok(())
     }
 
+    fn supported_dtypes(&self, op: TensorOpType) -> &'static [DataType] {
+        match op {
+            TensorOpType::TensorOpMul => &[DataType::F32],
+            _ => &[],
+        }
+    }
+
+    fn alignment(&self) -> usize {
+        GPU_TENSOR_ALIGNMENT
+    }
+
     fn graph_compute(&self, ctx: &Context, graph: &mut ComputeGraph) -> Result<()> {
+        #[cfg(feature = "tracing")]
+        let _span = crate::backend::graph_span(self.name(), graph).entered();
         for node in graph.nodes().iter() {
             let tensor = ctx.get_tensor(*node)?;
+            #[cfg(feature = "tracing")]
+            let _node_span = crate::backend::node_span(&tensor).entered();
             self.compute_forward(ctx, &tensor)?;
         }
 
@@ -45,7 +64,7 @@ impl Backend for CudaBackend {
         _offset: usize,
         _size: usize,
     ) -> Result<()> {
-        todo!()
+        Err(Error::new(ErrorKind::UnsupportedBackendOp { backend: "cuda", op: "write_async" }))
     }
 
     fn read_async(
@@ -55,7 +74,7 @@ impl Backend for CudaBackend {
         _offset: usize,
         _size: usize,
     ) -> Result<()> {
-        todo!()
+        Err(Error::new(ErrorKind::UnsupportedBackendOp { backend: "cuda", op: "read_async" }))
     }
 
     fn copy_async(&self, src: Tensor, dst: Tensor) -> Result<()> {
@@ -152,8 +171,10 @@ impl Backend for CudaBackend {
 
 impl CudaBackend {
     pub fn init(device_id: i32) -> Result<Box<dyn Backend>> {
-        let reg =
-            CudaBackendRegister::init().as_any().downcast_ref::<CudaBackendRegister>().unwrap();
+        let reg = CudaBackendRegister::init()
+            .as_any()
+            .downcast_ref::<CudaBackendRegister>()
+            .ok_or_else(|| Error::msg("registry is not the CUDA registry"))?;
         let device = reg.device(device_id as usize)?;
         let cuda_device = device
             .as_any()
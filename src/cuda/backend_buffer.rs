@@ -4,7 +4,7 @@ use std::rc::Rc;
 use super::backend_context::CudaBackendContext;
 use crate::backend::BackendBuffer;
 use crate::backend::BackendBufferUsage;
-use crate::error::{Error, Result};
+use crate::error::{Error, ErrorKind, Result};
 use crate::tensor::Tensor;
 use cuda_core::memory::{memcpy_dtoh_async, memcpy_htod_sync, memset_d8_async};
 use cuda_core::DeviceBuffer;
@@ -33,11 +33,11 @@ impl CudaBackendBuffer {
 
 impl BackendBuffer for CudaBackendBuffer {
     fn reset(&self) -> Result<()> {
-        todo!()
+        Err(Error::new(ErrorKind::UnsupportedBackendOp { backend: "cuda", op: "reset" }))
     }
 
     fn init_tensor(&self, _tensor: Tensor, _offset: usize) -> Result<()> {
-        todo!()
+        Err(Error::new(ErrorKind::UnsupportedBackendOp { backend: "cuda", op: "init_tensor" }))
     }
 
     fn fill(&self, tensor: Tensor, value: u8, offset: usize, size: usize) -> Result<()> {
@@ -2,7 +2,7 @@ use super::backend::CudaBackend;
 use super::backend_context::CudaBackendContext;
 use crate::backend::{Backend, BackendBuffer, BackendDevice, DeviceInfo};
 use crate::data_type::TensorOpType;
-use crate::error::{Error, Result};
+use crate::error::{Error, ErrorKind, Result};
 use crate::tensor::Tensor;
 use cuda_core::CudaContext;
 use std::cell::RefCell;
@@ -13,6 +13,7 @@ use std::sync::Arc;
 pub(super) struct CudaDeviceInfo {
     pub(super) device_id: i32,
     pub(super) name: String,
+    pub(super) uuid: String,
     pub(super) max_threads_per_block: i32, // max number of threads per block
     pub(super) max_threads_dim: [i32; 3],  // max size of each dimension of a block
     pub(super) max_grid_size: [i32; 3],    // max size of each dimension of a block
@@ -45,7 +46,7 @@ impl BackendDevice for CudaBackendDevice {
     }
 
     fn offload_op(&self, _tensor: Tensor) -> Result<bool> {
-        todo!()
+        Err(Error::new(ErrorKind::UnsupportedBackendOp { backend: "cuda", op: "offload_op" }))
     }
 
     fn buffer_from_host_ptr(
@@ -68,6 +69,7 @@ impl BackendDevice for CudaBackendDevice {
     fn info(&self) -> Result<DeviceInfo> {
         let mut device_info = DeviceInfo::default();
         device_info.name = self.info.name.clone();
+        device_info.id = self.info.uuid.clone();
         unsafe {
             cuda_bindings::cuMemGetInfo_v2(
                 &mut device_info.memory.free as *mut usize,
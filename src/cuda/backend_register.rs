@@ -53,6 +53,7 @@ impl BackendRegister for CudaBackendRegister {
             }
 
             device_info.device_id = device_id;
+            device_info.uuid = device_uuid(device_id)?;
             device_info.clock_rate = prop.clockRate;
             device_info.max_grid_size = prop.maxGridSize;
             device_info.max_threads_dim = prop.maxThreadsDim;
@@ -122,3 +123,32 @@ impl CudaBackendRegister {
         })
     }
 }
+
+/// Registration hook of the CUDA backend, listed by [`Registry::discover`] when the
+/// backend's feature is enabled.
+///
+/// [`Registry::discover`]: crate::registry::Registry::discover
+pub fn register() -> Result<Box<dyn BackendRegister>> {
+    let reg = CudaBackendRegister::new();
+    reg.probe_devices()?;
+    Ok(Box::new(reg))
+}
+
+/// UUID of `device` as printed by `nvidia-smi -L`, e.g.
+/// `GPU-8f6f2c1e-3b2a-4c5d-9e8f-0a1b2c3d4e5f`.
+fn device_uuid(device: i32) -> Result<String> {
+    let mut uuid: cuda_bindings::CUuuid = unsafe { std::mem::zeroed() };
+    let result = unsafe { cuda_bindings::cuDeviceGetUuid(&mut uuid, device) };
+    if result != 0 {
+        return Err(Error::msg(format!("cuDeviceGetUuid failed with code: {}", result)));
+    }
+    let hex: Vec<String> = uuid.bytes.iter().map(|byte| format!("{:02x}", *byte as u8)).collect();
+    Ok(format!(
+        "GPU-{}-{}-{}-{}-{}",
+        hex[..4].concat(),
+        hex[4..6].concat(),
+        hex[6..8].concat(),
+        hex[8..10].concat(),
+        hex[10..].concat()
+    ))
+}
@@ -1,9 +1,9 @@
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::tensor::Tensor;
 
 pub(super) fn get_cuda_tensor_ptr_and_size(tensor: Tensor) -> Result<(u64, usize)> {
     let storage = tensor.storage()?;
-    let buffer = storage.as_cuda().unwrap();
+    let buffer = storage.as_cuda().ok_or_else(|| Error::msg("tensor storage is not CUDA"))?;
     let offset = storage.offset();
     let size = storage.size();
     let ptr = buffer.buffer.cu_deviceptr() + offset as u64;
@@ -1,4 +1,8 @@
 use crate::error::{Error, Result};
+use alloc::format;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::sync::{PoisonError, RwLock};
 
 /// The different types of elements allowed in tensors.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -19,6 +23,53 @@ pub enum DataType {
     F32,
     // Floating-point using double precision (64 bits).
     F64,
+    // Signed 8 bits integer, e.g. rowwise quantized weights (see `crate::quant`).
+    I8,
+    // Defined at runtime with `register_data_type`.
+    Custom(CustomTypeId),
+}
+
+/// Handle of a data type registered with [`register_data_type`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CustomTypeId(u16);
+
+impl fmt::Debug for CustomTypeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(dtype_name(DataType::Custom(*self)))
+    }
+}
+
+/// Rust type of the elements of a [`DataType`], for typed views of raw memory such as
+/// [`BackendBuffer::host_memory`](crate::backend::BackendBuffer::host_memory).
+///
+/// # Safety
+///
+/// Every bit pattern of `size_of::<Self>()` bytes must be a valid value, and the size must
+/// match the type size of `DTYPE`.
+pub unsafe trait Element: Copy + 'static {
+    const DTYPE: DataType;
+}
+
+macro_rules! impl_element {
+    ($($ty:ty => $dtype:ident,)*) => {
+        $(
+            // SAFETY: plain integer and float types, which have no invalid bit patterns.
+            unsafe impl Element for $ty {
+                const DTYPE: DataType = DataType::$dtype;
+            }
+        )*
+    };
+}
+
+impl_element! {
+    u8 => U8,
+    u32 => U32,
+    i16 => I16,
+    i32 => I32,
+    i64 => I64,
+    f32 => F32,
+    f64 => F64,
+    i8 => I8,
 }
 
 pub struct DataTypeTraits {
@@ -28,27 +79,136 @@ pub struct DataTypeTraits {
     pub quantized: bool,
 }
 
-static DATA_TYPE_TRAITS: [DataTypeTraits; 8] = [
+static DATA_TYPE_TRAITS: [DataTypeTraits; 9] = [
     DataTypeTraits { name: "U8", block_size: 1, type_size: 1, quantized: true },
-    DataTypeTraits { name: "U32", block_size: 4, type_size: 4, quantized: true },
-    DataTypeTraits { name: "I16", block_size: 2, type_size: 2, quantized: true },
-    DataTypeTraits { name: "I32", block_size: 4, type_size: 4, quantized: true },
-    DataTypeTraits { name: "I64", block_size: 8, type_size: 8, quantized: true },
-    DataTypeTraits { name: "F16", block_size: 2, type_size: 2, quantized: false },
-    DataTypeTraits { name: "F32", block_size: 4, type_size: 4, quantized: false },
-    DataTypeTraits { name: "F64", block_size: 8, type_size: 8, quantized: false },
+    DataTypeTraits { name: "U32", block_size: 1, type_size: 4, quantized: true },
+    DataTypeTraits { name: "I16", block_size: 1, type_size: 2, quantized: true },
+    DataTypeTraits { name: "I32", block_size: 1, type_size: 4, quantized: true },
+    DataTypeTraits { name: "I64", block_size: 1, type_size: 8, quantized: true },
+    DataTypeTraits { name: "F16", block_size: 1, type_size: 2, quantized: false },
+    DataTypeTraits { name: "F32", block_size: 1, type_size: 4, quantized: false },
+    DataTypeTraits { name: "F64", block_size: 1, type_size: 8, quantized: false },
+    DataTypeTraits { name: "I8", block_size: 1, type_size: 1, quantized: true },
 ];
 
+/// Decodes whole blocks: `src` holds `dst.len() / block_size` blocks of `type_size` bytes.
+pub type ToF32Fn = fn(src: &[u8], dst: &mut [f32]);
+
+/// Inverse of [`ToF32Fn`]: encodes `src`, a whole number of blocks, into `dst`.
+pub type FromF32Fn = fn(src: &[f32], dst: &mut [u8]);
+
+/// Dot product of a row of a custom type and as many F32 values.
+pub type VecDotFn = fn(x: &[u8], y: &[f32]) -> f32;
+
+/// A data type defined outside the crate, such as an experimental quantization format,
+/// made known with [`register_data_type`].
+#[derive(Debug, Clone, Copy)]
+pub struct CustomDataType {
+    pub name: &'static str,
+    /// Elements per block; the elements of a block are stored and converted together.
+    pub block_size: usize,
+    /// Bytes per block.
+    pub type_size: usize,
+    pub to_f32: ToF32Fn,
+    pub from_f32: FromF32Fn,
+    /// Used by matrix products with weights of the type instead of converting every row
+    /// with `to_f32` first.
+    pub vec_dot: Option<VecDotFn>,
+}
+
+struct RegisteredType {
+    traits: DataTypeTraits,
+    custom: CustomDataType,
+}
+
+/// Registered types, indexed by their [`CustomTypeId`]. Entries are leaked so that their
+/// traits can be handed out as `'static` like those of the built-in types.
+#[cfg(feature = "std")]
+static CUSTOM_TYPES: RwLock<Vec<&'static RegisteredType>> = RwLock::new(Vec::new());
+
+/// Registers `custom` and returns the data type to create tensors of it with. Names must
+/// differ from those of the built-in and other registered types; registrations last until
+/// the process exits.
+#[cfg(feature = "std")]
+pub fn register_data_type(custom: CustomDataType) -> Result<DataType> {
+    let register = || {
+        if custom.block_size == 0 || custom.type_size == 0 {
+            return Err(Error::msg(format!(
+                "{} needs a nonzero block size and type size",
+                custom.name
+            )));
+        }
+        let mut types = CUSTOM_TYPES.write().unwrap_or_else(PoisonError::into_inner);
+        let mut names = DATA_TYPE_TRAITS.iter().chain(types.iter().map(|ty| &ty.traits));
+        if names.any(|traits| traits.name == custom.name) {
+            return Err(Error::msg(format!("data type {} already exists", custom.name)));
+        }
+        let id =
+            u16::try_from(types.len()).map_err(|_| Error::msg("too many custom data types"))?;
+        let traits = DataTypeTraits {
+            name: custom.name,
+            block_size: custom.block_size,
+            type_size: custom.type_size,
+            quantized: true,
+        };
+        types.push(Box::leak(Box::new(RegisteredType { traits, custom })));
+        Ok(DataType::Custom(CustomTypeId(id)))
+    };
+    register().map_err(|e: Error| e.context("in register_data_type"))
+}
+
+fn registered_type(dtype: DataType) -> Option<&'static RegisteredType> {
+    let DataType::Custom(CustomTypeId(id)) = dtype else {
+        return None;
+    };
+    #[cfg(feature = "std")]
+    {
+        let types = CUSTOM_TYPES.read().unwrap_or_else(PoisonError::into_inner);
+        types.get(usize::from(id)).copied()
+    }
+    // Without `std` nothing can be registered, so no custom type exists.
+    #[cfg(not(feature = "std"))]
+    {
+        let _ = id;
+        None
+    }
+}
+
+/// The definition of a registered type; `None` for the built-in types.
+pub fn custom_data_type(dtype: DataType) -> Option<&'static CustomDataType> {
+    registered_type(dtype).map(|ty| &ty.custom)
+}
+
+/// Name, block and type size of `dtype`, for the built-in and registered types alike.
+pub fn type_traits(dtype: DataType) -> &'static DataTypeTraits {
+    let index = match dtype {
+        DataType::U8 => 0,
+        DataType::U32 => 1,
+        DataType::I16 => 2,
+        DataType::I32 => 3,
+        DataType::I64 => 4,
+        DataType::F16 => 5,
+        DataType::F32 => 6,
+        DataType::F64 => 7,
+        DataType::I8 => 8,
+        DataType::Custom(_) => {
+            let ty = registered_type(dtype);
+            return &ty.expect("custom data types are only made by registering them").traits;
+        }
+    };
+    &DATA_TYPE_TRAITS[index]
+}
+
 pub fn get_type_size(dtype: DataType) -> usize {
-    DATA_TYPE_TRAITS[dtype as usize].type_size
+    type_traits(dtype).type_size
 }
 
 pub fn get_block_size(dtype: DataType) -> usize {
-    DATA_TYPE_TRAITS[dtype as usize].block_size
+    type_traits(dtype).block_size
 }
 
 pub fn is_quantized(dtype: DataType) -> bool {
-    DATA_TYPE_TRAITS[dtype as usize].quantized
+    type_traits(dtype).quantized
 }
 
 pub fn get_row_size(dtype: DataType, ne: usize) -> Result<usize> {
@@ -59,6 +219,187 @@ pub fn get_row_size(dtype: DataType, ne: usize) -> Result<usize> {
     Ok(get_type_size(dtype) * ne / get_block_size(dtype))
 }
 
+/// Converts an IEEE 754 half-precision value (stored as raw bits) to `f32`.
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits as u32) & 0x8000) << 16;
+    let exp = ((bits >> 10) & 0x1f) as u32;
+    let mant = (bits & 0x3ff) as u32;
+
+    let value = match (exp, mant) {
+        (0, 0) => sign,
+        (0, _) => {
+            // subnormal: renormalize the mantissa into the f32 exponent range
+            let shift = mant.leading_zeros() - 21;
+            let mant = (mant << shift) & 0x3ff;
+            sign | ((113 - shift) << 23) | (mant << 13)
+        }
+        (0x1f, 0) => sign | 0x7f80_0000,
+        (0x1f, _) => sign | 0x7fc0_0000 | (mant << 13),
+        _ => sign | ((exp + 112) << 23) | (mant << 13),
+    };
+
+    f32::from_bits(value)
+}
+
+/// Converts an `f32` to IEEE 754 half-precision bits, rounding to nearest even.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mant = bits & 0x007f_ffff;
+
+    if exp == 0xff {
+        let nan = if mant != 0 { 0x0200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let half_exp = exp - 127 + 15;
+    if half_exp >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    if half_exp <= 0 {
+        if half_exp < -10 {
+            return sign;
+        }
+        let mant = mant | 0x0080_0000;
+        let shift = (14 - half_exp) as u32;
+        let half_mant = mant >> shift;
+        let round_bit = 1 << (shift - 1);
+        let rounded = if (mant & round_bit) != 0 && (mant & (3 * round_bit - 1)) != 0 {
+            half_mant + 1
+        } else {
+            half_mant
+        };
+        return sign | rounded as u16;
+    }
+
+    let half = sign | ((half_exp as u16) << 10) | (mant >> 13) as u16;
+    let round_bit = 0x0000_1000;
+    if (mant & round_bit) != 0 && (mant & (3 * round_bit - 1)) != 0 {
+        // may carry into the exponent, which correctly rounds up to infinity
+        half + 1
+    } else {
+        half
+    }
+}
+
+/// Decodes a single element of type `dtype` from `bytes` as `f32`.
+pub fn to_f32(dtype: DataType, bytes: &[u8]) -> Result<f32> {
+    let size = get_type_size(dtype);
+    let bytes = bytes.get(..size).ok_or_else(|| {
+        Error::msg(format!("{} element needs {size} bytes, got {}", dtype_name(dtype), bytes.len()))
+    })?;
+
+    Ok(match dtype {
+        DataType::U8 => bytes[0] as f32,
+        DataType::U32 => u32::from_ne_bytes(bytes.try_into().unwrap()) as f32,
+        DataType::I16 => i16::from_ne_bytes(bytes.try_into().unwrap()) as f32,
+        DataType::I32 => i32::from_ne_bytes(bytes.try_into().unwrap()) as f32,
+        DataType::I64 => i64::from_ne_bytes(bytes.try_into().unwrap()) as f32,
+        DataType::F16 => f16_to_f32(u16::from_ne_bytes(bytes.try_into().unwrap())),
+        DataType::F32 => f32::from_ne_bytes(bytes.try_into().unwrap()),
+        DataType::F64 => f64::from_ne_bytes(bytes.try_into().unwrap()) as f32,
+        DataType::I8 => bytes[0] as i8 as f32,
+        DataType::Custom(_) => {
+            let mut value = [0.0];
+            (custom_elements(dtype)?.to_f32)(bytes, &mut value);
+            value[0]
+        }
+    })
+}
+
+/// Encodes `value` as a single element of type `dtype` into `bytes`.
+pub fn from_f32(dtype: DataType, value: f32, bytes: &mut [u8]) -> Result<()> {
+    let size = get_type_size(dtype);
+    let len = bytes.len();
+    let bytes = bytes.get_mut(..size).ok_or_else(|| {
+        Error::msg(format!("{} element needs {size} bytes, got {len}", dtype_name(dtype)))
+    })?;
+
+    match dtype {
+        DataType::U8 => bytes[0] = value as u8,
+        DataType::U32 => bytes.copy_from_slice(&(value as u32).to_ne_bytes()),
+        DataType::I16 => bytes.copy_from_slice(&(value as i16).to_ne_bytes()),
+        DataType::I32 => bytes.copy_from_slice(&(value as i32).to_ne_bytes()),
+        DataType::I64 => bytes.copy_from_slice(&(value as i64).to_ne_bytes()),
+        DataType::F16 => bytes.copy_from_slice(&f32_to_f16(value).to_ne_bytes()),
+        DataType::F32 => bytes.copy_from_slice(&value.to_ne_bytes()),
+        DataType::F64 => bytes.copy_from_slice(&(value as f64).to_ne_bytes()),
+        DataType::I8 => bytes[0] = value as i8 as u8,
+        DataType::Custom(_) => (custom_elements(dtype)?.from_f32)(&[value], bytes),
+    }
+
+    Ok(())
+}
+
+/// Decodes a single element of type `dtype` from `bytes` as `i32`.
+///
+/// Integer types are narrowed and floating-point types are truncated toward zero.
+pub fn to_i32(dtype: DataType, bytes: &[u8]) -> Result<i32> {
+    match dtype {
+        DataType::U32 | DataType::I64 => {
+            let size = get_type_size(dtype);
+            let bytes = bytes.get(..size).ok_or_else(|| {
+                Error::msg(format!(
+                    "{} element needs {size} bytes, got {}",
+                    dtype_name(dtype),
+                    bytes.len()
+                ))
+            })?;
+            Ok(match dtype {
+                DataType::U32 => u32::from_ne_bytes(bytes.try_into().unwrap()) as i32,
+                _ => i64::from_ne_bytes(bytes.try_into().unwrap()) as i32,
+            })
+        }
+        DataType::I32 => {
+            let bytes = bytes.get(..4).ok_or_else(|| {
+                Error::msg(format!("I32 element needs 4 bytes, got {}", bytes.len()))
+            })?;
+            Ok(i32::from_ne_bytes(bytes.try_into().unwrap()))
+        }
+        _ => to_f32(dtype, bytes).map(|value| value as i32),
+    }
+}
+
+/// Encodes `value` as a single element of type `dtype` into `bytes`.
+pub fn from_i32(dtype: DataType, value: i32, bytes: &mut [u8]) -> Result<()> {
+    let len = bytes.len();
+    match dtype {
+        DataType::U32 | DataType::I32 | DataType::I64 => {
+            let size = get_type_size(dtype);
+            let bytes = bytes.get_mut(..size).ok_or_else(|| {
+                Error::msg(format!("{} element needs {size} bytes, got {len}", dtype_name(dtype)))
+            })?;
+            match dtype {
+                DataType::U32 => bytes.copy_from_slice(&(value as u32).to_ne_bytes()),
+                DataType::I32 => bytes.copy_from_slice(&value.to_ne_bytes()),
+                _ => bytes.copy_from_slice(&(value as i64).to_ne_bytes()),
+            }
+            Ok(())
+        }
+        _ => from_f32(dtype, value as f32, bytes),
+    }
+}
+
+fn dtype_name(dtype: DataType) -> &'static str {
+    type_traits(dtype).name
+}
+
+/// The converters of a custom type whose elements are stored one by one, for the single
+/// element conversions.
+fn custom_elements(dtype: DataType) -> Result<&'static CustomDataType> {
+    let custom = custom_data_type(dtype)
+        .ok_or_else(|| Error::msg(format!("{dtype:?} is not a registered data type")))?;
+    if custom.block_size != 1 {
+        return Err(Error::msg(format!(
+            "{} elements are stored in blocks of {} and cannot be converted one by one",
+            custom.name, custom.block_size
+        )));
+    }
+    Ok(custom)
+}
+
 /// The different types of tensors.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum TensorType {
@@ -67,12 +408,131 @@ pub enum TensorType {
     OutputParam,
     FlagParam,
 }
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-pub enum TensorOpType {
-    UNKNOWN,
-    TensorOpView,
-    TensorOpMul,
-    TensorNone,
+/// Declares [`TensorOpType`] together with the list of all its variants.
+macro_rules! tensor_op_types {
+    ($($(#[$meta:meta])* $name:ident = $id:literal,)*) => {
+        /// The operation that computes a tensor.
+        ///
+        /// Every op has a fixed id, see [`TensorOpType::id`], that is kept across releases so
+        /// it can be serialized. Ids are grouped by family with room to grow: views and
+        /// copies from 10, binary ops from 30, unary ops from 50, reductions from 80, matrix
+        /// products from 100, norms from 120, attention and positions from 140, convolutions
+        /// and pooling from 160 and backward ops from 200. Ids of removed ops are not reused.
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+        #[repr(u16)]
+        pub enum TensorOpType {
+            $($(#[$meta])* $name = $id,)*
+        }
+
+        impl TensorOpType {
+            /// Every op, in id order.
+            pub const ALL: &'static [TensorOpType] = &[$(TensorOpType::$name,)*];
+        }
+    };
+}
+
+tensor_op_types! {
+    UNKNOWN = 0,
+    /// A leaf: parameters, inputs and constants.
+    TensorNone = 1,
+
+    TensorOpView = 10,
+    TensorOpReshape = 11,
+    TensorOpTranspose = 12,
+    TensorOpPermute = 13,
+    TensorOpCont = 14,
+    /// Copy into a tensor of another type or layout.
+    TensorOpCpy = 15,
+    TensorOpGetRows = 16,
+    /// Writes a source into a view of a copy of the destination.
+    TensorOpSet = 17,
+    TensorOpConcat = 18,
+    TensorOpRepeat = 19,
+
+    TensorOpAdd = 30,
+    TensorOpSub = 31,
+    TensorOpMul = 32,
+    TensorOpDiv = 33,
+    TensorOpAcc = 34,
+    TensorOpMapBinary = 35,
+
+    TensorOpNeg = 50,
+    TensorOpAbs = 51,
+    TensorOpSqr = 52,
+    TensorOpSqrt = 53,
+    TensorOpExp = 54,
+    TensorOpLog = 55,
+    TensorOpSin = 56,
+    TensorOpCos = 57,
+    TensorOpTanh = 58,
+    TensorOpSigmoid = 59,
+    TensorOpRelu = 60,
+    TensorOpLeakyRelu = 61,
+    TensorOpGelu = 62,
+    TensorOpSilu = 63,
+    TensorOpSwiGlu = 64,
+    TensorOpScale = 65,
+    TensorOpClamp = 66,
+    TensorOpMapUnary = 67,
+    /// Zeroes elements with a probability drawn from a [`crate::rng::RngState`] source.
+    TensorOpDropout = 68,
+
+    TensorOpSum = 80,
+    TensorOpSumRows = 81,
+    TensorOpMean = 82,
+    TensorOpMax = 83,
+    TensorOpArgmax = 84,
+
+    TensorOpMulMat = 100,
+    TensorOpOutProd = 101,
+    /// Matrix product of rowwise int8 weights, see [`crate::quant`].
+    TensorOpMulMatRowwise = 102,
+
+    /// Layer normalization over rows.
+    TensorOpNorm = 120,
+    TensorOpRmsNorm = 121,
+    TensorOpGroupNorm = 122,
+
+    TensorOpSoftMax = 140,
+    TensorOpDiagMaskInf = 141,
+    TensorOpDiagMaskZero = 142,
+    TensorOpRope = 143,
+    TensorOpAlibi = 144,
+    TensorOpAttention = 145,
+    /// Rotates keys and writes them and the values into a KV cache in one pass.
+    TensorOpRopeStoreKv = 146,
+
+    TensorOpIm2Col = 160,
+    TensorOpConv1d = 161,
+    TensorOpConvTranspose1d = 162,
+    TensorOpConv2d = 163,
+    TensorOpConvTranspose2d = 164,
+    TensorOpPool1d = 165,
+    TensorOpPool2d = 166,
+    TensorOpUpscale = 167,
+    TensorOpPad = 168,
+
+    TensorOpSoftMaxBack = 200,
+    TensorOpGroupNormBack = 201,
+    TensorOpIm2ColBack = 202,
+    TensorOpGetRowsBack = 203,
+    TensorOpRmsNormBack = 204,
+    TensorOpRopeBack = 205,
+    TensorOpSiluBack = 206,
+    /// Sums the repeats of a tensor made by [`TensorOpType::TensorOpRepeat`].
+    TensorOpRepeatBack = 207,
+}
+
+impl TensorOpType {
+    /// Stable id of the op.
+    pub fn id(self) -> u16 {
+        self as u16
+    }
+
+    /// The op with id `id`, if there is one.
+    pub fn from_id(id: u16) -> Option<Self> {
+        Self::ALL.iter().copied().find(|op| op.id() == id)
+    }
 }
 
 #[cfg(test)]
@@ -100,6 +560,7 @@ mod tests {
             DataType::F16,
             DataType::F32,
             DataType::F64,
+            DataType::I8,
         ];
 
         for i in 0..types.len() {
@@ -126,4 +587,87 @@ mod tests {
         assert_eq!(get_type_size(DataType::F32), 4);
         assert_eq!(get_type_size(DataType::F64), 8);
     }
+
+    #[test]
+    fn test_f16_round_trip() {
+        for value in
+            [0.0f32, -0.0, 1.0, -2.5, 0.333_251_95, 65504.0, 6.103_515_6e-5, 5.960_464_5e-8]
+        {
+            assert_eq!(f16_to_f32(f32_to_f16(value)), value);
+        }
+
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(1e6), 0x7c00);
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+    }
+
+    #[test]
+    fn test_op_ids_are_stable_and_unique() {
+        assert_eq!(TensorOpType::TensorOpMul.id(), 32);
+        assert_eq!(TensorOpType::TensorOpMulMat.id(), 100);
+        for (i, op) in TensorOpType::ALL.iter().enumerate() {
+            assert_eq!(TensorOpType::from_id(op.id()), Some(*op));
+            if i > 0 {
+                assert!(TensorOpType::ALL[i - 1].id() < op.id());
+            }
+        }
+        assert_eq!(TensorOpType::from_id(2), None);
+    }
+
+    #[test]
+    fn test_element_conversion() {
+        let mut bytes = [0u8; 8];
+
+        from_f32(DataType::F16, 1.5, &mut bytes).unwrap();
+        assert_eq!(to_f32(DataType::F16, &bytes).unwrap(), 1.5);
+
+        from_i32(DataType::I64, -7, &mut bytes).unwrap();
+        assert_eq!(to_i32(DataType::I64, &bytes).unwrap(), -7);
+
+        from_f32(DataType::I32, 3.9, &mut bytes).unwrap();
+        assert_eq!(to_i32(DataType::I32, &bytes).unwrap(), 3);
+
+        assert!(to_f32(DataType::F64, &bytes[..4]).is_err());
+    }
+
+    #[test]
+    fn test_register_data_type() {
+        // bfloat16: the upper half of an f32
+        let bf16 = CustomDataType {
+            name: "TEST_BF16",
+            block_size: 1,
+            type_size: 2,
+            to_f32: |src, dst| {
+                for (bytes, value) in src.chunks_exact(2).zip(dst) {
+                    *value =
+                        f32::from_bits(u32::from(u16::from_ne_bytes([bytes[0], bytes[1]])) << 16);
+                }
+            },
+            from_f32: |src, dst| {
+                for (value, bytes) in src.iter().zip(dst.chunks_exact_mut(2)) {
+                    bytes.copy_from_slice(&((value.to_bits() >> 16) as u16).to_ne_bytes());
+                }
+            },
+            vec_dot: None,
+        };
+        let dtype = register_data_type(bf16).unwrap();
+        assert_eq!(format!("{dtype:?}"), "Custom(TEST_BF16)");
+        assert_eq!((get_type_size(dtype), get_block_size(dtype)), (2, 1));
+        assert!(is_quantized(dtype));
+        assert_eq!(custom_data_type(dtype).map(|ty| ty.name), Some("TEST_BF16"));
+        assert!(custom_data_type(DataType::F32).is_none());
+
+        let mut bytes = [0u8; 2];
+        from_f32(dtype, -2.5, &mut bytes).unwrap();
+        assert_eq!(to_f32(dtype, &bytes).unwrap(), -2.5);
+        assert_eq!(to_i32(dtype, &bytes).unwrap(), -2);
+
+        assert!(register_data_type(bf16).is_err());
+        assert!(register_data_type(CustomDataType { name: "F16", ..bf16 }).is_err());
+        let blocks = CustomDataType { name: "TEST_BF16_X4", block_size: 4, type_size: 8, ..bf16 };
+        let blocks = register_data_type(blocks).unwrap();
+        assert_ne!(blocks, dtype);
+        assert_eq!(get_row_size(blocks, 8).unwrap(), 16);
+        assert!(to_f32(blocks, &[0; 8]).is_err());
+    }
 }
@@ -0,0 +1,259 @@
+//! Tensor dumps for debugging.
+//!
+//! [`dump_tensor`] writes a tensor to a NumPy `.npy` file, so intermediate values can be
+//! compared against a reference implementation with `numpy.load`. [`dump_graph`] does the
+//! same for the output of every node of one graph compute, using a [`DumpObserver`].
+
+use crate::backend::{Backend, GraphObserver};
+use crate::compute_graph::ComputeGraph;
+use crate::context::Context;
+use crate::data_type::{get_type_size, to_f32, DataType};
+use crate::error::{Error, Result};
+use crate::tensor::Tensor;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Summary of the values of a tensor. All fields are NaN for an empty tensor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TensorStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+}
+
+impl std::fmt::Display for TensorStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "min={} max={} mean={}", self.min, self.max, self.mean)
+    }
+}
+
+/// Computes the statistics of `tensor`, which must be bound to a buffer.
+pub fn tensor_stats(tensor: &Tensor) -> Result<TensorStats> {
+    let data = read_elements(tensor).map_err(|e| e.context("in debug::tensor_stats"))?;
+    stats(tensor.dtype(), &data)
+}
+
+/// Writes `tensor` to `path` in `.npy` format, outermost dimension first, and returns its
+/// statistics. The format has no room for anything but the shape and dtype, so the
+/// statistics are only returned.
+pub fn dump_tensor(tensor: &Tensor, path: impl AsRef<Path>) -> Result<TensorStats> {
+    let path = path.as_ref();
+    let dump = || {
+        let data = read_elements(tensor)?;
+        let mut file = fs::File::create(path)?;
+        file.write_all(&npy_header(tensor)?)?;
+        file.write_all(&data)?;
+        stats(tensor.dtype(), &data)
+    };
+    dump().map_err(|e: Error| e.context(format!("in debug::dump_tensor: {}", path.display())))
+}
+
+/// Computes `graph` on `backend` and dumps every node output to `dir`, see
+/// [`DumpObserver`]. Replaces any observer registered on the backend and removes it again
+/// afterwards. A failed write is returned in place of the compute result.
+pub fn dump_graph(
+    backend: &mut dyn Backend,
+    ctx: &Context,
+    graph: &mut ComputeGraph,
+    dir: impl AsRef<Path>,
+) -> Result<()> {
+    let mut dump = || {
+        let observer = DumpObserver::new(dir.as_ref())?;
+        let error = observer.error_slot();
+        backend.set_observer(Some(Box::new(observer)))?;
+        let result = backend.graph_compute(ctx, graph).and_then(|_| backend.synchronize());
+        backend.set_observer(None)?;
+        let error = error.lock().map_err(|_| Error::msg("dump error slot is poisoned"))?.take();
+        match error {
+            Some(err) => Err(err),
+            None => result,
+        }
+    };
+    dump().map_err(|e: Error| e.context("in debug::dump_graph"))
+}
+
+/// Observer writing the output of every node to `<dir>/<index>_<name>.npy`, where `index`
+/// counts the nodes of a graph, and a line per node with its dtype, shape and statistics
+/// to `<dir>/index.txt`. A failed node write stops the graph compute; the first error is
+/// kept in [`DumpObserver::error_slot`].
+pub struct DumpObserver {
+    dir: PathBuf,
+    node: usize,
+    index: String,
+    error: Arc<Mutex<Option<Error>>>,
+}
+
+impl DumpObserver {
+    /// Creates `dir` if needed.
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(|e| Error::from(e).context("in DumpObserver::new"))?;
+        Ok(Self { dir, node: 0, index: String::new(), error: Arc::default() })
+    }
+
+    /// Holds the first write error. The slot is shared, so it can still be read after the
+    /// observer has been handed to a backend.
+    pub fn error_slot(&self) -> Arc<Mutex<Option<Error>>> {
+        self.error.clone()
+    }
+
+    fn record(&self, err: Error) {
+        if let Ok(mut slot) = self.error.lock() {
+            slot.get_or_insert(err);
+        }
+    }
+
+    fn dump(&mut self, tensor: &Tensor) -> Result<()> {
+        let mut name = tensor.name();
+        if name.is_empty() {
+            name = format!("{:?}", tensor.op_type());
+        }
+        // Keep the file name to one path component.
+        let file_name: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || "-_.".contains(c) { c } else { '_' })
+            .collect();
+        let file_name = format!("{:04}_{file_name}.npy", self.node);
+        let stats = dump_tensor(tensor, self.dir.join(&file_name))?;
+        let _ = writeln!(
+            self.index,
+            "{file_name}\t{name}\t{:?}\t{}\t{stats}",
+            tensor.dtype(),
+            tensor.shape()
+        );
+        Ok(())
+    }
+}
+
+impl GraphObserver for DumpObserver {
+    fn on_graph_begin(&mut self, _graph: &ComputeGraph) {
+        self.node = 0;
+        self.index.clear();
+    }
+
+    fn on_graph_end(&mut self, _graph: &ComputeGraph) {
+        let path = self.dir.join("index.txt");
+        if let Err(err) = fs::write(&path, &self.index) {
+            self.record(
+                Error::from(err)
+                    .context(format!("in DumpObserver::on_graph_end: {}", path.display())),
+            );
+        }
+    }
+
+    fn on_node_end(&mut self, tensor: &Tensor) -> bool {
+        let result = self.dump(tensor);
+        self.node += 1;
+        match result {
+            Ok(()) => true,
+            Err(err) => {
+                self.record(err);
+                false
+            }
+        }
+    }
+}
+
+/// Elements of `tensor` in row-major order, gathered through its strides.
+fn read_elements(tensor: &Tensor) -> Result<Vec<u8>> {
+    let size = tensor.nbytes();
+    let mut bytes = vec![0; size];
+    tensor.storage()?.buffer().read(tensor.clone(), &mut bytes, 0, size)?;
+    if tensor.is_contiguous() {
+        return Ok(bytes);
+    }
+
+    let shape = *tensor.shape();
+    let stride = tensor.stride().to_vec();
+    let type_size = get_type_size(tensor.dtype());
+    let mut data =
+        Vec::with_capacity(shape.dims[..shape.rank].iter().product::<usize>() * type_size);
+    let mut index = [0; 4];
+    let dims = |axis: usize| if axis < shape.rank { shape.dims[axis] } else { 1 };
+    for i3 in 0..dims(3) {
+        index[3] = i3;
+        for i2 in 0..dims(2) {
+            index[2] = i2;
+            for i1 in 0..dims(1) {
+                index[1] = i1;
+                for i0 in 0..dims(0) {
+                    index[0] = i0;
+                    let start: usize = index.iter().zip(&stride).map(|(i, nb)| i * nb).sum();
+                    data.extend_from_slice(&bytes[start..start + type_size]);
+                }
+            }
+        }
+    }
+    Ok(data)
+}
+
+fn stats(dtype: DataType, data: &[u8]) -> Result<TensorStats> {
+    let mut min = f32::INFINITY;
+    let mut max = f32::NEG_INFINITY;
+    let mut sum = 0.0f64;
+    let mut count = 0;
+    for element in data.chunks_exact(get_type_size(dtype)) {
+        let value = to_f32(dtype, element)?;
+        min = min.min(value);
+        max = max.max(value);
+        sum += value as f64;
+        count += 1;
+    }
+    if count == 0 {
+        return Ok(TensorStats { min: f32::NAN, max: f32::NAN, mean: f32::NAN });
+    }
+    Ok(TensorStats { min, max, mean: (sum / count as f64) as f32 })
+}
+
+/// `.npy` version 1.0 header: magic, header length and a Python dict literal padded so
+/// that the data starts at a multiple of 64 bytes.
+fn npy_header(tensor: &Tensor) -> Result<Vec<u8>> {
+    let order = if cfg!(target_endian = "little") { '<' } else { '>' };
+    let descr = match tensor.dtype() {
+        DataType::U8 => "|u1".to_string(),
+        DataType::U32 => format!("{order}u4"),
+        DataType::I16 => format!("{order}i2"),
+        DataType::I32 => format!("{order}i4"),
+        DataType::I64 => format!("{order}i8"),
+        DataType::F16 => format!("{order}f2"),
+        DataType::F32 => format!("{order}f4"),
+        DataType::F64 => format!("{order}f8"),
+        DataType::I8 => "|i1".to_string(),
+        DataType::Custom(_) => {
+            return Err(Error::msg(format!("{:?} has no NumPy equivalent", tensor.dtype())));
+        }
+    };
+    let shape = tensor.shape();
+    let dims: Vec<String> = shape.dims[..shape.rank].iter().rev().map(|d| d.to_string()).collect();
+    let dims = match dims.len() {
+        1 => format!("({},)", dims[0]),
+        _ => format!("({})", dims.join(", ")),
+    };
+    let mut dict = format!("{{'descr': '{descr}', 'fortran_order': False, 'shape': {dims}, }}");
+
+    let unpadded = 10 + dict.len() + 1;
+    dict.extend(std::iter::repeat_n(' ', unpadded.next_multiple_of(64) - unpadded));
+    dict.push('\n');
+    let len = u16::try_from(dict.len()).map_err(|_| Error::msg("npy header is too long"))?;
+
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    header.extend_from_slice(&len.to_le_bytes());
+    header.extend_from_slice(dict.as_bytes());
+    Ok(header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let data: Vec<u8> = [1.0f32, -2.0, 4.0].iter().flat_map(|v| v.to_ne_bytes()).collect();
+        let summary = stats(DataType::F32, &data).unwrap();
+        assert_eq!(summary, TensorStats { min: -2.0, max: 4.0, mean: 1.0 });
+        assert!(stats(DataType::F32, &[]).unwrap().mean.is_nan());
+    }
+}
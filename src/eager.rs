@@ -0,0 +1,166 @@
+//! Eager execution and graph tracing.
+//!
+//! Ops on tensors only build graph nodes. An [`Eager`] session computes them right away
+//! instead: [`Eager::eval`] allocates and computes whatever a tensor still depends on, so
+//! values can be read after every step, as in an eager framework. [`Eager::trace`] runs such
+//! a sequence of steps once on example inputs and returns the [`ComputeGraph`] of the ops it
+//! ran, which then computes the same steps on new input values with one
+//! [`Backend::compute`] per call:
+//!
+//! ```no_run
+//! # use feml::{data_type::DataType, context::Context, eager::Eager, shape};
+//! # fn main() -> feml::error::Result<()> {
+//! let backend = feml::Backend::cpu().build()?;
+//! let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+//! let mut eager = Eager::new(&backend);
+//! let x = eager.input(&mut ctx, DataType::F32, &shape![4], &[0; 16])?;
+//! let mut graph = eager.trace(&mut ctx, |eager, ctx| {
+//!     let y = x.clone().mul(x.clone())?;
+//!     eager.eval(ctx, &y)?;
+//!     Ok(vec![y.clone().mul(x.clone())?])
+//! })?;
+//! backend.write(&x, &[0; 16])?;
+//! backend.compute(&ctx, &mut graph)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::api::Backend;
+use crate::backend::BackendBuffer;
+use crate::collections::HashSet;
+use crate::compute_graph::ComputeGraph;
+use crate::context::Context;
+use crate::data_type::{DataType, TensorOpType, TensorType};
+use crate::error::{Error, Result};
+use crate::shape::Shape;
+use crate::tensor::{Tensor, TensorId};
+
+/// Session computing ops as they are evaluated. The tensors it allocates, and the graphs it
+/// traces, are valid while the session is alive.
+pub struct Eager<'b> {
+    backend: &'b Backend,
+    buffers: Vec<Box<dyn BackendBuffer>>,
+    /// Nodes computed so far, which later evaluations do not compute again.
+    computed: HashSet<TensorId>,
+    tracing: bool,
+}
+
+impl<'b> Eager<'b> {
+    pub fn new(backend: &'b Backend) -> Self {
+        Self { backend, buffers: Vec::new(), computed: HashSet::new(), tracing: false }
+    }
+
+    /// A leaf tensor holding `data`, allocated by the session.
+    pub fn input(
+        &mut self,
+        ctx: &mut Context,
+        dtype: DataType,
+        shape: &Shape,
+        data: &[u8],
+    ) -> Result<Tensor> {
+        let mut input = || {
+            let tensor = ctx.new_tensor(dtype, shape)?;
+            tensor.set_op_type(TensorOpType::TensorNone);
+            tensor.set_tensor_type(TensorType::FlagParam);
+            if data.len() != tensor.nbytes() {
+                return Err(Error::msg(format!(
+                    "{} bytes do not fill a tensor of {}",
+                    data.len(),
+                    tensor.nbytes()
+                )));
+            }
+            self.buffers.push(self.backend.alloc(core::slice::from_ref(&tensor))?);
+            self.backend.write(&tensor, data)?;
+            Ok(tensor)
+        };
+        input().map_err(|e: Error| e.context("in Eager::input"))
+    }
+
+    /// Computes `tensor` and the nodes it depends on that no earlier evaluation computed,
+    /// allocating the ones that are not bound to a buffer yet. Leafs must be bound.
+    pub fn eval(&mut self, ctx: &Context, tensor: &Tensor) -> Result<()> {
+        self.eval_node(ctx, tensor).map_err(|e| e.context("in Eager::eval"))
+    }
+
+    /// Reads the bytes of an evaluated tensor.
+    pub fn read(&self, tensor: &Tensor) -> Result<Vec<u8>> {
+        self.backend.read(tensor).map_err(|e| e.context("in Eager::read"))
+    }
+
+    /// Runs `steps` on the current input values and returns the graph of the ops the
+    /// tensors it returns depend on, with those tensors evaluated and marked as
+    /// [graph outputs](ComputeGraph::add_output). Writing new values to the inputs and
+    /// computing the graph then repeats the steps without evaluating op by op. Nodes
+    /// computed before the trace that the outputs depend on are part of the graph as well,
+    /// so values that stay constant are best made inputs.
+    pub fn trace<F>(&mut self, ctx: &mut Context, steps: F) -> Result<ComputeGraph>
+    where
+        F: FnOnce(&mut Self, &mut Context) -> Result<Vec<Tensor>>,
+    {
+        if self.tracing {
+            return Err(Error::msg("traces cannot be nested").context("in Eager::trace"));
+        }
+        self.tracing = true;
+        let outputs = steps(self, ctx).and_then(|outputs| {
+            outputs.iter().try_for_each(|output| self.eval_node(ctx, output))?;
+            Ok(outputs)
+        });
+        self.tracing = false;
+
+        let trace = || {
+            let graph = ComputeGraph::new();
+            for output in &outputs? {
+                graph.add_output(ctx, output)?;
+            }
+            Ok(graph)
+        };
+        trace().map_err(|e: Error| e.context("in Eager::trace"))
+    }
+
+    fn eval_node(&mut self, ctx: &Context, tensor: &Tensor) -> Result<()> {
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(ctx, tensor.tensor_id(), false)?;
+        for &id in graph.leafs().iter() {
+            if ctx.get_tensor(id)?.borrow().storage.is_none() {
+                return Err(Error::msg(format!("leaf {} is not allocated", id.as_usize())));
+            }
+        }
+
+        let pending: Vec<TensorId> =
+            graph.nodes().iter().copied().filter(|id| !self.computed.contains(id)).collect();
+        let mut unbound = Vec::new();
+        let mut views = Vec::new();
+        for &id in &pending {
+            let node = ctx.get_tensor(id)?;
+            if node.borrow().storage.is_some() {
+                continue;
+            }
+            if node.borrow().view_tensor.is_some() {
+                views.push(node.clone());
+            } else {
+                unbound.push(node.clone());
+            }
+        }
+        if !unbound.is_empty() {
+            self.buffers.push(self.backend.alloc(&unbound)?);
+        }
+        // Views share the memory of their sources, which may sit in earlier buffers.
+        for mut view in views {
+            let source = view.borrow().view_tensor.clone();
+            let storage = source.map(|source| source.storage().map(|storage| storage.clone()));
+            view.set_storage(storage.transpose()?)?;
+        }
+
+        for &id in &pending {
+            if !self.backend.inner().compute_node(ctx, id)? {
+                // The backend only computes whole graphs; earlier nodes are computed again.
+                self.backend.compute(ctx, &mut graph)?;
+                break;
+            }
+        }
+        self.backend.inner().synchronize()?;
+
+        self.computed.extend(pending);
+        Ok(())
+    }
+}
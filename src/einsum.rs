@@ -0,0 +1,218 @@
+//! Einstein-summation contractions lowered to `permute`, `cont`, `reshape` and `mul_mat`
+//! nodes.
+//!
+//! Subscripts follow NumPy and name dimensions from the outermost to the innermost, so
+//! `"ij"` on a `[ne0, ne1]` tensor binds `i` to `ne1` and `j` to `ne0`. One or two
+//! operands are supported. Every index must either appear in the output or be shared by
+//! both operands; diagonals and single-operand sums have no lowering yet and are
+//! rejected when the graph is built.
+
+use crate::collections::HashMap;
+use crate::defs::MAX_DIMS;
+use crate::error::{Error, Result};
+use crate::shape::Shape;
+use crate::tensor::Tensor;
+use alloc::format;
+use alloc::vec::Vec;
+
+/// Parsed subscripts, each listed innermost dimension first like tensor shapes.
+#[derive(Debug, PartialEq, Eq)]
+struct Subscripts {
+    inputs: Vec<Vec<char>>,
+    output: Vec<char>,
+}
+
+fn invalid(subscripts: &str, reason: impl core::fmt::Display) -> Error {
+    Error::msg(format!("invalid einsum subscripts \"{subscripts}\": {reason}")).context("in einsum")
+}
+
+fn parse_term(subscripts: &str, term: &str) -> Result<Vec<char>> {
+    let letters: Vec<char> = term.trim().chars().collect();
+    if let Some(c) = letters.iter().find(|c| !c.is_ascii_alphabetic()) {
+        return Err(invalid(subscripts, format!("unexpected character '{c}'")));
+    }
+    if letters.len() > MAX_DIMS {
+        return Err(invalid(subscripts, format!("\"{term}\" has more than {MAX_DIMS} indices")));
+    }
+    for (i, c) in letters.iter().enumerate() {
+        if letters[i + 1..].contains(c) {
+            return Err(invalid(subscripts, format!("index '{c}' repeats within \"{term}\"")));
+        }
+    }
+    Ok(letters.into_iter().rev().collect())
+}
+
+fn parse(subscripts: &str, n_operands: usize) -> Result<Subscripts> {
+    let (lhs, rhs) = match subscripts.split_once("->") {
+        Some((lhs, rhs)) => (lhs, Some(rhs)),
+        None => (subscripts, None),
+    };
+
+    let inputs =
+        lhs.split(',').map(|term| parse_term(subscripts, term)).collect::<Result<Vec<_>>>()?;
+    if inputs.len() != n_operands || !(1..=2).contains(&n_operands) {
+        return Err(invalid(
+            subscripts,
+            format!("{} terms for {n_operands} operands, expected 1 or 2", inputs.len()),
+        ));
+    }
+
+    let count = |c: char| inputs.iter().filter(|term| term.contains(&c)).count();
+    let output = match rhs {
+        Some(rhs) => parse_term(subscripts, rhs)?,
+        // implicit mode: indices that appear once, in alphabetical order
+        None => {
+            let mut once: Vec<char> = inputs.iter().flatten().copied().collect();
+            once.retain(|&c| count(c) == 1);
+            once.sort_unstable();
+            once.into_iter().rev().collect()
+        }
+    };
+    // the product is reshaped to the free and batch indices, which are exactly the output
+    if output.len() > MAX_DIMS {
+        return Err(invalid(subscripts, format!("output has more than {MAX_DIMS} indices")));
+    }
+
+    if let Some(c) = output.iter().find(|&&c| count(c) == 0) {
+        return Err(invalid(subscripts, format!("output index '{c}' is not in any input")));
+    }
+    if let Some(c) = inputs.iter().flatten().find(|&&c| count(c) == 1 && !output.contains(&c)) {
+        return Err(invalid(
+            subscripts,
+            format!("summing index '{c}' of a single operand is not supported"),
+        ));
+    }
+
+    Ok(Subscripts { inputs, output })
+}
+
+/// Permutes `tensor`, whose dimensions are named by `letters`, so that they follow
+/// `order`, then makes the result contiguous. Returns `tensor` itself if nothing moves.
+fn arrange(tensor: &Tensor, letters: &[char], order: &[char]) -> Result<Tensor> {
+    let mut tensor = tensor.clone();
+    let mut axes = [0, 1, 2, 3];
+    for (src, c) in letters.iter().enumerate() {
+        axes[src] = order.iter().position(|o| o == c).ok_or_else(|| {
+            Error::msg(format!("index '{c}' is missing from {order:?}")).context("in einsum")
+        })?;
+    }
+
+    if axes != [0, 1, 2, 3] {
+        tensor = tensor.permute(axes)?;
+    }
+    if !tensor.is_contiguous() {
+        tensor = tensor.cont()?;
+    }
+    Ok(tensor)
+}
+
+fn shape_of(letters: &[char], sizes: &HashMap<char, usize>) -> Shape {
+    let dims: Vec<usize> = letters.iter().map(|c| sizes[c]).collect();
+    if dims.is_empty() {
+        Shape::new(&[1])
+    } else {
+        Shape::new(&dims)
+    }
+}
+
+fn product(letters: &[char], sizes: &HashMap<char, usize>) -> usize {
+    letters.iter().map(|c| sizes[c]).product()
+}
+
+/// Builds the nodes computing the contraction described by `subscripts`, e.g.
+/// `"ij,jk->ik"` for a matrix product or `"bhqd,bhkd->bhqk"` for attention scores.
+///
+/// Subscripts and operand shapes are checked before any node is created. Contractions of
+/// two operands produce F32, like [`Tensor::mul_mat`].
+pub fn einsum(subscripts: &str, operands: &[Tensor]) -> Result<Tensor> {
+    let spec = parse(subscripts, operands.len())?;
+
+    let mut sizes = HashMap::new();
+    for (term, operand) in spec.inputs.iter().zip(operands) {
+        let shape = *operand.shape();
+        if shape.rank != term.len() {
+            return Err(invalid(
+                subscripts,
+                format!("operand of rank {} for {} indices", shape.rank, term.len()),
+            ));
+        }
+        for (axis, &c) in term.iter().enumerate() {
+            let size = *sizes.entry(c).or_insert(shape.dim(axis));
+            if size != shape.dim(axis) {
+                return Err(invalid(
+                    subscripts,
+                    format!("index '{c}' is both {size} and {}", shape.dim(axis)),
+                ));
+            }
+        }
+    }
+
+    if let [a] = spec.inputs.as_slice() {
+        let mut result = arrange(&operands[0], a, &spec.output)?;
+        if result.tensor_id() == operands[0].tensor_id() {
+            result = result.cont()?;
+        }
+        return Ok(result);
+    }
+
+    let (a, b) = (&spec.inputs[0], &spec.inputs[1]);
+    let contracted: Vec<char> =
+        a.iter().copied().filter(|c| b.contains(c) && !spec.output.contains(c)).collect();
+    let batch: Vec<char> =
+        spec.output.iter().copied().filter(|c| a.contains(c) && b.contains(c)).collect();
+    let free_a: Vec<char> = a.iter().copied().filter(|c| !b.contains(c)).collect();
+    let free_b: Vec<char> = b.iter().copied().filter(|c| !a.contains(c)).collect();
+
+    let (k, n_batch) = (product(&contracted, &sizes), product(&batch, &sizes));
+    let a_order: Vec<char> =
+        [&contracted, &free_a, &batch].into_iter().flatten().copied().collect();
+    let b_order: Vec<char> =
+        [&contracted, &free_b, &batch].into_iter().flatten().copied().collect();
+
+    let mut lhs = arrange(&operands[0], a, &a_order)?.reshape(&Shape::new(&[
+        k,
+        product(&free_a, &sizes),
+        n_batch,
+    ]))?;
+    let rhs = arrange(&operands[1], b, &b_order)?.reshape(&Shape::new(&[
+        k,
+        product(&free_b, &sizes),
+        n_batch,
+    ]))?;
+
+    let letters: Vec<char> = [&free_a, &free_b, &batch].into_iter().flatten().copied().collect();
+    let product = lhs.mul_mat(rhs)?.reshape(&shape_of(&letters, &sizes))?;
+    arrange(&product, &letters, &spec.output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_explicit_and_implicit() {
+        let spec = parse("ij,jk->ik", 2).unwrap();
+        assert_eq!(spec.inputs, vec![vec!['j', 'i'], vec!['k', 'j']]);
+        assert_eq!(spec.output, vec!['k', 'i']);
+
+        let implicit = parse("ij,jk", 2).unwrap();
+        assert_eq!(implicit, spec);
+        assert_eq!(parse(" ba ", 1).unwrap().output, vec!['b', 'a']);
+    }
+
+    #[test]
+    fn test_parse_rejects_unsupported_subscripts() {
+        for (subscripts, n_operands, reason) in [
+            ("ij,jk->ik", 1, "operands"),
+            ("ii->i", 1, "repeats"),
+            ("ij->i", 1, "single operand"),
+            ("ij,jk->ix", 2, "not in any input"),
+            ("i1->i", 1, "unexpected character"),
+            ("abcde->abcde", 1, "more than"),
+            ("abc,de", 2, "more than"),
+        ] {
+            let err = parse(subscripts, n_operands).unwrap_err().to_string();
+            assert!(err.contains(reason), "{subscripts}: {err}");
+        }
+    }
+}
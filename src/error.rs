@@ -13,8 +13,9 @@ use crate::data_type::DataType;
 #[cfg(test)]
 use crate::shape;
 use crate::shape::Shape;
-use std::borrow::Cow;
-use std::fmt;
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+use core::fmt;
 
 /// The underlying kind of error that can occur in the feml library.
 ///
@@ -92,13 +93,14 @@ pub enum ErrorKind {
     ///
     /// @brief I/O operation error.
     /// @param e The underlying std::io::Error.
+    #[cfg(feature = "std")]
     Io(std::io::Error),
 
     /// Integer parsing error wrapper.
     ///
     /// @brief Integer parsing error.
     /// @param e The underlying std::num::ParseIntError.
-    ParseInt(std::num::ParseIntError),
+    ParseInt(core::num::ParseIntError),
 
     // ===== Runtime =====
     /// Generic error message.
@@ -133,8 +135,10 @@ pub struct Error {
     /// Additional context information providing details about where/why the error occurred.
     context: Vec<Cow<'static, str>>,
     /// Optional file path associated with the error.
+    #[cfg(feature = "std")]
     path: Option<std::path::PathBuf>,
     /// Optional backtrace captured at the time of error creation (feature-dependent).
+    #[cfg(feature = "std")]
     backtrace: Option<std::backtrace::Backtrace>,
 }
 
@@ -145,7 +149,14 @@ impl Error {
     /// @param kind The underlying error kind.
     /// @return A new Error instance with empty context, no path, and optional backtrace.
     pub fn new(kind: ErrorKind) -> Self {
-        Self { kind, context: Vec::new(), path: None, backtrace: capture_backtrace() }
+        Self {
+            kind,
+            context: Vec::new(),
+            #[cfg(feature = "std")]
+            path: None,
+            #[cfg(feature = "std")]
+            backtrace: capture_backtrace(),
+        }
     }
 
     /// Creates a new Error from a message.
@@ -196,6 +207,7 @@ impl Error {
     /// # use feml::error::Error;
     /// let err = Error::msg("file not found").with_path("/data/weights.bin");
     /// ```
+    #[cfg(feature = "std")]
     pub fn with_path(mut self, p: impl Into<std::path::PathBuf>) -> Self {
         self.path = Some(p.into());
         self
@@ -210,6 +222,7 @@ impl Error {
 /// @note This function is conditionally compiled based on the "backtrace" feature.
 /// @note Even when the feature is enabled, backtrace capture may fail if
 ///       the backtrace status is not Captured.
+#[cfg(feature = "std")]
 fn capture_backtrace() -> Option<std::backtrace::Backtrace> {
     #[cfg(feature = "backtrace")]
     {
@@ -239,11 +252,13 @@ impl fmt::Display for Error {
         }
 
         // 3 print path
+        #[cfg(feature = "std")]
         if let Some(p) = &self.path {
             write!(f, "\npath: {:?}", p)?;
         }
 
         // 4️ print backtrace
+        #[cfg(feature = "std")]
         if let Some(bt) = &self.backtrace {
             write!(f, "\n{bt}")?;
         }
@@ -267,7 +282,7 @@ impl fmt::Display for ErrorKind {
             }
 
             ErrorKind::UnexpectedNumberOfDims { expected, got, shape } => {
-                write!(f, "unexpected rank, expected: {expected}, got: {got} ({shape:?})")
+                write!(f, "unexpected rank, expected: {expected}, got: {got} ({shape})")
             }
 
             ErrorKind::BackendUnavailable { backend } => {
@@ -293,6 +308,7 @@ impl fmt::Display for ErrorKind {
             #[cfg(feature = "opencl")]
             ErrorKind::OpenCl(e) => write!(f, "{e}"),
 
+            #[cfg(feature = "std")]
             ErrorKind::Io(e) => write!(f, "{e}"),
 
             ErrorKind::ParseInt(e) => write!(f, "{e}"),
@@ -305,15 +321,16 @@ impl fmt::Display for ErrorKind {
 /// Implementation of the standard Error trait.
 ///
 /// This enables Error to be used with Rust's error handling infrastructure.
-impl std::error::Error for Error {
+impl core::error::Error for Error {
     /// Returns the underlying source error if one exists.
     ///
     /// @brief Get the underlying source error.
     /// @return Some(source) for Io and ParseInt errors, None otherwise.
     ///
     /// @note Only Io and ParseInt error variants have a source error.
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
         match &self.kind {
+            #[cfg(feature = "std")]
             ErrorKind::Io(e) => Some(e),
             ErrorKind::ParseInt(e) => Some(e),
             #[cfg(feature = "opencl")]
@@ -330,6 +347,7 @@ impl std::error::Error for Error {
 /// @return An Error instance wrapping the I/O error.
 ///
 /// @note This enables the `?` operator to work with std::io::Error automatically.
+#[cfg(feature = "std")]
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
         Error::new(ErrorKind::Io(e))
@@ -343,8 +361,8 @@ impl From<std::io::Error> for Error {
 /// @return An Error instance wrapping the parse error.
 ///
 /// @note This enables the `?` operator to work with std::num::ParseIntError automatically.
-impl From<std::num::ParseIntError> for Error {
-    fn from(e: std::num::ParseIntError) -> Self {
+impl From<core::num::ParseIntError> for Error {
+    fn from(e: core::num::ParseIntError) -> Self {
         Error::new(ErrorKind::ParseInt(e))
     }
 }
@@ -369,15 +387,17 @@ impl From<cuda_core::DriverError> for Error {
     }
 }
 
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = core::result::Result<T, Error>;
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::error::Error as _;
+    #[cfg(feature = "std")]
     use std::io;
 
     // Test Error::new()
+    #[cfg(feature = "std")]
     #[test]
     fn test_error_new() {
         let err = Error::new(ErrorKind::Msg("test error".into()));
@@ -407,6 +427,7 @@ mod tests {
     }
 
     // Test Error::with_path()
+    #[cfg(feature = "std")]
     #[test]
     fn test_error_with_path() {
         let err = Error::msg("file error").with_path("/tmp/test.txt");
@@ -414,6 +435,7 @@ mod tests {
     }
 
     // Test chained builder pattern
+    #[cfg(feature = "std")]
     #[test]
     fn test_error_builder_chain() {
         let err = Error::msg("operation failed")
@@ -456,9 +478,11 @@ mod tests {
         assert!(s.contains("unexpected rank"));
         assert!(s.contains("expected: 3"));
         assert!(s.contains("got: 4"));
+        assert!(s.contains("(1x3x224x224)"));
     }
 
     // Test Display for Io error
+    #[cfg(feature = "std")]
     #[test]
     fn test_display_io() {
         let io_err = io::Error::new(io::ErrorKind::NotFound, "file not found");
@@ -485,6 +509,7 @@ mod tests {
     }
 
     // Test full Error Display with context and path
+    #[cfg(feature = "std")]
     #[test]
     fn test_display_full_error() {
         let err = Error::msg("base error")
@@ -499,6 +524,7 @@ mod tests {
     }
 
     // Test Error source() for Io
+    #[cfg(feature = "std")]
     #[test]
     fn test_error_source_io() {
         let io_err = io::Error::new(io::ErrorKind::PermissionDenied, "access denied");
@@ -522,6 +548,7 @@ mod tests {
     }
 
     // Test From<io::Error>
+    #[cfg(feature = "std")]
     #[test]
     fn test_from_io_error() {
         let io_err = io::Error::new(io::ErrorKind::Other, "io error");
@@ -538,6 +565,7 @@ mod tests {
     }
 
     // Test backtrace is captured when feature is enabled
+    #[cfg(feature = "std")]
     #[test]
     fn test_backtrace_captured() {
         let err = Error::msg("test");
@@ -0,0 +1,182 @@
+//! Per-op timing across many graph computes.
+//!
+//! A single compute is too noisy, and too short, to show where an inference session spends
+//! its time. [`ExecStats`] accumulates the time of every node by op over any number of
+//! computes; [`StatsObserver`] fills it from a backend:
+//!
+//! ```no_run
+//! # fn main() -> feml::error::Result<()> {
+//! # let ctx = feml::context::Context::builder().build();
+//! # let mut graph = feml::compute_graph::ComputeGraph::new();
+//! use feml::exec_stats::{ExecStats, StatsObserver};
+//! use std::sync::{Arc, Mutex};
+//!
+//! let mut backend = feml::Backend::cpu().build()?;
+//! let stats = Arc::new(Mutex::new(ExecStats::default()));
+//! backend.inner_mut().set_observer(Some(Box::new(StatsObserver::new(stats.clone()))))?;
+//! for _ in 0..100 {
+//!     backend.compute(&ctx, &mut graph)?;
+//! }
+//! print!("{}", stats.lock().unwrap());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::backend::GraphObserver;
+use crate::compute_graph::ComputeGraph;
+use crate::data_type::TensorOpType;
+use crate::tensor::Tensor;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Timings of one op.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpStats {
+    /// Nodes of the op that were computed.
+    pub count: usize,
+    pub total: Duration,
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl OpStats {
+    fn new(elapsed: Duration) -> Self {
+        Self { count: 1, total: elapsed, min: elapsed, max: elapsed }
+    }
+
+    pub fn avg(&self) -> Duration {
+        self.total / self.count.max(1) as u32
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExecStats {
+    ops: HashMap<TensorOpType, OpStats>,
+    n_graphs: usize,
+}
+
+impl ExecStats {
+    /// Adds one node of `op` that took `elapsed`.
+    pub fn record(&mut self, op: TensorOpType, elapsed: Duration) {
+        self.ops
+            .entry(op)
+            .and_modify(|stats| {
+                stats.count += 1;
+                stats.total += elapsed;
+                stats.min = stats.min.min(elapsed);
+                stats.max = stats.max.max(elapsed);
+            })
+            .or_insert_with(|| OpStats::new(elapsed));
+    }
+
+    /// Graph computes seen by a [`StatsObserver`].
+    pub fn n_graphs(&self) -> usize {
+        self.n_graphs
+    }
+
+    pub fn op(&self, op: TensorOpType) -> Option<&OpStats> {
+        self.ops.get(&op)
+    }
+
+    /// Time of all recorded nodes.
+    pub fn total(&self) -> Duration {
+        self.ops.values().map(|stats| stats.total).sum()
+    }
+
+    /// The ops by total time, largest first.
+    pub fn ranked(&self) -> Vec<(TensorOpType, OpStats)> {
+        let mut ranked: Vec<_> = self.ops.iter().map(|(op, stats)| (*op, *stats)).collect();
+        ranked.sort_by(|a, b| b.1.total.cmp(&a.1.total).then(b.1.count.cmp(&a.1.count)));
+        ranked
+    }
+
+    pub fn reset(&mut self) {
+        self.ops.clear();
+        self.n_graphs = 0;
+    }
+}
+
+/// The [ranked](ExecStats::ranked) ops, one line each with their share of the total time,
+/// count and min/avg/max time per node.
+impl fmt::Display for ExecStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total();
+        writeln!(f, "{} graphs, {total:.3?} in ops", self.n_graphs)?;
+        let ranked = self.ranked();
+        let width = ranked.iter().map(|(op, _)| format!("{op:?}").len()).max().unwrap_or(0);
+        for (op, stats) in ranked {
+            let share = stats.total.as_secs_f64() / total.as_secs_f64().max(f64::MIN_POSITIVE);
+            writeln!(
+                f,
+                "{:<width$}  {:>5.1}%  {:>8}  {:>10.3?}  min {:.3?}  avg {:.3?}  max {:.3?}",
+                format!("{op:?}"),
+                100.0 * share,
+                stats.count,
+                stats.total,
+                stats.min,
+                stats.avg(),
+                stats.max,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Observer timing every node into a shared [`ExecStats`]. The time includes the backend's
+/// dispatch of the node, and for asynchronous backends only the time to queue it.
+pub struct StatsObserver {
+    stats: Arc<Mutex<ExecStats>>,
+    node_start: Option<Instant>,
+}
+
+impl StatsObserver {
+    pub fn new(stats: Arc<Mutex<ExecStats>>) -> Self {
+        Self { stats, node_start: None }
+    }
+}
+
+impl GraphObserver for StatsObserver {
+    fn on_graph_begin(&mut self, _graph: &ComputeGraph) {
+        if let Ok(mut stats) = self.stats.lock() {
+            stats.n_graphs += 1;
+        }
+    }
+
+    fn on_node_begin(&mut self, _tensor: &Tensor) {
+        self.node_start = Some(Instant::now());
+    }
+
+    fn on_node_end(&mut self, tensor: &Tensor) -> bool {
+        if let (Some(start), Ok(mut stats)) = (self.node_start.take(), self.stats.lock()) {
+            stats.record(tensor.op_type(), start.elapsed());
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exec_stats_record_and_rank() {
+        let mut stats = ExecStats::default();
+        let ms = Duration::from_millis;
+        stats.record(TensorOpType::TensorOpMul, ms(1));
+        stats.record(TensorOpType::TensorOpMulMat, ms(6));
+        stats.record(TensorOpType::TensorOpMul, ms(3));
+
+        let mul = stats.op(TensorOpType::TensorOpMul).unwrap();
+        assert_eq!((mul.count, mul.min, mul.avg(), mul.max), (2, ms(1), ms(2), ms(3)));
+        let ranked: Vec<_> = stats.ranked().into_iter().map(|(op, _)| op).collect();
+        assert_eq!(ranked, [TensorOpType::TensorOpMulMat, TensorOpType::TensorOpMul]);
+        assert_eq!(stats.total(), ms(10));
+
+        let summary = stats.to_string();
+        assert!(summary.lines().nth(1).unwrap().starts_with("TensorOpMulMat   60.0%"));
+        stats.reset();
+        assert!(stats.ranked().is_empty());
+    }
+}
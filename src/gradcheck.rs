@@ -0,0 +1,139 @@
+//! Finite-difference gradient checking.
+//!
+//! [`check_gradients`] estimates the gradient of a scalar graph output with respect to each
+//! parameter by central differences, `(f(x + eps) - f(x - eps)) / 2eps` for one element at a
+//! time, and compares the estimate with gradients computed analytically, e.g. by a backward
+//! graph built from the `*_back` ops. Every element costs two graph computes, so keep the
+//! checked parameters small.
+
+use crate::backend::Backend;
+use crate::compute_graph::ComputeGraph;
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::tensor::Tensor;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GradCheckOptions {
+    /// Step added to and subtracted from each element.
+    pub eps: f32,
+    /// Largest relative error of a passing parameter.
+    pub tolerance: f32,
+}
+
+impl Default for GradCheckOptions {
+    fn default() -> Self {
+        Self { eps: 1e-3, tolerance: 1e-2 }
+    }
+}
+
+/// Comparison of the numeric and analytic gradient of one parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradientError {
+    pub name: String,
+    pub max_abs_error: f32,
+    /// `|analytic - numeric| / max(|analytic|, |numeric|)`, zero where both are zero.
+    pub max_rel_error: f32,
+    /// Flat index, innermost dimension first, of the element with the largest relative
+    /// error.
+    pub worst_index: usize,
+    pub passed: bool,
+}
+
+/// Checks the gradients of the single element of `output`, computed by `graph`, with respect
+/// to each `(param, grad)` pair, where `grad` holds the analytic gradient in the shape of
+/// `param`. The parameters must be bound to buffers and are restored afterwards; `graph` is
+/// computed on `backend` repeatedly.
+pub fn check_gradients(
+    backend: &dyn Backend,
+    ctx: &Context,
+    graph: &mut ComputeGraph,
+    output: &Tensor,
+    params: &[(Tensor, Tensor)],
+    options: &GradCheckOptions,
+) -> Result<Vec<GradientError>> {
+    if element_count(output) != 1 {
+        return Err(
+            Error::msg("gradient check needs a scalar output").context("in check_gradients")
+        );
+    }
+    params
+        .iter()
+        .map(|(param, grad)| check_param(backend, ctx, graph, output, param, grad, options))
+        .collect::<Result<_>>()
+        .map_err(|e| e.context("in check_gradients"))
+}
+
+fn check_param(
+    backend: &dyn Backend,
+    ctx: &Context,
+    graph: &mut ComputeGraph,
+    output: &Tensor,
+    param: &Tensor,
+    grad: &Tensor,
+    options: &GradCheckOptions,
+) -> Result<GradientError> {
+    if *param.shape() != *grad.shape() {
+        return Err(Error::msg(format!(
+            "gradient of {} has shape {}, expected {}",
+            param.name(),
+            grad.shape(),
+            param.shape()
+        )));
+    }
+
+    let mut result = GradientError {
+        name: param.name(),
+        max_abs_error: 0.0,
+        max_rel_error: 0.0,
+        worst_index: 0,
+        passed: true,
+    };
+    let mut evaluate = |value: f32, index: &[usize]| -> Result<f32> {
+        param.set_f32_nd(index, value)?;
+        backend.graph_compute(ctx, graph)?;
+        backend.synchronize()?;
+        output.get_f32_nd(&[])
+    };
+
+    for flat in 0..element_count(param) {
+        let index = unravel(param, flat);
+        let value = param.get_f32_nd(&index)?;
+        let plus = evaluate(value + options.eps, &index);
+        let minus = evaluate(value - options.eps, &index);
+        param.set_f32_nd(&index, value)?;
+        let numeric = (plus? - minus?) / (2.0 * options.eps);
+
+        let analytic = grad.get_f32_nd(&index)?;
+        let abs_error = (analytic - numeric).abs();
+        let scale = analytic.abs().max(numeric.abs());
+        let rel_error = if scale == 0.0 { 0.0 } else { abs_error / scale };
+        result.max_abs_error = result.max_abs_error.max(abs_error);
+        if rel_error > result.max_rel_error {
+            result.max_rel_error = rel_error;
+            result.worst_index = flat;
+        }
+    }
+    result.passed = result.max_rel_error <= options.tolerance;
+    Ok(result)
+}
+
+fn element_count(tensor: &Tensor) -> usize {
+    let shape = tensor.shape();
+    shape.dims[..shape.rank].iter().product()
+}
+
+/// Coordinates of the `flat`-th element, innermost dimension first.
+fn unravel(tensor: &Tensor, mut flat: usize) -> Vec<usize> {
+    let shape = tensor.shape();
+    shape.dims[..shape.rank]
+        .iter()
+        .map(|&dim| {
+            let i = flat % dim;
+            flat /= dim;
+            i
+        })
+        .collect()
+}
@@ -0,0 +1,434 @@
+//! Key/value cache for autoregressive attention.
+//!
+//! Every layer stores its keys and values as `[head_dim, n_ctx, n_kv_heads]` tensors, the
+//! layout [`Tensor::attention`] reads. Only the `n_kv_heads` heads a grouped-query model
+//! produces are kept; attention broadcasts them over the query heads.
+//!
+//! A cache with a sliding window (see [`KvCache::with_window`]) is a ring buffer: position
+//! `p` lives in slot `p % n_ctx`, so a model attending to the last `window` positions runs
+//! for any number of tokens in `n_ctx` slots.
+
+use crate::context::Context;
+use crate::data_type::DataType;
+use crate::error::{Error, Result};
+use crate::memory::MemoryCategory;
+use crate::model_meta::RopeParams;
+use crate::serialize::{decode_tensors, encode_tensors, TensorData};
+use crate::shape::Shape;
+use crate::tensor::Tensor;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+
+/// Name of the logits record of a saved state.
+const LOGITS: &str = "logits";
+/// Name of the record holding the number of positions of a saved sliding-window state.
+const POSITIONS: &str = "positions";
+
+pub struct KvCache {
+    keys: Vec<Tensor>,
+    values: Vec<Tensor>,
+    n_ctx: usize,
+    n_kv_heads: usize,
+    head_dim: usize,
+    n_tokens: usize,
+    window: Option<usize>,
+}
+
+impl KvCache {
+    /// Creates the key and value tensors of `n_layers` layers in `ctx`. They still have to
+    /// be bound to a buffer before use, see [`KvCache::tensors`].
+    pub fn new(
+        ctx: &mut Context,
+        dtype: DataType,
+        n_layers: usize,
+        n_ctx: usize,
+        n_kv_heads: usize,
+        head_dim: usize,
+    ) -> Result<Self> {
+        let shape = Shape::new(&[head_dim, n_ctx, n_kv_heads]);
+        let mut keys = Vec::with_capacity(n_layers);
+        let mut values = Vec::with_capacity(n_layers);
+        for layer in 0..n_layers {
+            let k = ctx.new_tensor(dtype, &shape)?;
+            let v = ctx.new_tensor(dtype, &shape)?;
+            k.set_name(format!("cache_k_l{layer}")).set_memory_category(MemoryCategory::KvCache);
+            v.set_name(format!("cache_v_l{layer}")).set_memory_category(MemoryCategory::KvCache);
+            keys.push(k);
+            values.push(v);
+        }
+
+        Ok(Self { keys, values, n_ctx, n_kv_heads, head_dim, n_tokens: 0, window: None })
+    }
+
+    /// Turns the cache into a ring buffer for sliding-window attention over the last
+    /// `window` positions. Positions past `n_ctx` overwrite the oldest slots; a batch of
+    /// `n` tokens needs `n + window - 1 <= n_ctx` so that no query loses a key it still
+    /// attends to.
+    pub fn with_window(mut self, window: usize) -> Result<Self> {
+        if window == 0 || window > self.n_ctx {
+            return Err(Error::msg(format!(
+                "window of {window} positions does not fit the cache of {} positions",
+                self.n_ctx
+            ))
+            .context("in KvCache::with_window"));
+        }
+        self.window = Some(window);
+        Ok(self)
+    }
+
+    pub fn window(&self) -> Option<usize> {
+        self.window
+    }
+
+    pub fn n_layers(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn n_ctx(&self) -> usize {
+        self.n_ctx
+    }
+
+    pub fn n_kv_heads(&self) -> usize {
+        self.n_kv_heads
+    }
+
+    pub fn head_dim(&self) -> usize {
+        self.head_dim
+    }
+
+    /// Number of slots holding a position. Only a sliding-window cache holds fewer
+    /// positions than it has seen, see [`KvCache::n_past`].
+    pub fn len(&self) -> usize {
+        self.n_tokens.min(self.n_ctx)
+    }
+
+    /// Number of positions seen so far.
+    pub fn n_past(&self) -> usize {
+        self.n_tokens
+    }
+
+    /// Slot of `position`.
+    pub fn slot(&self, position: usize) -> usize {
+        position % self.n_ctx
+    }
+
+    /// `(start, len)` slot ranges the next `len` positions go to, for [`KvCache::view`]. A
+    /// sliding-window cache returns two ranges when the positions wrap around its end.
+    pub fn next_slots(&self, len: usize) -> Result<Vec<(usize, usize)>> {
+        self.check_batch(len).map_err(|e| e.context("in KvCache::next_slots"))?;
+        let start = self.slot(self.n_tokens);
+        Ok(if start + len <= self.n_ctx {
+            vec![(start, len)]
+        } else {
+            vec![(start, self.n_ctx - start), (0, start + len - self.n_ctx)]
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.n_tokens == 0
+    }
+
+    /// All key and value tensors, layer by layer.
+    pub fn tensors(&self) -> impl Iterator<Item = &Tensor> {
+        self.keys.iter().zip(&self.values).flat_map(|(k, v)| [k, v])
+    }
+
+    pub fn keys(&self, layer: usize) -> Result<&Tensor> {
+        self.keys.get(layer).ok_or_else(|| self.missing_layer(layer))
+    }
+
+    pub fn values(&self, layer: usize) -> Result<&Tensor> {
+        self.values.get(layer).ok_or_else(|| self.missing_layer(layer))
+    }
+
+    /// Key and value views `[head_dim, len, n_kv_heads]` over positions `start..start + len`
+    /// of `layer`.
+    pub fn view(&self, layer: usize, start: usize, len: usize) -> Result<(Tensor, Tensor)> {
+        if len == 0 || start.checked_add(len).is_none_or(|end| end > self.n_ctx) {
+            return Err(Error::msg(format!(
+                "positions {start}..{} are outside the cache of {} positions",
+                start.saturating_add(len),
+                self.n_ctx
+            ))
+            .context("in KvCache::view"));
+        }
+
+        let shape = Shape::new(&[self.head_dim, len, self.n_kv_heads]);
+        let mut keys = self.keys(layer)?.clone();
+        let mut values = self.values(layer)?.clone();
+        let offset = start * keys.stride()[1];
+        Ok((keys.view(&shape, offset)?, values.view(&shape, offset)?))
+    }
+
+    /// Rotates the keys `k` of new tokens with `rope` and stores them and the values `v` in
+    /// `layer` in one op, without the permuted and rotated intermediates, see
+    /// [`Tensor::rope_store_kv`]. `k` and `v` are `[head_dim, n_kv_heads, n_tokens]` and
+    /// `positions` holds the position of every token, which also picks its slot. Call
+    /// [`KvCache::advance`] once the graph has run.
+    pub fn store_rope(
+        &self,
+        layer: usize,
+        k: &mut Tensor,
+        v: &Tensor,
+        positions: &Tensor,
+        rope: RopeParams,
+    ) -> Result<Tensor> {
+        self.check_batch(k.shape().dim(2)).map_err(|e| e.context("in KvCache::store_rope"))?;
+        k.rope_store_kv(v, positions, self.keys(layer)?, self.values(layer)?, rope)
+    }
+
+    /// Key and value views over every slot holding a position, in slot order.
+    pub fn history(&self, layer: usize) -> Result<(Tensor, Tensor)> {
+        self.view(layer, 0, self.len())
+    }
+
+    /// Causal `[len, n_q]` mask of [`KvCache::history`] for the last `n_q` positions: 0
+    /// where the query attends to the slot and negative infinity elsewhere. Keys outside
+    /// the sliding window are masked too, wherever the ring buffer put them.
+    pub fn attention_mask(&self, n_q: usize) -> Result<Vec<f32>> {
+        if n_q > self.len() {
+            return Err(Error::msg(format!(
+                "{n_q} queries exceed the {} positions held",
+                self.len()
+            ))
+            .context("in KvCache::attention_mask"));
+        }
+        let window = self.window.unwrap_or(usize::MAX);
+        let n_kv = self.len();
+        let mut mask = vec![f32::NEG_INFINITY; n_kv * n_q];
+        for (i, row) in mask.chunks_exact_mut(n_kv.max(1)).enumerate() {
+            let query = self.n_tokens - n_q + i;
+            for (slot, value) in row.iter_mut().enumerate() {
+                // latest position stored in `slot`
+                let key = slot + (self.n_tokens - 1 - slot) / self.n_ctx * self.n_ctx;
+                if key <= query && query - key < window {
+                    *value = 0.0;
+                }
+            }
+        }
+        Ok(mask)
+    }
+
+    /// Marks `n_tokens` more positions as stored.
+    pub fn advance(&mut self, n_tokens: usize) -> Result<()> {
+        self.check_batch(n_tokens).map_err(|e| e.context("in KvCache::advance"))?;
+        self.n_tokens += n_tokens;
+        Ok(())
+    }
+
+    /// Checks that `n_tokens` more positions fit, see [`KvCache::with_window`].
+    fn check_batch(&self, n_tokens: usize) -> Result<()> {
+        let message = match self.window {
+            Some(window) if n_tokens + window - 1 > self.n_ctx => format!(
+                "a batch of {n_tokens} tokens with a window of {window} needs {} positions, \
+                 the cache has {}",
+                n_tokens + window - 1,
+                self.n_ctx
+            ),
+            None if self.n_tokens + n_tokens > self.n_ctx => format!(
+                "cache of {} positions cannot hold {} tokens",
+                self.n_ctx,
+                self.n_tokens + n_tokens
+            ),
+            _ => return Ok(()),
+        };
+        Err(Error::msg(message))
+    }
+
+    pub fn clear(&mut self) {
+        self.n_tokens = 0;
+    }
+
+    /// Saves the positions held, and `logits` if given, for [`KvCache::load_state`],
+    /// e.g. to reuse the cache of a common prompt or to move a session to another process.
+    /// The state is an uncompressed tensor file, see [`encode_tensors`]. The tensors must be
+    /// bound to a buffer.
+    pub fn save_state(&self, logits: Option<&Tensor>) -> Result<Vec<u8>> {
+        let save = || {
+            let mut records = Vec::with_capacity(self.n_layers() * 2 + 2);
+            for tensor in self.tensors() {
+                records.push(TensorData {
+                    name: tensor.name(),
+                    dtype: tensor.dtype(),
+                    shape: Shape::new(&[self.head_dim, self.len(), self.n_kv_heads]),
+                    data: self.read_positions(tensor)?,
+                });
+            }
+            if self.window.is_some() {
+                records.push(TensorData {
+                    name: String::from(POSITIONS),
+                    dtype: DataType::I64,
+                    shape: Shape::new(&[1]),
+                    data: (self.n_tokens as i64).to_le_bytes().to_vec(),
+                });
+            }
+            if let Some(logits) = logits {
+                let mut record = TensorData::from_tensor(logits)?;
+                record.name = String::from(LOGITS);
+                records.push(record);
+            }
+            encode_tensors(&records)
+        };
+        save().map_err(|e| e.context("in KvCache::save_state"))
+    }
+
+    /// Restores a state saved by [`KvCache::save_state`] from a cache of the same layout,
+    /// and the logits into `logits` if both the state and the call have them. Returns
+    /// whether logits were restored.
+    pub fn load_state(&mut self, state: &[u8], logits: Option<&Tensor>) -> Result<bool> {
+        let mut load = || {
+            let records = decode_tensors(state)?;
+            let (cache, saved_logits) = match records.split_last() {
+                Some((last, rest)) if last.name == LOGITS => (rest, Some(last)),
+                _ => (&records[..], None),
+            };
+            let (cache, positions) = match cache.split_last() {
+                Some((last, rest)) if last.name == POSITIONS => (rest, Some(last)),
+                _ => (cache, None),
+            };
+            if positions.is_some() != self.window.is_some() {
+                return Err(Error::msg("state and cache differ in having a sliding window"));
+            }
+            if cache.len() != self.n_layers() * 2 {
+                return Err(Error::msg(format!(
+                    "state has {} cache tensors, the cache has {} layers",
+                    cache.len(),
+                    self.n_layers()
+                )));
+            }
+
+            let n_slots = cache.first().map_or(0, |record| record.shape.dim(1));
+            if n_slots > self.n_ctx {
+                return Err(Error::msg(format!(
+                    "state of {n_slots} positions does not fit in {} positions",
+                    self.n_ctx
+                )));
+            }
+            let n_tokens = match positions {
+                Some(record) => {
+                    let bytes = record.data.get(..8).and_then(|bytes| bytes.try_into().ok());
+                    let n_tokens = bytes.map_or(0, |bytes| i64::from_le_bytes(bytes) as usize);
+                    if n_tokens.min(self.n_ctx) != n_slots {
+                        return Err(Error::msg(format!(
+                            "state of {n_tokens} positions does not match its {n_slots} slots"
+                        )));
+                    }
+                    n_tokens
+                }
+                None => n_slots,
+            };
+            let expected = Shape::new(&[self.head_dim, n_slots, self.n_kv_heads]);
+            let tensors: Vec<Tensor> = self.tensors().cloned().collect();
+            for (tensor, record) in tensors.iter().zip(cache) {
+                if record.dtype != tensor.dtype() || record.shape != expected {
+                    return Err(Error::msg(format!(
+                        "state tensor {} is {:?} {}, expected {:?} {expected}",
+                        record.name,
+                        record.dtype,
+                        record.shape,
+                        tensor.dtype()
+                    )));
+                }
+            }
+
+            for (tensor, record) in tensors.iter().zip(cache) {
+                self.write_positions(tensor, n_slots, &record.data)?;
+            }
+            self.n_tokens = n_tokens;
+            match (saved_logits, logits) {
+                (Some(record), Some(logits)) => record.upload(logits).map(|_| true),
+                _ => Ok(false),
+            }
+        };
+        load().map_err(|e| e.context("in KvCache::load_state"))
+    }
+
+    /// Bytes of the slots holding a position of `tensor`, head by head.
+    fn read_positions(&self, tensor: &Tensor) -> Result<Vec<u8>> {
+        let (head_stride, size) = (tensor.stride()[2], self.len() * tensor.stride()[1]);
+        let storage = tensor.storage()?;
+        let mut data = vec![0; self.n_kv_heads * size];
+        for (head, chunk) in data.chunks_exact_mut(size.max(1)).enumerate() {
+            storage.buffer().read(tensor.clone(), chunk, head * head_stride, size)?;
+        }
+        Ok(data)
+    }
+
+    /// Inverse of [`KvCache::read_positions`] for `n_slots` slots.
+    fn write_positions(&self, tensor: &Tensor, n_slots: usize, data: &[u8]) -> Result<()> {
+        let (head_stride, size) = (tensor.stride()[2], n_slots * tensor.stride()[1]);
+        let storage = tensor.storage()?;
+        let mut data = data.to_vec();
+        for (head, chunk) in data.chunks_exact_mut(size.max(1)).enumerate() {
+            storage.buffer().write(tensor.clone(), chunk, head * head_stride, size)?;
+        }
+        Ok(())
+    }
+
+    fn missing_layer(&self, layer: usize) -> Error {
+        Error::msg(format!("layer {layer} is outside the cache of {} layers", self.n_layers()))
+            .context("in KvCache")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape;
+
+    #[test]
+    fn test_kv_cache_layout() {
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let cache = KvCache::new(&mut ctx, DataType::F16, 2, 8, 2, 4).unwrap();
+
+        assert_eq!(cache.tensors().count(), 4);
+        assert_eq!(&*cache.keys(1).unwrap().shape(), &shape![4, 8, 2]);
+        assert!(cache.values(2).is_err());
+
+        let (k, v) = cache.view(0, 3, 2).unwrap();
+        assert_eq!(&*k.shape(), &shape![4, 2, 2]);
+        assert_eq!(k.view_offset(), 3 * 4 * 2);
+        assert_eq!(v.stride()[2], cache.values(0).unwrap().stride()[2]);
+        assert!(cache.view(0, 7, 2).is_err());
+    }
+
+    #[test]
+    fn test_kv_cache_advance() {
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut cache = KvCache::new(&mut ctx, DataType::F32, 1, 4, 1, 2).unwrap();
+
+        assert!(cache.is_empty());
+        assert!(cache.history(0).is_err());
+        cache.advance(3).unwrap();
+        assert_eq!(&*cache.history(0).unwrap().0.shape(), &shape![2, 3, 1]);
+        assert!(cache.advance(2).is_err());
+        cache.clear();
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn test_kv_cache_sliding_window() {
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let cache = KvCache::new(&mut ctx, DataType::F32, 1, 4, 1, 2).unwrap();
+        assert!(KvCache::new(&mut ctx, DataType::F32, 1, 4, 1, 2).unwrap().with_window(5).is_err());
+        let mut cache = cache.with_window(3).unwrap();
+
+        // a batch of 3 would overwrite a key its first query still attends to
+        assert!(cache.advance(3).is_err());
+        cache.advance(2).unwrap();
+        cache.advance(2).unwrap();
+        assert_eq!((cache.len(), cache.n_past()), (4, 4));
+        assert_eq!(cache.next_slots(2).unwrap(), [(0, 2)]);
+        cache.advance(1).unwrap();
+        assert_eq!(cache.next_slots(2).unwrap(), [(1, 2)]);
+        cache.advance(2).unwrap();
+        assert_eq!(cache.next_slots(2).unwrap(), [(3, 1), (0, 1)]);
+
+        // slots hold positions [4, 5, 6, 3]; queries 5 and 6 see the last 3 positions
+        let inf = f32::NEG_INFINITY;
+        let mask = cache.attention_mask(2).unwrap();
+        assert_eq!(mask, [0.0, 0.0, inf, 0.0, 0.0, 0.0, 0.0, inf]);
+        assert_eq!(&*cache.history(0).unwrap().0.shape(), &shape![2, 4, 1]);
+    }
+}
@@ -16,6 +16,17 @@ impl Layout {
         Self { shape, stride, start_offset }
     }
 
+    /// Strides of a dense tensor of `shape`, innermost dimension first.
+    pub(crate) fn contiguous_stride(shape: &Shape, dtype: DataType) -> [usize; 4] {
+        let mut stride = [0; 4];
+        stride[0] = data_type::get_type_size(dtype);
+        stride[1] = stride[0] * (shape.dim(0) / data_type::get_block_size(dtype));
+        for i in 2..4 {
+            stride[i] = stride[i - 1] * shape.dim(i - 1);
+        }
+        stride
+    }
+
     pub(crate) fn nbytes(&self, dtype: DataType) -> usize {
         if self.shape.iter().any(|&dim| dim == 0) {
             return 0;
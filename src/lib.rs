@@ -1,4 +1,20 @@
+//! Tensor library with pluggable compute backends.
+//!
+//! Without the default `std` feature the crate is `no_std` + `alloc`: shapes, data types,
+//! layouts, tensors, graph construction and the in-memory tensor format remain available,
+//! while file IO, threads, the backend registry and the backends themselves need `std`.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod api;
 pub mod backend;
+pub mod buffer_pool;
+#[cfg(feature = "std")]
+pub mod cache;
+mod collections;
 pub mod compute_graph;
 pub mod context;
 #[cfg(feature = "cpu")]
@@ -6,14 +22,44 @@ pub mod cpu;
 #[cfg(feature = "cuda")]
 pub mod cuda;
 pub mod data_type;
+#[cfg(feature = "std")]
+pub mod debug;
 pub mod defs;
+#[cfg(feature = "std")]
+pub mod eager;
+pub mod einsum;
 pub mod error;
+#[cfg(feature = "std")]
+pub mod exec_stats;
+pub mod gradcheck;
+pub mod kv_cache;
 pub mod layout;
+#[cfg(feature = "std")]
+pub mod loader;
+pub mod memory;
+pub mod model_meta;
 mod object_pool;
 #[cfg(feature = "opencl")]
 pub mod opencl;
-mod ops;
+pub mod ops;
+#[cfg(feature = "python")]
+mod python;
+pub mod quant;
+#[cfg(feature = "std")]
 pub mod registry;
+pub mod repack;
+pub mod rng;
+pub mod serialize;
 pub mod shape;
+pub mod shape_inference;
+#[cfg(feature = "std")]
+pub mod staging;
 pub mod storage;
 pub mod tensor;
+#[cfg(feature = "std")]
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod threadpool;
+
+#[cfg(feature = "std")]
+pub use api::{Backend, BackendBuilder};
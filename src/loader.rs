@@ -0,0 +1,661 @@
+//! Layer-by-layer loading of tensor files into backend buffers.
+//!
+//! [`StreamingLoader`] reads the records of a [`TensorReader`] one layer at a time,
+//! allocates a backend buffer for the layer, uploads it and drops the host copy before
+//! reading the next layer. Peak host memory is therefore one layer rather than the whole
+//! model, which is what allows loading models larger than RAM into device memory.
+//!
+//! Tensor name patterns, in which `*` matches any run of characters, select what the
+//! loader does with each tensor: [`StreamingLoader::include`] and
+//! [`StreamingLoader::exclude`] pick the tensors to load, [`StreamingLoader::dequantize`]
+//! and [`StreamingLoader::override_dtype`] change their type on the way,
+//! [`StreamingLoader::repack`] their layout, and [`StreamingLoader::place`] puts whole
+//! layers on another backend, e.g. to offload only some layers to a device. With
+//! [`StreamingLoader::threadpool`], the tensors of a layer are converted in parallel.
+
+use crate::backend::{Backend, BackendBuffer, BackendBufferUsage};
+use crate::context::Context;
+use crate::data_type::{from_f32, get_type_size, to_f32, DataType, TensorOpType};
+use crate::error::{Error, Result};
+use crate::model_meta::ModelMeta;
+use crate::quant::{RowwiseQ8, SCALES_SUFFIX};
+use crate::repack::interleave_rows;
+use crate::serialize::{TensorData, TensorReader};
+use crate::tensor::Tensor;
+use crate::threadpool::ThreadPool;
+#[cfg(feature = "safetensors")]
+use crate::{defs::MAX_DIMS, serialize::new_leaf_tensor, shape::Shape};
+use std::io::Read;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Layer a tensor belongs to: its name up to the first purely numeric component, e.g.
+/// `blk.3` for `blk.3.attn_q.weight`. Tensors outside numbered layers, such as embeddings,
+/// form a layer named after themselves.
+pub fn layer_name(tensor_name: &str) -> &str {
+    let mut end = 0;
+    for part in tensor_name.split('.') {
+        end += part.len();
+        if !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()) {
+            return &tensor_name[..end];
+        }
+        end += 1;
+    }
+    tensor_name
+}
+
+/// [`layer_name`], except that the scales of a quantized tensor go with the tensor.
+fn record_layer(tensor_name: &str) -> &str {
+    layer_name(tensor_name.strip_suffix(SCALES_SUFFIX).unwrap_or(tensor_name))
+}
+
+/// Whether `name` matches `pattern`, in which `*` stands for any run of characters.
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // no `*`: the whole name must match
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Reported after every layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadProgress {
+    pub layer: String,
+    pub layers_loaded: usize,
+    pub tensors_loaded: usize,
+    pub bytes_loaded: usize,
+}
+
+pub type ProgressCallback<'a> = Box<dyn FnMut(&LoadProgress) + 'a>;
+
+/// Tensors of one layer, bound to their own buffer.
+pub struct LoadedLayer {
+    pub name: String,
+    pub tensors: Vec<Tensor>,
+    pub buffer: Box<dyn BackendBuffer>,
+}
+
+pub struct StreamingLoader<'a, R: Read> {
+    reader: TensorReader<R>,
+    backend: &'a dyn Backend,
+    usage: BackendBufferUsage,
+    progress: Option<ProgressCallback<'a>>,
+    /// Name patterns of the tensors to load; empty loads every tensor.
+    include: Vec<String>,
+    exclude: Vec<String>,
+    /// Name patterns of quantized tensors to dequantize, with the type to dequantize to.
+    dequantize: Vec<(String, DataType)>,
+    dtypes: Vec<(String, DataType)>,
+    /// Name patterns of the weights to store interleaved.
+    repack: Vec<String>,
+    /// Layer name patterns with the backend their buffers are created on.
+    placements: Vec<(String, &'a dyn Backend)>,
+    /// Pool the tensors of a layer are dequantized and converted on.
+    threadpool: Option<Arc<ThreadPool>>,
+    /// First record of the next layer, read while looking for the end of the current one.
+    pending: Option<TensorData>,
+    loaded: LoadProgress,
+}
+
+impl<'a, R: Read> StreamingLoader<'a, R> {
+    pub fn new(reader: TensorReader<R>, backend: &'a dyn Backend) -> Self {
+        Self {
+            reader,
+            backend,
+            usage: BackendBufferUsage::Weights,
+            progress: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            dequantize: Vec::new(),
+            dtypes: Vec::new(),
+            repack: Vec::new(),
+            placements: Vec::new(),
+            threadpool: None,
+            pending: None,
+            loaded: LoadProgress {
+                layer: String::new(),
+                layers_loaded: 0,
+                tensors_loaded: 0,
+                bytes_loaded: 0,
+            },
+        }
+    }
+
+    /// Usage of the layer buffers, [`BackendBufferUsage::Weights`] by default.
+    pub fn usage(mut self, usage: BackendBufferUsage) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    /// Calls `progress` after every uploaded layer.
+    pub fn on_progress(mut self, progress: impl FnMut(&LoadProgress) + 'a) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Loads only the tensors matching `pattern` or another included pattern. The scales of
+    /// a quantized tensor go with the tensor.
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.include.push(pattern.into());
+        self
+    }
+
+    /// Skips the tensors matching `pattern`, even if they are included. The scales of a
+    /// quantized tensor go with the tensor. Layers left without tensors are skipped.
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Dequantizes the rowwise quantized tensors (see [`crate::quant`]) whose names match
+    /// `pattern` to `dtype`, F32 or F16, trading memory for accuracy. In the pattern `*`
+    /// matches any run of characters, e.g. `output.*` or `*.token_embd.weight`. The scales
+    /// of a dequantized tensor are not loaded. The first matching pattern wins.
+    pub fn dequantize(mut self, pattern: impl Into<String>, dtype: DataType) -> Self {
+        self.dequantize.push((pattern.into(), dtype));
+        self
+    }
+
+    /// Converts the tensors matching `pattern` to `dtype` element by element, e.g. F32
+    /// weights to F16. Quantized tensors and their scales are left to
+    /// [`StreamingLoader::dequantize`]. The first matching pattern wins.
+    pub fn override_dtype(mut self, pattern: impl Into<String>, dtype: DataType) -> Self {
+        self.dtypes.push((pattern.into(), dtype));
+        self
+    }
+
+    /// Stores the matrices matching `pattern` interleaved (see [`crate::repack`]) in the
+    /// panels their layer's backend streams matrix product weights in fastest, its
+    /// [`Backend::blk_size_interleave`], and marks them with [`Tensor::interleaved_rows`].
+    /// Tensors the backend has no such layout for, or whose rows do not fill whole panels,
+    /// are stored as they are. Only matrix products should read the repacked tensors.
+    pub fn repack(mut self, pattern: impl Into<String>) -> Self {
+        self.repack.push(pattern.into());
+        self
+    }
+
+    /// Creates the buffers of the layers whose names (see [`layer_name`]) match `pattern` on
+    /// `backend` instead of the loader's backend. A layer shares one buffer, so placement
+    /// is per layer. The first matching pattern wins.
+    pub fn place(mut self, pattern: impl Into<String>, backend: &'a dyn Backend) -> Self {
+        self.placements.push((pattern.into(), backend));
+        self
+    }
+
+    /// Dequantizes and converts the tensors of each layer on `pool`, one task per tensor,
+    /// rather than one after the other on the calling thread. Layers are still read one at
+    /// a time, so the host memory stays bounded by a layer and its converted copy.
+    pub fn threadpool(mut self, pool: Arc<ThreadPool>) -> Self {
+        self.threadpool = Some(pool);
+        self
+    }
+
+    /// Reads, allocates and uploads the next layer, creating its tensors in `ctx`. Returns
+    /// `None` once the file is exhausted.
+    pub fn next_layer(&mut self, ctx: &mut Context) -> Result<Option<LoadedLayer>> {
+        loop {
+            let first = match self.pending.take() {
+                Some(record) => record,
+                None => match self.reader.next_tensor()? {
+                    Some(record) => record,
+                    None => return Ok(None),
+                },
+            };
+
+            let name = record_layer(&first.name).to_string();
+            let mut records = vec![first];
+            while let Some(record) = self.reader.next_tensor()? {
+                if record_layer(&record.name) != name {
+                    self.pending = Some(record);
+                    break;
+                }
+                records.push(record);
+            }
+            records.retain(|record| self.is_selected(&record.name));
+            if records.is_empty() {
+                continue;
+            }
+
+            let records = self
+                .dequantize_records(records)
+                .and_then(|records| self.convert_records(records))
+                .map_err(|e| e.context("in StreamingLoader"))?;
+            let layer =
+                self.upload(ctx, name, &records).map_err(|e| e.context("in StreamingLoader"))?;
+            self.loaded.layer = layer.name.clone();
+            self.loaded.layers_loaded += 1;
+            self.loaded.tensors_loaded += records.len();
+            self.loaded.bytes_loaded +=
+                records.iter().map(|record| record.data.len()).sum::<usize>();
+            if let Some(progress) = self.progress.as_mut() {
+                progress(&self.loaded);
+            }
+            return Ok(Some(layer));
+        }
+    }
+
+    /// Loads every remaining layer.
+    pub fn load_all(mut self, ctx: &mut Context) -> Result<Vec<LoadedLayer>> {
+        let mut layers = Vec::new();
+        while let Some(layer) = self.next_layer(ctx)? {
+            layers.push(layer);
+        }
+        Ok(layers)
+    }
+
+    fn upload(
+        &self,
+        ctx: &mut Context,
+        name: String,
+        records: &[TensorData],
+    ) -> Result<LoadedLayer> {
+        let backend = self.layer_backend(&name);
+        let alignment = backend.alignment();
+        let mut offsets = Vec::with_capacity(records.len());
+        let mut size = 0;
+        for record in records {
+            offsets.push(size);
+            size = (size + record.data.len()).next_multiple_of(alignment);
+        }
+
+        let buffer = backend.create_buffer(size.max(alignment), self.usage)?;
+        let mut tensors = Vec::with_capacity(records.len());
+        for (record, offset) in records.iter().zip(offsets) {
+            let tensor = record.new_tensor(ctx)?;
+            buffer.init_tensor(tensor.clone(), offset)?;
+            match self.repack_rows(backend, record) {
+                Some(rows) => {
+                    let (size, row_len) = (get_type_size(record.dtype), record.shape.dim(0));
+                    let data = interleave_rows(&record.data, size, row_len, rows)?;
+                    TensorData { data, ..record.clone() }.upload(&tensor)?;
+                    tensor.set_interleaved_rows(Some(rows));
+                }
+                None => record.upload(&tensor)?,
+            }
+            tensors.push(tensor);
+        }
+        Ok(LoadedLayer { name, tensors, buffer })
+    }
+
+    /// Whether the filters select the tensor `name`.
+    fn is_selected(&self, name: &str) -> bool {
+        let name = name.strip_suffix(SCALES_SUFFIX).unwrap_or(name);
+        let matches = |pattern: &String| matches_pattern(pattern, name);
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+
+    fn layer_backend(&self, layer: &str) -> &'a dyn Backend {
+        let placement = self.placements.iter().find(|(pattern, _)| matches_pattern(pattern, layer));
+        placement.map_or(self.backend, |(_, backend)| *backend)
+    }
+
+    /// Panel rows `record` is stored interleaved in, if [`StreamingLoader::repack`]
+    /// selects it and `backend` has a layout for it.
+    fn repack_rows(&self, backend: &dyn Backend, record: &TensorData) -> Option<usize> {
+        if !self.repack.iter().any(|pattern| matches_pattern(pattern, &record.name)) {
+            return None;
+        }
+        let rows = backend.blk_size_interleave(TensorOpType::TensorOpMulMat, record.dtype)?;
+        let (row_len, ne1) = (record.shape.dim(0), record.shape.dim(1));
+        (rows > 0 && row_len > 0 && ne1 % rows == 0).then_some(rows)
+    }
+
+    /// `records` with the tensors selected by [`StreamingLoader::dequantize`] expanded and
+    /// their scales dropped.
+    fn dequantize_records(&self, records: Vec<TensorData>) -> Result<Vec<TensorData>> {
+        let dequantize = &self.dequantize;
+        let target = |record: &TensorData| {
+            let selected = dequantize.iter().find(|(pattern, _)| {
+                record.dtype == DataType::I8 && matches_pattern(pattern, &record.name)
+            });
+            selected.map(|(_, dtype)| *dtype)
+        };
+        if !records.iter().any(|record| target(record).is_some()) {
+            return Ok(records);
+        }
+
+        let expanded = convert_each(self.threadpool.as_deref(), records.len(), |i| {
+            let record = &records[i];
+            let Some(dtype) = target(record) else {
+                return Ok(None);
+            };
+            let scales_name = format!("{}{SCALES_SUFFIX}", record.name);
+            let scales = records.iter().find(|scales| scales.name == scales_name);
+            let scales = scales.ok_or_else(|| {
+                Error::msg(format!("quantized tensor {} has no {scales_name}", record.name))
+            })?;
+            dequantize_record(record, scales, dtype).map(Some)
+        })?;
+        let dropped: Vec<String> = records
+            .iter()
+            .zip(&expanded)
+            .filter(|(_, expanded)| expanded.is_some())
+            .map(|(record, _)| format!("{}{SCALES_SUFFIX}", record.name))
+            .collect();
+        let mut records: Vec<TensorData> = records
+            .into_iter()
+            .zip(expanded)
+            .map(|(record, expanded)| expanded.unwrap_or(record))
+            .collect();
+        records.retain(|record| !dropped.contains(&record.name));
+        Ok(records)
+    }
+
+    /// `records` with the types of [`StreamingLoader::override_dtype`].
+    fn convert_records(&self, mut records: Vec<TensorData>) -> Result<Vec<TensorData>> {
+        if self.dtypes.is_empty() {
+            return Ok(records);
+        }
+        let dtypes = &self.dtypes;
+        let converted = convert_each(self.threadpool.as_deref(), records.len(), |i| {
+            let record = &records[i];
+            let quantized = record.name.ends_with(SCALES_SUFFIX)
+                || records.iter().any(|other| {
+                    other.name.strip_suffix(SCALES_SUFFIX) == Some(record.name.as_str())
+                });
+            let selected =
+                dtypes.iter().find(|(pattern, _)| matches_pattern(pattern, &record.name));
+            match selected {
+                Some(&(_, dtype)) if !quantized && dtype != record.dtype => {
+                    convert_record(record, dtype).map(Some)
+                }
+                _ => Ok(None),
+            }
+        })?;
+        for (record, converted) in records.iter_mut().zip(converted) {
+            if let Some(converted) = converted {
+                *record = converted;
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// `convert(i)` for every `i` in `0..n`, run as one task each on `pool` if there is one.
+fn convert_each<F>(
+    pool: Option<&ThreadPool>,
+    n: usize,
+    convert: F,
+) -> Result<Vec<Option<TensorData>>>
+where
+    F: Fn(usize) -> Result<Option<TensorData>> + Sync,
+{
+    let Some(pool) = pool else {
+        return (0..n).map(convert).collect();
+    };
+    let slots: Vec<Mutex<Option<TensorData>>> = (0..n).map(|_| Mutex::new(None)).collect();
+    pool.run(n, &|i| {
+        let converted = convert(i)?;
+        *slots[i].lock().unwrap_or_else(PoisonError::into_inner) = converted;
+        Ok(())
+    })?;
+    Ok(slots
+        .into_iter()
+        .map(|slot| slot.into_inner().unwrap_or_else(PoisonError::into_inner))
+        .collect())
+}
+
+/// `record` with every element converted to `dtype`.
+fn convert_record(record: &TensorData, dtype: DataType) -> Result<TensorData> {
+    let (from, to) = (get_type_size(record.dtype), get_type_size(dtype));
+    let mut data = vec![0; record.data.len() / from * to];
+    for (src, dst) in record.data.chunks_exact(from).zip(data.chunks_exact_mut(to)) {
+        from_f32(dtype, to_f32(record.dtype, src)?, dst)?;
+    }
+    Ok(TensorData { name: record.name.clone(), dtype, shape: record.shape, data })
+}
+
+/// Rowwise quantized `values` with their `scales` as a `dtype` record of the same shape.
+fn dequantize_record(
+    values: &TensorData,
+    scales: &TensorData,
+    dtype: DataType,
+) -> Result<TensorData> {
+    let dequantize = || {
+        if !matches!(dtype, DataType::F32 | DataType::F16) || scales.dtype != DataType::F32 {
+            return Err(Error::msg(format!(
+                "cannot dequantize to {dtype:?} with {:?} scales",
+                scales.dtype
+            )));
+        }
+        let scale_values = scales.data.chunks_exact(4).map(|bytes| to_f32(DataType::F32, bytes));
+        let quantized = RowwiseQ8::from_parts(
+            values.data.iter().map(|&q| q as i8).collect(),
+            scale_values.collect::<Result<_>>()?,
+            values.shape.dim(0),
+        )?;
+
+        let size = get_type_size(dtype);
+        let mut data = vec![0; quantized.values.len() * size];
+        for (bytes, value) in data.chunks_exact_mut(size).zip(quantized.dequantize()) {
+            from_f32(dtype, value, bytes)?;
+        }
+        Ok(TensorData { name: values.name.clone(), dtype, shape: values.shape, data })
+    };
+    dequantize().map_err(|e| e.context(format!("in dequantizing {}", values.name)))
+}
+
+/// Tensors of a model file, bound to one buffer.
+pub struct LoadedModel {
+    /// In file order.
+    pub tensors: Vec<Tensor>,
+    pub buffer: Box<dyn BackendBuffer>,
+    /// Whether the tensors point straight at the mapped file rather than at a copy.
+    pub zero_copy: bool,
+    /// Key-value pairs of the file header, sorted by key.
+    pub metadata: Vec<(String, String)>,
+}
+
+impl LoadedModel {
+    /// The hyperparameters in [`LoadedModel::metadata`].
+    pub fn meta(&self) -> Result<ModelMeta> {
+        ModelMeta::from_pairs(self.metadata.iter().map(|(key, value)| (&**key, &**value)))
+    }
+}
+
+/// Loads a safetensors file. On the CPU backend with the `mmap` feature, the file is mapped
+/// into a host buffer and the tensors point at their bytes in it, so nothing is copied and
+/// pages are only read when used; a tensor whose data is not aligned to its element size
+/// makes the whole file fall back to copying. Other backends get a copy in a
+/// [`BackendBufferUsage::Weights`] buffer.
+#[cfg(feature = "safetensors")]
+pub fn load_safetensors(
+    path: impl AsRef<std::path::Path>,
+    ctx: &mut Context,
+    backend: &dyn Backend,
+) -> Result<LoadedModel> {
+    let path = path.as_ref();
+    #[cfg(all(feature = "cpu", feature = "mmap"))]
+    if let Some(model) =
+        map_safetensors(path, ctx, backend).map_err(|e| e.context("in load_safetensors"))?
+    {
+        return Ok(model);
+    }
+
+    let mut bytes = std::fs::read(path)
+        .map_err(|e| Error::from(e).context(format!("in load_safetensors: {}", path.display())))?;
+    let (data_start, entries, metadata) = safetensors_entries(&bytes)?;
+    let data = &mut bytes[data_start..];
+    let buffer = backend.create_buffer(data.len().max(1), BackendBufferUsage::Weights)?;
+    let mut tensors = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let tensor = new_leaf_tensor(ctx, &entry.name, entry.dtype, &entry.shape)?;
+        buffer.init_tensor(tensor.clone(), entry.range.start)?;
+        let size = entry.range.len();
+        buffer.write(tensor.clone(), &mut data[entry.range], 0, size)?;
+        tensors.push(tensor);
+    }
+    Ok(LoadedModel { tensors, buffer, zero_copy: false, metadata })
+}
+
+#[cfg(all(feature = "safetensors", feature = "cpu", feature = "mmap"))]
+fn map_safetensors(
+    path: &std::path::Path,
+    ctx: &mut Context,
+    backend: &dyn Backend,
+) -> Result<Option<LoadedModel>> {
+    use crate::cpu::backend::CpuBackend;
+    use crate::data_type::get_type_size;
+
+    let Some(cpu) = backend.as_any().downcast_ref::<CpuBackend>() else {
+        return Ok(None);
+    };
+
+    // Only the header is read through this mapping, and it is dropped before the buffer
+    // maps the file, so the buffer is only created for files it can serve in place.
+    let (data_start, entries, metadata) = {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: read-only mapping used only to parse the header; the file is not expected
+        // to change while it is loaded.
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        safetensors_entries(&map)?
+    };
+    let aligned = entries
+        .iter()
+        .all(|entry| (data_start + entry.range.start) % get_type_size(entry.dtype) == 0);
+    if !aligned {
+        return Ok(None);
+    }
+
+    let buffer = cpu.buffer_from_file(path, BackendBufferUsage::Weights)?;
+    let mut tensors = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let tensor = new_leaf_tensor(ctx, &entry.name, entry.dtype, &entry.shape)?;
+        buffer.init_tensor(tensor.clone(), data_start + entry.range.start)?;
+        tensors.push(tensor);
+    }
+    Ok(Some(LoadedModel { tensors, buffer, zero_copy: true, metadata }))
+}
+
+#[cfg(feature = "safetensors")]
+type MetadataPairs = Vec<(String, String)>;
+
+#[cfg(feature = "safetensors")]
+struct SafetensorsEntry {
+    name: String,
+    dtype: DataType,
+    shape: Shape,
+    /// Byte range within the data section.
+    range: std::ops::Range<usize>,
+}
+
+/// Parses the header of a whole safetensors file. Returns where the data section starts,
+/// the tensors sorted by offset and the `__metadata__` pairs sorted by key.
+#[cfg(feature = "safetensors")]
+fn safetensors_entries(bytes: &[u8]) -> Result<(usize, Vec<SafetensorsEntry>, MetadataPairs)> {
+    use safetensors::{Dtype, SafeTensors};
+
+    let invalid = |e| Error::msg(format!("invalid safetensors file: {e:?}"));
+    let (header_len, metadata) = SafeTensors::read_metadata(bytes).map_err(invalid)?;
+    let mut entries = Vec::new();
+    for (name, info) in metadata.tensors() {
+        let dtype = match info.dtype {
+            Dtype::U8 => DataType::U8,
+            Dtype::I8 => DataType::I8,
+            Dtype::I16 => DataType::I16,
+            Dtype::I32 => DataType::I32,
+            Dtype::U32 => DataType::U32,
+            Dtype::I64 => DataType::I64,
+            Dtype::F16 => DataType::F16,
+            Dtype::F32 => DataType::F32,
+            Dtype::F64 => DataType::F64,
+            other => {
+                return Err(Error::msg(format!("tensor {name} has unsupported dtype {other:?}")));
+            }
+        };
+        if info.shape.len() > MAX_DIMS {
+            return Err(Error::msg(format!(
+                "tensor {name} has unsupported rank {}",
+                info.shape.len()
+            )));
+        }
+        let (start, end) = info.data_offsets;
+        // safetensors lists the outermost dimension first, feml the innermost. A scalar has
+        // an empty shape.
+        let mut dims: Vec<usize> = info.shape.iter().rev().copied().collect();
+        if dims.is_empty() {
+            dims.push(1);
+        }
+        entries.push(SafetensorsEntry { name, dtype, shape: Shape::new(&dims), range: start..end });
+    }
+    entries.sort_by_key(|entry| entry.range.start);
+    let mut pairs: Vec<(String, String)> =
+        metadata.metadata().iter().flatten().map(|(k, v)| (k.clone(), v.clone())).collect();
+    pairs.sort();
+    Ok((8 + header_len, entries, pairs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layer_name() {
+        assert_eq!(layer_name("blk.3.attn_q.weight"), "blk.3");
+        assert_eq!(layer_name("model.layers.12.mlp.up_proj"), "model.layers.12");
+        assert_eq!(layer_name("token_embd.weight"), "token_embd.weight");
+        assert_eq!(layer_name("blk.3x.weight"), "blk.3x.weight");
+        assert_eq!(record_layer("output.weight.scales"), "output.weight");
+    }
+
+    #[cfg(feature = "cpu")]
+    #[test]
+    fn test_streaming_loader_filters_and_placement() {
+        use crate::serialize::{Compression, TensorWriter};
+
+        let default = crate::Backend::cpu().build().unwrap();
+        let offload = crate::Backend::cpu().build().unwrap();
+        let bytes = TensorWriter::new(Vec::new(), Compression::None).unwrap().finish().unwrap();
+        let loader =
+            StreamingLoader::new(TensorReader::new(bytes.as_slice()).unwrap(), default.inner())
+                .include("blk.*")
+                .include("output.weight")
+                .exclude("*.ffn_*")
+                .place("blk.1*", offload.inner());
+
+        assert!(loader.is_selected("blk.0.attn_q.weight"));
+        assert!(loader.is_selected("output.weight.scales"));
+        assert!(!loader.is_selected("blk.0.ffn_up.weight.scales"));
+        assert!(!loader.is_selected("token_embd.weight"));
+        let placed = |layer| loader.layer_backend(layer) as *const dyn Backend;
+        assert!(core::ptr::addr_eq(placed("blk.12"), offload.inner()));
+        assert!(core::ptr::addr_eq(placed("blk.2"), default.inner()));
+    }
+
+    #[cfg(feature = "safetensors")]
+    #[test]
+    fn test_safetensors_rejects_unsupported_ranks() {
+        let header = br#"{"huge":{"dtype":"U8","shape":[1,1,1,1,2],"data_offsets":[0,2]}}"#;
+        let mut bytes = (header.len() as u64).to_le_bytes().to_vec();
+        bytes.extend_from_slice(header);
+        bytes.extend_from_slice(&[0; 2]);
+
+        let error = safetensors_entries(&bytes).err().unwrap().to_string();
+        assert!(error.contains("huge") && error.contains("rank 5"), "{error}");
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("output.weight", "output.weight"));
+        assert!(!matches_pattern("output", "output.weight"));
+        assert!(matches_pattern("output.*", "output.weight"));
+        assert!(matches_pattern("*embd*", "token_embd.weight"));
+        assert!(matches_pattern("blk.*.attn_*.weight", "blk.12.attn_q.weight"));
+        assert!(!matches_pattern("blk.*.ffn_*", "blk.12.attn_q.weight"));
+        assert!(!matches_pattern("*.weight.*", "a.weight"));
+        assert!(matches_pattern("*", ""));
+    }
+}
@@ -0,0 +1,232 @@
+//! Memory accounting.
+//!
+//! [`Context::memory_report`] and [`ComputeGraph::memory_report`] add up the bytes of the
+//! tensors bound to a buffer, split by what they hold. Views share the bytes of the tensor
+//! they look into and are not counted again.
+//!
+//! When a buffer allocation fails, the hook of [`set_alloc_failure_hook`] gets the chance to
+//! free memory, e.g. by shrinking a KV cache, before the allocation is retried once.
+
+use crate::backend::{Backend, BackendBuffer, BackendBufferUsage};
+use crate::compute_graph::ComputeGraph;
+use crate::context::Context;
+use crate::data_type::{TensorOpType, TensorType};
+use crate::error::{Error, Result};
+use crate::tensor::Tensor;
+use alloc::boxed::Box;
+use alloc::format;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// What the bytes of a tensor are used for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MemoryCategory {
+    /// Parameters, i.e. tensors flagged with [`TensorType::FlagParam`].
+    Weights,
+    /// Results of ops.
+    Activations,
+    /// Keys and values of a [`KvCache`](crate::kv_cache::KvCache).
+    KvCache,
+    /// Anything else, e.g. graph inputs.
+    Other,
+}
+
+/// Allocated bytes by [`MemoryCategory`].
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryReport {
+    pub weights: usize,
+    pub activations: usize,
+    pub kv_cache: usize,
+    pub other: usize,
+    /// Number of tensors counted.
+    pub n_tensors: usize,
+}
+
+impl MemoryReport {
+    pub fn total(&self) -> usize {
+        self.weights + self.activations + self.kv_cache + self.other
+    }
+
+    pub fn bytes(&self, category: MemoryCategory) -> usize {
+        match category {
+            MemoryCategory::Weights => self.weights,
+            MemoryCategory::Activations => self.activations,
+            MemoryCategory::KvCache => self.kv_cache,
+            MemoryCategory::Other => self.other,
+        }
+    }
+
+    /// Counts `tensor` if it owns allocated bytes.
+    pub(crate) fn add(&mut self, tensor: &Tensor) {
+        {
+            let inner = tensor.borrow();
+            if inner.storage.is_none() || inner.view_tensor.is_some() {
+                return;
+            }
+        }
+        let nbytes = tensor.nbytes();
+        match tensor.memory_category() {
+            MemoryCategory::Weights => self.weights += nbytes,
+            MemoryCategory::Activations => self.activations += nbytes,
+            MemoryCategory::KvCache => self.kv_cache += nbytes,
+            MemoryCategory::Other => self.other += nbytes,
+        }
+        self.n_tensors += 1;
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const MIB: f64 = 1024.0 * 1024.0;
+        write!(
+            f,
+            "weights {:.2} MiB, activations {:.2} MiB, kv cache {:.2} MiB, other {:.2} MiB, \
+             total {:.2} MiB in {} tensors",
+            self.weights as f64 / MIB,
+            self.activations as f64 / MIB,
+            self.kv_cache as f64 / MIB,
+            self.other as f64 / MIB,
+            self.total() as f64 / MIB,
+            self.n_tensors
+        )
+    }
+}
+
+impl Tensor {
+    /// Category the bytes of this tensor are reported under: the one set with
+    /// [`Tensor::set_memory_category`], else [`MemoryCategory::Weights`] for parameters,
+    /// [`MemoryCategory::Activations`] for op results and [`MemoryCategory::Other`] for the
+    /// rest.
+    pub fn memory_category(&self) -> MemoryCategory {
+        let inner = self.borrow();
+        if let Some(category) = inner.memory_category {
+            return category;
+        }
+        match (inner.tensor_type, inner.op_type) {
+            (TensorType::FlagParam, _) => MemoryCategory::Weights,
+            (_, TensorOpType::UNKNOWN | TensorOpType::TensorNone) => MemoryCategory::Other,
+            _ => MemoryCategory::Activations,
+        }
+    }
+
+    pub fn set_memory_category(&self, category: MemoryCategory) -> &Self {
+        self.borrow_mut().memory_category = Some(category);
+        self
+    }
+}
+
+impl Context {
+    /// Bytes allocated for the tensors of this context.
+    pub fn memory_report(&self) -> MemoryReport {
+        let mut report = MemoryReport::default();
+        for tensor in self.borrow().tensor_tables.values() {
+            report.add(tensor);
+        }
+        report
+    }
+}
+
+impl ComputeGraph {
+    /// Bytes allocated for the nodes and leafs of this graph.
+    pub fn memory_report(&self, context: &Context) -> Result<MemoryReport> {
+        let mut report = MemoryReport::default();
+        for &id in self.nodes().iter().chain(self.leafs().iter()) {
+            let tensor = context.get_tensor(id).map_err(|e| e.context("in memory_report"))?;
+            report.add(&tensor);
+        }
+        Ok(report)
+    }
+}
+
+/// Allocation that failed, as passed to the hook of [`set_alloc_failure_hook`].
+#[derive(Debug)]
+pub struct AllocFailure<'a> {
+    /// Name of the backend.
+    pub backend: &'a str,
+    pub size: usize,
+    pub usage: BackendBufferUsage,
+    pub error: &'a Error,
+    /// Memory of the context whose tensors were being allocated, if known.
+    pub report: Option<MemoryReport>,
+}
+
+/// Called when a buffer allocation fails. Returns whether it freed memory, in which case
+/// the allocation is retried once.
+pub type AllocFailureHook = Box<dyn Fn(&AllocFailure<'_>) -> bool + Send + Sync>;
+
+#[cfg(feature = "std")]
+type SharedHook = Arc<dyn Fn(&AllocFailure<'_>) -> bool + Send + Sync>;
+
+#[cfg(feature = "std")]
+static ALLOC_FAILURE_HOOK: RwLock<Option<SharedHook>> = RwLock::new(None);
+
+/// Sets the process-wide hook for failed allocations of
+/// [`Backend::alloc`](crate::api::Backend::alloc),
+/// [`Backend::alloc_graph`](crate::api::Backend::alloc_graph) and
+/// [`BufferPool`](crate::buffer_pool::BufferPool); `None` removes it. The hook runs on the
+/// allocating thread and may allocate itself.
+#[cfg(feature = "std")]
+pub fn set_alloc_failure_hook(hook: Option<AllocFailureHook>) {
+    *ALLOC_FAILURE_HOOK.write().unwrap_or_else(PoisonError::into_inner) = hook.map(Arc::from);
+}
+
+/// `backend.create_buffer`, retried once if it fails and the alloc failure hook frees
+/// memory. `report` is only computed for the hook.
+pub(crate) fn create_buffer(
+    backend: &dyn Backend,
+    size: usize,
+    usage: BackendBufferUsage,
+    report: impl FnOnce() -> Option<MemoryReport>,
+) -> Result<Box<dyn BackendBuffer>> {
+    let max_size = backend.max_buffer_size();
+    if size > max_size {
+        return Err(Error::msg(format!(
+            "{size} bytes exceed the largest {} buffer of {max_size} bytes",
+            backend.name()
+        )));
+    }
+    let error = match backend.create_buffer(size, usage) {
+        Ok(buffer) => return Ok(buffer),
+        Err(error) => error,
+    };
+    #[cfg(feature = "std")]
+    {
+        // Clone the hook so that it runs without the lock held.
+        let hook = ALLOC_FAILURE_HOOK.read().unwrap_or_else(PoisonError::into_inner).clone();
+        if let Some(hook) = hook {
+            let failure = AllocFailure {
+                backend: backend.name(),
+                size,
+                usage,
+                error: &error,
+                report: report(),
+            };
+            if hook(&failure) {
+                return backend.create_buffer(size, usage);
+            }
+        }
+    }
+    #[cfg(not(feature = "std"))]
+    let _ = report;
+    Err(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_totals() {
+        let report = MemoryReport {
+            weights: 1 << 20,
+            activations: 1 << 19,
+            kv_cache: 0,
+            other: 0,
+            n_tensors: 3,
+        };
+        assert_eq!(report.total(), 3 << 19);
+        assert_eq!(report.bytes(MemoryCategory::Activations), 1 << 19);
+        assert!(report.to_string().contains("total 1.50 MiB in 3 tensors"));
+    }
+}
@@ -0,0 +1,138 @@
+//! Typed model hyperparameters.
+//!
+//! Model files describe their architecture with string key-value pairs. [`ModelMeta`] reads
+//! the usual ones, named as in GGUF: `general.architecture` gives the architecture, and the
+//! other keys are prefixed with it, e.g. `llama.block_count`. Safetensors files carry the
+//! same pairs in their `__metadata__` header, see
+//! [`LoadedModel::meta`](crate::loader::LoadedModel::meta).
+
+use crate::error::{Error, Result};
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use core::str::FromStr;
+
+/// Key of the architecture name, which prefixes the other keys.
+pub const ARCHITECTURE_KEY: &str = "general.architecture";
+
+/// Rotary position embedding settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RopeParams {
+    /// Base of the rotation frequencies, `{arch}.rope.freq_base`, 10000 if missing.
+    pub freq_base: f32,
+    /// Factor of the positions, the inverse of `{arch}.rope.scale_linear`, 1 if missing.
+    pub freq_scale: f32,
+    /// Rotated dimensions per head, `{arch}.rope.dimension_count`; `None` rotates the
+    /// whole head.
+    pub n_dims: Option<usize>,
+}
+
+impl Default for RopeParams {
+    fn default() -> Self {
+        Self { freq_base: 10000.0, freq_scale: 1.0, n_dims: None }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelMeta {
+    pub architecture: String,
+    /// `{arch}.block_count`
+    pub n_layers: usize,
+    /// `{arch}.attention.head_count`
+    pub n_heads: usize,
+    /// `{arch}.attention.head_count_kv`, `n_heads` if missing.
+    pub n_kv_heads: usize,
+    /// `{arch}.embedding_length`
+    pub n_embd: Option<usize>,
+    /// `{arch}.context_length`, the context the model was trained with.
+    pub n_ctx_train: Option<usize>,
+    /// `{arch}.vocab_size`
+    pub vocab_size: Option<usize>,
+    pub rope: RopeParams,
+}
+
+impl ModelMeta {
+    /// Reads the hyperparameters from key-value `pairs`; unknown keys are ignored. The
+    /// architecture, layer count and head count are required.
+    pub fn from_pairs<'a>(pairs: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Self> {
+        let pairs: BTreeMap<&str, &str> = pairs.into_iter().collect();
+        let parse = || {
+            let architecture = match pairs.get(ARCHITECTURE_KEY) {
+                Some(architecture) => String::from(*architecture),
+                None => return Err(Error::msg(format!("missing {ARCHITECTURE_KEY}"))),
+            };
+            let key = |key: &str| format!("{architecture}.{key}");
+            let get = |name: &str| parse_value::<usize>(&pairs, &key(name));
+            let require =
+                |name: &str| get(name)?.ok_or_else(|| Error::msg(format!("missing {}", key(name))));
+
+            let n_heads = require("attention.head_count")?;
+            let scale_linear = parse_value::<f32>(&pairs, &key("rope.scale_linear"))?;
+            let freq_base = parse_value::<f32>(&pairs, &key("rope.freq_base"))?;
+            let defaults = RopeParams::default();
+            Ok(Self {
+                n_layers: require("block_count")?,
+                n_heads,
+                n_kv_heads: get("attention.head_count_kv")?.unwrap_or(n_heads),
+                n_embd: get("embedding_length")?,
+                n_ctx_train: get("context_length")?,
+                vocab_size: get("vocab_size")?,
+                rope: RopeParams {
+                    freq_base: freq_base.unwrap_or(defaults.freq_base),
+                    freq_scale: scale_linear.map_or(defaults.freq_scale, |scale| 1.0 / scale),
+                    n_dims: get("rope.dimension_count")?,
+                },
+                architecture,
+            })
+        };
+        parse().map_err(|e| e.context("in ModelMeta::from_pairs"))
+    }
+
+    /// Size of an attention head, if the embedding size is known.
+    pub fn head_dim(&self) -> Option<usize> {
+        self.n_embd.map(|n_embd| n_embd / self.n_heads.max(1))
+    }
+}
+
+fn parse_value<T: FromStr>(pairs: &BTreeMap<&str, &str>, key: &str) -> Result<Option<T>> {
+    match pairs.get(key) {
+        Some(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| Error::msg(format!("{key} has an invalid value {value:?}"))),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_meta_from_pairs() {
+        let pairs = [
+            ("general.architecture", "llama"),
+            ("general.name", "tiny"),
+            ("llama.block_count", "2"),
+            ("llama.attention.head_count", "8"),
+            ("llama.attention.head_count_kv", "2"),
+            ("llama.embedding_length", "512"),
+            ("llama.rope.freq_base", "500000"),
+            ("llama.rope.scale_linear", "4"),
+        ];
+        let meta = ModelMeta::from_pairs(pairs).unwrap();
+
+        assert_eq!(meta.architecture, "llama");
+        assert_eq!((meta.n_layers, meta.n_heads, meta.n_kv_heads), (2, 8, 2));
+        assert_eq!(meta.head_dim(), Some(64));
+        assert_eq!((meta.vocab_size, meta.n_ctx_train), (None, None));
+        let rope = RopeParams { freq_base: 500000.0, freq_scale: 0.25, n_dims: None };
+        assert_eq!(meta.rope, rope);
+
+        assert!(ModelMeta::from_pairs(pairs[1..].iter().copied()).is_err());
+        let invalid = [pairs[0], pairs[2], ("llama.attention.head_count", "eight")];
+        let error = ModelMeta::from_pairs(invalid).unwrap_err();
+        assert!(error.to_string().contains("llama.attention.head_count"));
+    }
+}
@@ -1,3 +1,5 @@
+use alloc::vec::Vec;
+
 pub struct ObjectPool<T> {
     free: Vec<T>,
     factory: fn() -> T,
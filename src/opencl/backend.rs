@@ -2,7 +2,7 @@ use super::ops::mul::mul;
 use crate::backend::{Backend, BackendBuffer, BackendBufferUsage};
 use crate::compute_graph::ComputeGraph;
 use crate::context::Context;
-use crate::data_type::TensorOpType;
+use crate::data_type::{DataType, TensorOpType};
 use crate::error::{Error, ErrorKind, Result};
 use crate::tensor::Tensor;
 
@@ -13,6 +13,10 @@ use super::backend_register::OpenclBackendRegister;
 use std::any::Any;
 use std::cell::RefCell;
 use std::rc::Rc;
+/// Tensors start 256 bytes apart, at least the base address alignment of current devices,
+/// so that kernels may read them with the widest loads.
+const GPU_TENSOR_ALIGNMENT: usize = 256;
+
 pub struct OpenclBackend {
     pub(super) backend_ctx: Rc<RefCell<OpenclBackendContext>>,
 }
@@ -29,9 +33,31 @@ impl Backend for OpenclBackend {
         Ok(())
     }
 
+    fn supported_dtypes(&self, op: TensorOpType) -> &'static [DataType] {
+        match op {
+            TensorOpType::TensorOpMul => &[DataType::F32],
+            _ => &[],
+        }
+    }
+
+    fn alignment(&self) -> usize {
+        GPU_TENSOR_ALIGNMENT
+    }
+
+    fn max_buffer_size(&self) -> usize {
+        match self.backend_ctx.borrow().max_alloc_size {
+            0 => usize::MAX,
+            size => size,
+        }
+    }
+
     fn graph_compute(&self, ctx: &Context, graph: &mut ComputeGraph) -> Result<()> {
+        #[cfg(feature = "tracing")]
+        let _span = crate::backend::graph_span(self.name(), graph).entered();
         for node in graph.nodes().iter() {
             let tensor = ctx.get_tensor(*node)?;
+            #[cfg(feature = "tracing")]
+            let _node_span = crate::backend::node_span(&tensor).entered();
             self.compute_forward(ctx, &tensor)?;
         }
 
@@ -92,11 +118,17 @@ impl Backend for OpenclBackend {
 
 impl OpenclBackend {
     pub fn init() -> Result<Box<dyn Backend>> {
-        let reg =
-            OpenclBackendRegister::init().as_any().downcast_ref::<OpenclBackendRegister>().unwrap();
+        let reg = OpenclBackendRegister::init()
+            .as_any()
+            .downcast_ref::<OpenclBackendRegister>()
+            .ok_or_else(|| Error::msg("unexpected OpenCL register type"))?;
         let device = reg.opencl_device(0)?;
+        let backend_ctx = device
+            .backend_ctx
+            .clone()
+            .ok_or_else(|| Error::msg("OpenCL device is not initialized"))?;
 
-        Ok(Box::new(Self { backend_ctx: device.backend_ctx.as_ref().unwrap().clone() }))
+        Ok(Box::new(Self { backend_ctx }))
     }
 
     fn compute_forward(&self, ctx: &Context, tensor: &Tensor) -> Result<()> {
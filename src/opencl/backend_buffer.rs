@@ -2,7 +2,7 @@ use ocl::ffi::libc::BUFSIZ;
 
 use super::backend_context::OpenclBackendContext;
 use crate::backend::{BackendBuffer, BackendBufferUsage};
-use crate::error::{Error, Result};
+use crate::error::{Error, ErrorKind, Result};
 use crate::storage::TensorStorage;
 use crate::tensor::Tensor;
 use std::any::Any;
@@ -26,6 +26,11 @@ impl OpenclBackendBuffer {
     ) -> Self {
         OpenclBackendBuffer { backend_ctx: Some(backend_ctx), buffer, usage, size }
     }
+
+    fn ctx(&self) -> Result<std::cell::Ref<'_, OpenclBackendContext>> {
+        let ctx = self.backend_ctx.as_ref().ok_or_else(|| Error::msg("backend_ctx is none"))?;
+        Ok(ctx.borrow())
+    }
 }
 
 impl BackendBuffer for OpenclBackendBuffer {
@@ -46,7 +51,7 @@ impl BackendBuffer for OpenclBackendBuffer {
     }
 
     fn fill(&self, tensor: Tensor, value: u8, offset: usize, size: usize) -> Result<()> {
-        let ctx = self.backend_ctx.as_ref().unwrap().borrow();
+        let ctx = self.ctx()?;
         let cl_queue = &ctx.queue;
 
         let storage = tensor.storage()?;
@@ -70,21 +75,17 @@ impl BackendBuffer for OpenclBackendBuffer {
     }
 
     fn write(&self, tensor: Tensor, data: &mut [u8], offset: usize, _size: usize) -> Result<()> {
-        let ctx = self.backend_ctx.as_ref().unwrap().borrow();
+        let ctx = self.ctx()?;
         let cl_queue = &ctx.queue;
 
         let storage = tensor.storage()?;
-        let buffer = storage.as_opencl();
+        let buffer = storage.as_opencl().ok_or_else(|| Error::msg("storage is not OpenCL type"))?;
         let real_offset = storage.offset() + offset;
 
-        if !matches!(*storage, TensorStorage::Opencl { .. }) {
-            return Err(Error::msg("storage is not OpenCL type"));
-        }
-
         unsafe {
             ocl::core::enqueue_write_buffer(
                 cl_queue,
-                &buffer.unwrap().buffer,
+                &buffer.buffer,
                 true,
                 real_offset,
                 data,
@@ -97,21 +98,17 @@ impl BackendBuffer for OpenclBackendBuffer {
     }
 
     fn read(&self, tensor: Tensor, data: &mut [u8], offset: usize, _size: usize) -> Result<()> {
-        let ctx = self.backend_ctx.as_ref().unwrap().borrow();
+        let ctx = self.ctx()?;
         let cl_queue = &ctx.queue;
 
         let storage = tensor.storage()?;
-        let buffer = storage.as_opencl();
+        let buffer = storage.as_opencl().ok_or_else(|| Error::msg("storage is not OpenCL type"))?;
         let real_offset = storage.offset() + tensor.view_offset() + offset;
 
-        if !matches!(*storage, TensorStorage::Opencl { .. }) {
-            return Err(Error::msg("storage is not OpenCL type"));
-        }
-
         unsafe {
             ocl::core::enqueue_read_buffer(
                 cl_queue,
-                &buffer.unwrap().buffer,
+                &buffer.buffer,
                 true,
                 real_offset,
                 data,
@@ -130,7 +127,7 @@ impl BackendBuffer for OpenclBackendBuffer {
     }
 
     fn reset(&self) -> Result<()> {
-        todo!("add context reset")
+        Err(Error::new(ErrorKind::UnsupportedBackendOp { backend: "opencl", op: "reset" }))
     }
 
     fn as_any(&self) -> &dyn Any {
@@ -7,7 +7,7 @@ use crate::backend::{
     Backend, BackendBuffer, BackendDevice, BackendDeviceCaps, BackendDeviceProps, BackendDeviceType,
 };
 use crate::data_type::TensorOpType;
-use crate::error::{Error, Result};
+use crate::error::{Error, ErrorKind, Result};
 use crate::tensor::Tensor;
 use ocl::ocl_core::OpenclVersion;
 use ocl::{Context, Device, Platform};
@@ -31,7 +31,7 @@ pub struct OpenclBackendDevice {
 
 impl BackendDevice for OpenclBackendDevice {
     fn info(&self) -> Result<DeviceInfo> {
-        todo!()
+        Err(Error::new(ErrorKind::UnsupportedBackendOp { backend: "opencl", op: "info" }))
     }
 
     fn init_backend(&self) -> Result<Box<dyn Backend>> {
@@ -76,7 +76,8 @@ impl OpenclBackendDevice {
         if self.backend_ctx.is_some() {
             return Ok(());
         }
-        let ocl_ctx = OpenclBackendContext::new(self).expect("create opencl context failed!");
+        let ocl_ctx = OpenclBackendContext::new(self)
+            .map_err(|e| e.context("in OpenclBackendDevice::init: create context"))?;
         let ctx = Rc::new(RefCell::new(ocl_ctx));
 
         let mut guard = ctx.borrow_mut();
@@ -99,6 +100,9 @@ impl OpenclBackendDevice {
             ocl::core::get_device_info(guard.device, ocl::ocl_core::DeviceInfo::MaxMemAllocSize)
                 .map_err(|e| Error::msg(format!("Failed to get device info: {}", e)))?;
         info!("Opencl: max mem alloc size {}", max_alloc);
+        if let ocl::core::DeviceInfoResult::MaxMemAllocSize(size) = max_alloc {
+            guard.max_alloc_size = size as usize;
+        }
         let max_img_buf =
             ocl::core::get_device_info(guard.device, ocl::ocl_core::DeviceInfo::ImageMaxBufferSize)
                 .map_err(|e| Error::msg(format!("Failed to get device info: {}", e)))?;
@@ -125,3 +125,13 @@ impl OpenclBackendRegister {
         Self { devices: RefCell::new(Vec::new()), contexts: RefCell::new(Vec::new()) }
     }
 }
+
+/// Registration hook of the OpenCL backend, listed by [`Registry::discover`] when the
+/// backend's feature is enabled.
+///
+/// [`Registry::discover`]: crate::registry::Registry::discover
+pub fn register() -> Result<Box<dyn BackendRegister>> {
+    let reg = OpenclBackendRegister::new();
+    reg.probe_devices()?;
+    Ok(Box::new(reg))
+}
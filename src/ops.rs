@@ -1,5 +1,20 @@
+//! Op metadata shared by the graph builders and the backends.
+
+#[cfg(feature = "cpu")]
+use crate::data_type::{DataType, TensorOpType};
+use crate::model_meta::RopeParams;
+use crate::tensor::{AttentionParams, GeluMode, Im2ColParams, UpscaleMode};
+use alloc::sync::Arc;
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+
+pub(crate) type UnaryFn = Arc<dyn Fn(f32) -> f32 + Send + Sync>;
+pub(crate) type BinaryFn = Arc<dyn Fn(f32, f32) -> f32 + Send + Sync>;
+
+// Only the CPU kernels read the parameters.
+#[cfg_attr(not(feature = "cpu"), allow(dead_code))]
 #[derive(Clone)]
-pub enum OpParams {
+pub(crate) enum OpParams {
     None, // Mul, Add, Relu
 
     Gemm { trans_a: bool, trans_b: bool, alpha: f32, beta: f32 },
@@ -7,4 +22,112 @@ pub enum OpParams {
     Softmax { axis: i32 },
 
     Reshape { shape: [usize; 4] },
+
+    // Byte strides and offset of the destination view that src1 is added into or set to.
+    Acc { nb1: usize, nb2: usize, nb3: usize, offset: usize },
+
+    Im2Col(Im2ColParams),
+
+    DiagMask { n_past: usize },
+
+    // Epsilon added to the variance, or mean square, of the normalized rows.
+    Norm { eps: f32 },
+
+    GroupNorm { n_groups: usize, eps: f32 },
+
+    Upscale { mode: UpscaleMode },
+
+    Gelu { mode: GeluMode },
+
+    // Factor of the negative inputs of a leaky relu.
+    LeakyRelu { slope: f32 },
+
+    MapUnary(UnaryFn),
+
+    Dropout { p: f32 },
+
+    MapBinary(BinaryFn),
+
+    Attention(AttentionParams),
+
+    Rope(RopeParams),
+
+    // Scale of the I8 output of a rowwise int8 product, `q = round(y / out_scale)`.
+    Requantize { out_scale: f32 },
+}
+
+/// Every data type, for the kernels that accept any.
+#[cfg(feature = "cpu")]
+const ANY: &[DataType] = &[
+    DataType::U8,
+    DataType::U32,
+    DataType::I16,
+    DataType::I32,
+    DataType::I64,
+    DataType::F16,
+    DataType::F32,
+    DataType::F64,
+    DataType::I8,
+];
+
+/// Data types `op` supports on the CPU, the reference for every backend; empty if the op
+/// is not implemented. These are the types of the value operands, i.e. the sources other
+/// than index tensors, that the [`Isa::Scalar`] entry of `op` in [`KERNELS`] accepts; the
+/// SIMD entries accept a subset. Results are written in the operand type, except that
+/// convolutions and `im2col_back` always produce F32. Other backends report their own
+/// coverage through [`Backend::supports`](crate::backend::Backend::supports).
+///
+/// [`Isa::Scalar`]: crate::cpu::kernels::Isa::Scalar
+/// [`KERNELS`]: crate::cpu::kernels
+#[cfg(feature = "cpu")]
+pub fn supported_dtypes(op: TensorOpType) -> &'static [DataType] {
+    use crate::cpu::kernels::{Isa, KERNELS};
+
+    match KERNELS.iter().find(|entry| entry.op == op && entry.isa == Isa::Scalar) {
+        Some(entry) if entry.dtypes.is_empty() => ANY,
+        Some(entry) => entry.dtypes,
+        None => &[],
+    }
+}
+
+/// ALiBi slopes of `n_head` heads for `max_bias`, as in the ALiBi paper for a power of two
+/// heads (`2^(-max_bias * (h + 1) / n_head)`); the heads past the largest power of two take
+/// the odd slopes of the next one. Needs `std` for `powf`.
+#[cfg(feature = "std")]
+pub fn alibi_slopes(n_head: usize, max_bias: f32) -> Vec<f32> {
+    let n_head_log2 = if n_head == 0 { 1 } else { 1 << n_head.ilog2() };
+    let m0 = 2f32.powf(-max_bias / n_head_log2 as f32);
+    let m1 = 2f32.powf(-max_bias / 2.0 / n_head_log2 as f32);
+    (0..n_head)
+        .map(|h| {
+            if h < n_head_log2 {
+                m0.powi(h as i32 + 1)
+            } else {
+                m1.powi(2 * (h - n_head_log2) as i32 + 1)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "cpu")]
+    #[test]
+    fn test_supported_dtypes() {
+        assert_eq!(supported_dtypes(TensorOpType::TensorOpMul), [DataType::F32, DataType::F16]);
+        assert!(supported_dtypes(TensorOpType::TensorOpCont).contains(&DataType::I64));
+        assert!(supported_dtypes(TensorOpType::UNKNOWN).is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_alibi_slopes() {
+        assert_eq!(alibi_slopes(4, 8.0), [0.25, 0.0625, 0.015625, 0.00390625]);
+        let slopes = alibi_slopes(6, 8.0);
+        assert_eq!(slopes[..4], alibi_slopes(4, 8.0)[..]);
+        assert_eq!(slopes[4..], [0.5, 0.125]);
+        assert!(alibi_slopes(3, 0.0).iter().all(|&slope| slope == 1.0));
+    }
 }
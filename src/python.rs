@@ -0,0 +1,419 @@
+//! Python bindings, built with the `python` feature as the `feml._core` extension module.
+//!
+//! The module exposes `Context`, `Tensor`, `Graph`, `Backend` and `Buffer` classes, enough to
+//! build a graph, run it and compare the result with NumPy. Shapes use NumPy's order,
+//! outermost dimension first, the reverse of [`Shape`]. Tensors bound to CPU memory
+//! implement the buffer protocol, so `numpy.asarray(tensor)` is a zero-copy view; NumPy
+//! arrays and other buffers are copied in with `Tensor.write`.
+//!
+//! Tensors and backends are reference counted without atomics, so every class is
+//! `unsendable`: objects can only be used from the thread that created them.
+
+use crate::api;
+use crate::backend::BackendBuffer;
+use crate::compute_graph::ComputeGraph;
+use crate::context::Context;
+use crate::data_type::{get_type_size, DataType, TensorOpType, TensorType};
+use crate::defs::MAX_DIMS;
+use crate::error::Error;
+use crate::shape::Shape;
+use crate::tensor::{GeluMode, Tensor};
+use pyo3::buffer::{Element, PyBuffer};
+use pyo3::exceptions::{PyBufferError, PyRuntimeError, PyValueError};
+use pyo3::ffi;
+use pyo3::prelude::*;
+use std::ffi::{c_int, c_void, CStr};
+
+impl From<Error> for PyErr {
+    fn from(err: Error) -> Self {
+        PyRuntimeError::new_err(err.to_string())
+    }
+}
+
+/// NumPy name and buffer protocol format of each data type.
+const DTYPE_NAMES: [(DataType, &str, &CStr); 9] = [
+    (DataType::U8, "uint8", c"B"),
+    (DataType::U32, "uint32", c"I"),
+    (DataType::I16, "int16", c"h"),
+    (DataType::I32, "int32", c"i"),
+    (DataType::I64, "int64", c"q"),
+    (DataType::F16, "float16", c"e"),
+    (DataType::F32, "float32", c"f"),
+    (DataType::F64, "float64", c"d"),
+    (DataType::I8, "int8", c"b"),
+];
+
+fn parse_dtype(name: &str) -> PyResult<DataType> {
+    DTYPE_NAMES
+        .iter()
+        .find(|(_, numpy, _)| *numpy == name)
+        .map(|(dtype, ..)| *dtype)
+        .ok_or_else(|| PyValueError::new_err(format!("unsupported dtype {name:?}")))
+}
+
+/// Entry of a tensor made from Python, which can only use the dtypes of [`DTYPE_NAMES`].
+fn dtype_entry(dtype: DataType) -> &'static (DataType, &'static str, &'static CStr) {
+    DTYPE_NAMES.iter().find(|(entry, ..)| *entry == dtype).expect("every built-in dtype has a name")
+}
+
+/// Shape from NumPy's outermost-first order.
+fn shape_from_numpy(dims: &[usize]) -> PyResult<Shape> {
+    if dims.is_empty() || dims.len() > MAX_DIMS {
+        return Err(PyValueError::new_err(format!("tensors have 1 to {MAX_DIMS} dimensions")));
+    }
+    let dims: Vec<usize> = dims.iter().rev().copied().collect();
+    Ok(Shape::new(&dims))
+}
+
+#[pyclass(name = "Context", module = "feml._core", unsendable)]
+pub struct PyContext {
+    ctx: Context,
+}
+
+#[pymethods]
+impl PyContext {
+    #[new]
+    #[pyo3(signature = (tensor_capacity = 1024))]
+    fn new(tensor_capacity: usize) -> Self {
+        Self { ctx: Context::builder().tensor_pool_capacity(tensor_capacity).build() }
+    }
+
+    /// Creates a leaf tensor, e.g. `ctx.tensor("float32", [2, 3])`.
+    #[pyo3(signature = (dtype, shape, name = None))]
+    fn tensor(&mut self, dtype: &str, shape: Vec<usize>, name: Option<&str>) -> PyResult<PyTensor> {
+        let tensor = self.ctx.new_tensor(parse_dtype(dtype)?, &shape_from_numpy(&shape)?)?;
+        tensor.set_tensor_type(TensorType::FlagParam);
+        tensor.set_op_type(TensorOpType::TensorNone);
+        if let Some(name) = name {
+            tensor.set_name(name);
+        }
+        Ok(PyTensor { tensor })
+    }
+}
+
+#[pyclass(name = "Tensor", module = "feml._core", unsendable)]
+pub struct PyTensor {
+    tensor: Tensor,
+}
+
+#[pymethods]
+impl PyTensor {
+    /// Dimensions, outermost first.
+    #[getter]
+    fn shape(&self) -> Vec<usize> {
+        let shape = *self.tensor.shape();
+        shape.dims[..shape.rank].iter().rev().copied().collect()
+    }
+
+    #[getter]
+    fn dtype(&self) -> &'static str {
+        dtype_entry(self.tensor.dtype()).1
+    }
+
+    #[getter]
+    fn name(&self) -> String {
+        self.tensor.name()
+    }
+
+    #[setter]
+    fn set_name(&self, name: &str) {
+        self.tensor.set_name(name);
+    }
+
+    fn is_contiguous(&self) -> bool {
+        self.tensor.is_contiguous()
+    }
+
+    fn add(&self, other: &PyTensor) -> PyResult<PyTensor> {
+        Ok(PyTensor { tensor: self.tensor.clone().add(other.tensor.clone())? })
+    }
+
+    fn __add__(&self, other: &PyTensor) -> PyResult<PyTensor> {
+        self.add(other)
+    }
+
+    fn sub(&self, other: &PyTensor) -> PyResult<PyTensor> {
+        Ok(PyTensor { tensor: self.tensor.clone().sub(other.tensor.clone())? })
+    }
+
+    fn __sub__(&self, other: &PyTensor) -> PyResult<PyTensor> {
+        self.sub(other)
+    }
+
+    fn mul(&self, other: &PyTensor) -> PyResult<PyTensor> {
+        Ok(PyTensor { tensor: self.tensor.clone().mul(other.tensor.clone())? })
+    }
+
+    fn __mul__(&self, other: &PyTensor) -> PyResult<PyTensor> {
+        self.mul(other)
+    }
+
+    fn div(&self, other: &PyTensor) -> PyResult<PyTensor> {
+        Ok(PyTensor { tensor: self.tensor.clone().div(other.tensor.clone())? })
+    }
+
+    fn __truediv__(&self, other: &PyTensor) -> PyResult<PyTensor> {
+        self.div(other)
+    }
+
+    /// `ggml_mul_mat`: contracts the innermost dimension of both operands.
+    fn mul_mat(&self, other: &PyTensor) -> PyResult<PyTensor> {
+        Ok(PyTensor { tensor: self.tensor.clone().mul_mat(other.tensor.clone())? })
+    }
+
+    /// Layer normalization along the innermost dimension, scaled by `weight` and shifted by
+    /// `bias`.
+    #[pyo3(signature = (weight, bias, eps = 1e-5))]
+    fn layer_norm(&self, weight: &PyTensor, bias: &PyTensor, eps: f32) -> PyResult<PyTensor> {
+        let (weight, bias) = (weight.tensor.clone(), bias.tensor.clone());
+        Ok(PyTensor { tensor: self.tensor.clone().layer_norm(weight, bias, eps)? })
+    }
+
+    /// RMS normalization along the innermost dimension.
+    #[pyo3(signature = (eps = 1e-6))]
+    fn rms_norm(&self, eps: f32) -> PyResult<PyTensor> {
+        Ok(PyTensor { tensor: self.tensor.clone().rms_norm(eps)? })
+    }
+
+    /// GELU of every element, with the tanh approximation unless `exact` is set.
+    #[pyo3(signature = (exact = false))]
+    fn gelu(&self, exact: bool) -> PyResult<PyTensor> {
+        let mode = if exact { GeluMode::Exact } else { GeluMode::Tanh };
+        Ok(PyTensor { tensor: self.tensor.clone().gelu(mode)? })
+    }
+
+    /// Rectified linear unit of every element.
+    fn relu(&self) -> PyResult<PyTensor> {
+        Ok(PyTensor { tensor: self.tensor.clone().relu()? })
+    }
+
+    /// Leaky relu of every element, scaling negative values by `slope`.
+    #[pyo3(signature = (slope = 0.01))]
+    fn leaky_relu(&self, slope: f32) -> PyResult<PyTensor> {
+        Ok(PyTensor { tensor: self.tensor.clone().leaky_relu(slope)? })
+    }
+
+    /// SiLU (swish) of every element.
+    fn silu(&self) -> PyResult<PyTensor> {
+        Ok(PyTensor { tensor: self.tensor.clone().silu()? })
+    }
+
+    /// SwiGLU gating, `silu(self) * other`, computed in one op.
+    fn swiglu(&self, other: &PyTensor) -> PyResult<PyTensor> {
+        Ok(PyTensor { tensor: self.tensor.clone().swiglu(other.tensor.clone())? })
+    }
+
+    /// Softmax along the innermost dimension.
+    fn soft_max(&self) -> PyResult<PyTensor> {
+        Ok(PyTensor { tensor: self.tensor.clone().soft_max()? })
+    }
+
+    fn transpose(&self) -> PyResult<PyTensor> {
+        Ok(PyTensor { tensor: self.tensor.clone().transpose()? })
+    }
+
+    fn cont(&self) -> PyResult<PyTensor> {
+        Ok(PyTensor { tensor: self.tensor.clone().cont()? })
+    }
+
+    fn reshape(&self, shape: Vec<usize>) -> PyResult<PyTensor> {
+        Ok(PyTensor { tensor: self.tensor.clone().reshape(&shape_from_numpy(&shape)?)? })
+    }
+
+    /// Copies a NumPy array, or any object with the buffer protocol and a matching element
+    /// type, into the tensor, which must be bound to a buffer.
+    fn write(&self, data: &Bound<'_, PyAny>) -> PyResult<()> {
+        let mut bytes = match self.tensor.dtype() {
+            DataType::U8 => buffer_bytes::<u8>(data)?,
+            DataType::U32 => buffer_bytes::<u32>(data)?,
+            DataType::I16 => buffer_bytes::<i16>(data)?,
+            DataType::I32 => buffer_bytes::<i32>(data)?,
+            DataType::I64 => buffer_bytes::<i64>(data)?,
+            DataType::F32 => buffer_bytes::<f32>(data)?,
+            DataType::F64 => buffer_bytes::<f64>(data)?,
+            DataType::I8 => buffer_bytes::<i8>(data)?,
+            DataType::F16 => {
+                return Err(PyValueError::new_err("float16 tensors cannot be written yet"));
+            }
+            DataType::Custom(_) => {
+                return Err(PyValueError::new_err("custom dtype tensors cannot be written"));
+            }
+        };
+        if bytes.len() != self.tensor.nbytes() {
+            return Err(PyValueError::new_err(format!(
+                "tensor has {} bytes, data has {}",
+                self.tensor.nbytes(),
+                bytes.len()
+            )));
+        }
+        let size = bytes.len();
+        let storage = self.tensor.storage()?;
+        storage.buffer().write(self.tensor.clone(), &mut bytes, 0, size)?;
+        Ok(())
+    }
+
+    /// Copy of the tensor's bytes.
+    fn to_bytes(&self) -> PyResult<Vec<u8>> {
+        let mut bytes = vec![0; self.tensor.nbytes()];
+        let size = bytes.len();
+        let storage = self.tensor.storage()?;
+        storage.buffer().read(self.tensor.clone(), &mut bytes, 0, size)?;
+        Ok(bytes)
+    }
+
+    unsafe fn __getbuffer__(
+        slf: Bound<'_, Self>,
+        view: *mut ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        if view.is_null() {
+            return Err(PyBufferError::new_err("view is null"));
+        }
+        let this = slf.borrow();
+        let tensor = &this.tensor;
+        if flags & ffi::PyBUF_STRIDES != ffi::PyBUF_STRIDES && !tensor.is_contiguous() {
+            return Err(PyBufferError::new_err("tensor is not contiguous"));
+        }
+        let data = host_data(tensor)?;
+
+        let shape = *tensor.shape();
+        let rank = shape.rank;
+        // NumPy order: outermost dimension first. Shape and strides share one allocation,
+        // released in `__releasebuffer__`.
+        let mut dims: Vec<isize> = shape.dims[..rank].iter().rev().map(|&d| d as isize).collect();
+        dims.extend(tensor.stride()[..rank].iter().rev().map(|&nb| nb as isize));
+        let dims = Box::into_raw(dims.into_boxed_slice()) as *mut isize;
+
+        // SAFETY: `view` is non-null and owned by the caller for the duration of the export;
+        // the exporter keeps `slf`, and with it the tensor and its memory, alive through
+        // `obj` until the view is released.
+        unsafe {
+            (*view).obj = slf.clone().into_any().into_ptr();
+            (*view).buf = data as *mut c_void;
+            (*view).len = tensor.nbytes() as isize;
+            (*view).readonly = 0;
+            (*view).itemsize = get_type_size(tensor.dtype()) as isize;
+            (*view).format = if flags & ffi::PyBUF_FORMAT == ffi::PyBUF_FORMAT {
+                dtype_entry(tensor.dtype()).2.as_ptr() as *mut _
+            } else {
+                std::ptr::null_mut()
+            };
+            (*view).ndim = rank as c_int;
+            (*view).shape = dims;
+            (*view).strides = dims.add(rank);
+            (*view).suboffsets = std::ptr::null_mut();
+            (*view).internal = dims as *mut c_void;
+        }
+        Ok(())
+    }
+
+    unsafe fn __releasebuffer__(&self, view: *mut ffi::Py_buffer) {
+        // SAFETY: `internal` holds the shape and strides allocated by `__getbuffer__`,
+        // `2 * ndim` values.
+        unsafe {
+            let len = 2 * (*view).ndim as usize;
+            let dims = std::ptr::slice_from_raw_parts_mut((*view).internal as *mut isize, len);
+            drop(Box::from_raw(dims));
+        }
+    }
+}
+
+/// Address of the first element of a tensor bound to CPU memory.
+fn host_data(tensor: &Tensor) -> PyResult<*mut u8> {
+    let storage = tensor.storage()?;
+    let buffer = storage.as_cpu().ok_or_else(|| {
+        PyBufferError::new_err("only tensors in CPU buffers can be exported without a copy")
+    })?;
+    // SAFETY: the offset of a bound tensor lies within its buffer.
+    Ok(unsafe { buffer.host_ptr().add(storage.offset() + tensor.view_offset()) })
+}
+
+/// Elements of a buffer-protocol object, as native-endian bytes.
+fn buffer_bytes<T: Element + Copy>(data: &Bound<'_, PyAny>) -> PyResult<Vec<u8>> {
+    let values = PyBuffer::<T>::get(data)?.to_vec(data.py())?;
+    let size = std::mem::size_of_val(values.as_slice());
+    let mut bytes = vec![0; size];
+    // SAFETY: buffer elements are plain numbers without padding.
+    unsafe {
+        std::ptr::copy_nonoverlapping(values.as_ptr() as *const u8, bytes.as_mut_ptr(), size)
+    };
+    Ok(bytes)
+}
+
+#[pyclass(name = "Graph", module = "feml._core", unsendable)]
+pub struct PyGraph {
+    graph: ComputeGraph,
+}
+
+#[pymethods]
+impl PyGraph {
+    /// Graph computing `output` and everything it depends on.
+    #[new]
+    fn new(ctx: &PyContext, output: &PyTensor) -> PyResult<Self> {
+        let graph = ComputeGraph::new();
+        graph.build_forward(&ctx.ctx, output.tensor.tensor_id(), false)?;
+        Ok(Self { graph })
+    }
+
+    fn __len__(&self) -> usize {
+        self.graph.nodes().len()
+    }
+}
+
+/// Backend memory holding tensors; they stay usable while it is alive.
+#[pyclass(name = "Buffer", module = "feml._core", unsendable)]
+pub struct PyBackendBuffer {
+    #[allow(dead_code)]
+    buffer: Box<dyn BackendBuffer>,
+}
+
+#[pyclass(name = "Backend", module = "feml._core", unsendable)]
+pub struct PyBackend {
+    backend: api::Backend,
+}
+
+#[pymethods]
+impl PyBackend {
+    /// Opens a backend by name, e.g. `"cpu"` or `"cuda:0"`.
+    #[new]
+    #[pyo3(signature = (name = "cpu", threads = None))]
+    fn new(name: &str, threads: Option<usize>) -> PyResult<Self> {
+        let mut builder = api::Backend::by_name(name);
+        if let Some(threads) = threads {
+            builder = builder.with_threads(threads);
+        }
+        Ok(Self { backend: builder.build()? })
+    }
+
+    #[staticmethod]
+    #[pyo3(signature = (threads = None))]
+    fn cpu(threads: Option<usize>) -> PyResult<Self> {
+        Self::new("cpu", threads)
+    }
+
+    #[getter]
+    fn name(&self) -> String {
+        self.backend.name().to_string()
+    }
+
+    /// Allocates one buffer for `tensors` and binds them to it.
+    fn alloc(&self, tensors: Vec<PyRef<'_, PyTensor>>) -> PyResult<PyBackendBuffer> {
+        let tensors: Vec<Tensor> = tensors.iter().map(|t| t.tensor.clone()).collect();
+        Ok(PyBackendBuffer { buffer: self.backend.alloc(&tensors)? })
+    }
+
+    fn compute(&self, ctx: &PyContext, mut graph: PyRefMut<'_, PyGraph>) -> PyResult<()> {
+        Ok(self.backend.compute(&ctx.ctx, &mut graph.graph)?)
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "_core")]
+fn core_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyContext>()?;
+    m.add_class::<PyTensor>()?;
+    m.add_class::<PyGraph>()?;
+    m.add_class::<PyBackendBuffer>()?;
+    m.add_class::<PyBackend>()?;
+    Ok(())
+}
@@ -0,0 +1,278 @@
+//! Rowwise int8 quantization.
+//!
+//! Every row is stored as [`DataType::I8`](crate::data_type::DataType::I8) values with one
+//! `f32` scale, `x ~= scale * q` with `q` in `-127..=127`. A scale per row follows the
+//! range of that row alone, which keeps outlier rows from costing the others precision and
+//! suits activations. [`Tensor::mul_mat_rowwise`](crate::tensor::Tensor::mul_mat_rowwise)
+//! multiplies such weights with float activations, quantizing the activations row by row.
+//!
+//! [`quantize_rows_q8_imatrix`] picks the weight scales with an importance matrix, the
+//! statistics of calibration activations per column, so that the columns that meet large
+//! inputs keep the most precision.
+//!
+//! In tensor files a quantized tensor `name` holds the I8 values and `name.scales` (see
+//! [`SCALES_SUFFIX`]) the F32 scales of its rows.
+
+use crate::error::{Error, Result};
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Largest quantized magnitude; -128 is left out so that the range is symmetric.
+pub(crate) const Q8_MAX: f32 = 127.0;
+
+/// Suffix of the name of the scales tensor of a quantized tensor.
+pub const SCALES_SUFFIX: &str = ".scales";
+
+/// Rows quantized by [`quantize_rows_q8`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowwiseQ8 {
+    /// Quantized values, row after row.
+    pub values: Vec<i8>,
+    /// One scale per row.
+    pub scales: Vec<f32>,
+    pub row_len: usize,
+}
+
+impl RowwiseQ8 {
+    /// Checks that `scales` has one scale per row of `row_len` `values`.
+    pub fn from_parts(values: Vec<i8>, scales: Vec<f32>, row_len: usize) -> Result<Self> {
+        if row_len == 0 || values.len() != scales.len() * row_len {
+            return Err(Error::msg(format!(
+                "{} values are not {} rows of {row_len}",
+                values.len(),
+                scales.len()
+            ))
+            .context("in RowwiseQ8::from_parts"));
+        }
+        Ok(Self { values, scales, row_len })
+    }
+
+    pub fn n_rows(&self) -> usize {
+        self.scales.len()
+    }
+
+    /// The values as the bytes of an I8 tensor.
+    pub fn value_bytes(&self) -> Vec<u8> {
+        self.values.iter().map(|&q| q as u8).collect()
+    }
+
+    pub fn dequantize(&self) -> Vec<f32> {
+        let mut data = vec![0.0; self.values.len()];
+        for ((dst, src), &scale) in data
+            .chunks_exact_mut(self.row_len.max(1))
+            .zip(self.values.chunks_exact(self.row_len.max(1)))
+            .zip(&self.scales)
+        {
+            dequantize_row_q8(src, scale, dst);
+        }
+        data
+    }
+}
+
+/// Quantizes `row` into `dst`, which has the same length, and returns the scale.
+pub fn quantize_row_q8(row: &[f32], dst: &mut [i8]) -> f32 {
+    let amax = row.iter().fold(0.0f32, |amax, x| amax.max(x.abs()));
+    let scale = amax / Q8_MAX;
+    let inv = if scale > 0.0 { 1.0 / scale } else { 0.0 };
+    for (q, x) in dst.iter_mut().zip(row) {
+        *q = round_q8(x * inv);
+    }
+    scale
+}
+
+/// `x` clamped to the quantized range and rounded half away from zero, as `f32::round`
+/// does; that is not in `core`, so `no_std` builds could not use it.
+fn round_q8(x: f32) -> i8 {
+    let x = x.clamp(-Q8_MAX, Q8_MAX);
+    let truncated = x as i8;
+    let fraction = x - f32::from(truncated);
+    if fraction >= 0.5 {
+        truncated + 1
+    } else if fraction <= -0.5 {
+        truncated - 1
+    } else {
+        truncated
+    }
+}
+
+pub fn dequantize_row_q8(row: &[i8], scale: f32, dst: &mut [f32]) {
+    for (x, &q) in dst.iter_mut().zip(row) {
+        *x = scale * q as f32;
+    }
+}
+
+/// Quantizes `data`, rows of `row_len` values one after the other.
+pub fn quantize_rows_q8(data: &[f32], row_len: usize) -> Result<RowwiseQ8> {
+    if row_len == 0 || !data.len().is_multiple_of(row_len) {
+        return Err(Error::msg(format!(
+            "{} values are not a whole number of rows of {row_len}",
+            data.len()
+        ))
+        .context("in quantize_rows_q8"));
+    }
+
+    let mut values = vec![0; data.len()];
+    let scales = data
+        .chunks_exact(row_len)
+        .zip(values.chunks_exact_mut(row_len))
+        .map(|(row, dst)| quantize_row_q8(row, dst))
+        .collect();
+    Ok(RowwiseQ8 { values, scales, row_len })
+}
+
+/// [`quantize_row_q8`] minimizing the rounding error weighted by `importance`, one weight
+/// per value of `row`, instead of keeping the largest value exact. Returns the scale.
+///
+/// Scales around `amax / 127` are tried, each with the least-squares scale of its rounded
+/// values, so important columns can gain precision at the cost of clipping or coarser
+/// unimportant ones. The weighted error is never above that of [`quantize_row_q8`].
+pub fn quantize_row_q8_weighted(row: &[f32], importance: &[f32], dst: &mut [i8]) -> f32 {
+    let amax = row.iter().fold(0.0f32, |amax, x| amax.max(x.abs()));
+    let mut best = (quantize_row_q8(row, dst), f32::INFINITY);
+    if amax == 0.0 {
+        return best.0;
+    }
+    best.1 = weighted_error(row, importance, dst, best.0);
+
+    let mut candidate = vec![0; row.len()];
+    for step in -9..=9 {
+        let inv = (Q8_MAX + 0.1 * step as f32) / amax;
+        let (mut xq, mut qq) = (0.0, 0.0);
+        for ((q, &x), &w) in candidate.iter_mut().zip(row).zip(importance) {
+            *q = round_q8(x * inv);
+            xq += w * x * *q as f32;
+            qq += w * *q as f32 * *q as f32;
+        }
+        if qq <= 0.0 {
+            continue;
+        }
+        let scale = xq / qq;
+        let error = weighted_error(row, importance, &candidate, scale);
+        if error < best.1 {
+            best = (scale, error);
+            dst.copy_from_slice(&candidate);
+        }
+    }
+    best.0
+}
+
+/// `sum_j importance[j] * (row[j] - scale * q[j])^2`
+fn weighted_error(row: &[f32], importance: &[f32], q: &[i8], scale: f32) -> f32 {
+    let errors = row.iter().zip(q).map(|(x, &q)| x - scale * q as f32);
+    errors.zip(importance).map(|(e, w)| w * e * e).sum()
+}
+
+/// [`quantize_rows_q8`] with the rounding error of column `j` weighted by `importance[j]`,
+/// see [`quantize_row_q8_weighted`]. `importance` has `row_len` non-negative entries,
+/// e.g. from [`importance_from_activations`].
+pub fn quantize_rows_q8_imatrix(
+    data: &[f32],
+    row_len: usize,
+    importance: &[f32],
+) -> Result<RowwiseQ8> {
+    if importance.len() != row_len || importance.iter().any(|w| w.is_nan() || *w < 0.0) {
+        return Err(Error::msg(format!(
+            "importance needs {row_len} non-negative values, got {}",
+            importance.len()
+        ))
+        .context("in quantize_rows_q8_imatrix"));
+    }
+    let mut quantized =
+        quantize_rows_q8(data, row_len).map_err(|e| e.context("in quantize_rows_q8_imatrix"))?;
+    for ((row, dst), scale) in data
+        .chunks_exact(row_len)
+        .zip(quantized.values.chunks_exact_mut(row_len))
+        .zip(quantized.scales.iter_mut())
+    {
+        *scale = quantize_row_q8_weighted(row, importance, dst);
+    }
+    Ok(quantized)
+}
+
+/// Importance of each of the `row_len` columns of a weight matrix from calibration
+/// `activations`, rows of `row_len` inputs to that matrix: the mean square of every input.
+/// An output row's error `sum_j (w_j - w'_j) x_j` grows with the inputs its errors meet.
+pub fn importance_from_activations(activations: &[f32], row_len: usize) -> Result<Vec<f32>> {
+    if row_len == 0 || activations.is_empty() || !activations.len().is_multiple_of(row_len) {
+        return Err(Error::msg(format!(
+            "{} activations are not a whole number of rows of {row_len}",
+            activations.len()
+        ))
+        .context("in importance_from_activations"));
+    }
+    let mut importance = vec![0.0; row_len];
+    for row in activations.chunks_exact(row_len) {
+        for (w, x) in importance.iter_mut().zip(row) {
+            *w += x * x;
+        }
+    }
+    let n_rows = (activations.len() / row_len) as f32;
+    importance.iter_mut().for_each(|w| *w /= n_rows);
+    Ok(importance)
+}
+
+/// Dot product of two quantized rows, accumulated exactly in `i32`. Rows up to 2^17 values
+/// long cannot overflow.
+pub fn dot_q8(a: &[i8], b: &[i8]) -> i32 {
+    a.iter().zip(b).map(|(&a, &b)| a as i32 * b as i32).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantize_rows_q8_round_trip() {
+        let data = [1.0, -0.5, 0.25, 0.0, 0.0, 0.0, 0.0, 0.0, 100.0, 50.0, -25.0, 0.1];
+        let quantized = quantize_rows_q8(&data, 4).unwrap();
+
+        assert_eq!(quantized.n_rows(), 3);
+        assert_eq!(quantized.values[..4], [127, -64, 32, 0]);
+        // an all-zero row keeps a zero scale instead of dividing by zero
+        assert_eq!(quantized.scales[1], 0.0);
+        let dequantized = quantized.dequantize();
+        for (i, (x, y)) in data.iter().zip(&dequantized).enumerate() {
+            // rounding is off by at most half a step
+            let step = quantized.scales[i / 4];
+            assert!((x - y).abs() <= step / 2.0 + 1e-6, "{x} != {y}");
+        }
+        assert!(quantize_rows_q8(&data, 5).is_err());
+    }
+
+    #[test]
+    fn test_round_q8_matches_round() {
+        for i in -600..=600 {
+            let x = i as f32 / 4.0;
+            assert_eq!(round_q8(x), x.round().clamp(-Q8_MAX, Q8_MAX) as i8, "{x}");
+        }
+        assert_eq!(round_q8(0.499_999_97), 0);
+        assert_eq!(round_q8(f32::NAN), 0);
+    }
+
+    #[test]
+    fn test_quantize_rows_q8_imatrix() {
+        let data: Vec<f32> = (0..64).map(|i| ((i * 37 % 23) as f32 - 11.0) / 7.0).collect();
+        let importance: Vec<f32> = (0..16).map(|j| if j % 4 == 0 { 100.0 } else { 0.1 }).collect();
+        let plain = quantize_rows_q8(&data, 16).unwrap();
+        let weighted = quantize_rows_q8_imatrix(&data, 16, &importance).unwrap();
+
+        let error = |quantized: &RowwiseQ8| {
+            let rows = data.chunks_exact(16).zip(quantized.values.chunks_exact(16));
+            rows.zip(&quantized.scales)
+                .map(|((row, q), &scale)| weighted_error(row, &importance, q, scale))
+                .sum::<f32>()
+        };
+        assert!(error(&weighted) < error(&plain));
+        assert!(quantize_rows_q8_imatrix(&data, 16, &importance[1..]).is_err());
+        assert!(quantize_rows_q8_imatrix(&data, 16, &[f32::NAN; 16]).is_err());
+
+        let activations = [1.0, 2.0, 3.0, 0.0];
+        assert_eq!(importance_from_activations(&activations, 2).unwrap(), [5.0, 2.0]);
+    }
+
+    #[test]
+    fn test_dot_q8() {
+        assert_eq!(dot_q8(&[127, -127, 3], &[127, 127, -2]), -6);
+    }
+}
@@ -1,5 +1,135 @@
-use crate::backend::{Backend, BackendDevice, BackendRegister};
+use crate::backend::{
+    AbortCallback, Backend, BackendDevice, BackendFeature, BackendRegister, NumaStrategy,
+};
 use crate::error::{Error, Result};
+use crate::threadpool::ThreadPool;
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, PoisonError, RwLock};
+
+pub type SetNThreadsFn = fn(&mut dyn Backend, usize) -> Result<()>;
+pub type SetAbortCallbackFn = fn(&mut dyn Backend, Option<AbortCallback>) -> Result<()>;
+pub type SetThreadpoolFn = fn(&mut dyn Backend, Option<Arc<ThreadPool>>) -> Result<()>;
+pub type GetFeaturesFn = fn() -> Vec<BackendFeature>;
+pub type NumaInitFn = fn(NumaStrategy) -> Result<()>;
+
+/// Backend entry point that is not part of the backend traits, looked up by name with
+/// [`Registry::get_proc_address`].
+#[derive(Clone)]
+pub enum BackendFunction {
+    SetNThreads(SetNThreadsFn),
+    SetAbortCallback(SetAbortCallbackFn),
+    SetThreadpool(SetThreadpoolFn),
+    GetFeatures(GetFeaturesFn),
+    NumaInit(NumaInitFn),
+    /// Anything else a backend wants to expose; fetch it with [`Registry::custom_fn`].
+    Custom(Arc<dyn Any + Send + Sync>),
+}
+
+impl BackendFunction {
+    pub fn as_set_n_threads(&self) -> Option<SetNThreadsFn> {
+        match self {
+            Self::SetNThreads(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_set_abort_callback(&self) -> Option<SetAbortCallbackFn> {
+        match self {
+            Self::SetAbortCallback(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_set_threadpool(&self) -> Option<SetThreadpoolFn> {
+        match self {
+            Self::SetThreadpool(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_get_features(&self) -> Option<GetFeaturesFn> {
+        match self {
+            Self::GetFeatures(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_numa_init(&self) -> Option<NumaInitFn> {
+        match self {
+            Self::NumaInit(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_custom<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        match self {
+            Self::Custom(f) => f.clone().downcast::<T>().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Creates the register of one backend and probes its devices.
+pub type RegisterHook = fn() -> Result<Box<dyn BackendRegister>>;
+
+static BACKEND_HOOKS: OnceLock<RwLock<Vec<(String, RegisterHook)>>> = OnceLock::new();
+
+fn lock_hooks() -> &'static RwLock<Vec<(String, RegisterHook)>> {
+    BACKEND_HOOKS.get_or_init(|| RwLock::new(Vec::new()))
+}
+
+/// Adds a backend that is not part of this crate, e.g. from a separate backend crate, to
+/// every later [`Registry::discover`]. Registering a name again replaces its hook.
+pub fn register_backend(name: &str, hook: RegisterHook) {
+    let mut hooks = lock_hooks().write().unwrap_or_else(PoisonError::into_inner);
+    match hooks.iter_mut().find(|(registered, _)| registered.eq_ignore_ascii_case(name)) {
+        Some(registered) => registered.1 = hook,
+        None => hooks.push((name.to_string(), hook)),
+    }
+}
+
+/// Backends compiled into the crate, each behind its cargo feature, in discovery order.
+fn builtin_hooks() -> Vec<(&'static str, RegisterHook)> {
+    vec![
+        #[cfg(feature = "cpu")]
+        ("cpu", crate::cpu::backend_register::register as RegisterHook),
+        #[cfg(feature = "opencl")]
+        ("opencl", crate::opencl::backend_register::register as RegisterHook),
+        #[cfg(feature = "cuda")]
+        ("cuda", crate::cuda::backend_register::register as RegisterHook),
+    ]
+}
+
+static BACKEND_FUNCTIONS: OnceLock<RwLock<BackendRegistry>> = OnceLock::new();
+
+/// Functions registered for a backend on top of the ones its register exposes.
+#[derive(Default, Clone)]
+pub struct BackendRegistry {
+    functions: HashMap<(String, String), BackendFunction>,
+}
+
+impl BackendRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process-wide registry consulted by [`Registry::get_proc_address`], created on first
+    /// use. Backends can add their functions to it while they initialize.
+    pub fn global() -> &'static RwLock<BackendRegistry> {
+        BACKEND_FUNCTIONS.get_or_init(|| RwLock::new(BackendRegistry::new()))
+    }
+
+    /// Registers `func` as `name` of `backend`, replacing an earlier registration. Backend
+    /// names are matched case-insensitively.
+    pub fn register(&mut self, backend: &str, name: &str, func: BackendFunction) {
+        self.functions.insert((backend.to_ascii_lowercase(), name.to_string()), func);
+    }
+
+    pub fn get(&self, backend: &str, name: &str) -> Option<BackendFunction> {
+        self.functions.get(&(backend.to_ascii_lowercase(), name.to_string())).cloned()
+    }
+}
 
 #[derive(Default)]
 pub struct Registry {
@@ -7,34 +137,31 @@ pub struct Registry {
 }
 
 impl Registry {
+    /// Creates the registers of the backends compiled in, then of the ones added with
+    /// [`register_backend`]. A hook added under the name of a built-in backend replaces it.
     pub fn discover() -> Result<Self> {
-        let mut registers: Vec<Box<dyn BackendRegister>> = Vec::new();
-
-        #[cfg(feature = "cpu")]
-        {
-            use crate::cpu::backend_register::CpuBackendRegister;
-            let reg = CpuBackendRegister::new();
-            reg.probe_devices()?;
-            registers.push(Box::new(reg));
-        }
-        #[cfg(feature = "opencl")]
-        {
-            use crate::opencl::backend_register::OpenclBackendRegister;
-            let reg = OpenclBackendRegister::new();
-            reg.probe_devices()?;
-            registers.push(Box::new(reg));
-        }
-        #[cfg(feature = "cuda")]
-        {
-            use crate::cuda::backend_register::CudaBackendRegister;
-            let reg = CudaBackendRegister::new();
-            reg.probe_devices()?;
-            registers.push(Box::new(reg));
+        let mut hooks: Vec<(String, RegisterHook)> =
+            builtin_hooks().into_iter().map(|(name, hook)| (name.to_string(), hook)).collect();
+        for (name, hook) in lock_hooks().read().unwrap_or_else(PoisonError::into_inner).iter() {
+            match hooks.iter_mut().find(|(builtin, _)| builtin.eq_ignore_ascii_case(name)) {
+                Some(builtin) => builtin.1 = *hook,
+                None => hooks.push((name.clone(), *hook)),
+            }
         }
 
+        let mut registers = Vec::with_capacity(hooks.len());
+        for (name, hook) in hooks {
+            registers
+                .push(hook().map_err(|e| e.context(format!("in Registry::discover: {name}")))?);
+        }
         Ok(Registry { registers })
     }
 
+    /// Names of the backends enabled by cargo features, e.g. `["cpu"]` in a default build.
+    pub fn builtin_backends() -> Vec<&'static str> {
+        builtin_hooks().into_iter().map(|(name, _)| name).collect()
+    }
+
     pub fn init_all(&self) -> Result<()> {
         for reg in &self.registers {
             reg.init_devices()?;
@@ -77,6 +204,24 @@ impl Registry {
         reg.device(device_index)
     }
 
+    /// Backend name and device index of the device whose
+    /// [`DeviceInfo::id`](crate::backend::DeviceInfo::id) is `id`, to open the same physical
+    /// device again in a later run even if the device order changed.
+    pub fn find_device(&self, id: &str) -> Option<(&str, usize)> {
+        if id.is_empty() {
+            return None;
+        }
+        self.registers.iter().find_map(|reg| {
+            (0..reg.device_count())
+                .find(|&index| {
+                    reg.device(index)
+                        .and_then(|device| device.info())
+                        .is_ok_and(|info| info.id == id)
+                })
+                .map(|index| (reg.name(), index))
+        })
+    }
+
     pub fn open_best(&self) -> Result<Box<dyn Backend>> {
         if let Some(reg) = self.find("CUDA") {
             if reg.device_count() > 0 {
@@ -102,4 +247,46 @@ impl Registry {
             .or_else(|| self.find("cpu"))
             .filter(|r| r.device_count() > 0)
     }
+
+    /// Registers an extra entry point for `backend` in the global [`BackendRegistry`], e.g.
+    /// from an external backend crate. It stays visible to every `Registry`.
+    pub fn register_function(&self, backend: &str, name: &str, func: BackendFunction) {
+        BackendRegistry::global()
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .register(backend, name, func);
+    }
+
+    /// Looks `name` up in the functions registered for `backend`, then in its register.
+    pub fn get_proc_address(&self, backend: &str, name: &str) -> Option<BackendFunction> {
+        let registered = BackendRegistry::global()
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(backend, name);
+        registered.or_else(|| self.find(backend).and_then(|reg| reg.get_proc_address(name)))
+    }
+
+    pub fn set_n_threads_fn(&self, backend: &str) -> Option<SetNThreadsFn> {
+        self.get_proc_address(backend, "set_n_threads")?.as_set_n_threads()
+    }
+
+    pub fn set_abort_callback_fn(&self, backend: &str) -> Option<SetAbortCallbackFn> {
+        self.get_proc_address(backend, "set_abort_callback")?.as_set_abort_callback()
+    }
+
+    pub fn set_threadpool_fn(&self, backend: &str) -> Option<SetThreadpoolFn> {
+        self.get_proc_address(backend, "set_threadpool")?.as_set_threadpool()
+    }
+
+    pub fn get_features_fn(&self, backend: &str) -> Option<GetFeaturesFn> {
+        self.get_proc_address(backend, "get_features")?.as_get_features()
+    }
+
+    pub fn numa_init_fn(&self, backend: &str) -> Option<NumaInitFn> {
+        self.get_proc_address(backend, "numa_init")?.as_numa_init()
+    }
+
+    pub fn custom_fn<T: Any + Send + Sync>(&self, backend: &str, name: &str) -> Option<Arc<T>> {
+        self.get_proc_address(backend, name)?.as_custom()
+    }
 }
@@ -0,0 +1,93 @@
+//! Interleaved weight layouts.
+//!
+//! A matrix product reads every row of its weights once per output row. Stored row after
+//! row, a kernel computing `n` outputs at a time streams `n` rows at once, `row_len`
+//! elements apart. Interleaved, the rows are grouped into panels of `n` rows whose elements
+//! alternate: element `k` of row `r` of panel `p` is at `(p * row_len + k) * n + r`, so the
+//! same kernel reads the panel with unit stride. The panel size a backend's kernel wants is
+//! [`Backend::blk_size_interleave`](crate::backend::Backend::blk_size_interleave), and
+//! [`StreamingLoader::repack`](crate::loader::StreamingLoader::repack) stores weights that
+//! way at load time, marking them with
+//! [`Tensor::interleaved_rows`](crate::tensor::Tensor::interleaved_rows).
+
+use crate::error::{Error, Result};
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// `data`, rows of `row_len` elements of `elem_size` bytes, interleaved in panels of `rows`
+/// rows. The number of rows must be a multiple of `rows`.
+pub fn interleave_rows(
+    data: &[u8],
+    elem_size: usize,
+    row_len: usize,
+    rows: usize,
+) -> Result<Vec<u8>> {
+    check_panels(data, elem_size, row_len, rows).map_err(|e| e.context("in interleave_rows"))?;
+    let mut packed = vec![0; data.len()];
+    for_each_element(data.len() / elem_size, row_len, rows, |plain, interleaved| {
+        let (src, dst) = (plain * elem_size, interleaved * elem_size);
+        packed[dst..dst + elem_size].copy_from_slice(&data[src..src + elem_size]);
+    });
+    Ok(packed)
+}
+
+/// The rows of `data` interleaved by [`interleave_rows`], one after another again.
+pub fn deinterleave_rows(
+    data: &[u8],
+    elem_size: usize,
+    row_len: usize,
+    rows: usize,
+) -> Result<Vec<u8>> {
+    check_panels(data, elem_size, row_len, rows).map_err(|e| e.context("in deinterleave_rows"))?;
+    let mut plain = vec![0; data.len()];
+    for_each_element(data.len() / elem_size, row_len, rows, |row_major, interleaved| {
+        let (src, dst) = (interleaved * elem_size, row_major * elem_size);
+        plain[dst..dst + elem_size].copy_from_slice(&data[src..src + elem_size]);
+    });
+    Ok(plain)
+}
+
+fn check_panels(data: &[u8], elem_size: usize, row_len: usize, rows: usize) -> Result<()> {
+    if elem_size == 0 || row_len == 0 || rows == 0 {
+        return Err(Error::msg("element size, row length and panel rows must be positive"));
+    }
+    if !data.len().is_multiple_of(elem_size * row_len * rows) {
+        return Err(Error::msg(format!(
+            "{} bytes are not panels of {rows} rows of {row_len} elements of {elem_size} bytes",
+            data.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Calls `f` with the plain and the interleaved index of each of the `len` elements.
+fn for_each_element(len: usize, row_len: usize, rows: usize, mut f: impl FnMut(usize, usize)) {
+    for index in 0..len {
+        let (row, k) = (index / row_len, index % row_len);
+        let (panel, r) = (row / rows, row % rows);
+        f(index, (panel * row_len + k) * rows + r);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interleave_rows() {
+        // four rows of three one-byte elements, in panels of two rows
+        let data = [0, 1, 2, 10, 11, 12, 20, 21, 22, 30, 31, 32];
+        let packed = interleave_rows(&data, 1, 3, 2).unwrap();
+        assert_eq!(packed, [0, 10, 1, 11, 2, 12, 20, 30, 21, 31, 22, 32]);
+        assert_eq!(deinterleave_rows(&packed, 1, 3, 2).unwrap(), data);
+
+        let wide: Vec<u8> = (0..48).collect();
+        let packed = interleave_rows(&wide, 4, 3, 4).unwrap();
+        assert_eq!(packed[4..8], wide[12..16]);
+        assert_eq!(deinterleave_rows(&packed, 4, 3, 4).unwrap(), wide);
+
+        assert!(interleave_rows(&data, 1, 3, 3).is_err());
+        assert!(interleave_rows(&data, 1, 0, 2).is_err());
+    }
+}
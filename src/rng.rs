@@ -0,0 +1,92 @@
+//! Random state of stochastic graphs.
+//!
+//! Ops such as [`Tensor::dropout`](crate::tensor::Tensor::dropout) draw their random numbers
+//! from an [`RngState`] held in a small tensor of the graph, see
+//! [`ComputeGraph::rng`](crate::compute_graph::ComputeGraph::rng). The numbers are a hash of
+//! the seed, a counter and the element index, so they do not depend on the thread count;
+//! every op that draws from the state advances its counter. Saving the state with
+//! [`Backend::rng_state`](crate::api::Backend::rng_state) and restoring it with
+//! [`Backend::set_rng_state`](crate::api::Backend::set_rng_state) replays a compute exactly.
+
+use crate::error::{Error, Result};
+use alloc::format;
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Seed and counter of a counter-based generator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct RngState {
+    pub seed: u64,
+    /// Draws made so far; every op that draws from the state increments it once.
+    pub counter: u64,
+}
+
+impl RngState {
+    /// Size of the state in a tensor, two little-endian `u64`s.
+    pub const NBYTES: usize = 16;
+
+    pub fn new(seed: u64) -> Self {
+        Self { seed, counter: 0 }
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::NBYTES] {
+        let mut bytes = [0; Self::NBYTES];
+        bytes[..8].copy_from_slice(&self.seed.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.counter.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != Self::NBYTES {
+            return Err(Error::msg(format!(
+                "an rng state has {} bytes, got {}",
+                Self::NBYTES,
+                bytes.len()
+            ))
+            .context("in RngState::from_bytes"));
+        }
+        let word = |i: usize| u64::from_le_bytes(bytes[8 * i..8 * i + 8].try_into().unwrap());
+        Ok(Self { seed: word(0), counter: word(1) })
+    }
+
+    /// Uniform number in `[0, 1)` for element `index` of the current draw.
+    pub fn uniform(&self, index: u64) -> f32 {
+        let key = mix(self.seed ^ mix(self.counter.wrapping_add(1).wrapping_mul(GOLDEN_GAMMA)));
+        let bits = mix(key.wrapping_add(index.wrapping_mul(GOLDEN_GAMMA)));
+        (bits >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Moves on to the next draw.
+    pub fn advance(&mut self) {
+        self.counter = self.counter.wrapping_add(1);
+    }
+}
+
+/// The splitmix64 finalizer.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rng_state_draws() {
+        let mut state = RngState { seed: 42, counter: 7 };
+        assert_eq!(RngState::from_bytes(&state.to_bytes()).unwrap(), state);
+        assert!(RngState::from_bytes(&[0; 8]).is_err());
+
+        let draw: alloc::vec::Vec<f32> = (0..1000).map(|i| state.uniform(i)).collect();
+        assert!(draw.iter().all(|u| (0.0..1.0).contains(u)));
+        let mean = draw.iter().sum::<f32>() / draw.len() as f32;
+        assert!((mean - 0.5).abs() < 0.05, "{mean}");
+
+        state.advance();
+        assert_eq!(state.counter, 8);
+        assert_ne!(state.uniform(0), draw[0]);
+        assert_ne!(RngState::new(43).uniform(0), RngState::new(42).uniform(0));
+    }
+}
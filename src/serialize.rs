@@ -0,0 +1,612 @@
+//! Binary tensor files, optionally compressed with zstd.
+//!
+//! A file starts with an 8-byte magic, a format version and the compression of the rest.
+//! The rest is a sequence of records, one per tensor: name, data type, shape and raw
+//! bytes, followed by an end marker. Records are written and read one at a time, so a file
+//! never has to fit in memory as a whole, and compressed files are decompressed
+//! transparently on load.
+//!
+//! Files written by [`TensorWriter::with_checksums`] have a CRC-32 after the bytes of every
+//! record. Readers verify it, so a corrupted tensor fails to load with its name rather than
+//! loading garbage.
+//!
+//! zstd support needs the `zstd` feature; files written with it can only be read by builds
+//! that have it too. Without `std`, [`encode_tensors`] and [`decode_tensors`] still read and
+//! write uncompressed files held in memory.
+
+use crate::context::Context;
+use crate::data_type::{DataType, TensorOpType, TensorType};
+use crate::defs::MAX_DIMS;
+use crate::error::{Error, Result};
+use crate::shape::Shape;
+use crate::tensor::Tensor;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::{BufReader, BufWriter, Read, Write};
+#[cfg(feature = "std")]
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"FEMLTNSR";
+const VERSION: u32 = 1;
+/// Version of the files with a checksum after every record.
+const VERSION_CHECKSUMS: u32 = 2;
+/// Name length that marks the end of the records.
+const END_OF_RECORDS: u32 = u32::MAX;
+
+/// All data types, indexed by their tag in the file.
+const DTYPES: [DataType; 9] = [
+    DataType::U8,
+    DataType::U32,
+    DataType::I16,
+    DataType::I32,
+    DataType::I64,
+    DataType::F16,
+    DataType::F32,
+    DataType::F64,
+    DataType::I8,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    /// zstd at the given level, 1 (fast) to 22 (small); 0 picks zstd's default.
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+impl Compression {
+    fn tag(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(_) => 1,
+        }
+    }
+}
+
+/// Host copy of one tensor as stored in a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorData {
+    pub name: String,
+    pub dtype: DataType,
+    pub shape: Shape,
+    pub data: Vec<u8>,
+}
+
+impl TensorData {
+    /// Reads the bytes of `tensor`, which must be bound to a buffer, under its name.
+    pub fn from_tensor(tensor: &Tensor) -> Result<Self> {
+        let size = tensor.nbytes();
+        let mut data = vec![0; size];
+        let storage = tensor.storage().map_err(|e| e.context("in TensorData::from_tensor"))?;
+        storage.buffer().read(tensor.clone(), &mut data, 0, size)?;
+        Ok(Self { name: tensor.name(), dtype: tensor.dtype(), shape: *tensor.shape(), data })
+    }
+
+    /// Creates a leaf tensor with this name, type and shape in `ctx`. It still has to be
+    /// bound to a buffer before [`TensorData::upload`].
+    pub fn new_tensor(&self, ctx: &mut Context) -> Result<Tensor> {
+        new_leaf_tensor(ctx, &self.name, self.dtype, &self.shape)
+    }
+
+    /// Writes the bytes into `tensor`, which must be bound and have the same size.
+    pub fn upload(&self, tensor: &Tensor) -> Result<()> {
+        if tensor.nbytes() != self.data.len() {
+            return Err(Error::msg(format!(
+                "tensor {} has {} bytes, stored data has {}",
+                self.name,
+                tensor.nbytes(),
+                self.data.len()
+            ))
+            .context("in TensorData::upload"));
+        }
+
+        let mut data = self.data.clone();
+        let storage = tensor.storage().map_err(|e| e.context("in TensorData::upload"))?;
+        storage.buffer().write(tensor.clone(), &mut data, 0, self.data.len())
+    }
+}
+
+/// Named leaf tensor, as the loaders create for stored weights.
+pub(crate) fn new_leaf_tensor(
+    ctx: &mut Context,
+    name: &str,
+    dtype: DataType,
+    shape: &Shape,
+) -> Result<Tensor> {
+    let tensor = ctx.new_tensor(dtype, shape)?;
+    tensor.set_name(name);
+    tensor.set_tensor_type(TensorType::FlagParam);
+    tensor.set_op_type(TensorOpType::TensorNone);
+    Ok(tensor)
+}
+
+/// Magic, version and compression tag.
+fn file_header(compression: Compression, checksums: bool) -> [u8; 13] {
+    let version = if checksums { VERSION_CHECKSUMS } else { VERSION };
+    let mut header = [0u8; 13];
+    header[..8].copy_from_slice(MAGIC);
+    header[8..12].copy_from_slice(&version.to_le_bytes());
+    header[12] = compression.tag();
+    header
+}
+
+/// Checks magic and version and returns the compression tag and whether the records have
+/// checksums.
+fn parse_file_header(header: &[u8; 13]) -> Result<(u8, bool)> {
+    if &header[..8] != MAGIC {
+        return Err(Error::msg("not a feml tensor file"));
+    }
+    let checksums = match u32::from_le_bytes(header[8..12].try_into().unwrap()) {
+        VERSION => false,
+        VERSION_CHECKSUMS => true,
+        version => return Err(Error::msg(format!("unsupported tensor file version {version}"))),
+    };
+    Ok((header[12], checksums))
+}
+
+/// CRC-32 (IEEE) of every byte value, for [`crc32`].
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 (IEEE), the checksum of zlib and PNG.
+fn crc32(bytes: &[u8]) -> u32 {
+    let crc = bytes
+        .iter()
+        .fold(!0u32, |crc, &byte| CRC32_TABLE[usize::from((crc as u8) ^ byte)] ^ (crc >> 8));
+    !crc
+}
+
+/// Everything of a record that comes before its data.
+fn record_header(tensor: &TensorData) -> Result<Vec<u8>> {
+    let name = tensor.name.as_bytes();
+    if name.len() >= END_OF_RECORDS as usize {
+        return Err(Error::msg("tensor name is too long"));
+    }
+
+    // Registered types get ids in the order of registration, which files cannot rely on.
+    let tag = DTYPES.iter().position(|&dtype| dtype == tensor.dtype).ok_or_else(|| {
+        Error::msg(format!("{:?} tensors cannot be written to tensor files", tensor.dtype))
+    })?;
+    let rank = tensor.shape.rank;
+    let mut header = Vec::with_capacity(4 + name.len() + 2 + 8 * rank + 8);
+    header.extend_from_slice(&(name.len() as u32).to_le_bytes());
+    header.extend_from_slice(name);
+    header.extend_from_slice(&[tag as u8, rank as u8]);
+    for dim in &tensor.shape.dims[..rank] {
+        header.extend_from_slice(&(*dim as u64).to_le_bytes());
+    }
+    header.extend_from_slice(&(tensor.data.len() as u64).to_le_bytes());
+    Ok(header)
+}
+
+/// Byte stream the records are parsed from.
+trait RecordInput {
+    fn read_bytes(&mut self, bytes: &mut [u8]) -> Result<()>;
+
+    fn read_vec(&mut self, len: usize) -> Result<Vec<u8>>;
+
+    fn read_fixed<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut bytes = [0u8; N];
+        self.read_bytes(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl RecordInput for &[u8] {
+    fn read_bytes(&mut self, bytes: &mut [u8]) -> Result<()> {
+        let data = self.read_vec(bytes.len())?;
+        bytes.copy_from_slice(&data);
+        Ok(())
+    }
+
+    fn read_vec(&mut self, len: usize) -> Result<Vec<u8>> {
+        if self.len() < len {
+            return Err(Error::msg("tensor file is truncated"));
+        }
+        let (data, rest) = self.split_at(len);
+        *self = rest;
+        Ok(data.to_vec())
+    }
+}
+
+/// Reads the next record, or `None` at the end marker. With `checksums`, the data is
+/// verified against the checksum that follows it.
+fn read_record(input: &mut impl RecordInput, checksums: bool) -> Result<Option<TensorData>> {
+    let name_len = u32::from_le_bytes(input.read_fixed()?);
+    if name_len == END_OF_RECORDS {
+        return Ok(None);
+    }
+    let name = String::from_utf8(input.read_vec(name_len as usize)?)
+        .map_err(|_| Error::msg("tensor name is not UTF-8"))?;
+
+    let [dtype, rank] = input.read_fixed()?;
+    let dtype = *DTYPES
+        .get(dtype as usize)
+        .ok_or_else(|| Error::msg(format!("unknown data type tag {dtype}")))?;
+    if rank as usize > MAX_DIMS {
+        return Err(Error::msg(format!("tensor {name} has unsupported rank {rank}")));
+    }
+    let mut dims = Vec::with_capacity(rank as usize);
+    for _ in 0..rank {
+        dims.push(u64::from_le_bytes(input.read_fixed()?) as usize);
+    }
+    let shape = Shape::new(&dims);
+
+    let data = read_data(input, checksums).map_err(|e| e.context(format!("in tensor {name}")))?;
+    Ok(Some(TensorData { name, dtype, shape, data }))
+}
+
+/// Reads the size and data of a record, and verifies its checksum if there is one.
+fn read_data(input: &mut impl RecordInput, checksums: bool) -> Result<Vec<u8>> {
+    let size = u64::from_le_bytes(input.read_fixed()?) as usize;
+    let data = input.read_vec(size)?;
+    if checksums && u32::from_le_bytes(input.read_fixed()?) != crc32(&data) {
+        return Err(Error::msg("data does not match its checksum"));
+    }
+    Ok(data)
+}
+
+/// Encodes `tensors` as an uncompressed tensor file in memory.
+pub fn encode_tensors(tensors: &[TensorData]) -> Result<Vec<u8>> {
+    let mut bytes = file_header(Compression::None, false).to_vec();
+    for tensor in tensors {
+        bytes.extend(record_header(tensor).map_err(|e| e.context("in encode_tensors"))?);
+        bytes.extend_from_slice(&tensor.data);
+    }
+    bytes.extend_from_slice(&END_OF_RECORDS.to_le_bytes());
+    Ok(bytes)
+}
+
+/// Decodes an uncompressed tensor file held in memory.
+pub fn decode_tensors(mut bytes: &[u8]) -> Result<Vec<TensorData>> {
+    let decode = |bytes: &mut &[u8]| {
+        let checksums = match parse_file_header(&bytes.read_fixed()?)? {
+            (0, checksums) => checksums,
+            (tag, _) => {
+                return Err(Error::msg(format!(
+                    "tensor file compression {tag} is only supported by TensorReader"
+                )));
+            }
+        };
+        let mut tensors = Vec::new();
+        while let Some(tensor) = read_record(bytes, checksums)? {
+            tensors.push(tensor);
+        }
+        Ok(tensors)
+    };
+    decode(&mut bytes).map_err(|e| e.context("in decode_tensors"))
+}
+
+#[cfg(feature = "std")]
+enum Sink<W: Write> {
+    Plain(W),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> Write for Sink<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Sink::Plain(writer) => writer.write(buf),
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Sink::Plain(writer) => writer.flush(),
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Writes tensor records to `W`.
+#[cfg(feature = "std")]
+pub struct TensorWriter<W: Write> {
+    sink: Sink<W>,
+    checksums: bool,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> TensorWriter<W> {
+    pub fn new(writer: W, compression: Compression) -> Result<Self> {
+        Self::create(writer, compression, false)
+    }
+
+    /// Writer that stores a checksum of the data of every tensor, which readers verify.
+    /// Such files cannot be read by builds older than checksum support.
+    pub fn with_checksums(writer: W, compression: Compression) -> Result<Self> {
+        Self::create(writer, compression, true)
+    }
+
+    fn create(mut writer: W, compression: Compression, checksums: bool) -> Result<Self> {
+        writer.write_all(&file_header(compression, checksums))?;
+
+        let sink = match compression {
+            Compression::None => Sink::Plain(writer),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(level) => Sink::Zstd(zstd::Encoder::new(writer, level)?),
+        };
+        Ok(Self { sink, checksums })
+    }
+
+    pub fn write(&mut self, tensor: &TensorData) -> Result<()> {
+        let header = record_header(tensor).map_err(|e| e.context("in TensorWriter::write"))?;
+        self.sink.write_all(&header)?;
+        self.sink.write_all(&tensor.data)?;
+        if self.checksums {
+            self.sink.write_all(&crc32(&tensor.data).to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Writes the end marker and returns the underlying writer.
+    #[cfg_attr(not(feature = "zstd"), allow(clippy::infallible_destructuring_match))]
+    pub fn finish(mut self) -> Result<W> {
+        self.sink.write_all(&END_OF_RECORDS.to_le_bytes())?;
+        let mut writer = match self.sink {
+            Sink::Plain(writer) => writer,
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(encoder) => encoder.finish()?,
+        };
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+#[cfg(feature = "std")]
+enum Source<R: Read> {
+    Plain(R),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Decoder<'static, BufReader<R>>),
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Read for Source<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Source::Plain(reader) => reader.read(buf),
+            #[cfg(feature = "zstd")]
+            Source::Zstd(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> RecordInput for Source<R> {
+    fn read_bytes(&mut self, bytes: &mut [u8]) -> Result<()> {
+        Ok(self.read_exact(bytes)?)
+    }
+
+    fn read_vec(&mut self, len: usize) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        let read = self.take(len as u64).read_to_end(&mut bytes)?;
+        if read != len {
+            return Err(Error::msg("tensor file is truncated"));
+        }
+        Ok(bytes)
+    }
+}
+
+/// Reads the tensor records written by a [`TensorWriter`], one at a time.
+#[cfg(feature = "std")]
+pub struct TensorReader<R: Read> {
+    source: Source<R>,
+    checksums: bool,
+    done: bool,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> TensorReader<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut header = [0u8; 13];
+        reader.read_exact(&mut header).map_err(|e| Error::from(e).context("in TensorReader"))?;
+        let (tag, checksums) =
+            parse_file_header(&header).map_err(|e| e.context("in TensorReader::new"))?;
+
+        let source = match tag {
+            0 => Source::Plain(reader),
+            #[cfg(feature = "zstd")]
+            1 => Source::Zstd(zstd::Decoder::new(reader)?),
+            #[cfg(not(feature = "zstd"))]
+            1 => {
+                return Err(Error::msg("tensor file is zstd-compressed, enable the zstd feature")
+                    .context("in TensorReader::new"));
+            }
+            tag => {
+                return Err(Error::msg(format!("unknown tensor file compression {tag}"))
+                    .context("in TensorReader::new"));
+            }
+        };
+        Ok(Self { source, checksums, done: false })
+    }
+
+    /// Whether the file has checksums, which every record read is verified against.
+    pub fn has_checksums(&self) -> bool {
+        self.checksums
+    }
+
+    /// Reads the next record, or returns `None` after the last one.
+    pub fn next_tensor(&mut self) -> Result<Option<TensorData>> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let record = read_record(&mut self.source, self.checksums)
+            .map_err(|e| e.context("in TensorReader"))?;
+        self.done = record.is_none();
+        Ok(record)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Iterator for TensorReader<R> {
+    type Item = Result<TensorData>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_tensor().transpose()
+    }
+}
+
+/// Saves `tensors`, which must be bound to buffers, to `path` under their names.
+#[cfg(feature = "std")]
+pub fn save_tensors(
+    path: impl AsRef<Path>,
+    tensors: &[Tensor],
+    compression: Compression,
+) -> Result<()> {
+    let file =
+        File::create(path.as_ref()).map_err(|e| Error::from(e).context("in save_tensors"))?;
+    let mut writer = TensorWriter::new(BufWriter::new(file), compression)?;
+    for tensor in tensors {
+        writer.write(&TensorData::from_tensor(tensor)?)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Loads every tensor stored in `path`, decompressing it if needed.
+#[cfg(feature = "std")]
+pub fn load_tensors(path: impl AsRef<Path>) -> Result<Vec<TensorData>> {
+    let file = File::open(path.as_ref()).map_err(|e| Error::from(e).context("in load_tensors"))?;
+    TensorReader::new(BufReader::new(file))?.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape;
+
+    fn sample() -> Vec<TensorData> {
+        vec![
+            TensorData {
+                name: "weight".into(),
+                dtype: DataType::F32,
+                shape: shape![2, 3],
+                data: (0..24).collect(),
+            },
+            TensorData {
+                name: "bias".into(),
+                dtype: DataType::I32,
+                shape: shape![1],
+                data: vec![7; 4],
+            },
+        ]
+    }
+
+    #[cfg(feature = "std")]
+    fn round_trip(compression: Compression) -> Vec<u8> {
+        let mut writer = TensorWriter::new(Vec::new(), compression).unwrap();
+        for tensor in sample() {
+            writer.write(&tensor).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+
+        let loaded: Vec<TensorData> =
+            TensorReader::new(bytes.as_slice()).unwrap().collect::<Result<_>>().unwrap();
+        assert_eq!(loaded, sample());
+        bytes
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_round_trip_uncompressed() {
+        let bytes = round_trip(Compression::None);
+        assert!(TensorReader::new(&bytes[..bytes.len() - 2]).unwrap().any(|t| t.is_err()));
+        assert!(TensorReader::new(&b"NOTFEML!\x01\0\0\0\0"[..]).is_err());
+    }
+
+    #[test]
+    fn test_in_memory_encoding() {
+        let bytes = encode_tensors(&sample()).unwrap();
+        assert_eq!(decode_tensors(&bytes).unwrap(), sample());
+        assert!(decode_tensors(&bytes[..bytes.len() - 2]).is_err());
+        #[cfg(feature = "std")]
+        assert_eq!(bytes, round_trip(Compression::None));
+    }
+
+    #[test]
+    fn test_rejects_unsupported_ranks() {
+        let mut bytes = file_header(Compression::None, false).to_vec();
+        bytes.extend_from_slice(&4u32.to_le_bytes());
+        bytes.extend_from_slice(b"huge");
+        bytes.extend_from_slice(&[0, 5]);
+        for _ in 0..5 {
+            bytes.extend_from_slice(&1u64.to_le_bytes());
+        }
+        bytes.extend_from_slice(&4u64.to_le_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(&END_OF_RECORDS.to_le_bytes());
+
+        let error = decode_tensors(&bytes).unwrap_err().to_string();
+        assert!(error.contains("huge") && error.contains("rank 5"), "{error}");
+        #[cfg(feature = "std")]
+        assert!(TensorReader::new(bytes.as_slice()).unwrap().next_tensor().is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_checksums_detect_corrupted_tensors() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+
+        let mut writer = TensorWriter::with_checksums(Vec::new(), Compression::None).unwrap();
+        for tensor in sample() {
+            writer.write(&tensor).unwrap();
+        }
+        let mut bytes = writer.finish().unwrap();
+        let reader = TensorReader::new(bytes.as_slice()).unwrap();
+        assert!(reader.has_checksums());
+        assert_eq!(reader.collect::<Result<Vec<_>>>().unwrap(), sample());
+        assert_eq!(decode_tensors(&bytes).unwrap(), sample());
+
+        // last byte of the data of "bias", right before its checksum and the end marker
+        let at = bytes.len() - 9;
+        bytes[at] ^= 1;
+        let mut reader = TensorReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.next_tensor().unwrap().unwrap(), sample()[0]);
+        let error = reader.next_tensor().unwrap_err().to_string();
+        assert!(error.contains("bias") && error.contains("checksum"), "{error}");
+        assert!(decode_tensors(&bytes).is_err());
+        assert!(!TensorReader::new(encode_tensors(&sample()).unwrap().as_slice())
+            .unwrap()
+            .has_checksums());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_round_trip_zstd() {
+        let mut zeros = TensorWriter::new(Vec::new(), Compression::Zstd(3)).unwrap();
+        let tensor = TensorData {
+            name: "zeros".into(),
+            dtype: DataType::U8,
+            shape: shape![4096],
+            data: vec![0; 4096],
+        };
+        zeros.write(&tensor).unwrap();
+        assert!(zeros.finish().unwrap().len() < 256);
+
+        round_trip(Compression::Zstd(0));
+    }
+}
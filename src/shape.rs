@@ -1,5 +1,9 @@
 use crate::defs::MAX_DIMS;
-use std::ops::Index;
+use crate::error::{Error, Result};
+use alloc::format;
+use core::fmt;
+use core::ops::Index;
+use core::str::FromStr;
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Shape {
     pub dims: [usize; MAX_DIMS],
@@ -30,13 +34,81 @@ impl Shape {
         Self { dims: storage, rank: dims.len() }
     }
 
-    pub fn iter(&self) -> std::slice::Iter<'_, usize> {
+    pub fn iter(&self) -> core::slice::Iter<'_, usize> {
         self.dims[..self.rank].iter()
     }
 
+    /// Returns the extent of dimension `index`, treating dimensions beyond the rank as 1.
+    pub fn dim(&self, index: usize) -> usize {
+        if index < self.rank {
+            self.dims[index]
+        } else {
+            1
+        }
+    }
+
     pub(crate) fn nrows(&self) -> usize {
         self.dims[1] * self.dims[2] * self.dims[3]
     }
+
+    /// Number of elements.
+    pub fn numel(&self) -> usize {
+        self.iter().product()
+    }
+
+    /// Copy with dimension `index` set to `dim`. Setting a dimension beyond the rank raises
+    /// the rank, and the dimensions in between are 1.
+    pub fn with_dim(mut self, index: usize, dim: usize) -> Self {
+        assert!(index < MAX_DIMS);
+        for extra in self.rank..index {
+            self.dims[extra] = 1;
+        }
+        self.dims[index] = dim;
+        self.rank = self.rank.max(index + 1);
+        self
+    }
+}
+
+/// Dimensions joined by `x` in storage order, innermost first, e.g. `128x128x32x1` for
+/// `shape![128, 128, 32, 1]`; `()` for rank 0.
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.rank == 0 {
+            return f.write_str("()");
+        }
+        for (i, dim) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str("x")?;
+            }
+            write!(f, "{dim}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the [`Display`](fmt::Display) format.
+impl FromStr for Shape {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s == "()" {
+            return Ok(Self::new(&[]));
+        }
+        let mut dims = [0; MAX_DIMS];
+        let mut rank = 0;
+        for dim in s.split('x') {
+            if rank == MAX_DIMS {
+                return Err(Error::msg(format!("shape {s:?} has more than {MAX_DIMS} dims")));
+            }
+            dims[rank] = dim
+                .trim()
+                .parse()
+                .map_err(|e| Error::from(e).context(format!("in Shape::from_str: {s:?}")))?;
+            rank += 1;
+        }
+        Ok(Self { dims, rank })
+    }
 }
 
 #[macro_export]
@@ -85,6 +157,25 @@ mod tests {
         assert!(debug_str.contains("4"));
     }
 
+    #[test]
+    fn test_shape_display_and_parse() {
+        let shape = shape![1, 32, 128, 128];
+        assert_eq!(shape.to_string(), "1x32x128x128");
+        assert_eq!("1x32x128x128".parse::<Shape>().unwrap(), shape);
+        assert_eq!(" 7 x 3 ".parse::<Shape>().unwrap(), shape![7, 3]);
+        assert_eq!(Shape::new(&[]).to_string().parse::<Shape>().unwrap(), Shape::new(&[]));
+        assert!("1x2x3x4x5".parse::<Shape>().is_err());
+        assert!("1x-2".parse::<Shape>().is_err());
+        assert!("".parse::<Shape>().is_err());
+    }
+
+    #[test]
+    fn test_numel_and_with_dim() {
+        assert_eq!(shape![2, 3, 4].numel(), 24);
+        assert_eq!(shape![2, 3].with_dim(1, 5), shape![2, 5]);
+        assert_eq!(shape![2].with_dim(2, 4), shape![2, 1, 4]);
+    }
+
     #[test]
     fn test_shape_default() {
         let shape = Shape::default();
@@ -93,6 +184,7 @@ mod tests {
 }
 
 impl Shape {
+    /// Number of elements, see [`Shape::numel`].
     pub fn len(&self) -> usize {
         self.dims[..self.rank].iter().product()
     }
@@ -0,0 +1,92 @@
+//! Output shapes of ops, computed from the shapes of their inputs.
+//!
+//! The op builders on [`Tensor`](crate::tensor::Tensor) check their inputs with these
+//! functions, and front-ends such as model importers can call them to validate shapes
+//! before building any graph node. Shapes are innermost dimension first, as everywhere in
+//! the crate; missing dimensions count as 1.
+
+use crate::defs::MAX_DIMS;
+use crate::error::{Error, Result};
+use crate::shape::Shape;
+use crate::tensor::Im2ColParams;
+use alloc::format;
+
+/// Shape of the matrix product of `a` (`[K, M, ...]`) and `b` (`[K, N, ...]`), see
+/// [`Tensor::mul_mat`](crate::tensor::Tensor::mul_mat): `[M, N, ...]` with the batch
+/// dimensions of `b`, which must be multiples of those of `a`.
+pub fn matmul_shape(a: &Shape, b: &Shape) -> Result<Shape> {
+    if a.dim(0) != b.dim(0)
+        || !b.dim(2).is_multiple_of(a.dim(2))
+        || !b.dim(3).is_multiple_of(a.dim(3))
+    {
+        return Err(Error::msg(format!("mul_mat shapes {a} and {b} are not compatible"))
+            .context("in matmul_shape"));
+    }
+
+    let rank = a.rank.max(b.rank).max(2);
+    let dims = [a.dim(1), b.dim(1), b.dim(2), b.dim(3)];
+    Ok(Shape::new(&dims[..rank]))
+}
+
+/// Shape of the 2-D convolution of a `[W, H, IC, N]` `input` with a `[KW, KH, IC, OC]`
+/// `kernel`: `[OW, OH, OC, N]`, where the output extents follow from the stride, padding
+/// and dilation of `params` as in [`Im2ColParams::output_len`].
+pub fn conv2d_shape(input: &Shape, kernel: &Shape, params: &Im2ColParams) -> Result<Shape> {
+    let infer = || {
+        if kernel.dim(2) != input.dim(2) {
+            return Err(Error::msg(format!(
+                "conv_2d kernel {kernel} does not match input {input}"
+            )));
+        }
+        let width = params.output_len(0, input.dim(0), kernel.dim(0))?;
+        let height = params.output_len(1, input.dim(1), kernel.dim(1))?;
+        Ok(Shape::new(&[width, height, kernel.dim(3), input.dim(3)]))
+    };
+    infer().map_err(|e: Error| e.context("in conv2d_shape"))
+}
+
+/// Shape of a reduction of `shape` over `axes`, such as
+/// [`Tensor::sum_rows`](crate::tensor::Tensor::sum_rows) over axis 0: every reduced
+/// dimension becomes 1 and the rank stays the same.
+pub fn reduce_shape(shape: &Shape, axes: &[usize]) -> Result<Shape> {
+    let mut reduced = *shape;
+    for &axis in axes {
+        if axis >= MAX_DIMS {
+            return Err(Error::msg(format!("axis {axis} is out of range for shape {shape}"))
+                .context("in reduce_shape"));
+        }
+        reduced = reduced.with_dim(axis, 1);
+    }
+    Ok(reduced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape;
+
+    #[test]
+    fn test_matmul_shape_broadcasts_batches() {
+        let shape = matmul_shape(&shape![8, 3, 2], &shape![8, 5, 4, 3]).unwrap();
+        assert_eq!(shape, shape![3, 5, 4, 3]);
+        assert_eq!(matmul_shape(&shape![8], &shape![8]).unwrap(), shape![1, 1]);
+        assert!(matmul_shape(&shape![8, 3], &shape![7, 5]).is_err());
+        assert!(matmul_shape(&shape![8, 3, 2], &shape![8, 5, 3]).is_err());
+    }
+
+    #[test]
+    fn test_conv2d_shape() {
+        let params = Im2ColParams { stride: [2, 1], padding: [1, 0], ..Default::default() };
+        let shape = conv2d_shape(&shape![9, 6, 4, 2], &shape![3, 3, 4, 16], &params).unwrap();
+        assert_eq!(shape, shape![5, 4, 16, 2]);
+        assert!(conv2d_shape(&shape![9, 6, 4], &shape![3, 3, 3, 16], &params).is_err());
+        assert!(conv2d_shape(&shape![2, 2, 4], &shape![3, 3, 4], &params).is_err());
+    }
+
+    #[test]
+    fn test_reduce_shape_keeps_the_rank() {
+        assert_eq!(reduce_shape(&shape![4, 3, 2], &[0, 2]).unwrap(), shape![1, 3, 1]);
+        assert_eq!(reduce_shape(&shape![4], &[1]).unwrap(), shape![4, 1]);
+        assert!(reduce_shape(&shape![4], &[MAX_DIMS]).is_err());
+    }
+}
@@ -0,0 +1,207 @@
+//! Staging buffers for host↔device copies.
+//!
+//! A [`StagingPool`] keeps a few host buffers of a fixed size alive between transfers.
+//! Copies larger than its threshold are split into chunks that alternate between two
+//! staging buffers: while the backend moves one chunk with `write_async`/`read_async`, the
+//! host fills or drains the other. Backends that implement the async entry points get
+//! double-buffered transfers without implementing them themselves; smaller copies go
+//! straight to the backend.
+
+use crate::backend::Backend;
+use crate::error::{Error, Result};
+use crate::tensor::Tensor;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Mutex, PoisonError};
+
+pub struct StagingPool {
+    buffer_size: usize,
+    threshold: usize,
+    free: Mutex<Vec<Vec<u8>>>,
+}
+
+impl StagingPool {
+    /// Default size of a staging buffer.
+    pub const DEFAULT_BUFFER_SIZE: usize = 4 << 20;
+
+    /// Pool of `buffer_size` byte buffers. Copies of up to `buffer_size` bytes are not
+    /// staged.
+    pub fn new(buffer_size: usize) -> Result<Self> {
+        if buffer_size == 0 {
+            return Err(
+                Error::msg("staging buffer size must not be zero").context("in StagingPool::new")
+            );
+        }
+        Ok(Self { buffer_size, threshold: buffer_size, free: Mutex::new(Vec::new()) })
+    }
+
+    /// Stages copies larger than `threshold` bytes.
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Buffers currently kept for reuse.
+    pub fn available(&self) -> usize {
+        self.free.lock().unwrap_or_else(PoisonError::into_inner).len()
+    }
+
+    /// Takes a buffer from the pool, allocating one if none is free. It returns to the pool
+    /// when dropped.
+    pub fn acquire(&self) -> StagingBuffer<'_> {
+        let data = self.free.lock().unwrap_or_else(PoisonError::into_inner).pop();
+        StagingBuffer { pool: self, data: data.unwrap_or_else(|| vec![0; self.buffer_size]) }
+    }
+
+    /// Copies `data` into `tensor` starting at byte `offset`, and waits for the copy.
+    pub fn upload(
+        &self,
+        backend: &dyn Backend,
+        tensor: &Tensor,
+        data: &mut [u8],
+        offset: usize,
+    ) -> Result<()> {
+        self.upload_chunks(backend, tensor, data, offset)
+            .map_err(|e| e.context("in StagingPool::upload"))
+    }
+
+    /// Copies `data.len()` bytes of `tensor` starting at byte `offset` into `data`.
+    pub fn download(
+        &self,
+        backend: &dyn Backend,
+        tensor: &Tensor,
+        data: &mut [u8],
+        offset: usize,
+    ) -> Result<()> {
+        self.download_chunks(backend, tensor, data, offset)
+            .map_err(|e| e.context("in StagingPool::download"))
+    }
+
+    fn upload_chunks(
+        &self,
+        backend: &dyn Backend,
+        tensor: &Tensor,
+        data: &mut [u8],
+        offset: usize,
+    ) -> Result<()> {
+        if data.len() <= self.threshold {
+            let size = data.len();
+            backend.write_async(tensor.clone(), data, offset, size)?;
+            return backend.synchronize();
+        }
+
+        let mut slots = [self.acquire(), self.acquire()];
+        for (i, chunk) in data.chunks(self.buffer_size).enumerate() {
+            // Filling this slot overlaps the previous chunk's copy; waiting for that copy
+            // afterwards frees the other slot for the next chunk.
+            let slot = &mut slots[i % 2];
+            slot[..chunk.len()].copy_from_slice(chunk);
+            backend.synchronize()?;
+            let chunk_offset = offset + i * self.buffer_size;
+            backend.write_async(tensor.clone(), slot, chunk_offset, chunk.len())?;
+        }
+        backend.synchronize()
+    }
+
+    fn download_chunks(
+        &self,
+        backend: &dyn Backend,
+        tensor: &Tensor,
+        data: &mut [u8],
+        offset: usize,
+    ) -> Result<()> {
+        if data.len() <= self.threshold {
+            let size = data.len();
+            backend.read_async(tensor.clone(), data, offset, size)?;
+            return backend.synchronize();
+        }
+
+        let mut slots = [self.acquire(), self.acquire()];
+        let total = data.len();
+        let n_chunks = total.div_ceil(self.buffer_size);
+        let chunk_len = |i: usize| self.buffer_size.min(total - i * self.buffer_size);
+        backend.read_async(tensor.clone(), &mut slots[0], offset, chunk_len(0))?;
+        for i in 0..n_chunks {
+            backend.synchronize()?;
+            // Start reading the next chunk before draining this one.
+            if i + 1 < n_chunks {
+                let next_offset = offset + (i + 1) * self.buffer_size;
+                let slot = &mut slots[(i + 1) % 2];
+                backend.read_async(tensor.clone(), slot, next_offset, chunk_len(i + 1))?;
+            }
+            let start = i * self.buffer_size;
+            let len = chunk_len(i);
+            data[start..start + len].copy_from_slice(&slots[i % 2][..len]);
+        }
+        Ok(())
+    }
+}
+
+impl Default for StagingPool {
+    fn default() -> Self {
+        Self {
+            buffer_size: Self::DEFAULT_BUFFER_SIZE,
+            threshold: Self::DEFAULT_BUFFER_SIZE,
+            free: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+/// Host buffer borrowed from a [`StagingPool`].
+pub struct StagingBuffer<'a> {
+    pool: &'a StagingPool,
+    data: Vec<u8>,
+}
+
+impl Deref for StagingBuffer<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl DerefMut for StagingBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+}
+
+impl Drop for StagingBuffer<'_> {
+    fn drop(&mut self) {
+        let data = std::mem::take(&mut self.data);
+        self.pool.free.lock().unwrap_or_else(PoisonError::into_inner).push(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffers_return_to_the_pool() {
+        let pool = StagingPool::new(16).unwrap();
+        assert_eq!(pool.available(), 0);
+        {
+            let mut a = pool.acquire();
+            let b = pool.acquire();
+            a[0] = 1;
+            assert_eq!((a.len(), b.len()), (16, 16));
+        }
+        assert_eq!(pool.available(), 2);
+        let _a = pool.acquire();
+        assert_eq!(pool.available(), 1);
+    }
+
+    #[test]
+    fn test_new_rejects_empty_buffers() {
+        assert!(StagingPool::new(0).is_err());
+    }
+}
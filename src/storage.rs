@@ -5,7 +5,7 @@ use crate::cpu::backend_buffers::CpuBackendBuffer;
 use crate::cuda::backend_buffer::CudaBackendBuffer;
 #[cfg(feature = "opencl")]
 use crate::opencl::backend_buffer::OpenclBackendBuffer;
-use std::rc::Rc;
+use alloc::rc::Rc;
 
 #[derive(Clone)]
 pub enum TensorStorage {
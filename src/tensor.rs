@@ -1,26 +1,36 @@
+use crate::backend::MemoryAdvice;
 use crate::context::Context;
 use crate::context::ContextInner;
-use crate::data_type::{get_type_size, DataType, TensorOpType, TensorType};
-use crate::defs::MAX_SRC;
-use crate::error::{Error, Result};
+use crate::data_type::{
+    from_f32, from_i32, get_type_size, to_f32, to_i32, DataType, TensorOpType, TensorType,
+};
+use crate::defs::{MAX_DIMS, MAX_SRC};
+use crate::error::{Error, ErrorKind, Result};
 use crate::layout::Layout;
+use crate::memory::MemoryCategory;
+use crate::model_meta::RopeParams;
 use crate::ops::OpParams;
+use crate::rng::RngState;
 #[cfg(test)]
 use crate::shape;
 use crate::shape::Shape;
+use crate::shape_inference;
 use crate::storage::TensorStorage;
-use std::cell::Ref;
-use std::cell::RefCell;
-use std::rc::Rc;
-use std::rc::Weak;
+use alloc::rc::{Rc, Weak};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::cell::{Ref, RefCell};
+use core::panic::Location;
 /// Unique identifier for tensors.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct TensorId(usize);
 
 impl TensorId {
     pub fn new() -> Self {
         // https://users.rust-lang.org/t/idiomatic-rust-way-to-generate-unique-id/33805
-        use std::sync::atomic;
+        use core::sync::atomic;
         static COUNTER: atomic::AtomicUsize = atomic::AtomicUsize::new(1);
         Self(COUNTER.fetch_add(1, atomic::Ordering::Relaxed))
     }
@@ -65,6 +75,85 @@ impl TensorIdArray {
     }
 }
 
+/// Stride, padding and dilation of an im2col unfolding or convolution, `[width, height]`.
+///
+/// With `is_2d == false` only the width entries are used and the height is 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Im2ColParams {
+    pub stride: [usize; 2],
+    pub padding: [usize; 2],
+    pub dilation: [usize; 2],
+    pub is_2d: bool,
+}
+
+impl Default for Im2ColParams {
+    fn default() -> Self {
+        Self { stride: [1, 1], padding: [0, 0], dilation: [1, 1], is_2d: true }
+    }
+}
+
+/// Settings of [`Tensor::attention_ext`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AttentionParams {
+    /// Factor of the `K^T Q` scores, usually `1 / sqrt(D)`.
+    pub scale: f32,
+    /// ALiBi position bias, see [`Tensor::attention_alibi`]. 0 adds none.
+    pub max_bias: f32,
+    /// Sliding window: a query attends only to the `window` most recent positions up to
+    /// and including its own. `None` attends to every key.
+    pub window: Option<usize>,
+}
+
+impl Default for AttentionParams {
+    fn default() -> Self {
+        Self { scale: 1.0, max_bias: 0.0, window: None }
+    }
+}
+
+impl Im2ColParams {
+    /// Number of kernel positions along `axis` (0 = width, 1 = height) for an input
+    /// extent `input` and kernel extent `kernel`.
+    pub fn output_len(&self, axis: usize, input: usize, kernel: usize) -> Result<usize> {
+        let padded = input + 2 * self.padding[axis];
+        let span = self.dilation[axis] * (kernel.max(1) - 1) + 1;
+        if self.stride[axis] == 0 || span > padded {
+            return Err(Error::msg(format!(
+                "im2col kernel span {span} does not fit padded input {padded}"
+            ))
+            .context("in Im2ColParams::output_len"));
+        }
+        Ok((padded - span) / self.stride[axis] + 1)
+    }
+
+    /// Output extent along `axis` of a transposed convolution over an input extent
+    /// `input` with kernel extent `kernel`.
+    pub fn transposed_len(&self, axis: usize, input: usize, kernel: usize) -> Result<usize> {
+        let full =
+            (input.max(1) - 1) * self.stride[axis] + self.dilation[axis] * (kernel.max(1) - 1) + 1;
+        full.checked_sub(2 * self.padding[axis]).filter(|&len| len > 0).ok_or_else(|| {
+            Error::msg(format!("transposed convolution output {full} is smaller than its padding"))
+                .context("in Im2ColParams::transposed_len")
+        })
+    }
+}
+
+/// Interpolation used by [`Tensor::upscale`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpscaleMode {
+    Nearest,
+    /// Half-pixel centered linear interpolation along width and height.
+    Bilinear,
+}
+
+/// Formula used by [`Tensor::gelu`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeluMode {
+    /// `0.5 * x * (1 + tanh(sqrt(2 / pi) * (x + 0.044715 * x^3)))`, as in GPT-2 and BERT.
+    Tanh,
+    /// `0.5 * x * (1 + erf(x / sqrt(2)))`.
+    Exact,
+}
+
 pub struct TensorInner {
     pub(crate) id: TensorId,
     pub(crate) name: String,
@@ -77,6 +166,13 @@ pub struct TensorInner {
     pub(crate) view_offset: usize,
     pub(crate) op_type: TensorOpType,
     pub(crate) params: Option<OpParams>,
+    pub(crate) memory_category: Option<MemoryCategory>,
+    pub(crate) copy_on_write: bool,
+    pub(crate) interleaved_rows: Option<usize>,
+    /// Where the op of the tensor was built, see [`ContextBuilder::provenance`].
+    ///
+    /// [`ContextBuilder::provenance`]: crate::context::ContextBuilder::provenance
+    pub(crate) origin: Option<&'static Location<'static>>,
     pub(crate) ctx: Weak<RefCell<ContextInner>>,
 }
 
@@ -97,13 +193,18 @@ impl TensorInner {
             view_offset: 0,
             op_type: TensorOpType::UNKNOWN,
             params: None,
+            memory_category: None,
+            copy_on_write: false,
+            interleaved_rows: None,
+            origin: None,
             ctx: Weak::new(),
         }
     }
 }
 
 impl TensorInner {
-    fn ctx(&self) -> Result<Context> {
+    #[cfg(feature = "std")]
+    pub(crate) fn ctx(&self) -> Result<Context> {
         self.ctx.upgrade().map(Context).ok_or_else(|| Error::msg("context has been dropped!"))
     }
 }
@@ -113,6 +214,7 @@ impl Tensor {
         Tensor(Rc::new(RefCell::new(TensorInner::default())))
     }
 
+    #[cfg(test)]
     pub(crate) fn set_tensor_id(&mut self, id: TensorId) -> &mut Self {
         self.borrow_mut().id = id;
         self
@@ -184,6 +286,78 @@ impl Tensor {
         self.borrow().tensor_type
     }
 
+    /// Marks `self` copy-on-write. An `_inplace` op on it, or on a view of it, then writes
+    /// a private copy instead of the shared memory, so tensors such as weights can be used
+    /// by in-place graphs without being modified. The copy is an ordinary tensor, so
+    /// further in-place ops on it do work in place.
+    pub fn set_copy_on_write(&self, copy_on_write: bool) -> &Self {
+        self.borrow_mut().copy_on_write = copy_on_write;
+        self
+    }
+
+    /// Whether `self` or a tensor it views is marked copy-on-write.
+    pub fn is_copy_on_write(&self) -> bool {
+        let mut tensor = self.clone();
+        loop {
+            if tensor.borrow().copy_on_write {
+                return true;
+            }
+            let source = tensor.borrow().view_tensor.clone();
+            match source {
+                Some(source) => tensor = source,
+                None => return false,
+            }
+        }
+    }
+
+    /// Marks the data of `self` as interleaved in panels of `rows` rows, see
+    /// [`crate::repack`]; `None` marks it as plain rows. Matrix products read interleaved
+    /// weights, other ops read them as if they were plain.
+    pub fn set_interleaved_rows(&self, rows: Option<usize>) -> &Self {
+        self.borrow_mut().interleaved_rows = rows;
+        self
+    }
+
+    /// The panel rows `self` is interleaved in, `None` for plain rows.
+    pub fn interleaved_rows(&self) -> Option<usize> {
+        self.borrow().interleaved_rows
+    }
+
+    /// The source location that built the op of the tensor, recorded when its context
+    /// was built with [`ContextBuilder::provenance`].
+    ///
+    /// [`ContextBuilder::provenance`]: crate::context::ContextBuilder::provenance
+    pub fn origin(&self) -> Option<&'static Location<'static>> {
+        self.borrow().origin
+    }
+
+    /// `node <id> '<name>' (<op>)`, followed by the [origin](Tensor::origin) if there is
+    /// one, for error messages.
+    pub fn describe(&self) -> String {
+        let inner = self.borrow();
+        let mut description =
+            format!("node {} '{}' ({:?})", inner.id.as_usize(), inner.name, inner.op_type);
+        if let Some(origin) = inner.origin {
+            description.push_str(&format!(" built at {origin}"));
+        }
+        description
+    }
+
+    /// The tensor an `_inplace` op on `self` writes: a view of `self`, or a new tensor if
+    /// `self` is [copy-on-write](Tensor::set_copy_on_write).
+    fn inplace_result(&self, ctx: &mut Context) -> Result<Tensor> {
+        if self.is_copy_on_write() {
+            ctx.dup_tensor(self.clone())
+        } else {
+            ctx.new_tensor_view(self.clone())
+        }
+    }
+
+    #[cfg(feature = "cpu")]
+    pub(crate) fn op_params(&self) -> Option<OpParams> {
+        self.borrow().params.clone()
+    }
+
     pub fn view_offset(&self) -> usize {
         self.borrow().view_offset
     }
@@ -200,6 +374,14 @@ impl Tensor {
         Ok(Ref::map(borrow, |inner| inner.storage.as_ref().unwrap()))
     }
 
+    /// Applies a paging hint to the bytes of this tensor, e.g. to prefetch the weights of the
+    /// next layer of an mmap-backed model or to drop a cold one.
+    pub fn advise(&self, advice: MemoryAdvice) -> Result<()> {
+        let storage = self.storage().map_err(|e| e.context("in Tensor::advise"))?;
+        storage.buffer().advise(self.clone(), advice)
+    }
+
+    #[cfg(feature = "std")]
     pub(crate) fn set_storage(&mut self, storage: Option<TensorStorage>) -> Result<()> {
         self.borrow_mut().storage = storage;
         Ok(())
@@ -213,7 +395,86 @@ impl Tensor {
         self.borrow_mut().layout.shape.nrows()
     }
 
+    /// Reads the element at `index` converted to `f32`.
+    ///
+    /// `index` lists coordinates from the innermost dimension outwards; trailing
+    /// dimensions may be omitted and default to 0. Strides and the tensor's data
+    /// type are honored, so this also works on views and non-F32 tensors.
+    pub fn get_f32_nd(&self, index: &[usize]) -> Result<f32> {
+        let bytes = self.read_element(index)?;
+        to_f32(self.dtype(), &bytes)
+    }
+
+    /// Writes `value` to the element at `index`, converting it to the tensor's data type.
+    pub fn set_f32_nd(&self, index: &[usize], value: f32) -> Result<()> {
+        let mut bytes = vec![0; self.element_size()];
+        from_f32(self.dtype(), value, &mut bytes)?;
+        self.write_element(index, &mut bytes)
+    }
+
+    /// Reads the element at `index` converted to `i32`.
+    pub fn get_i32_nd(&self, index: &[usize]) -> Result<i32> {
+        let bytes = self.read_element(index)?;
+        to_i32(self.dtype(), &bytes)
+    }
+
+    /// Writes `value` to the element at `index`, converting it to the tensor's data type.
+    pub fn set_i32_nd(&self, index: &[usize], value: i32) -> Result<()> {
+        let mut bytes = vec![0; self.element_size()];
+        from_i32(self.dtype(), value, &mut bytes)?;
+        self.write_element(index, &mut bytes)
+    }
+
+    fn element_offset(&self, index: &[usize]) -> Result<usize> {
+        let inner = self.borrow();
+        let shape = &inner.layout.shape;
+
+        if index.len() > shape.rank {
+            return Err(Error::new(ErrorKind::UnexpectedNumberOfDims {
+                expected: shape.rank,
+                got: index.len(),
+                shape: *shape,
+            })
+            .context("in Tensor::element_offset"));
+        }
+
+        let mut offset = 0usize;
+        for (axis, &i) in index.iter().enumerate() {
+            if i >= shape.dims[axis] {
+                return Err(Error::msg(format!(
+                    "index {i} is out of bounds for axis {axis} with size {}",
+                    shape.dims[axis]
+                ))
+                .context("in Tensor::element_offset"));
+            }
+            offset = i
+                .checked_mul(inner.layout.stride[axis])
+                .and_then(|delta| offset.checked_add(delta))
+                .ok_or_else(|| Error::msg("tensor element offset overflow"))?;
+        }
+
+        Ok(offset)
+    }
+
+    fn read_element(&self, index: &[usize]) -> Result<Vec<u8>> {
+        let offset = self.element_offset(index)?;
+        let mut bytes = vec![0; self.element_size()];
+        let size = bytes.len();
+        self.storage()?.buffer().read(self.clone(), &mut bytes, offset, size)?;
+        Ok(bytes)
+    }
+
+    fn write_element(&self, index: &[usize], bytes: &mut [u8]) -> Result<()> {
+        let offset = self.element_offset(index)?;
+        let size = bytes.len();
+        self.storage()?.buffer().write(self.clone(), bytes, offset, size)
+    }
+
+    #[track_caller]
     fn set_op(&mut self, op_kind: TensorOpType, op_params: OpParams, sources: &[TensorId]) {
+        if self.ctx().is_ok_and(|ctx| ctx.borrow().provenance) {
+            self.borrow_mut().origin = Some(Location::caller());
+        }
         self.borrow_mut().op_type = op_kind;
         self.borrow_mut().params = Some(op_params);
         for src in sources {
@@ -221,7 +482,7 @@ impl Tensor {
         }
     }
 
-    fn ctx(&self) -> Result<Context> {
+    pub(crate) fn ctx(&self) -> Result<Context> {
         self.borrow()
             .ctx
             .upgrade()
@@ -229,16 +490,222 @@ impl Tensor {
             .ok_or_else(|| Error::msg("context has been dropped!"))
     }
 
-    fn mul_impl(&mut self, other: Tensor, inplace: bool) -> Result<Tensor> {
+    #[track_caller]
+    fn binary_impl(&mut self, op: TensorOpType, other: Tensor, inplace: bool) -> Result<Tensor> {
+        let (lhs, rhs) = (*self.shape(), *other.shape());
+        if (0..MAX_DIMS).any(|i| rhs.dim(i) == 0 || !lhs.dim(i).is_multiple_of(rhs.dim(i))) {
+            let name = match op {
+                TensorOpType::TensorOpAdd => "add",
+                TensorOpType::TensorOpSub => "sub",
+                TensorOpType::TensorOpMul => "mul",
+                _ => "div",
+            };
+            return Err(Error::msg(format!("{name} cannot repeat shape {rhs} to {lhs}"))
+                .context(format!("in Tensor::{name}")));
+        }
+
         let mut ctx = self.ctx()?;
-        let mut result = if inplace {
-            ctx.new_tensor_view(self.clone())?
-        } else {
-            ctx.dup_tensor(self.clone())?
-        };
+        let mut result =
+            if inplace { self.inplace_result(&mut ctx)? } else { ctx.dup_tensor(self.clone())? };
+
+        result.set_op(op, OpParams::None, &[self.tensor_id(), other.tensor_id()]);
+
+        Ok(result)
+    }
+
+    /// `self + other`, element-wise. `other` is repeated along the dimensions it is
+    /// smaller in, which must divide those of `self`; the same goes for [`Tensor::sub`],
+    /// [`Tensor::mul`] and [`Tensor::div`].
+    #[track_caller]
+    pub fn add(&mut self, other: Tensor) -> Result<Tensor> {
+        self.binary_impl(TensorOpType::TensorOpAdd, other, false)
+    }
+
+    #[track_caller]
+    pub fn add_inplace(&mut self, other: Tensor) -> Result<Tensor> {
+        self.binary_impl(TensorOpType::TensorOpAdd, other, true)
+    }
+
+    #[track_caller]
+    pub fn sub(&mut self, other: Tensor) -> Result<Tensor> {
+        self.binary_impl(TensorOpType::TensorOpSub, other, false)
+    }
+
+    #[track_caller]
+    pub fn sub_inplace(&mut self, other: Tensor) -> Result<Tensor> {
+        self.binary_impl(TensorOpType::TensorOpSub, other, true)
+    }
+
+    #[track_caller]
+    pub fn mul(&mut self, other: Tensor) -> Result<Tensor> {
+        self.binary_impl(TensorOpType::TensorOpMul, other, false)
+    }
+
+    #[track_caller]
+    pub fn mul_inplace(&mut self, other: Tensor) -> Result<Tensor> {
+        self.binary_impl(TensorOpType::TensorOpMul, other, true)
+    }
+
+    #[track_caller]
+    pub fn div(&mut self, other: Tensor) -> Result<Tensor> {
+        self.binary_impl(TensorOpType::TensorOpDiv, other, false)
+    }
+
+    #[track_caller]
+    pub fn div_inplace(&mut self, other: Tensor) -> Result<Tensor> {
+        self.binary_impl(TensorOpType::TensorOpDiv, other, true)
+    }
+
+    #[track_caller]
+    fn map_impl(
+        &mut self,
+        op_kind: TensorOpType,
+        op_params: OpParams,
+        sources: &[TensorId],
+        inplace: bool,
+    ) -> Result<Tensor> {
+        let mut ctx = self.ctx()?;
+        let mut result =
+            if inplace { self.inplace_result(&mut ctx)? } else { ctx.dup_tensor(self.clone())? };
+
+        result.set_op(op_kind, op_params, sources);
+
+        Ok(result)
+    }
+
+    /// Applies `f` to every element of `self`. Kernels call `f` from several threads at
+    /// once, in no particular order.
+    #[track_caller]
+    pub fn map_unary<F>(&mut self, f: F) -> Result<Tensor>
+    where
+        F: Fn(f32) -> f32 + Send + Sync + 'static,
+    {
+        let sources = [self.tensor_id()];
+        self.map_impl(
+            TensorOpType::TensorOpMapUnary,
+            OpParams::MapUnary(Arc::new(f)),
+            &sources,
+            false,
+        )
+    }
+
+    /// Same as [`Tensor::map_unary`], but writes into the storage of `self`.
+    #[track_caller]
+    pub fn map_unary_inplace<F>(&mut self, f: F) -> Result<Tensor>
+    where
+        F: Fn(f32) -> f32 + Send + Sync + 'static,
+    {
+        let sources = [self.tensor_id()];
+        self.map_impl(
+            TensorOpType::TensorOpMapUnary,
+            OpParams::MapUnary(Arc::new(f)),
+            &sources,
+            true,
+        )
+    }
+
+    fn check_same_shape(&self, other: &Tensor, op: &'static str) -> Result<()> {
+        if *other.shape() != *self.shape() {
+            return Err(Error::msg(format!(
+                "{op} shapes {} and {} differ",
+                self.shape(),
+                other.shape()
+            ))
+            .context(format!("in Tensor::{op}")));
+        }
+        Ok(())
+    }
 
+    /// Combines `self` and `other` element by element with `f(self, other)`. Both tensors
+    /// must have the same shape. Kernels call `f` from several threads at once.
+    #[track_caller]
+    pub fn map_binary<F>(&mut self, other: Tensor, f: F) -> Result<Tensor>
+    where
+        F: Fn(f32, f32) -> f32 + Send + Sync + 'static,
+    {
+        self.check_same_shape(&other, "map_binary")?;
+        let sources = [self.tensor_id(), other.tensor_id()];
+        self.map_impl(
+            TensorOpType::TensorOpMapBinary,
+            OpParams::MapBinary(Arc::new(f)),
+            &sources,
+            false,
+        )
+    }
+
+    /// Same as [`Tensor::map_binary`], but writes into the storage of `self`.
+    #[track_caller]
+    pub fn map_binary_inplace<F>(&mut self, other: Tensor, f: F) -> Result<Tensor>
+    where
+        F: Fn(f32, f32) -> f32 + Send + Sync + 'static,
+    {
+        self.check_same_shape(&other, "map_binary")?;
+        let sources = [self.tensor_id(), other.tensor_id()];
+        self.map_impl(
+            TensorOpType::TensorOpMapBinary,
+            OpParams::MapBinary(Arc::new(f)),
+            &sources,
+            true,
+        )
+    }
+
+    /// Gaussian error linear unit of every element of `self`, computed with `mode`.
+    #[track_caller]
+    pub fn gelu(&mut self, mode: GeluMode) -> Result<Tensor> {
+        let sources = [self.tensor_id()];
+        self.map_impl(TensorOpType::TensorOpGelu, OpParams::Gelu { mode }, &sources, false)
+    }
+
+    /// Rectified linear unit of every element of `self`: `max(x, 0)`.
+    #[track_caller]
+    pub fn relu(&mut self) -> Result<Tensor> {
+        let sources = [self.tensor_id()];
+        self.map_impl(TensorOpType::TensorOpRelu, OpParams::None, &sources, false)
+    }
+
+    /// Leaky relu of every element of `self`: `x` if it is positive, `slope * x` otherwise.
+    #[track_caller]
+    pub fn leaky_relu(&mut self, slope: f32) -> Result<Tensor> {
+        let sources = [self.tensor_id()];
+        self.map_impl(
+            TensorOpType::TensorOpLeakyRelu,
+            OpParams::LeakyRelu { slope },
+            &sources,
+            false,
+        )
+    }
+
+    /// SiLU, also called swish, of every element of `self`: `x * sigmoid(x)`.
+    #[track_caller]
+    pub fn silu(&mut self) -> Result<Tensor> {
+        let sources = [self.tensor_id()];
+        self.map_impl(TensorOpType::TensorOpSilu, OpParams::None, &sources, false)
+    }
+
+    /// SwiGLU gating of gated MLPs, `silu(self) * other`, in one pass over both tensors
+    /// instead of a [`Tensor::silu`] node followed by a [`Tensor::mul`]. Both tensors must
+    /// have the same shape.
+    #[track_caller]
+    pub fn swiglu(&mut self, other: Tensor) -> Result<Tensor> {
+        self.check_same_shape(&other, "swiglu")?;
+        let sources = [self.tensor_id(), other.tensor_id()];
+        self.map_impl(TensorOpType::TensorOpSwiGlu, OpParams::None, &sources, false)
+    }
+
+    /// Matrix product of `self` (`[K, M, ...]`) and `other` (`[K, N, ...]`), contracting the
+    /// first dimension of both: `[K, M, ...] x [K, N, ...] -> [M, N, ...]`.
+    ///
+    /// `self` is broadcast along the batch dimensions 2 and 3 of `other`. Either operand
+    /// may be a strided view such as [`Tensor::transpose`]. The result is F32.
+    #[track_caller]
+    pub fn mul_mat(&mut self, other: Tensor) -> Result<Tensor> {
+        let shape = shape_inference::matmul_shape(&self.shape(), &other.shape())
+            .map_err(|e| e.context("in Tensor::mul_mat"))?;
+
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.new_tensor(DataType::F32, &shape)?;
         result.set_op(
-            TensorOpType::TensorOpMul,
+            TensorOpType::TensorOpMulMat,
             OpParams::None,
             &[self.tensor_id(), other.tensor_id()],
         );
@@ -246,12 +713,966 @@ impl Tensor {
         Ok(result)
     }
 
-    pub fn mul(&mut self, other: Tensor) -> Result<Tensor> {
-        self.mul_impl(other, false)
+    /// [`Tensor::mul_mat`] of rowwise quantized weights: `self` is I8 `[K, M, ...]` with
+    /// the F32 `scales` `[M, ...]` of its rows, see [`crate::quant`]. The rows of `other`
+    /// are quantized the same way before the products are summed in integers, with the dot
+    /// product instructions of the CPU where it has them (AVX-512 VNNI, SDOT), so both sides
+    /// lose precision only to rounding within their own row. The result is F32.
+    #[track_caller]
+    pub fn mul_mat_rowwise(&mut self, scales: Tensor, other: Tensor) -> Result<Tensor> {
+        self.mul_mat_rowwise_impl(scales, other, DataType::F32, OpParams::None)
     }
 
-    pub fn mul_inplace(&mut self, other: Tensor) -> Result<Tensor> {
-        self.mul_impl(other, true)
+    /// [`Tensor::mul_mat_rowwise`] requantized to I8 for the next int8 product: every
+    /// result `y` becomes `round(y / out_scale)`, clamped to `-127..=127`. `out_scale` is
+    /// usually calibrated as the largest magnitude of the output over `127`.
+    #[track_caller]
+    pub fn mul_mat_rowwise_q8(
+        &mut self,
+        scales: Tensor,
+        other: Tensor,
+        out_scale: f32,
+    ) -> Result<Tensor> {
+        if !out_scale.is_finite() || out_scale <= 0.0 {
+            return Err(Error::msg(format!("output scale {out_scale} is not positive"))
+                .context("in Tensor::mul_mat_rowwise_q8"));
+        }
+        self.mul_mat_rowwise_impl(scales, other, DataType::I8, OpParams::Requantize { out_scale })
+    }
+
+    #[track_caller]
+    fn mul_mat_rowwise_impl(
+        &mut self,
+        scales: Tensor,
+        other: Tensor,
+        dtype: DataType,
+        params: OpParams,
+    ) -> Result<Tensor> {
+        let lhs = *self.shape();
+        let rhs = *other.shape();
+        let scales_shape = *scales.shape();
+        let fail = |msg: String| Error::msg(msg).context("in Tensor::mul_mat_rowwise");
+
+        if self.dtype() != DataType::I8 || scales.dtype() != DataType::F32 {
+            return Err(fail(format!(
+                "weights must be I8 and scales F32, got {:?} and {:?}",
+                self.dtype(),
+                scales.dtype()
+            )));
+        }
+        if (0..3).any(|i| scales_shape.dim(i) != lhs.dim(i + 1)) || scales_shape.dim(3) != 1 {
+            return Err(fail(format!("scales {scales_shape} do not match the rows of {lhs}")));
+        }
+        if lhs.dim(0) != rhs.dim(0)
+            || !rhs.dim(2).is_multiple_of(lhs.dim(2))
+            || !rhs.dim(3).is_multiple_of(lhs.dim(3))
+        {
+            return Err(fail(format!("shapes {lhs} and {rhs} are not compatible")));
+        }
+
+        let rank = lhs.rank.max(rhs.rank).max(2);
+        let dims = [lhs.dim(1), rhs.dim(1), rhs.dim(2), rhs.dim(3)];
+
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.new_tensor(dtype, &Shape::new(&dims[..rank]))?;
+        result.set_op(
+            TensorOpType::TensorOpMulMatRowwise,
+            params,
+            &[self.tensor_id(), other.tensor_id(), scales.tensor_id()],
+        );
+
+        Ok(result)
+    }
+
+    /// View of `self` with `shape`, starting `offset` bytes into `self` and keeping its
+    /// strides. No data is moved.
+    #[track_caller]
+    pub fn view(&mut self, shape: &Shape, offset: usize) -> Result<Tensor> {
+        let stride = self.borrow().layout.stride;
+        let view_end = Layout::new(*shape, stride, 0).nbytes(self.dtype()).checked_add(offset);
+        if view_end.is_none_or(|end| end > self.nbytes()) {
+            return Err(Error::msg(format!(
+                "view {:?} at byte {offset} does not fit in {} bytes",
+                shape.dims,
+                self.nbytes()
+            ))
+            .context("in Tensor::view"));
+        }
+
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.new_tensor_view(self.clone())?;
+        {
+            let mut inner = result.borrow_mut();
+            inner.layout.shape = *shape;
+            inner.view_offset += offset;
+        }
+
+        result.set_op(TensorOpType::TensorOpView, OpParams::None, &[self.tensor_id()]);
+
+        Ok(result)
+    }
+
+    /// View of `self` with its first two dimensions and their strides swapped. No data
+    /// is moved.
+    #[track_caller]
+    pub fn transpose(&mut self) -> Result<Tensor> {
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.new_tensor_view(self.clone())?;
+        {
+            let mut inner = result.borrow_mut();
+            let layout = &mut inner.layout;
+            if layout.shape.rank < 2 {
+                layout.shape.dims[1] = 1;
+                layout.shape.rank = 2;
+            }
+            layout.shape.dims.swap(0, 1);
+            layout.stride.swap(0, 1);
+        }
+
+        result.set_op(TensorOpType::TensorOpTranspose, OpParams::None, &[self.tensor_id()]);
+
+        Ok(result)
+    }
+
+    /// Returns true if the elements of `self` are laid out densely, innermost dimension
+    /// first, without gaps or reordering.
+    pub fn is_contiguous(&self) -> bool {
+        let shape = *self.shape();
+        let dense = Layout::contiguous_stride(&shape, self.dtype());
+        let stride = self.stride();
+        stride
+            .iter()
+            .zip(dense)
+            .enumerate()
+            .all(|(axis, (&nb, dense))| shape.dim(axis) <= 1 || nb == dense)
+    }
+
+    /// View of `self` where source dimension `i` becomes dimension `axes[i]`, as in
+    /// `ggml_permute`. No data is moved.
+    #[track_caller]
+    pub fn permute(&mut self, axes: [usize; 4]) -> Result<Tensor> {
+        let mut seen = [false; 4];
+        for &axis in &axes {
+            if axis >= 4 || core::mem::replace(&mut seen[axis], true) {
+                return Err(Error::msg(format!("{axes:?} is not a permutation of 0..4"))
+                    .context("in Tensor::permute"));
+            }
+        }
+
+        let shape = *self.shape();
+        let stride = self.borrow().layout.stride;
+        let mut dims = [1; 4];
+        let mut strides = [0; 4];
+        for (src, &dst) in axes.iter().enumerate() {
+            dims[dst] = shape.dim(src);
+            strides[dst] = stride[src];
+        }
+        let rank = (0..shape.rank).map(|src| axes[src] + 1).max().unwrap_or(0).max(shape.rank);
+
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.new_tensor_view(self.clone())?;
+        {
+            let mut inner = result.borrow_mut();
+            inner.layout.shape = Shape::new(&dims[..rank]);
+            inner.layout.stride = strides;
+        }
+
+        result.set_op(TensorOpType::TensorOpPermute, OpParams::None, &[self.tensor_id()]);
+
+        Ok(result)
+    }
+
+    /// Copies `self` into a new contiguous tensor of the same shape and dtype.
+    #[track_caller]
+    pub fn cont(&mut self) -> Result<Tensor> {
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.dup_tensor(self.clone())?;
+        result.set_op(TensorOpType::TensorOpCont, OpParams::None, &[self.tensor_id()]);
+
+        Ok(result)
+    }
+
+    /// View of the contiguous tensor `self` with a new shape holding the same number of
+    /// elements. Use [`Tensor::cont`] first for strided tensors.
+    #[track_caller]
+    pub fn reshape(&mut self, shape: &Shape) -> Result<Tensor> {
+        let from = self.shape().iter().product::<usize>();
+        let to = shape.iter().product::<usize>();
+        if from != to || !self.is_contiguous() {
+            return Err(Error::msg(format!("cannot reshape {} into {shape}", self.shape()))
+                .context("in Tensor::reshape"));
+        }
+
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.new_tensor_view(self.clone())?;
+        {
+            let mut inner = result.borrow_mut();
+            inner.layout.shape = *shape;
+            let mut nb = get_type_size(inner.dtype);
+            for axis in 0..4 {
+                inner.layout.stride[axis] = nb;
+                nb *= shape.dim(axis);
+            }
+        }
+
+        result.set_op(
+            TensorOpType::TensorOpReshape,
+            OpParams::Reshape { shape: [shape.dim(0), shape.dim(1), shape.dim(2), shape.dim(3)] },
+            &[self.tensor_id()],
+        );
+
+        Ok(result)
+    }
+
+    /// Shared by [`Tensor::acc`] and [`Tensor::set`], which write `other` into the same kind
+    /// of view of `self`.
+    #[track_caller]
+    fn acc_impl(
+        &mut self,
+        other: Tensor,
+        nb: [usize; 3],
+        offset: usize,
+        inplace: bool,
+        op: TensorOpType,
+    ) -> Result<Tensor> {
+        let name = if op == TensorOpType::TensorOpSet { "set" } else { "acc" };
+        if other.dtype() != self.dtype() {
+            return Err(Error::new(ErrorKind::UnexpectedDType {
+                msg: "acc/set source must match the destination dtype",
+                expected: self.dtype(),
+                got: other.dtype(),
+            }));
+        }
+
+        // the last byte touched by the view must stay inside the destination
+        let view_stride = [self.element_size(), nb[0], nb[1], nb[2]];
+        let view_end = other
+            .shape()
+            .iter()
+            .zip(view_stride.iter())
+            .try_fold(offset + self.element_size(), |end, (&ne, &nb)| {
+                (ne - 1).checked_mul(nb).and_then(|delta| end.checked_add(delta))
+            })
+            .ok_or_else(|| Error::msg(format!("{name} view size overflow")))?;
+        if view_end > self.nbytes() {
+            return Err(Error::msg(format!(
+                "{name} view ends at byte {view_end}, destination has {} bytes",
+                self.nbytes()
+            ))
+            .context(format!("in Tensor::{name}")));
+        }
+
+        let mut ctx = self.ctx()?;
+        let mut result =
+            if inplace { self.inplace_result(&mut ctx)? } else { ctx.dup_tensor(self.clone())? };
+
+        result.set_op(
+            op,
+            OpParams::Acc { nb1: nb[0], nb2: nb[1], nb3: nb[2], offset },
+            &[self.tensor_id(), other.tensor_id()],
+        );
+
+        Ok(result)
+    }
+
+    /// Adds `other` into the view of `self` described by the byte strides `nb1..nb3`
+    /// and byte `offset`, returning a new tensor. `self` is left untouched.
+    #[track_caller]
+    pub fn acc(
+        &mut self,
+        other: Tensor,
+        nb1: usize,
+        nb2: usize,
+        nb3: usize,
+        offset: usize,
+    ) -> Result<Tensor> {
+        self.acc_impl(other, [nb1, nb2, nb3], offset, false, TensorOpType::TensorOpAcc)
+    }
+
+    /// Same as [`Tensor::acc`], but accumulates into the storage of `self`.
+    #[track_caller]
+    pub fn acc_inplace(
+        &mut self,
+        other: Tensor,
+        nb1: usize,
+        nb2: usize,
+        nb3: usize,
+        offset: usize,
+    ) -> Result<Tensor> {
+        self.acc_impl(other, [nb1, nb2, nb3], offset, true, TensorOpType::TensorOpAcc)
+    }
+
+    /// Writes `other` over the view of `self` described by the byte strides `nb1..nb3` and
+    /// byte `offset`, returning a new tensor. `self` is left untouched.
+    #[track_caller]
+    pub fn set(
+        &mut self,
+        other: Tensor,
+        nb1: usize,
+        nb2: usize,
+        nb3: usize,
+        offset: usize,
+    ) -> Result<Tensor> {
+        self.acc_impl(other, [nb1, nb2, nb3], offset, false, TensorOpType::TensorOpSet)
+    }
+
+    /// Same as [`Tensor::set`], but writes into the storage of `self`, e.g. to append the
+    /// keys and values of new tokens to a cache from within a graph.
+    #[track_caller]
+    pub fn set_inplace(
+        &mut self,
+        other: Tensor,
+        nb1: usize,
+        nb2: usize,
+        nb3: usize,
+        offset: usize,
+    ) -> Result<Tensor> {
+        self.acc_impl(other, [nb1, nb2, nb3], offset, true, TensorOpType::TensorOpSet)
+    }
+
+    /// Outer product of `self` and `other` summed over their second dimension:
+    /// `[n, k, ...] x [m, k, ...] -> [n, m, ...]`.
+    ///
+    /// `self` is broadcast along dimensions 2 and 3 of `other`. The result is F32.
+    #[track_caller]
+    pub fn out_prod(&mut self, other: Tensor) -> Result<Tensor> {
+        let lhs = *self.shape();
+        let rhs = *other.shape();
+
+        if lhs.dim(1) != rhs.dim(1)
+            || !rhs.dim(2).is_multiple_of(lhs.dim(2))
+            || !rhs.dim(3).is_multiple_of(lhs.dim(3))
+        {
+            return Err(Error::msg(format!("out_prod shapes {lhs} and {rhs} are not compatible"))
+                .context("in Tensor::out_prod"));
+        }
+
+        let rank = lhs.rank.max(rhs.rank).max(2);
+        let dims = [lhs.dim(0), rhs.dim(0), rhs.dim(2), rhs.dim(3)];
+
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.new_tensor(DataType::F32, &Shape::new(&dims[..rank]))?;
+        result.set_op(
+            TensorOpType::TensorOpOutProd,
+            OpParams::None,
+            &[self.tensor_id(), other.tensor_id()],
+        );
+
+        Ok(result)
+    }
+
+    #[track_caller]
+    fn diag_mask_inf_impl(&mut self, n_past: usize, inplace: bool) -> Result<Tensor> {
+        let mut ctx = self.ctx()?;
+        let mut result =
+            if inplace { self.inplace_result(&mut ctx)? } else { ctx.dup_tensor(self.clone())? };
+
+        result.set_op(
+            TensorOpType::TensorOpDiagMaskInf,
+            OpParams::DiagMask { n_past },
+            &[self.tensor_id()],
+        );
+
+        Ok(result)
+    }
+
+    /// Causal mask: sets every element with `i0 > n_past + i1` to `-inf`, leaving the
+    /// rest of `self` unchanged.
+    #[track_caller]
+    pub fn diag_mask_inf(&mut self, n_past: usize) -> Result<Tensor> {
+        self.diag_mask_inf_impl(n_past, false)
+    }
+
+    /// Same as [`Tensor::diag_mask_inf`], but masks the storage of `self`.
+    #[track_caller]
+    pub fn diag_mask_inf_inplace(&mut self, n_past: usize) -> Result<Tensor> {
+        self.diag_mask_inf_impl(n_past, true)
+    }
+
+    /// Inverted dropout: zeroes every element with probability `p` and scales the others by
+    /// `1 / (1 - p)`. The random numbers come from `rng`, a state tensor such as
+    /// [`ComputeGraph::rng`](crate::compute_graph::ComputeGraph::rng), which the op advances.
+    #[track_caller]
+    pub fn dropout(&mut self, rng: &Tensor, p: f32) -> Result<Tensor> {
+        if !(0.0..1.0).contains(&p) {
+            return Err(Error::msg(format!("dropout probability {p} is not in [0, 1)"))
+                .context("in Tensor::dropout"));
+        }
+        if rng.dtype() != DataType::U8 || rng.nbytes() != RngState::NBYTES {
+            return Err(
+                Error::msg("dropout needs an rng state tensor").context("in Tensor::dropout")
+            );
+        }
+
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.dup_tensor(self.clone())?;
+        result.set_op(
+            TensorOpType::TensorOpDropout,
+            OpParams::Dropout { p },
+            &[self.tensor_id(), rng.tensor_id()],
+        );
+
+        Ok(result)
+    }
+
+    /// Layer normalization of every row of `self`, along dimension 0:
+    /// `(x - mean(x)) / sqrt(var(x) + eps) * weight + bias`, with `weight` (gamma) and
+    /// `bias` (beta) rows of the length of those of `self`.
+    #[track_caller]
+    pub fn layer_norm(&mut self, weight: Tensor, bias: Tensor, eps: f32) -> Result<Tensor> {
+        let row = Shape::new(&[self.shape().dim(0)]);
+        for tensor in [&weight, &bias] {
+            if (0..MAX_DIMS).any(|i| tensor.shape().dim(i) != row.dim(i)) {
+                return Err(Error::msg(format!(
+                    "layer_norm weight and bias must have shape {row}, not {}",
+                    tensor.shape()
+                ))
+                .context("in Tensor::layer_norm"));
+            }
+        }
+
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.dup_tensor(self.clone())?;
+        result.set_op(
+            TensorOpType::TensorOpNorm,
+            OpParams::Norm { eps },
+            &[self.tensor_id(), weight.tensor_id(), bias.tensor_id()],
+        );
+
+        Ok(result)
+    }
+
+    /// RMS normalization of every row of `self`, along dimension 0:
+    /// `x / sqrt(mean(x^2) + eps)`. LLaMA-style models multiply the result by a learned
+    /// weight row afterwards, e.g. with [`Tensor::mul`].
+    #[track_caller]
+    pub fn rms_norm(&mut self, eps: f32) -> Result<Tensor> {
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.dup_tensor(self.clone())?;
+        result.set_op(TensorOpType::TensorOpRmsNorm, OpParams::Norm { eps }, &[self.tensor_id()]);
+
+        Ok(result)
+    }
+
+    fn check_groups(&self, n_groups: usize) -> Result<()> {
+        let channels = self.shape().dim(2);
+        if n_groups == 0 || !channels.is_multiple_of(n_groups) {
+            return Err(Error::msg(format!(
+                "{channels} channels cannot be split into {n_groups} groups"
+            ))
+            .context("in Tensor::group_norm"));
+        }
+        Ok(())
+    }
+
+    /// Group normalization of a `[W, H, C, N]` tensor: the `C` channels are split into
+    /// `n_groups` equal groups and each group of each batch is normalized to zero mean
+    /// and unit variance, with `eps` added to the variance.
+    #[track_caller]
+    pub fn group_norm(&mut self, n_groups: usize, eps: f32) -> Result<Tensor> {
+        self.check_groups(n_groups)?;
+
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.dup_tensor(self.clone())?;
+        result.set_op(
+            TensorOpType::TensorOpGroupNorm,
+            OpParams::GroupNorm { n_groups, eps },
+            &[self.tensor_id()],
+        );
+
+        Ok(result)
+    }
+
+    /// Gradient of [`Tensor::group_norm`]: `self` is the gradient of the output and
+    /// `input` the tensor that was normalized.
+    #[track_caller]
+    pub fn group_norm_back(&mut self, input: Tensor, n_groups: usize, eps: f32) -> Result<Tensor> {
+        if *input.shape() != *self.shape() {
+            return Err(Error::msg(format!(
+                "group_norm_back shapes {} and {} differ",
+                self.shape(),
+                input.shape()
+            ))
+            .context("in Tensor::group_norm_back"));
+        }
+        input.check_groups(n_groups)?;
+
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.dup_tensor(self.clone())?;
+        result.set_op(
+            TensorOpType::TensorOpGroupNormBack,
+            OpParams::GroupNorm { n_groups, eps },
+            &[self.tensor_id(), input.tensor_id()],
+        );
+
+        Ok(result)
+    }
+
+    #[track_caller]
+    fn conv_impl(
+        &mut self,
+        input: Tensor,
+        op: TensorOpType,
+        params: Im2ColParams,
+        dims: &[usize],
+    ) -> Result<Tensor> {
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.new_tensor(DataType::F32, &Shape::new(dims))?;
+        result.set_op(op, OpParams::Im2Col(params), &[self.tensor_id(), input.tensor_id()]);
+
+        Ok(result)
+    }
+
+    fn conv_mismatch(&self, input: &Tensor, op: &'static str) -> Error {
+        Error::msg(format!(
+            "{op} kernel {:?} does not match input {:?}",
+            self.shape().dims,
+            input.shape().dims
+        ))
+        .context(format!("in Tensor::{op}"))
+    }
+
+    /// 1-D convolution with `self` as the `[K, IC, OC]` kernel over an `[L, IC, N]`
+    /// input, producing an F32 `[OL, OC, N]` tensor.
+    #[track_caller]
+    pub fn conv_1d(
+        &mut self,
+        input: Tensor,
+        stride: usize,
+        padding: usize,
+        dilation: usize,
+    ) -> Result<Tensor> {
+        let kernel = *self.shape();
+        let data = *input.shape();
+        if kernel.dim(1) != data.dim(1) || kernel.dim(3) != 1 || data.dim(3) != 1 {
+            return Err(self.conv_mismatch(&input, "conv_1d"));
+        }
+
+        let params = Im2ColParams {
+            stride: [stride, 1],
+            padding: [padding, 0],
+            dilation: [dilation, 1],
+            is_2d: false,
+        };
+        let len = params.output_len(0, data.dim(0), kernel.dim(0))?;
+        let dims = [len, kernel.dim(2), data.dim(2)];
+        self.conv_impl(input, TensorOpType::TensorOpConv1d, params, &dims)
+    }
+
+    /// 1-D transposed convolution with `self` as the `[K, OC, IC]` kernel over an
+    /// `[L, IC, N]` input, producing an F32 `[OL, OC, N]` tensor with
+    /// `OL = (L - 1) * stride + dilation * (K - 1) + 1 - 2 * padding`.
+    #[track_caller]
+    pub fn conv_transpose_1d(
+        &mut self,
+        input: Tensor,
+        stride: usize,
+        padding: usize,
+        dilation: usize,
+    ) -> Result<Tensor> {
+        let kernel = *self.shape();
+        let data = *input.shape();
+        if kernel.dim(2) != data.dim(1) || kernel.dim(3) != 1 || data.dim(3) != 1 {
+            return Err(self.conv_mismatch(&input, "conv_transpose_1d"));
+        }
+
+        let params = Im2ColParams {
+            stride: [stride, 1],
+            padding: [padding, 0],
+            dilation: [dilation, 1],
+            is_2d: false,
+        };
+        let len = params.transposed_len(0, data.dim(0), kernel.dim(0))?;
+        let dims = [len, kernel.dim(1), data.dim(2)];
+        self.conv_impl(input, TensorOpType::TensorOpConvTranspose1d, params, &dims)
+    }
+
+    /// 2-D transposed convolution with `self` as the `[KW, KH, OC, IC]` kernel over a
+    /// `[W, H, IC, N]` input, producing an F32 `[OW, OH, OC, N]` tensor. `stride` and
+    /// `padding` are given as `[width, height]`.
+    #[track_caller]
+    pub fn conv_transpose_2d(
+        &mut self,
+        input: Tensor,
+        stride: [usize; 2],
+        padding: [usize; 2],
+    ) -> Result<Tensor> {
+        let kernel = *self.shape();
+        let data = *input.shape();
+        if kernel.dim(3) != data.dim(2) {
+            return Err(self.conv_mismatch(&input, "conv_transpose_2d"));
+        }
+
+        let params = Im2ColParams { stride, padding, dilation: [1, 1], is_2d: true };
+        let width = params.transposed_len(0, data.dim(0), kernel.dim(0))?;
+        let height = params.transposed_len(1, data.dim(1), kernel.dim(1))?;
+        let dims = [width, height, kernel.dim(2), data.dim(3)];
+        self.conv_impl(input, TensorOpType::TensorOpConvTranspose2d, params, &dims)
+    }
+
+    /// Resizes the first two dimensions (width and height) of `self` by `scale`,
+    /// interpolating with `mode`. Each output extent is `floor(extent * scale)`.
+    #[track_caller]
+    pub fn upscale(&mut self, scale: [f32; 2], mode: UpscaleMode) -> Result<Tensor> {
+        let shape = *self.shape();
+        let mut dims = shape.dims;
+        for (axis, &factor) in scale.iter().enumerate() {
+            // The cast below truncates, which is the floor for the positive extents allowed.
+            let extent = shape.dim(axis) as f32 * factor;
+            if !(extent >= 1.0 && extent <= usize::MAX as f32) {
+                return Err(Error::msg(format!(
+                    "scale {factor} maps dimension {axis} of {:?} to an empty extent",
+                    shape.dims
+                ))
+                .context("in Tensor::upscale"));
+            }
+            dims[axis] = extent as usize;
+        }
+
+        let mut ctx = self.ctx()?;
+        let rank = shape.rank.max(2);
+        let mut result = ctx.new_tensor(self.dtype(), &Shape::new(&dims[..rank]))?;
+        result.set_op(
+            TensorOpType::TensorOpUpscale,
+            OpParams::Upscale { mode },
+            &[self.tensor_id()],
+        );
+
+        Ok(result)
+    }
+
+    /// Scaled dot-product attention with `self` as the queries:
+    /// `softmax(scale * K^T Q + mask) V`, computed per head.
+    ///
+    /// `self` is `[D, n_q, H, B]`, `k` and `v` are `[D, n_kv, H_kv, B]` and the optional
+    /// additive `mask` is `[n_kv, n_q]`, or `[n_kv, n_q, H]` for a bias per head. `H` must
+    /// be a multiple of `H_kv`; each group of `H / H_kv` query heads reads the same K/V
+    /// head, so grouped-query and multi-query attention need no duplicated K/V. The result
+    /// is F32 `[D, n_q, H, B]`.
+    #[track_caller]
+    pub fn attention(
+        &mut self,
+        k: Tensor,
+        v: Tensor,
+        mask: Option<Tensor>,
+        scale: f32,
+    ) -> Result<Tensor> {
+        self.attention_ext(k, v, mask, AttentionParams { scale, ..Default::default() })
+    }
+
+    /// [`Tensor::attention`] with the ALiBi position bias of `max_bias` added to the scores:
+    /// head `h` adds `-slope(h) * |i - j|` for query position `i` and key position `j`, with
+    /// the slopes of [`alibi_slopes`](crate::ops::alibi_slopes) and the queries taken to be
+    /// the last `n_q` of the `n_kv` positions. A `max_bias` of 0 adds nothing.
+    #[track_caller]
+    pub fn attention_alibi(
+        &mut self,
+        k: Tensor,
+        v: Tensor,
+        mask: Option<Tensor>,
+        scale: f32,
+        max_bias: f32,
+    ) -> Result<Tensor> {
+        self.attention_ext(k, v, mask, AttentionParams { scale, max_bias, window: None })
+    }
+
+    /// [`Tensor::attention`] with every setting of [`AttentionParams`].
+    ///
+    /// Like the ALiBi bias, the sliding window takes key `j` to be position `j` and the
+    /// queries to be the last `n_q` positions. Keys of a ring-buffer cache are not in
+    /// position order; mask them with [`KvCache::attention_mask`] instead.
+    ///
+    /// [`KvCache::attention_mask`]: crate::kv_cache::KvCache::attention_mask
+    #[track_caller]
+    pub fn attention_ext(
+        &mut self,
+        k: Tensor,
+        v: Tensor,
+        mask: Option<Tensor>,
+        params: AttentionParams,
+    ) -> Result<Tensor> {
+        let q_shape = *self.shape();
+        let kv_shape = *k.shape();
+        let mismatch = |what: &str| {
+            Error::msg(format!("attention {what}: q {q_shape}, k {kv_shape}, v {}", v.shape()))
+                .context("in Tensor::attention")
+        };
+        if params.max_bias.is_nan() || params.max_bias < 0.0 {
+            return Err(mismatch("ALiBi max bias must not be negative"));
+        }
+        if params.window == Some(0) {
+            return Err(mismatch("sliding window must not be empty"));
+        }
+
+        if (0..4).any(|i| v.shape().dim(i) != kv_shape.dim(i)) {
+            return Err(mismatch("k and v shapes differ"));
+        }
+        if q_shape.dim(0) != kv_shape.dim(0) || q_shape.dim(3) != kv_shape.dim(3) {
+            return Err(mismatch("head size or batch differs"));
+        }
+        if !q_shape.dim(2).is_multiple_of(kv_shape.dim(2)) {
+            return Err(mismatch("query heads are not a multiple of key/value heads"));
+        }
+        if let Some(mask) = &mask {
+            let mask_shape = *mask.shape();
+            if mask_shape.dim(0) != kv_shape.dim(1)
+                || mask_shape.dim(1) != q_shape.dim(1)
+                || (mask_shape.dim(2) != 1 && mask_shape.dim(2) != q_shape.dim(2))
+                || mask_shape.dim(3) != 1
+            {
+                return Err(mismatch("mask must be [n_kv, n_q] or [n_kv, n_q, H]"));
+            }
+        }
+
+        let mut sources = vec![self.tensor_id(), k.tensor_id(), v.tensor_id()];
+        sources.extend(mask.map(|mask| mask.tensor_id()));
+
+        let mut ctx = self.ctx()?;
+        let rank = q_shape.rank.max(3);
+        let dims = [q_shape.dim(0), q_shape.dim(1), q_shape.dim(2), q_shape.dim(3)];
+        let mut result = ctx.new_tensor(DataType::F32, &Shape::new(&dims[..rank]))?;
+        result.set_op(TensorOpType::TensorOpAttention, OpParams::Attention(params), &sources);
+
+        Ok(result)
+    }
+
+    /// Applies RoPE to the keys `self` and writes them and the values `v`, both
+    /// `[head_dim, n_kv_heads, n_tokens]` as reshaped from their projections, straight
+    /// into the caches `cache_k` and `cache_v` of layout `[head_dim, n_ctx, n_kv_heads]`.
+    /// Token `t` is rotated by position `positions[t]`, an I32 tensor of `n_tokens`, and
+    /// goes to slot `positions[t] % n_ctx`; other slots are left untouched.
+    ///
+    /// The result is a view of `cache_k`. Add it to the graph before the nodes that read
+    /// the caches, see [`KvCache::store_rope`](crate::kv_cache::KvCache::store_rope).
+    #[track_caller]
+    pub fn rope_store_kv(
+        &mut self,
+        v: &Tensor,
+        positions: &Tensor,
+        cache_k: &Tensor,
+        cache_v: &Tensor,
+        rope: RopeParams,
+    ) -> Result<Tensor> {
+        let kv_shape = *self.shape();
+        let cache_shape = *cache_k.shape();
+        let mismatch = |what: &str| {
+            Error::msg(format!("rope_store_kv {what}: k {kv_shape}, cache {cache_shape}"))
+                .context("in Tensor::rope_store_kv")
+        };
+        let head_dim = kv_shape.dim(0);
+        let n_tokens = kv_shape.dim(2);
+        if *v.shape() != kv_shape || kv_shape.dim(3) != 1 {
+            return Err(mismatch("k and v must be [head_dim, n_kv_heads, n_tokens]"));
+        }
+        if *cache_v.shape() != cache_shape || cache_k.dtype() != cache_v.dtype() {
+            return Err(mismatch("key and value caches differ"));
+        }
+        if cache_shape.dim(0) != head_dim
+            || cache_shape.dim(2) != kv_shape.dim(1)
+            || cache_shape.dim(3) != 1
+        {
+            return Err(mismatch("cache must be [head_dim, n_ctx, n_kv_heads]"));
+        }
+        if n_tokens > cache_shape.dim(1) {
+            return Err(mismatch("more tokens than cache slots"));
+        }
+        if !rope.n_dims.unwrap_or(head_dim).is_multiple_of(2) || rope.n_dims > Some(head_dim) {
+            return Err(mismatch("rotated dimensions must be even and fit the head"));
+        }
+        if positions.dtype() != DataType::I32 || positions.shape().numel() != n_tokens {
+            return Err(mismatch("positions must be I32 with one entry per token"));
+        }
+
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.new_tensor_view(cache_k.clone())?;
+        result.set_op(
+            TensorOpType::TensorOpRopeStoreKv,
+            OpParams::Rope(rope),
+            &[self.tensor_id(), v.tensor_id(), positions.tensor_id(), cache_v.tensor_id()],
+        );
+
+        Ok(result)
+    }
+
+    /// Softmax of every row of `self`, along dimension 0: `exp(x) / sum(exp(x))`, computed
+    /// with the row maximum subtracted first. Rows that are `-inf` throughout, such as rows
+    /// masked out by [`Tensor::diag_mask_inf`], become zeros.
+    #[track_caller]
+    pub fn soft_max(&mut self) -> Result<Tensor> {
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.dup_tensor(self.clone())?;
+        result.set_op(TensorOpType::TensorOpSoftMax, OpParams::None, &[self.tensor_id()]);
+
+        Ok(result)
+    }
+
+    /// Gradient of a row-wise softmax: `self` is the gradient of the softmax output and
+    /// `output` the forward result. Rows run along dimension 0.
+    #[track_caller]
+    pub fn soft_max_back(&mut self, output: Tensor) -> Result<Tensor> {
+        if *output.shape() != *self.shape() {
+            return Err(Error::msg(format!(
+                "soft_max_back shapes {} and {} differ",
+                self.shape(),
+                output.shape()
+            ))
+            .context("in Tensor::soft_max_back"));
+        }
+
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.dup_tensor(self.clone())?;
+        result.set_op(
+            TensorOpType::TensorOpSoftMaxBack,
+            OpParams::None,
+            &[self.tensor_id(), output.tensor_id()],
+        );
+
+        Ok(result)
+    }
+
+    /// Gradient of im2col: folds the column gradient `self` back onto an F32 tensor of
+    /// `input_shape`, summing the contributions of overlapping kernel positions.
+    ///
+    /// The layouts follow im2col: the input is `[IW, IH, IC, N]` (`[IW, IC, N]` in 1D),
+    /// `kernel` is `[KW, KH, ...]` (`[KW, ...]` in 1D) and the columns are
+    /// `[IC * KH * KW, OW, OH, N]` (`[IC * KW, OW, N]` in 1D).
+    #[track_caller]
+    pub fn im2col_back(
+        &mut self,
+        kernel: Tensor,
+        input_shape: &Shape,
+        params: Im2ColParams,
+    ) -> Result<Tensor> {
+        let (kw, kh) = if params.is_2d {
+            (kernel.shape().dim(0), kernel.shape().dim(1))
+        } else {
+            (kernel.shape().dim(0), 1)
+        };
+        let [iw, ih, ic, n] = if params.is_2d {
+            [input_shape.dim(0), input_shape.dim(1), input_shape.dim(2), input_shape.dim(3)]
+        } else {
+            [input_shape.dim(0), 1, input_shape.dim(1), input_shape.dim(2)]
+        };
+        let ow = params.output_len(0, iw, kw)?;
+        let oh = if params.is_2d { params.output_len(1, ih, kh)? } else { 1 };
+
+        let columns = if params.is_2d {
+            Shape::new(&[ic * kh * kw, ow, oh, n])
+        } else {
+            Shape::new(&[ic * kw, ow, n])
+        };
+        let grad = *self.shape();
+        if (0..4).any(|i| grad.dim(i) != columns.dim(i)) {
+            return Err(Error::msg(format!(
+                "im2col_back gradient shape {grad} does not match columns {columns}"
+            ))
+            .context("in Tensor::im2col_back"));
+        }
+
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.new_tensor(DataType::F32, input_shape)?;
+        result.set_op(
+            TensorOpType::TensorOpIm2ColBack,
+            OpParams::Im2Col(params),
+            &[self.tensor_id(), kernel.tensor_id()],
+        );
+
+        Ok(result)
+    }
+
+    /// Sum of every row of `self`: `[n, ...] -> [1, ...]`.
+    #[track_caller]
+    pub fn sum_rows(&mut self) -> Result<Tensor> {
+        self.sum_rows_impl(TensorOpType::TensorOpSumRows)
+    }
+
+    /// Mean of every row of `self`: `[n, ...] -> [1, ...]`.
+    #[track_caller]
+    pub fn mean(&mut self) -> Result<Tensor> {
+        self.sum_rows_impl(TensorOpType::TensorOpMean)
+    }
+
+    #[track_caller]
+    fn sum_rows_impl(&mut self, op: TensorOpType) -> Result<Tensor> {
+        let shape = shape_inference::reduce_shape(&self.shape(), &[0])?;
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.new_tensor(self.dtype(), &shape)?;
+        result.set_op(op, OpParams::None, &[self.tensor_id()]);
+
+        Ok(result)
+    }
+
+    /// Tiles `self` to `shape`: every dimension of `shape` must be a multiple of the one of
+    /// `self`, and element `i` of the result is element `i % self.dims` of `self` along each
+    /// dimension. Used to broadcast e.g. a bias over a batch explicitly.
+    #[track_caller]
+    pub fn repeat(&mut self, shape: &Shape) -> Result<Tensor> {
+        self.repeat_impl(shape, TensorOpType::TensorOpRepeat, "repeat")
+    }
+
+    /// Gradient of [`Tensor::repeat`]: sums the repeats of `self` into a tensor of `shape`,
+    /// whose dimensions must divide the ones of `self`.
+    #[track_caller]
+    pub fn repeat_back(&mut self, shape: &Shape) -> Result<Tensor> {
+        self.repeat_impl(shape, TensorOpType::TensorOpRepeatBack, "repeat_back")
+    }
+
+    #[track_caller]
+    fn repeat_impl(&mut self, shape: &Shape, op: TensorOpType, name: &str) -> Result<Tensor> {
+        let src = *self.shape();
+        let (small, large) =
+            if op == TensorOpType::TensorOpRepeat { (src, *shape) } else { (*shape, src) };
+        if (0..MAX_DIMS).any(|i| !large.dim(i).is_multiple_of(small.dim(i))) {
+            return Err(Error::msg(format!("{name} from {src} to {shape} is not a whole tiling"))
+                .context(format!("in Tensor::{name}")));
+        }
+
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.new_tensor(self.dtype(), shape)?;
+        result.set_op(op, OpParams::None, &[self.tensor_id()]);
+
+        Ok(result)
+    }
+
+    /// Gradient of get_rows: scatters the rows of `self` back to the positions listed in
+    /// the I32 tensor `rows`, summing repeated rows. The F32 result is shaped like `like`.
+    #[track_caller]
+    pub fn get_rows_back(&mut self, rows: Tensor, like: &Tensor) -> Result<Tensor> {
+        if rows.dtype() != DataType::I32 {
+            return Err(Error::new(ErrorKind::UnexpectedDType {
+                msg: "get_rows_back row indices must be I32",
+                expected: DataType::I32,
+                got: rows.dtype(),
+            }));
+        }
+
+        let grad = *self.shape();
+        let target = *like.shape();
+        if grad.dim(2) != 1
+            || grad.dim(3) != 1
+            || target.dim(2) != 1
+            || target.dim(3) != 1
+            || grad.dim(0) != target.dim(0)
+            || grad.dim(1) != rows.shape().iter().product::<usize>()
+        {
+            return Err(Error::msg(format!(
+                "get_rows_back gradient {:?} does not fit rows {:?} and target {:?}",
+                grad.dims,
+                rows.shape().dims,
+                target.dims
+            ))
+            .context("in Tensor::get_rows_back"));
+        }
+
+        let mut ctx = self.ctx()?;
+        let mut result = ctx.new_tensor(DataType::F32, &target)?;
+        result.set_op(
+            TensorOpType::TensorOpGetRowsBack,
+            OpParams::None,
+            &[self.tensor_id(), rows.tensor_id()],
+        );
+
+        Ok(result)
     }
 }
 
@@ -261,7 +1682,7 @@ impl AsRef<Tensor> for Tensor {
     }
 }
 
-impl std::ops::Deref for Tensor {
+impl core::ops::Deref for Tensor {
     type Target = RefCell<TensorInner>;
 
     fn deref(&self) -> &Self::Target {
@@ -336,6 +1757,7 @@ mod tests {
             DataType::F16,
             DataType::F32,
             DataType::F64,
+            DataType::I8,
         ] {
             tensor.set_dtype(dtype);
             assert_eq!(tensor.dtype(), dtype);
@@ -450,6 +1872,7 @@ mod tests {
             DataType::F16,
             DataType::F32,
             DataType::F64,
+            DataType::I8,
         ];
 
         for dtype in dtypes {
@@ -458,4 +1881,37 @@ mod tests {
             assert_eq!(tensor.dtype(), dtype);
         }
     }
+
+    #[test]
+    fn test_im2col_output_len() {
+        let params =
+            Im2ColParams { stride: [2, 1], padding: [1, 0], dilation: [1, 2], is_2d: true };
+        assert_eq!(params.output_len(0, 5, 3).unwrap(), 3);
+        assert_eq!(params.output_len(1, 5, 3).unwrap(), 1);
+        assert!(params.output_len(1, 3, 3).is_err());
+
+        assert_eq!(params.transposed_len(0, 3, 3).unwrap(), 5);
+        assert_eq!(params.transposed_len(1, 3, 2).unwrap(), 5);
+        assert!(params.transposed_len(0, 1, 1).is_err());
+    }
+
+    #[test]
+    fn test_permute_reshape_contiguity() {
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut tensor = ctx.new_tensor(DataType::F32, &shape![2, 3, 4]).unwrap();
+        assert!(tensor.is_contiguous());
+
+        let mut permuted = tensor.permute([2, 0, 1, 3]).unwrap();
+        assert_eq!(*permuted.shape(), shape![3, 4, 2]);
+        assert_eq!(permuted.stride()[2], 4);
+        assert!(!permuted.is_contiguous());
+        assert!(permuted.reshape(&shape![24]).is_err());
+        assert!(tensor.permute([0, 0, 1, 2]).is_err());
+
+        let mut copy = permuted.cont().unwrap();
+        assert!(copy.is_contiguous());
+        let flat = copy.reshape(&shape![6, 4]).unwrap();
+        assert_eq!(flat.stride()[1], 24);
+        assert!(copy.reshape(&shape![5, 5]).is_err());
+    }
 }
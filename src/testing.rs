@@ -0,0 +1,373 @@
+//! Reference values for validating backends.
+//!
+//! [`op_fixtures`] lists small, canonical cases per op: F32 inputs, the op applied to them and
+//! the output the CPU reference kernels compute. A backend implementation runs them with
+//! [`run_op_fixtures`] (or [`OpFixture::run`] one at a time) to check its kernels against the
+//! reference without the crate's own test suite:
+//!
+//! ```no_run
+//! # fn main() -> feml::error::Result<()> {
+//! let backend = feml::Backend::by_name("opencl").build()?;
+//! for report in feml::testing::run_op_fixtures(&backend)? {
+//!     assert!(report.passed, "{} is off by {}", report.name, report.max_abs_error);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::api::Backend;
+use crate::compute_graph::ComputeGraph;
+use crate::context::Context;
+use crate::data_type::{DataType, TensorOpType, TensorType};
+use crate::error::{Error, Result};
+use crate::shape;
+use crate::shape::Shape;
+use crate::tensor::{GeluMode, Tensor, UpscaleMode};
+
+/// Values of a fixture tensor, innermost dimension first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixtureTensor {
+    pub shape: Shape,
+    pub values: Vec<f32>,
+}
+
+impl FixtureTensor {
+    fn new(shape: Shape, values: &[f32]) -> Self {
+        Self { shape, values: values.to_vec() }
+    }
+}
+
+/// One op applied to fixed inputs, with its expected output.
+#[derive(Debug, Clone)]
+pub struct OpFixture {
+    pub name: &'static str,
+    /// The op of the output node; other nodes of the fixture only make views.
+    pub op: TensorOpType,
+    pub inputs: Vec<FixtureTensor>,
+    pub expected: FixtureTensor,
+    /// Largest absolute error of a passing output element.
+    pub tolerance: f32,
+    build: fn(&[Tensor]) -> Result<Tensor>,
+}
+
+/// Result of running one fixture on a backend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixtureReport {
+    pub name: &'static str,
+    pub max_abs_error: f32,
+    /// Flat index, innermost dimension first, of the element with the largest error.
+    pub worst_index: usize,
+    pub passed: bool,
+}
+
+impl OpFixture {
+    /// Creates the inputs as leafs of `ctx` and applies the op to them, returning the
+    /// inputs and the output node.
+    pub fn build(&self, ctx: &mut Context) -> Result<(Vec<Tensor>, Tensor)> {
+        let mut build = || {
+            let inputs = self
+                .inputs
+                .iter()
+                .map(|input| {
+                    let tensor = ctx.new_tensor(DataType::F32, &input.shape)?;
+                    tensor.set_op_type(TensorOpType::TensorNone);
+                    tensor.set_tensor_type(TensorType::FlagParam);
+                    Ok(tensor)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let output = (self.build)(&inputs)?;
+            Ok((inputs, output))
+        };
+        build().map_err(|e: Error| e.context(format!("in OpFixture::build of {}", self.name)))
+    }
+
+    /// Compares computed output values with the expected ones.
+    pub fn check(&self, actual: &[f32]) -> Result<FixtureReport> {
+        let expected = &self.expected.values;
+        if actual.len() != expected.len() {
+            return Err(Error::msg(format!(
+                "{} output values, expected {}",
+                actual.len(),
+                expected.len()
+            ))
+            .context(format!("in OpFixture::check of {}", self.name)));
+        }
+        let mut report =
+            FixtureReport { name: self.name, max_abs_error: 0.0, worst_index: 0, passed: true };
+        for (index, (&actual, &expected)) in actual.iter().zip(expected).enumerate() {
+            // masked elements are infinite on both sides
+            let error = if actual == expected { 0.0 } else { (actual - expected).abs() };
+            if error > report.max_abs_error || error.is_nan() {
+                report.max_abs_error = error;
+                report.worst_index = index;
+            }
+        }
+        report.passed = report.max_abs_error <= self.tolerance;
+        Ok(report)
+    }
+
+    /// Computes the fixture on `backend` and checks the output.
+    pub fn run(&self, backend: &Backend) -> Result<FixtureReport> {
+        let run = || {
+            let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+            let (inputs, output) = self.build(&mut ctx)?;
+            let mut graph = ComputeGraph::new();
+            graph.add_output(&ctx, &output)?;
+            let _buffer = backend.alloc_graph(&ctx, &graph)?;
+            for (tensor, input) in inputs.iter().zip(&self.inputs) {
+                let bytes: Vec<u8> = input.values.iter().flat_map(|v| v.to_ne_bytes()).collect();
+                backend.write(tensor, &bytes)?;
+            }
+            backend.compute(&ctx, &mut graph)?;
+            let bytes = backend.read(&output)?;
+            let values: Vec<f32> = bytes
+                .chunks_exact(4)
+                .map(|chunk| f32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                .collect();
+            self.check(&values)
+        };
+        run().map_err(|e: Error| e.context(format!("in OpFixture::run of {}", self.name)))
+    }
+}
+
+/// Runs every fixture whose op `backend` supports on F32, see
+/// [`backend::Backend::supports`](crate::backend::Backend::supports).
+pub fn run_op_fixtures(backend: &Backend) -> Result<Vec<FixtureReport>> {
+    op_fixtures()
+        .iter()
+        .filter(|fixture| backend.inner().supports(fixture.op, &[DataType::F32]))
+        .map(|fixture| fixture.run(backend))
+        .collect::<Result<_>>()
+        .map_err(|e| e.context("in run_op_fixtures"))
+}
+
+/// The fixtures, covering the element-wise, matrix, reduction, layout and normalization ops.
+pub fn op_fixtures() -> Vec<OpFixture> {
+    use TensorOpType::*;
+
+    let fixture = |name, op, inputs: Vec<FixtureTensor>, expected, build| OpFixture {
+        name,
+        op,
+        inputs,
+        expected,
+        tolerance: 1e-5,
+        build,
+    };
+    let matrix = FixtureTensor::new(shape![3, 2], &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    let row = FixtureTensor::new(shape![3], &[0.5, -1.0, 2.0]);
+    let activations = FixtureTensor::new(shape![6], &[-3.0, -1.0, -0.5, 0.0, 0.5, 2.0]);
+
+    vec![
+        fixture(
+            "add_broadcast",
+            TensorOpAdd,
+            vec![matrix.clone(), row.clone()],
+            FixtureTensor::new(shape![3, 2], &[1.5, 1.0, 5.0, 4.5, 4.0, 8.0]),
+            |t| t[0].clone().add(t[1].clone()),
+        ),
+        fixture(
+            "sub_broadcast",
+            TensorOpSub,
+            vec![matrix.clone(), row.clone()],
+            FixtureTensor::new(shape![3, 2], &[0.5, 3.0, 1.0, 3.5, 6.0, 4.0]),
+            |t| t[0].clone().sub(t[1].clone()),
+        ),
+        fixture(
+            "mul_broadcast",
+            TensorOpMul,
+            vec![matrix.clone(), row.clone()],
+            FixtureTensor::new(shape![3, 2], &[0.5, -2.0, 6.0, 2.0, -5.0, 12.0]),
+            |t| t[0].clone().mul(t[1].clone()),
+        ),
+        fixture(
+            "div_broadcast",
+            TensorOpDiv,
+            vec![matrix.clone(), row.clone()],
+            FixtureTensor::new(shape![3, 2], &[2.0, -2.0, 1.5, 8.0, -5.0, 3.0]),
+            |t| t[0].clone().div(t[1].clone()),
+        ),
+        fixture(
+            "mul_mat",
+            TensorOpMulMat,
+            vec![
+                matrix.clone(),
+                FixtureTensor::new(shape![3, 2], &[1.0, 0.0, -1.0, 2.0, 1.0, 0.5]),
+            ],
+            FixtureTensor::new(shape![2, 2], &[-2.0, -2.0, 5.5, 16.0]),
+            |t| t[0].clone().mul_mat(t[1].clone()),
+        ),
+        fixture(
+            "out_prod",
+            TensorOpOutProd,
+            vec![
+                matrix.clone(),
+                FixtureTensor::new(shape![4, 2], &[1.0, 0.0, -1.0, 2.0, 0.5, 1.0, 0.0, -2.0]),
+            ],
+            FixtureTensor::new(
+                shape![3, 4],
+                &[3.0, 4.5, 6.0, 4.0, 5.0, 6.0, -1.0, -2.0, -3.0, -6.0, -6.0, -6.0],
+            ),
+            |t| t[0].clone().out_prod(t[1].clone()),
+        ),
+        fixture(
+            "cont_transpose",
+            TensorOpCont,
+            vec![matrix.clone()],
+            FixtureTensor::new(shape![2, 3], &[1.0, 4.0, 2.0, 5.0, 3.0, 6.0]),
+            |t| t[0].clone().transpose()?.cont(),
+        ),
+        fixture(
+            "sum_rows",
+            TensorOpSumRows,
+            vec![matrix.clone()],
+            FixtureTensor::new(shape![1, 2], &[6.0, 15.0]),
+            |t| t[0].clone().sum_rows(),
+        ),
+        fixture(
+            "mean",
+            TensorOpMean,
+            vec![matrix.clone()],
+            FixtureTensor::new(shape![1, 2], &[2.0, 5.0]),
+            |t| t[0].clone().mean(),
+        ),
+        fixture(
+            "repeat",
+            TensorOpRepeat,
+            vec![row.clone()],
+            FixtureTensor::new(
+                shape![6, 2],
+                &[0.5, -1.0, 2.0, 0.5, -1.0, 2.0, 0.5, -1.0, 2.0, 0.5, -1.0, 2.0],
+            ),
+            |t| t[0].clone().repeat(&shape![6, 2]),
+        ),
+        fixture(
+            "repeat_back",
+            TensorOpRepeatBack,
+            vec![matrix.clone()],
+            FixtureTensor::new(shape![3], &[5.0, 7.0, 9.0]),
+            |t| t[0].clone().repeat_back(&shape![3]),
+        ),
+        fixture(
+            "diag_mask_inf",
+            TensorOpDiagMaskInf,
+            vec![FixtureTensor::new(shape![3, 3], &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0])],
+            FixtureTensor::new(
+                shape![3, 3],
+                &[1.0, -f32::INFINITY, -f32::INFINITY, 4.0, 5.0, -f32::INFINITY, 7.0, 8.0, 9.0],
+            ),
+            |t| t[0].clone().diag_mask_inf(0),
+        ),
+        fixture(
+            "layer_norm",
+            TensorOpNorm,
+            vec![
+                matrix.clone(),
+                FixtureTensor::new(shape![3], &[1.0, 2.0, 0.5]),
+                FixtureTensor::new(shape![3], &[0.0, 1.0, -1.0]),
+            ],
+            FixtureTensor::new(
+                shape![3, 2],
+                &[-1.224736, 1.0, -0.387632, -1.224736, 1.0, -0.387632],
+            ),
+            |t| t[0].clone().layer_norm(t[1].clone(), t[2].clone(), 1e-5),
+        ),
+        fixture(
+            "rms_norm",
+            TensorOpRmsNorm,
+            vec![matrix.clone()],
+            FixtureTensor::new(
+                shape![3, 2],
+                &[0.46291, 0.92582, 1.38873, 0.789542, 0.986928, 1.184313],
+            ),
+            |t| t[0].clone().rms_norm(1e-6),
+        ),
+        fixture(
+            "group_norm",
+            TensorOpGroupNorm,
+            vec![FixtureTensor::new(shape![2, 1, 2], &[1.0, 3.0, -2.0, 2.0])],
+            FixtureTensor::new(shape![2, 1, 2], &[-0.999995, 0.999995, -0.9999988, 0.9999988]),
+            |t| t[0].clone().group_norm(2, 1e-5),
+        ),
+        fixture(
+            "upscale_nearest",
+            TensorOpUpscale,
+            vec![FixtureTensor::new(shape![2, 2], &[1.0, 2.0, 3.0, 4.0])],
+            FixtureTensor::new(
+                shape![4, 4],
+                &[1.0, 1.0, 2.0, 2.0, 1.0, 1.0, 2.0, 2.0, 3.0, 3.0, 4.0, 4.0, 3.0, 3.0, 4.0, 4.0],
+            ),
+            |t| t[0].clone().upscale([2.0, 2.0], UpscaleMode::Nearest),
+        ),
+        fixture(
+            "soft_max",
+            TensorOpSoftMax,
+            vec![FixtureTensor::new(shape![3, 2], &[0.0, 1.0, 2.0, 1000.0, 1001.0, 1002.0])],
+            FixtureTensor::new(
+                shape![3, 2],
+                &[0.09003057, 0.24472847, 0.66524096, 0.09003057, 0.24472847, 0.66524096],
+            ),
+            |t| t[0].clone().soft_max(),
+        ),
+        fixture(
+            "soft_max_back",
+            TensorOpSoftMaxBack,
+            vec![row.clone(), FixtureTensor::new(shape![3], &[0.2, 0.3, 0.5])],
+            FixtureTensor::new(shape![3], &[-0.06, -0.54, 0.6]),
+            |t| t[0].clone().soft_max_back(t[1].clone()),
+        ),
+        fixture(
+            "gelu_tanh",
+            TensorOpGelu,
+            vec![activations.clone()],
+            FixtureTensor::new(
+                shape![6],
+                &[-0.0036374, -0.158808, -0.154286, 0.0, 0.345714, 1.9545977],
+            ),
+            |t| t[0].clone().gelu(GeluMode::Tanh),
+        ),
+        fixture(
+            "gelu_exact",
+            TensorOpGelu,
+            vec![activations.clone()],
+            FixtureTensor::new(
+                shape![6],
+                &[-0.0040497, -0.1586553, -0.1542688, 0.0, 0.3457312, 1.9544997],
+            ),
+            |t| t[0].clone().gelu(GeluMode::Exact),
+        ),
+        fixture(
+            "relu",
+            TensorOpRelu,
+            vec![activations.clone()],
+            FixtureTensor::new(shape![6], &[0.0, 0.0, 0.0, 0.0, 0.5, 2.0]),
+            |t| t[0].clone().relu(),
+        ),
+        fixture(
+            "leaky_relu",
+            TensorOpLeakyRelu,
+            vec![activations.clone()],
+            FixtureTensor::new(shape![6], &[-0.3, -0.1, -0.05, 0.0, 0.5, 2.0]),
+            |t| t[0].clone().leaky_relu(0.1),
+        ),
+        fixture(
+            "silu",
+            TensorOpSilu,
+            vec![activations.clone()],
+            FixtureTensor::new(
+                shape![6],
+                &[-0.1422776, -0.2689414, -0.1887703, 0.0, 0.3112297, 1.7615942],
+            ),
+            |t| t[0].clone().silu(),
+        ),
+        fixture(
+            "swiglu",
+            TensorOpSwiGlu,
+            vec![activations, FixtureTensor::new(shape![6], &[1.0, -2.0, 4.0, 3.0, 0.5, -1.5])],
+            FixtureTensor::new(
+                shape![6],
+                &[-0.1422776, 0.5378828, -0.7550813, 0.0, 0.1556148, -2.6423912],
+            ),
+            |t| t[0].clone().swiglu(t[1].clone()),
+        ),
+    ]
+}
@@ -0,0 +1,278 @@
+//! Worker threads shared by the compute backends.
+//!
+//! A [`ThreadPool`] keeps `n_threads - 1` workers alive between kernels; the thread that
+//! calls [`ThreadPool::run`] works as the last one. Runs are serialized, so one pool can
+//! be shared by several backends, but a task must not start another run on its own pool.
+
+use crate::error::{Error, Result};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThreadPoolParams {
+    pub n_threads: usize,
+    /// Microseconds an idle thread busy-spins for new work, or for the workers to finish,
+    /// before it sleeps on a condition variable. Spinning cuts the wake-up latency of
+    /// back-to-back small kernels, e.g. during decode, at the cost of burning CPU; `0`
+    /// always sleeps, which suits large batches.
+    pub poll_us: u64,
+}
+
+impl ThreadPoolParams {
+    pub fn new(n_threads: usize) -> Self {
+        Self { n_threads, ..Self::default() }
+    }
+
+    fn poll(&self) -> Duration {
+        Duration::from_micros(self.poll_us)
+    }
+}
+
+impl Default for ThreadPoolParams {
+    fn default() -> Self {
+        Self { n_threads: std::thread::available_parallelism().map_or(1, |n| n.get()), poll_us: 50 }
+    }
+}
+
+type Task = dyn Fn(usize) -> Result<()> + Sync;
+
+/// One call to [`ThreadPool::run`]. Tasks are claimed from `next`, so faster threads pick
+/// up the work slower ones have not started yet.
+struct Job {
+    task: &'static Task,
+    n_tasks: usize,
+    next: AtomicUsize,
+    error: Mutex<Option<Error>>,
+}
+
+impl Job {
+    fn execute(&self) {
+        loop {
+            let index = self.next.fetch_add(1, Ordering::Relaxed);
+            if index >= self.n_tasks {
+                return;
+            }
+
+            let result = catch_unwind(AssertUnwindSafe(|| (self.task)(index)))
+                .unwrap_or_else(|_| Err(Error::msg("cpu worker thread panicked")));
+            if let Err(err) = result {
+                lock(&self.error).get_or_insert(err);
+                // Skip the remaining tasks, the run fails anyway.
+                self.next.store(self.n_tasks, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    job: Option<Arc<Job>>,
+    shutdown: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    /// Bumped under the `state` lock for every job, read without it while spinning.
+    generation: AtomicU64,
+    /// Workers that have not finished the current job yet.
+    active: AtomicUsize,
+    work_ready: Condvar,
+    work_done: Condvar,
+}
+
+pub struct ThreadPool {
+    params: ThreadPoolParams,
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+    run_lock: Mutex<()>,
+}
+
+impl ThreadPool {
+    pub fn new(params: ThreadPoolParams) -> Result<Self> {
+        if params.n_threads == 0 {
+            return Err(
+                Error::msg("thread pool needs at least one thread").context("in ThreadPool::new")
+            );
+        }
+
+        let shared = Arc::new(Shared::default());
+        let mut pool = Self { params, shared, workers: Vec::new(), run_lock: Mutex::new(()) };
+        for i in 1..params.n_threads {
+            let shared = pool.shared.clone();
+            let worker = std::thread::Builder::new()
+                .name(format!("feml-worker-{i}"))
+                .spawn(move || worker_loop(&shared, params.poll()))
+                .map_err(|e| Error::from(e).context("in ThreadPool::new"))?;
+            pool.workers.push(worker);
+        }
+
+        Ok(pool)
+    }
+
+    /// Pool without workers; every run executes on the calling thread.
+    pub fn sequential() -> Self {
+        Self {
+            params: ThreadPoolParams::new(1),
+            shared: Arc::new(Shared::default()),
+            workers: Vec::new(),
+            run_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn params(&self) -> ThreadPoolParams {
+        self.params
+    }
+
+    pub fn n_threads(&self) -> usize {
+        self.params.n_threads
+    }
+
+    /// Runs `task(i)` for every `i` in `0..n_tasks` on the pool and the calling thread and
+    /// returns once all of them have finished. The first error or panic is returned.
+    pub fn run(&self, n_tasks: usize, task: &(dyn Fn(usize) -> Result<()> + Sync)) -> Result<()> {
+        if self.workers.is_empty() || n_tasks <= 1 {
+            return (0..n_tasks).try_for_each(task);
+        }
+
+        let _run = lock(&self.run_lock);
+        // SAFETY: the job, and with it this reference, is dropped by every worker before
+        // `run` returns: we wait below until no worker is active and then clear the job.
+        let task = unsafe {
+            std::mem::transmute::<&(dyn Fn(usize) -> Result<()> + Sync + '_), &'static Task>(task)
+        };
+        let job =
+            Arc::new(Job { task, n_tasks, next: AtomicUsize::new(0), error: Mutex::new(None) });
+
+        {
+            let mut state = lock(&self.shared.state);
+            state.job = Some(job.clone());
+            self.shared.active.store(self.workers.len(), Ordering::Release);
+            self.shared.generation.fetch_add(1, Ordering::AcqRel);
+            self.shared.work_ready.notify_all();
+        }
+
+        job.execute();
+
+        spin_while(self.params.poll(), || self.shared.active.load(Ordering::Acquire) > 0);
+        let mut state = lock(&self.shared.state);
+        while self.shared.active.load(Ordering::Acquire) > 0 {
+            state = self.shared.work_done.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+        state.job = None;
+        drop(state);
+
+        match lock(&job.error).take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        lock(&self.shared.state).shutdown = true;
+        self.shared.work_ready.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn worker_loop(shared: &Shared, poll: Duration) {
+    let mut seen = 0;
+    loop {
+        spin_while(poll, || shared.generation.load(Ordering::Acquire) == seen);
+        let job = {
+            let mut state = lock(&shared.state);
+            while shared.generation.load(Ordering::Acquire) == seen && !state.shutdown {
+                state = shared.work_ready.wait(state).unwrap_or_else(PoisonError::into_inner);
+            }
+            if state.shutdown {
+                return;
+            }
+            seen = shared.generation.load(Ordering::Acquire);
+            state.job.clone()
+        };
+
+        if let Some(job) = job {
+            job.execute();
+        }
+
+        if shared.active.fetch_sub(1, Ordering::AcqRel) == 1 {
+            let _state = lock(&shared.state);
+            shared.work_done.notify_all();
+        }
+    }
+}
+
+/// Busy-waits while `busy` holds, for at most `poll`.
+fn spin_while(poll: Duration, busy: impl Fn() -> bool) {
+    if poll.is_zero() {
+        return;
+    }
+
+    let start = Instant::now();
+    while busy() && start.elapsed() < poll {
+        std::hint::spin_loop();
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_visits_every_task_once() {
+        let pool = ThreadPool::new(ThreadPoolParams::new(4)).unwrap();
+        for n_tasks in [0, 1, 3, 64] {
+            let counts: Vec<AtomicUsize> = (0..n_tasks).map(|_| AtomicUsize::new(0)).collect();
+            pool.run(n_tasks, &|i| {
+                counts[i].fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            })
+            .unwrap();
+            assert!(counts.iter().all(|count| count.load(Ordering::Relaxed) == 1));
+        }
+    }
+
+    #[test]
+    fn test_run_reports_errors_and_panics() {
+        let pool = ThreadPool::new(ThreadPoolParams::new(3)).unwrap();
+        let err = pool.run(8, &|i| if i == 5 { Err(Error::msg("task failed")) } else { Ok(()) });
+        assert!(err.unwrap_err().to_string().contains("task failed"));
+
+        let err = pool.run(8, &|i| if i == 2 { panic!("boom") } else { Ok(()) });
+        assert!(err.unwrap_err().to_string().contains("panicked"));
+
+        pool.run(8, &|_| Ok(())).expect("pool should keep working after a failed run");
+    }
+
+    #[test]
+    fn test_run_with_and_without_polling() {
+        for poll_us in [0, 1_000] {
+            let pool = ThreadPool::new(ThreadPoolParams { n_threads: 4, poll_us }).unwrap();
+            let total = AtomicUsize::new(0);
+            for _ in 0..100 {
+                pool.run(16, &|i| {
+                    total.fetch_add(i, Ordering::Relaxed);
+                    Ok(())
+                })
+                .unwrap();
+            }
+            assert_eq!(total.load(Ordering::Relaxed), 100 * (0..16).sum::<usize>());
+        }
+    }
+
+    #[test]
+    fn test_new_rejects_zero_threads() {
+        assert!(ThreadPool::new(ThreadPoolParams::new(0)).is_err());
+    }
+}
@@ -1,11 +1,15 @@
 #[cfg(feature = "cpu")]
 mod cpu_backend {
-    use feml::backend::BackendBufferUsage;
+    use feml::backend::{copy_tensor, BackendBufferUsage};
     use feml::compute_graph::ComputeGraph;
     use feml::context::Context;
     use feml::data_type::{DataType, TensorOpType, TensorType};
+    use feml::einsum::einsum;
+    use feml::kv_cache::KvCache;
     use feml::registry::Registry;
+    use feml::rng::RngState;
     use feml::shape;
+    use feml::tensor::{Im2ColParams, Tensor, UpscaleMode};
 
     fn encode_f32(values: &[f32]) -> Vec<u8> {
         let mut bytes = Vec::with_capacity(values.len() * 4);
@@ -22,6 +26,11 @@ mod cpu_backend {
             .collect()
     }
 
+    fn mark_as_leaf(tensor: &Tensor) {
+        tensor.set_tensor_type(TensorType::FlagParam);
+        tensor.set_op_type(TensorOpType::TensorNone);
+    }
+
     #[test]
     fn registry_cpu_open_backend() {
         let registry = Registry::discover().expect("registry discover should succeed");
@@ -32,6 +41,21 @@ mod cpu_backend {
         assert!(registry.open_backend("CPU", 0).is_ok());
     }
 
+    #[test]
+    fn device_id_is_stable_and_finds_the_device() {
+        let registry = Registry::discover().unwrap();
+        registry.init_all().unwrap();
+        let id = registry.open_device("CPU", 0).unwrap().info().unwrap().id;
+        assert!(id.starts_with("cpu-"), "{id}");
+        assert_eq!(registry.open_device("CPU", 0).unwrap().info().unwrap().id, id);
+
+        let (name, index) = registry.find_device(&id).expect("the CPU device has an id");
+        assert!(name.eq_ignore_ascii_case("cpu"));
+        assert_eq!(index, 0);
+        assert!(registry.find_device("GPU-00000000-0000-0000-0000-000000000000").is_none());
+        assert!(registry.find_device("").is_none());
+    }
+
     #[test]
     fn backend_buffer_write_read() {
         let registry = Registry::discover().expect("registry discover should succeed");
@@ -98,4 +122,3318 @@ mod cpu_backend {
 
         assert_eq!(decode_f32(&output[..16]), vec![10.0, 40.0, 90.0, 160.0]);
     }
+
+    #[test]
+    fn tensor_element_accessors() {
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(64, BackendBufferUsage::Any)
+            .expect("CPU buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(4).build();
+        let tensor = ctx.new_tensor(DataType::F32, &shape![3, 2]).unwrap();
+        buffer.init_tensor(tensor.clone(), 0).unwrap();
+
+        for i1 in 0..2 {
+            for i0 in 0..3 {
+                tensor.set_f32_nd(&[i0, i1], (i1 * 10 + i0) as f32).unwrap();
+            }
+        }
+
+        assert_eq!(tensor.get_f32_nd(&[2, 1]).unwrap(), 12.0);
+        assert_eq!(tensor.get_i32_nd(&[1, 1]).unwrap(), 11);
+        assert_eq!(tensor.get_f32_nd(&[1]).unwrap(), 1.0);
+
+        tensor.set_i32_nd(&[0, 1], -4).unwrap();
+        assert_eq!(tensor.get_f32_nd(&[0, 1]).unwrap(), -4.0);
+
+        assert!(tensor.get_f32_nd(&[3, 0]).is_err());
+        assert!(tensor.get_f32_nd(&[0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn graph_compute_acc_f32() {
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(256, BackendBufferUsage::Any)
+            .expect("CPU buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut dst = ctx.new_tensor(DataType::F32, &shape![4, 3]).unwrap();
+        let src = ctx.new_tensor(DataType::F32, &shape![2, 2]).unwrap();
+        mark_as_leaf(&dst);
+        mark_as_leaf(&src);
+
+        // add `src` into the 2x2 block that starts at row 1, column 1
+        let nb1 = dst.stride()[1];
+        let out = dst.acc(src.clone(), nb1, nb1 * 3, nb1 * 3, nb1 + 4).unwrap();
+
+        buffer.init_tensor(dst.clone(), 0).unwrap();
+        buffer.init_tensor(src.clone(), 64).unwrap();
+        buffer.init_tensor(out.clone(), 128).unwrap();
+
+        let mut dst_bytes = encode_f32(&[1.0; 12]);
+        let mut src_bytes = encode_f32(&[1.0, 2.0, 3.0, 4.0]);
+        buffer.write(dst.clone(), &mut dst_bytes, 0, 48).unwrap();
+        buffer.write(src.clone(), &mut src_bytes, 0, 16).unwrap();
+
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, out.tensor_id(), false).unwrap();
+        backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+
+        let mut output = vec![0; out.nbytes()];
+        buffer.read(out, &mut output, 0, 48).unwrap();
+        #[rustfmt::skip]
+        assert_eq!(
+            decode_f32(&output),
+            vec![
+                1.0, 1.0, 1.0, 1.0,
+                1.0, 2.0, 3.0, 1.0,
+                1.0, 4.0, 5.0, 1.0,
+            ]
+        );
+        assert_eq!(dst.get_f32_nd(&[1, 1]).unwrap(), 1.0);
+        assert!(dst.acc(src, nb1, nb1 * 3, nb1 * 3, nb1 * 2 + 12).is_err());
+    }
+
+    #[test]
+    fn graph_compute_out_prod_f32_f16() {
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(128, BackendBufferUsage::Any)
+            .expect("CPU buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut lhs = ctx.new_tensor(DataType::F32, &shape![2, 3]).unwrap();
+        let rhs = ctx.new_tensor(DataType::F16, &shape![2, 3]).unwrap();
+        mark_as_leaf(&lhs);
+        mark_as_leaf(&rhs);
+
+        let dst = lhs.out_prod(rhs.clone()).expect("out_prod tensor should be created");
+        assert_eq!(&*dst.shape(), &shape![2, 2]);
+        assert!(lhs.out_prod(dst.clone()).is_err());
+
+        buffer.init_tensor(lhs.clone(), 0).unwrap();
+        buffer.init_tensor(rhs.clone(), 32).unwrap();
+        buffer.init_tensor(dst.clone(), 64).unwrap();
+
+        for k in 0..3 {
+            for i in 0..2 {
+                let value = (k * 2 + i + 1) as f32;
+                lhs.set_f32_nd(&[i, k], value).unwrap();
+                rhs.set_f32_nd(&[i, k], value).unwrap();
+            }
+        }
+
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, dst.tensor_id(), false).unwrap();
+        backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+
+        let mut output = vec![0; dst.nbytes()];
+        buffer.read(dst, &mut output, 0, 16).unwrap();
+        assert_eq!(decode_f32(&output), vec![35.0, 44.0, 44.0, 56.0]);
+    }
+
+    #[test]
+    fn graph_compute_soft_max_back_f32() {
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(128, BackendBufferUsage::Any)
+            .expect("CPU buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut grad = ctx.new_tensor(DataType::F32, &shape![3, 2]).unwrap();
+        let output = ctx.new_tensor(DataType::F32, &shape![3, 2]).unwrap();
+        let other = ctx.new_tensor(DataType::F32, &shape![2, 3]).unwrap();
+        mark_as_leaf(&grad);
+        mark_as_leaf(&output);
+
+        assert!(grad.soft_max_back(other).is_err());
+        let dst = grad.soft_max_back(output.clone()).expect("soft_max_back should be created");
+
+        buffer.init_tensor(grad.clone(), 0).unwrap();
+        buffer.init_tensor(output.clone(), 32).unwrap();
+        buffer.init_tensor(dst.clone(), 64).unwrap();
+
+        let rows = [([1.0, 0.0, -1.0], [0.2, 0.3, 0.5]), ([2.0, 2.0, 2.0], [0.5, 0.5, 0.0])];
+        for (i1, (dy, y)) in rows.iter().enumerate() {
+            for i0 in 0..3 {
+                grad.set_f32_nd(&[i0, i1], dy[i0]).unwrap();
+                output.set_f32_nd(&[i0, i1], y[i0]).unwrap();
+            }
+        }
+
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, dst.tensor_id(), false).unwrap();
+        backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+
+        let mut output = vec![0; dst.nbytes()];
+        buffer.read(dst, &mut output, 0, 24).unwrap();
+        let expected = [0.26, 0.09, -0.35, 0.0, 0.0, 0.0];
+        for (got, expected) in decode_f32(&output).iter().zip(expected) {
+            assert!((got - expected).abs() < 1e-6, "{got} != {expected}");
+        }
+    }
+
+    #[test]
+    fn graph_compute_im2col_back_f32() {
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(256, BackendBufferUsage::Any)
+            .expect("CPU buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut cols = ctx.new_tensor(DataType::F32, &shape![4, 2, 2, 1]).unwrap();
+        let kernel = ctx.new_tensor(DataType::F32, &shape![2, 2, 1, 1]).unwrap();
+        mark_as_leaf(&cols);
+        mark_as_leaf(&kernel);
+
+        let params = Im2ColParams::default();
+        assert!(cols.im2col_back(kernel.clone(), &shape![4, 4, 1, 1], params).is_err());
+        let dst = cols
+            .im2col_back(kernel.clone(), &shape![3, 3, 1, 1], params)
+            .expect("im2col_back should be created");
+
+        buffer.init_tensor(cols.clone(), 0).unwrap();
+        buffer.init_tensor(kernel.clone(), 64).unwrap();
+        buffer.init_tensor(dst.clone(), 128).unwrap();
+
+        let mut ones = vec![0; cols.nbytes()];
+        ones.chunks_mut(4).for_each(|chunk| chunk.copy_from_slice(&1.0f32.to_ne_bytes()));
+        buffer.write(cols.clone(), &mut ones, 0, 64).unwrap();
+
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, dst.tensor_id(), false).unwrap();
+        backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+
+        let mut output = vec![0; dst.nbytes()];
+        buffer.read(dst, &mut output, 0, 36).unwrap();
+        // each input pixel receives one unit per kernel position covering it
+        assert_eq!(decode_f32(&output), vec![1.0, 2.0, 1.0, 2.0, 4.0, 2.0, 1.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn graph_compute_get_rows_back_f32() {
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(128, BackendBufferUsage::Any)
+            .expect("CPU buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut grad = ctx.new_tensor(DataType::F32, &shape![2, 3]).unwrap();
+        let rows = ctx.new_tensor(DataType::I32, &shape![3]).unwrap();
+        let like = ctx.new_tensor(DataType::F32, &shape![2, 4]).unwrap();
+        mark_as_leaf(&grad);
+        mark_as_leaf(&rows);
+
+        assert!(grad.get_rows_back(like.clone(), &like).is_err());
+        let dst = grad.get_rows_back(rows.clone(), &like).expect("get_rows_back should be created");
+        assert_eq!(&*dst.shape(), &shape![2, 4]);
+
+        buffer.init_tensor(grad.clone(), 0).unwrap();
+        buffer.init_tensor(rows.clone(), 32).unwrap();
+        buffer.init_tensor(dst.clone(), 64).unwrap();
+
+        for (i, row) in [2, 0, 2].into_iter().enumerate() {
+            rows.set_i32_nd(&[i], row).unwrap();
+            grad.set_f32_nd(&[0, i], (i * 2 + 1) as f32).unwrap();
+            grad.set_f32_nd(&[1, i], (i * 2 + 2) as f32).unwrap();
+        }
+
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, dst.tensor_id(), false).unwrap();
+        backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+
+        let mut output = vec![0; dst.nbytes()];
+        buffer.read(dst, &mut output, 0, 32).unwrap();
+        assert_eq!(decode_f32(&output), vec![3.0, 4.0, 0.0, 0.0, 6.0, 8.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn graph_compute_diag_mask_inf_f32() {
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(128, BackendBufferUsage::Any)
+            .expect("CPU buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut scores = ctx.new_tensor(DataType::F32, &shape![4, 3]).unwrap();
+        mark_as_leaf(&scores);
+
+        let mut masked = scores.diag_mask_inf(1).expect("diag_mask_inf should be created");
+
+        buffer.init_tensor(scores.clone(), 0).unwrap();
+        buffer.init_tensor(masked.clone(), 64).unwrap();
+        let inplace = masked.diag_mask_inf_inplace(0).expect("inplace mask should be created");
+
+        let mut input = encode_f32(&(1..=12).map(|value| value as f32).collect::<Vec<_>>());
+        buffer.write(scores.clone(), &mut input, 0, 48).unwrap();
+
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, masked.tensor_id(), false).unwrap();
+        backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+
+        let inf = f32::NEG_INFINITY;
+        let mut output = vec![0; masked.nbytes()];
+        buffer.read(masked.clone(), &mut output, 0, 48).unwrap();
+        #[rustfmt::skip]
+        assert_eq!(
+            decode_f32(&output),
+            vec![
+                1.0, 2.0, inf, inf,
+                5.0, 6.0, 7.0, inf,
+                9.0, 10.0, 11.0, 12.0,
+            ]
+        );
+
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, inplace.tensor_id(), false).unwrap();
+        backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+
+        buffer.read(masked, &mut output, 0, 48).unwrap();
+        assert_eq!(&decode_f32(&output)[..4], &[1.0, inf, inf, inf]);
+        assert_eq!(&decode_f32(&output)[4..8], &[5.0, 6.0, inf, inf]);
+    }
+
+    #[test]
+    fn graph_compute_group_norm_f32() {
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(256, BackendBufferUsage::Any)
+            .expect("CPU buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut input = ctx.new_tensor(DataType::F32, &shape![2, 1, 4, 1]).unwrap();
+        let mut grad = ctx.new_tensor(DataType::F32, &shape![2, 1, 4, 1]).unwrap();
+        mark_as_leaf(&input);
+        mark_as_leaf(&grad);
+
+        assert!(input.group_norm(3, 1e-5).is_err());
+        let eps = 1e-5;
+        let dst = input.group_norm(2, eps).expect("group_norm should be created");
+        let dx = grad.group_norm_back(input.clone(), 2, eps).expect("backward should be created");
+
+        buffer.init_tensor(input.clone(), 0).unwrap();
+        buffer.init_tensor(grad.clone(), 32).unwrap();
+        buffer.init_tensor(dst.clone(), 64).unwrap();
+        buffer.init_tensor(dx.clone(), 96).unwrap();
+
+        let x = [1.0, 2.0, 4.0, 7.0, -3.0, 0.5, 2.0, 2.5];
+        let dy = [0.5, -1.0, 2.0, 0.25, 1.0, -2.0, 0.0, 3.0];
+        buffer.write(input.clone(), &mut encode_f32(&x), 0, 32).unwrap();
+        buffer.write(grad.clone(), &mut encode_f32(&dy), 0, 32).unwrap();
+
+        for id in [dst.tensor_id(), dx.tensor_id()] {
+            let mut graph = ComputeGraph::new();
+            graph.build_forward(&ctx, id, false).unwrap();
+            backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+        }
+
+        let group_norm = |x: &[f32]| -> Vec<f32> {
+            x.chunks(4)
+                .flat_map(|group| {
+                    let mean = group.iter().sum::<f32>() / 4.0;
+                    let var = group.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / 4.0;
+                    group.iter().map(move |v| (v - mean) / (var + eps).sqrt())
+                })
+                .collect()
+        };
+
+        let mut output = vec![0; 32];
+        buffer.read(dst, &mut output, 0, 32).unwrap();
+        for (got, expected) in decode_f32(&output).iter().zip(group_norm(&x)) {
+            assert!((got - expected).abs() < 1e-5, "{got} != {expected}");
+        }
+
+        // compare against central differences of sum(dy * group_norm(x))
+        let loss = |x: &[f32]| group_norm(x).iter().zip(dy).map(|(y, dy)| y * dy).sum::<f32>();
+        buffer.read(dx, &mut output, 0, 32).unwrap();
+        for (i, got) in decode_f32(&output).into_iter().enumerate() {
+            let h = 1e-2;
+            let (mut plus, mut minus) = (x, x);
+            plus[i] += h;
+            minus[i] -= h;
+            let expected = (loss(&plus) - loss(&minus)) / (2.0 * h);
+            assert!((got - expected).abs() < 1e-2, "dx[{i}]: {got} != {expected}");
+        }
+    }
+
+    #[test]
+    fn graph_compute_conv_f32() {
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(512, BackendBufferUsage::Any)
+            .expect("CPU buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let mut kernel_1d = ctx.new_tensor(DataType::F32, &shape![3, 1, 1]).unwrap();
+        let mut kernel_t1d = ctx.new_tensor(DataType::F32, &shape![2, 1, 1]).unwrap();
+        let mut kernel_t2d = ctx.new_tensor(DataType::F32, &shape![2, 2, 1, 1]).unwrap();
+        let signal = ctx.new_tensor(DataType::F32, &shape![5, 1, 1]).unwrap();
+        let short = ctx.new_tensor(DataType::F32, &shape![3, 1, 1]).unwrap();
+        let image = ctx.new_tensor(DataType::F32, &shape![2, 2, 1, 1]).unwrap();
+        for tensor in [&kernel_1d, &kernel_t1d, &kernel_t2d, &signal, &short, &image] {
+            mark_as_leaf(tensor);
+        }
+
+        assert!(kernel_1d.conv_1d(image.clone(), 1, 0, 1).is_err());
+        let conv = kernel_1d.conv_1d(signal.clone(), 1, 1, 1).unwrap();
+        let conv_t1d = kernel_t1d.conv_transpose_1d(short.clone(), 2, 0, 1).unwrap();
+        let conv_t2d = kernel_t2d.conv_transpose_2d(image.clone(), [1, 1], [0, 0]).unwrap();
+        let cropped = kernel_t2d.conv_transpose_2d(image.clone(), [1, 1], [1, 1]).unwrap();
+        assert_eq!(&*conv.shape(), &shape![5, 1, 1]);
+        assert_eq!(&*conv_t1d.shape(), &shape![6, 1, 1]);
+        assert_eq!(&*conv_t2d.shape(), &shape![3, 3, 1, 1]);
+        assert_eq!(&*cropped.shape(), &shape![1, 1, 1, 1]);
+
+        let inputs: [(&Tensor, &[f32]); 6] = [
+            (&kernel_1d, &[1.0, 0.0, -1.0]),
+            (&kernel_t1d, &[1.0, 2.0]),
+            (&kernel_t2d, &[1.0; 4]),
+            (&signal, &[1.0, 2.0, 3.0, 4.0, 5.0]),
+            (&short, &[1.0, 2.0, 3.0]),
+            (&image, &[1.0, 2.0, 3.0, 4.0]),
+        ];
+        let mut offset = 0;
+        for (tensor, values) in inputs {
+            buffer.init_tensor(tensor.clone(), offset).unwrap();
+            buffer.write(tensor.clone(), &mut encode_f32(values), 0, values.len() * 4).unwrap();
+            offset += 32;
+        }
+
+        let expected: [(&Tensor, &[f32]); 4] = [
+            (&conv, &[-2.0, -2.0, -2.0, -2.0, 4.0]),
+            (&conv_t1d, &[1.0, 2.0, 2.0, 4.0, 3.0, 6.0]),
+            (&conv_t2d, &[1.0, 3.0, 2.0, 4.0, 10.0, 6.0, 3.0, 7.0, 4.0]),
+            (&cropped, &[10.0]),
+        ];
+        for (tensor, values) in expected {
+            buffer.init_tensor(tensor.clone(), offset).unwrap();
+            offset += 64;
+
+            let mut graph = ComputeGraph::new();
+            graph.build_forward(&ctx, tensor.tensor_id(), false).unwrap();
+            backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+
+            let mut output = vec![0; tensor.nbytes()];
+            buffer.read(tensor.clone(), &mut output, 0, values.len() * 4).unwrap();
+            assert_eq!(decode_f32(&output), values);
+        }
+    }
+
+    #[test]
+    fn graph_compute_upscale_f32() {
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(256, BackendBufferUsage::Any)
+            .expect("CPU buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut image = ctx.new_tensor(DataType::F32, &shape![2, 2]).unwrap();
+        mark_as_leaf(&image);
+
+        let uneven = image.upscale([1.5, 0.5], UpscaleMode::Nearest).unwrap();
+        assert_eq!(&*uneven.shape(), &shape![3, 1]);
+        assert!(image.upscale([0.1, 1.0], UpscaleMode::Nearest).is_err());
+
+        let nearest = image.upscale([2.0, 2.0], UpscaleMode::Nearest).unwrap();
+        let bilinear = image.upscale([2.0, 2.0], UpscaleMode::Bilinear).unwrap();
+        assert_eq!(&*bilinear.shape(), &shape![4, 4]);
+
+        buffer.init_tensor(image.clone(), 0).unwrap();
+        buffer.init_tensor(nearest.clone(), 64).unwrap();
+        buffer.init_tensor(bilinear.clone(), 128).unwrap();
+        buffer.write(image.clone(), &mut encode_f32(&[1.0, 2.0, 3.0, 4.0]), 0, 16).unwrap();
+
+        #[rustfmt::skip]
+        let expected: [(&Tensor, [f32; 16]); 2] = [
+            (&nearest, [
+                1.0, 1.0, 2.0, 2.0,
+                1.0, 1.0, 2.0, 2.0,
+                3.0, 3.0, 4.0, 4.0,
+                3.0, 3.0, 4.0, 4.0,
+            ]),
+            (&bilinear, [
+                1.0, 1.25, 1.75, 2.0,
+                1.5, 1.75, 2.25, 2.5,
+                2.5, 2.75, 3.25, 3.5,
+                3.0, 3.25, 3.75, 4.0,
+            ]),
+        ];
+        for (tensor, values) in expected {
+            let mut graph = ComputeGraph::new();
+            graph.build_forward(&ctx, tensor.tensor_id(), false).unwrap();
+            backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+
+            let mut output = vec![0; tensor.nbytes()];
+            buffer.read(tensor.clone(), &mut output, 0, 64).unwrap();
+            assert_eq!(decode_f32(&output), values);
+        }
+    }
+
+    #[test]
+    fn graph_compute_map_unary_binary_f32() {
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(128, BackendBufferUsage::Any)
+            .expect("CPU buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut lhs = ctx.new_tensor(DataType::F32, &shape![3, 2]).unwrap();
+        let rhs = ctx.new_tensor(DataType::F32, &shape![3, 2]).unwrap();
+        let other = ctx.new_tensor(DataType::F32, &shape![2, 3]).unwrap();
+        mark_as_leaf(&lhs);
+        mark_as_leaf(&rhs);
+
+        assert!(lhs.map_binary(other, |a, b| a + b).is_err());
+        let mut squared = lhs.map_unary(|x| x * x).expect("map_unary should be created");
+        let dst =
+            squared.map_binary(rhs.clone(), |a, b| a.max(b)).expect("map_binary should be created");
+
+        buffer.init_tensor(lhs.clone(), 0).unwrap();
+        buffer.init_tensor(rhs.clone(), 32).unwrap();
+        buffer.init_tensor(squared.clone(), 64).unwrap();
+        buffer.init_tensor(dst.clone(), 96).unwrap();
+        buffer.write(lhs, &mut encode_f32(&[1.0, -2.0, 3.0, -4.0, 0.5, 6.0]), 0, 24).unwrap();
+        buffer.write(rhs, &mut encode_f32(&[2.0, 2.0, 2.0, 20.0, 20.0, 20.0]), 0, 24).unwrap();
+
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, dst.tensor_id(), false).unwrap();
+        backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+
+        let mut output = vec![0; dst.nbytes()];
+        buffer.read(dst, &mut output, 0, 24).unwrap();
+        assert_eq!(decode_f32(&output), vec![2.0, 4.0, 9.0, 20.0, 20.0, 36.0]);
+    }
+
+    #[test]
+    fn graph_compute_mul_mat_batched_and_transposed_f32() {
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(256, BackendBufferUsage::Any)
+            .expect("CPU buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut lhs = ctx.new_tensor(DataType::F32, &shape![2, 2, 1]).unwrap();
+        let rhs = ctx.new_tensor(DataType::F32, &shape![2, 2, 2]).unwrap();
+        let odd = ctx.new_tensor(DataType::F32, &shape![3, 2, 2]).unwrap();
+        mark_as_leaf(&lhs);
+        mark_as_leaf(&rhs);
+
+        assert!(lhs.mul_mat(odd).is_err());
+        let batched = lhs.mul_mat(rhs.clone()).expect("mul_mat should be created");
+        let mut transposed = lhs.transpose().expect("transpose should be created");
+        let strided = transposed.mul_mat(rhs.clone()).expect("mul_mat should be created");
+        assert_eq!(&*batched.shape(), &shape![2, 2, 2]);
+        assert_eq!(transposed.stride()[0], lhs.stride()[1]);
+
+        buffer.init_tensor(lhs.clone(), 0).unwrap();
+        buffer.init_tensor(rhs.clone(), 32).unwrap();
+        buffer.init_tensor(transposed.clone(), 0).unwrap();
+        buffer.init_tensor(batched.clone(), 64).unwrap();
+        buffer.init_tensor(strided.clone(), 128).unwrap();
+        buffer.write(lhs, &mut encode_f32(&[1.0, 2.0, 3.0, 4.0]), 0, 16).unwrap();
+        let mut input = encode_f32(&[1.0, 0.0, 0.0, 1.0, 1.0, 1.0, 2.0, 0.0]);
+        buffer.write(rhs, &mut input, 0, 32).unwrap();
+
+        let expected = [
+            (&batched, [1.0, 3.0, 2.0, 4.0, 3.0, 7.0, 2.0, 6.0]),
+            (&strided, [1.0, 2.0, 3.0, 4.0, 4.0, 6.0, 2.0, 4.0]),
+        ];
+        for (tensor, values) in expected {
+            let mut graph = ComputeGraph::new();
+            graph.build_forward(&ctx, tensor.tensor_id(), false).unwrap();
+            backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+
+            let mut output = vec![0; tensor.nbytes()];
+            buffer.read(tensor.clone(), &mut output, 0, 32).unwrap();
+            assert_eq!(decode_f32(&output), values);
+        }
+    }
+
+    #[test]
+    fn graph_compute_grouped_query_attention_f32() {
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(512, BackendBufferUsage::Any)
+            .expect("CPU buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let mut cache = KvCache::new(&mut ctx, DataType::F32, 1, 4, 1, 2).unwrap();
+        let mut q = ctx.new_tensor(DataType::F32, &shape![2, 1, 2, 1]).unwrap();
+        let mask = ctx.new_tensor(DataType::F32, &shape![2, 1]).unwrap();
+
+        let mut offset = 0;
+        for tensor in cache.tensors().chain([&q, &mask]) {
+            mark_as_leaf(tensor);
+            buffer.init_tensor(tensor.clone(), offset).unwrap();
+            offset += 64;
+        }
+        let mut keys = encode_f32(&[1.0, 0.0, 0.0, 1.0]);
+        let mut values = encode_f32(&[1.0, 2.0, 3.0, 4.0]);
+        buffer.write(cache.keys(0).unwrap().clone(), &mut keys, 0, 16).unwrap();
+        buffer.write(cache.values(0).unwrap().clone(), &mut values, 0, 16).unwrap();
+        buffer.write(q.clone(), &mut encode_f32(&[0.0, 0.0, 1.0, 0.0]), 0, 16).unwrap();
+        buffer.write(mask.clone(), &mut encode_f32(&[0.0, f32::NEG_INFINITY]), 0, 8).unwrap();
+        cache.advance(2).unwrap();
+
+        let (k, v) = cache.history(0).unwrap();
+        let mut wide = ctx.new_tensor(DataType::F32, &shape![3, 1, 2, 1]).unwrap();
+        assert!(wide.attention(k.clone(), v.clone(), None, 1.0).is_err());
+        assert!(q.attention(k.clone(), v.clone(), Some(wide.clone()), 1.0).is_err());
+
+        let unmasked = q.attention(k.clone(), v.clone(), None, 1.0).unwrap();
+        let masked = q.attention(k, v, Some(mask), 1.0).unwrap();
+        assert_eq!(&*unmasked.shape(), &shape![2, 1, 2, 1]);
+
+        let weight = 1.0f32.exp() / (1.0f32.exp() + 1.0);
+        let expected = [
+            (&unmasked, [2.0, 3.0, 3.0 - 2.0 * weight, 4.0 - 2.0 * weight]),
+            (&masked, [1.0, 2.0, 1.0, 2.0]),
+        ];
+        for (tensor, values) in expected {
+            buffer.init_tensor(tensor.clone(), offset).unwrap();
+            offset += 64;
+
+            let mut graph = ComputeGraph::new();
+            graph.build_forward(&ctx, tensor.tensor_id(), false).unwrap();
+            backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+
+            let mut output = vec![0; tensor.nbytes()];
+            buffer.read(tensor.clone(), &mut output, 0, 16).unwrap();
+            for (got, expected) in decode_f32(&output).iter().zip(values) {
+                assert!((got - expected).abs() < 1e-6, "{got} != {expected}");
+            }
+        }
+    }
+
+    #[test]
+    fn graph_compute_einsum_f32() {
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(2048, BackendBufferUsage::Any)
+            .expect("CPU buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(64).build();
+        // row-major 2x3 and 3x2 matrices, stored innermost dimension first
+        let a = ctx.new_tensor(DataType::F32, &shape![3, 2]).unwrap();
+        let b = ctx.new_tensor(DataType::F32, &shape![2, 3]).unwrap();
+        let batch_a = ctx.new_tensor(DataType::F32, &shape![3, 2, 2]).unwrap();
+        let batch_b = ctx.new_tensor(DataType::F32, &shape![2, 3, 2]).unwrap();
+        let u = ctx.new_tensor(DataType::F32, &shape![2]).unwrap();
+        let v = ctx.new_tensor(DataType::F32, &shape![3]).unwrap();
+
+        let inputs: [(&Tensor, Vec<f32>); 6] = [
+            (&a, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]),
+            (&b, vec![1.0, 0.0, 0.0, 1.0, 1.0, 1.0]),
+            (&batch_a, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]),
+            (&batch_b, vec![1.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0]),
+            (&u, vec![1.0, 2.0]),
+            (&v, vec![3.0, 4.0, 5.0]),
+        ];
+        let mut offset = 0;
+        for (tensor, values) in &inputs {
+            mark_as_leaf(tensor);
+            buffer.init_tensor((*tensor).clone(), offset).unwrap();
+            buffer.write((*tensor).clone(), &mut encode_f32(values), 0, values.len() * 4).unwrap();
+            offset += 64;
+        }
+
+        assert!(einsum("ij,jk->ik", &[a.clone(), a.clone()]).is_err());
+        assert!(einsum("ijk,jk->ik", &[a.clone(), b.clone()]).is_err());
+        assert!(einsum("ij->i", std::slice::from_ref(&a)).is_err());
+
+        let cases = [
+            (
+                einsum("ij,jk->ik", &[a.clone(), b.clone()]),
+                shape![2, 2],
+                vec![4.0, 5.0, 10.0, 11.0],
+            ),
+            (einsum("ij,jk", &[a.clone(), b.clone()]), shape![2, 2], vec![4.0, 5.0, 10.0, 11.0]),
+            (
+                einsum("bij,bjk->bik", &[batch_a, batch_b]),
+                shape![2, 2, 2],
+                vec![4.0, 5.0, 10.0, 11.0, 1.0, 2.0, 3.0, 4.0],
+            ),
+            (einsum("ij->ji", &[a]), shape![2, 3], vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0]),
+            (einsum("i,j->ij", &[u, v]), shape![3, 2], vec![3.0, 4.0, 5.0, 6.0, 8.0, 10.0]),
+        ];
+
+        for (result, shape, expected) in cases {
+            let result = result.expect("einsum should build");
+            assert_eq!(&*result.shape(), &shape);
+
+            let mut graph = ComputeGraph::new();
+            graph.build_forward(&ctx, result.tensor_id(), false).unwrap();
+            for id in graph.nodes().iter() {
+                let node = ctx.get_tensor(*id).unwrap();
+                if node.tensor_type() != TensorType::FlagParam {
+                    buffer.init_tensor(node.clone(), offset).unwrap();
+                    offset += node.nbytes().next_multiple_of(32);
+                }
+            }
+            backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+
+            let mut output = vec![0; result.nbytes()];
+            buffer.read(result.clone(), &mut output, 0, expected.len() * 4).unwrap();
+            assert_eq!(decode_f32(&output), expected);
+        }
+    }
+
+    fn map_chain(ctx: &mut Context, len: usize) -> (Tensor, Tensor) {
+        let input = ctx.new_tensor(DataType::F32, &shape![4]).unwrap();
+        mark_as_leaf(&input);
+
+        let mut output = input.clone();
+        for _ in 0..len {
+            output = output.map_unary(|x| x + 1.0).unwrap();
+        }
+        (input, output)
+    }
+
+    #[test]
+    fn graph_compute_on_streams_with_events() {
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(256, BackendBufferUsage::Any)
+            .expect("CPU buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let (prefill_in, prefill_out) = map_chain(&mut ctx, 2);
+        let (decode_in, decode_out) = map_chain(&mut ctx, 1);
+        let mut prefill = ComputeGraph::new();
+        prefill.build_forward(&ctx, prefill_out.tensor_id(), false).unwrap();
+        let mut decode = ComputeGraph::new();
+        decode.build_forward(&ctx, decode_out.tensor_id(), false).unwrap();
+
+        let mut tensors = vec![prefill_in.clone(), decode_in.clone()];
+        for id in prefill.nodes().iter().chain(decode.nodes().iter()) {
+            tensors.push(ctx.get_tensor(*id).unwrap());
+        }
+        for (i, tensor) in tensors.into_iter().enumerate() {
+            buffer.init_tensor(tensor, i * 16).unwrap();
+        }
+        buffer.write(prefill_in, &mut encode_f32(&[0.0, 1.0, 2.0, 3.0]), 0, 16).unwrap();
+        buffer.write(decode_in, &mut encode_f32(&[10.0; 4]), 0, 16).unwrap();
+
+        let first = backend.new_stream().expect("CPU stream should be created");
+        let second = backend.new_stream().expect("CPU stream should be created");
+        let prefill_done = backend.new_event().expect("CPU event should be created");
+
+        first.graph_compute(&ctx, &mut prefill).unwrap();
+        first.record_event(prefill_done.as_ref()).unwrap();
+        second.wait_event(prefill_done.as_ref()).unwrap();
+        second.graph_compute(&ctx, &mut decode).unwrap();
+        prefill_done.synchronize().unwrap();
+        assert!(prefill_done.is_complete().unwrap());
+        second.synchronize().unwrap();
+
+        let mut bytes = vec![0; 16];
+        buffer.read(prefill_out, &mut bytes, 0, 16).unwrap();
+        assert_eq!(decode_f32(&bytes), vec![2.0, 3.0, 4.0, 5.0]);
+        buffer.read(decode_out, &mut bytes, 0, 16).unwrap();
+        assert_eq!(decode_f32(&bytes), vec![11.0; 4]);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap_buffer_prefetch_and_drop() {
+        use feml::backend::MemoryAdvice;
+        use feml::cpu::backend::CpuBackend;
+
+        let values: Vec<f32> = (0..2048).map(|i| i as f32).collect();
+        let path = std::env::temp_dir().join(format!("feml-mmap-{}.bin", std::process::id()));
+        std::fs::write(&path, encode_f32(&values)).unwrap();
+
+        let backend = CpuBackend::init().expect("CPU backend should open");
+        let buffer = backend
+            .buffer_from_file(&path, BackendBufferUsage::Weights)
+            .expect("mmap buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(4).build();
+        let first = ctx.new_tensor(DataType::F32, &shape![1024]).unwrap();
+        let second = ctx.new_tensor(DataType::F32, &shape![1024]).unwrap();
+        buffer.init_tensor(first.clone(), 0).unwrap();
+        buffer.init_tensor(second.clone(), 4096).unwrap();
+
+        second.advise(MemoryAdvice::WillNeed).unwrap();
+        second.advise(MemoryAdvice::Touch).unwrap();
+        let mut bytes = vec![0; 4096];
+        buffer.read(second.clone(), &mut bytes, 0, 4096).unwrap();
+        assert_eq!(decode_f32(&bytes), values[1024..]);
+
+        buffer.write(first.clone(), &mut encode_f32(&[-1.0]), 0, 4).unwrap();
+        first.advise(MemoryAdvice::DontNeed).unwrap();
+        buffer.read(first, &mut bytes, 0, 4096).unwrap();
+        assert_eq!(decode_f32(&bytes), values[..1024]);
+        assert_eq!(std::fs::read(&path).unwrap(), encode_f32(&values));
+
+        buffer.write(second.clone(), &mut encode_f32(&[-1.0]), 0, 4).unwrap();
+        buffer.reset().unwrap();
+        buffer.init_tensor(second.clone(), 4096).unwrap();
+        buffer.read(second, &mut bytes, 0, 4096).unwrap();
+        assert_eq!(decode_f32(&bytes), values[1024..]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap_drop_keeps_shared_pages() {
+        use feml::backend::MemoryAdvice;
+        use feml::cpu::backend::CpuBackend;
+
+        let values: Vec<f32> = (0..2048).map(|i| i as f32).collect();
+        let path =
+            std::env::temp_dir().join(format!("feml-mmap-shared-{}.bin", std::process::id()));
+        std::fs::write(&path, encode_f32(&values)).unwrap();
+
+        let backend = CpuBackend::init().expect("CPU backend should open");
+        let buffer = backend
+            .buffer_from_file(&path, BackendBufferUsage::Weights)
+            .expect("mmap buffer should be created");
+
+        // `first` fills half of page 0; `second` covers the other half and all of page 1.
+        let mut ctx = Context::builder().tensor_pool_capacity(4).build();
+        let first = ctx.new_tensor(DataType::F32, &shape![512]).unwrap();
+        let second = ctx.new_tensor(DataType::F32, &shape![1536]).unwrap();
+        buffer.init_tensor(first.clone(), 0).unwrap();
+        buffer.init_tensor(second.clone(), 2048).unwrap();
+
+        buffer.write(first.clone(), &mut encode_f32(&[-1.0]), 0, 4).unwrap();
+        buffer.write(second.clone(), &mut encode_f32(&[-2.0]), 0, 4).unwrap();
+        buffer.write(second.clone(), &mut encode_f32(&[-3.0]), 2048, 4).unwrap();
+        second.advise(MemoryAdvice::DontNeed).unwrap();
+        first.advise(MemoryAdvice::DontNeed).unwrap();
+
+        let mut bytes = vec![0; 4];
+        buffer.read(first, &mut bytes, 0, 4).unwrap();
+        assert_eq!(decode_f32(&bytes), vec![-1.0]);
+        buffer.read(second.clone(), &mut bytes, 0, 4).unwrap();
+        assert_eq!(decode_f32(&bytes), vec![-2.0]);
+        buffer.read(second, &mut bytes, 2048, 4).unwrap();
+        assert_eq!(decode_f32(&bytes), vec![1024.0]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn backend_buffer_reset_unbinds_tensors() {
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(64, BackendBufferUsage::Compute)
+            .expect("CPU buffer should be created");
+        let other = backend
+            .create_buffer(64, BackendBufferUsage::Compute)
+            .expect("CPU buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(4).build();
+        let tensor = ctx.new_tensor(DataType::F32, &shape![4]).unwrap();
+        let view = ctx.new_tensor_view(tensor.clone()).unwrap();
+        buffer.init_tensor(tensor.clone(), 16).unwrap();
+        assert!(other.init_tensor(view.clone(), 0).is_err());
+        buffer.init_tensor(view.clone(), 0).unwrap();
+        buffer.write(tensor.clone(), &mut encode_f32(&[1.0, 2.0, 3.0, 4.0]), 0, 16).unwrap();
+
+        buffer.reset().unwrap();
+        let mut bytes = vec![0; 16];
+        assert!(buffer.read(tensor.clone(), &mut bytes, 0, 16).is_err());
+        assert!(buffer.read(view, &mut bytes, 0, 16).is_err());
+
+        buffer.init_tensor(tensor.clone(), 16).unwrap();
+        buffer.read(tensor, &mut bytes, 0, 16).unwrap();
+        assert_eq!(decode_f32(&bytes), vec![0.0; 4]);
+    }
+
+    #[test]
+    fn backend_copy_tensor_between_buffers() {
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let src_buffer = backend.create_buffer(16, BackendBufferUsage::Any).unwrap();
+        let dst_buffer = backend.create_buffer(16, BackendBufferUsage::Any).unwrap();
+
+        let mut ctx = Context::builder().tensor_pool_capacity(4).build();
+        let src = ctx.new_tensor(DataType::F32, &shape![4]).unwrap();
+        let dst = ctx.new_tensor(DataType::F32, &shape![4]).unwrap();
+        let small = ctx.new_tensor(DataType::F32, &shape![2]).unwrap();
+        src_buffer.init_tensor(src.clone(), 0).unwrap();
+        dst_buffer.init_tensor(dst.clone(), 0).unwrap();
+        dst_buffer.init_tensor(small.clone(), 0).unwrap();
+        src_buffer.write(src.clone(), &mut encode_f32(&[1.0, 2.0, 3.0, 4.0]), 0, 16).unwrap();
+
+        copy_tensor(&src, &dst).expect("copy between CPU buffers should succeed");
+        let mut bytes = vec![0; 16];
+        dst_buffer.read(dst, &mut bytes, 0, 16).unwrap();
+        assert_eq!(decode_f32(&bytes), vec![1.0, 2.0, 3.0, 4.0]);
+        assert!(copy_tensor(&src, &small).is_err());
+    }
+
+    #[test]
+    fn cpu_compute_plan_reuse_and_update() {
+        use feml::backend::Backend;
+        use feml::cpu::backend::CpuBackend;
+        use feml::cpu::kernels::Isa;
+
+        let backend = CpuBackend::init().expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(256, BackendBufferUsage::Any)
+            .expect("CPU buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let (input, output) = map_chain(&mut ctx, 2);
+        let graph = ComputeGraph::new();
+        graph.build_forward(&ctx, output.tensor_id(), false).unwrap();
+        buffer.init_tensor(input.clone(), 0).unwrap();
+        for (i, id) in graph.nodes().iter().enumerate() {
+            buffer.init_tensor(ctx.get_tensor(*id).unwrap(), (i + 1) * 16).unwrap();
+        }
+
+        let mut plan = backend.plan_create(&ctx, &graph).unwrap();
+        assert_eq!(plan.n_nodes(), 2);
+        assert_eq!(plan.graph_id(), graph.id());
+        assert_eq!(plan.kernel(0), Some(("map_unary", Isa::Scalar)));
+        assert_eq!(plan.kernel(2), None);
+
+        let mut bytes = vec![0; 16];
+        for start in [0.0, 10.0] {
+            let values = [start, start + 1.0, start + 2.0, start + 3.0];
+            buffer.write(input.clone(), &mut encode_f32(&values), 0, 16).unwrap();
+            backend.plan_compute(&plan).unwrap();
+            buffer.read(output.clone(), &mut bytes, 0, 16).unwrap();
+            assert_eq!(decode_f32(&bytes), values.map(|x| x + 2.0));
+        }
+
+        let rebuilt = ComputeGraph::new();
+        rebuilt.build_forward(&ctx, output.tensor_id(), false).unwrap();
+        backend.plan_update(&mut plan, &rebuilt).unwrap();
+        assert_eq!(plan.graph_id(), rebuilt.id());
+
+        let (_, longer) = map_chain(&mut ctx, 3);
+        let changed = ComputeGraph::new();
+        changed.build_forward(&ctx, longer.tensor_id(), false).unwrap();
+        assert!(backend.plan_update(&mut plan, &changed).is_err());
+    }
+
+    #[test]
+    fn cpu_compute_plan_sizes_its_work_buffer() {
+        use feml::backend::Backend;
+        use feml::cpu::backend::CpuBackend;
+        use feml::cpu::compute_plan::work_size_for;
+
+        let backend = CpuBackend::init().expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(256, BackendBufferUsage::Any)
+            .expect("CPU buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let mut a = ctx.new_tensor(DataType::F32, &shape![2, 3]).unwrap();
+        let b = ctx.new_tensor(DataType::F32, &shape![2, 2]).unwrap();
+        mark_as_leaf(&a);
+        mark_as_leaf(&b);
+        let product = a.mul_mat(b.clone()).unwrap();
+        for (tensor, offset) in [(&a, 0), (&b, 32), (&product, 64)] {
+            buffer.init_tensor(tensor.clone(), offset).unwrap();
+        }
+        buffer.write(a.clone(), &mut encode_f32(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]), 0, 24).unwrap();
+        buffer.write(b.clone(), &mut encode_f32(&[1.0, 0.0, 1.0, 1.0]), 0, 16).unwrap();
+
+        let graph = ComputeGraph::new();
+        graph.build_forward(&ctx, product.tensor_id(), false).unwrap();
+        let plan = backend.plan_create(&ctx, &graph).unwrap();
+        assert_eq!(plan.work_size(), 6 * 4);
+        assert_eq!(work_size_for(&product, backend.n_threads()).unwrap(), plan.work_size());
+
+        let mut bytes = vec![0; 24];
+        for _ in 0..2 {
+            backend.plan_compute(&plan).unwrap();
+            buffer.read(product.clone(), &mut bytes, 0, 24).unwrap();
+            assert_eq!(decode_f32(&bytes), vec![1.0, 3.0, 5.0, 3.0, 7.0, 11.0]);
+        }
+        assert_eq!(plan.work_size(), 6 * 4);
+    }
+
+    #[test]
+    fn registry_proc_address_functions() {
+        use feml::registry::BackendFunction;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let mut backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+
+        let set_n_threads = registry.set_n_threads_fn("cpu").expect("CPU exposes set_n_threads");
+        set_n_threads(backend.as_mut(), 4).unwrap();
+        let features = registry.get_features_fn("CPU").expect("CPU exposes get_features")();
+        assert!(features.iter().any(|feature| feature.name == "mmap"));
+        assert!(registry.numa_init_fn("CPU").is_some());
+        assert!(registry.get_proc_address("CPU", "no_such_function").is_none());
+
+        let abort = Arc::new(AtomicBool::new(false));
+        let flag = abort.clone();
+        let set_abort_callback =
+            registry.set_abort_callback_fn("CPU").expect("CPU exposes set_abort_callback");
+        set_abort_callback(backend.as_mut(), Some(Box::new(move || flag.load(Ordering::Relaxed))))
+            .unwrap();
+
+        let buffer = backend.create_buffer(64, BackendBufferUsage::Any).unwrap();
+        let mut ctx = Context::builder().tensor_pool_capacity(4).build();
+        let (input, output) = map_chain(&mut ctx, 1);
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, output.tensor_id(), false).unwrap();
+        buffer.init_tensor(input, 0).unwrap();
+        buffer.init_tensor(output, 16).unwrap();
+        backend.graph_compute(&ctx, &mut graph).expect("compute should run");
+        abort.store(true, Ordering::Relaxed);
+        assert!(backend.graph_compute(&ctx, &mut graph).is_err());
+
+        registry.register_function("CPU", "answer", BackendFunction::Custom(Arc::new(42usize)));
+        assert_eq!(registry.custom_fn::<usize>("cpu", "answer").as_deref(), Some(&42));
+        assert!(registry.custom_fn::<u32>("cpu", "answer").is_none());
+
+        let rediscovered = Registry::discover().expect("registry discover should succeed");
+        assert_eq!(rediscovered.custom_fn::<usize>("CPU", "answer").as_deref(), Some(&42));
+    }
+
+    #[test]
+    fn cpu_backend_threads_and_shared_threadpool() {
+        use feml::cpu::backend::{set_n_threads, set_threadpool, CpuBackend};
+        use feml::threadpool::{ThreadPool, ThreadPoolParams};
+        use std::sync::Arc;
+
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let mut first = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let mut second = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        set_n_threads(first.as_mut(), 3).unwrap();
+        let pool = Arc::new(ThreadPool::new(ThreadPoolParams::new(4)).unwrap());
+        set_threadpool(second.as_mut(), Some(pool.clone())).unwrap();
+
+        let n_threads = |backend: &dyn feml::backend::Backend| {
+            backend.as_any().downcast_ref::<CpuBackend>().unwrap().n_threads()
+        };
+        assert_eq!(n_threads(first.as_ref()), 3);
+        assert_eq!(n_threads(second.as_ref()), 4);
+
+        let buffer = first.create_buffer(1024, BackendBufferUsage::Any).unwrap();
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut lhs = ctx.new_tensor(DataType::F32, &shape![3, 8]).unwrap();
+        let rhs = ctx.new_tensor(DataType::F32, &shape![3, 5]).unwrap();
+        mark_as_leaf(&lhs);
+        mark_as_leaf(&rhs);
+        let product = lhs.mul_mat(rhs.clone()).unwrap();
+        buffer.init_tensor(lhs.clone(), 0).unwrap();
+        buffer.init_tensor(rhs.clone(), 128).unwrap();
+        buffer.init_tensor(product.clone(), 256).unwrap();
+        let lhs_values: Vec<f32> = (0..24).map(|i| i as f32).collect();
+        let rhs_values: Vec<f32> = (0..15).map(|i| (i % 4) as f32).collect();
+        buffer.write(lhs, &mut encode_f32(&lhs_values), 0, 96).unwrap();
+        buffer.write(rhs, &mut encode_f32(&rhs_values), 0, 60).unwrap();
+
+        let expected: Vec<f32> = (0..5)
+            .flat_map(|n| {
+                let (lhs_values, rhs_values) = (&lhs_values, &rhs_values);
+                (0..8).map(move |m| {
+                    (0..3).map(|k| lhs_values[m * 3 + k] * rhs_values[n * 3 + k]).sum()
+                })
+            })
+            .collect();
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, product.tensor_id(), false).unwrap();
+        for backend in [&first, &second] {
+            buffer.fill(product.clone(), 0, 0, 160).unwrap();
+            backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+            let mut bytes = vec![0; 160];
+            buffer.read(product.clone(), &mut bytes, 0, 160).unwrap();
+            assert_eq!(decode_f32(&bytes), expected);
+        }
+
+        set_threadpool(second.as_mut(), None).unwrap();
+        assert_eq!(n_threads(second.as_ref()), 1);
+    }
+
+    #[test]
+    fn cpu_mul_mat_gemm_blocking_matches_reference() {
+        use feml::backend::Backend;
+        use feml::cpu::autotune::GemmBlocking;
+        use feml::cpu::backend::CpuBackend;
+
+        let (k, m, n) = (37, 19, 23);
+        let mut backend = CpuBackend::init().expect("CPU backend should open");
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut lhs = ctx.new_tensor(DataType::F32, &shape![k, m]).unwrap();
+        let rhs = ctx.new_tensor(DataType::F32, &shape![k, n, 2]).unwrap();
+        mark_as_leaf(&lhs);
+        mark_as_leaf(&rhs);
+        let dst = lhs.mul_mat(rhs.clone()).unwrap();
+
+        let buffer = backend.create_buffer(16384, BackendBufferUsage::Any).unwrap();
+        buffer.init_tensor(lhs.clone(), 0).unwrap();
+        buffer.init_tensor(rhs.clone(), 4096).unwrap();
+        buffer.init_tensor(dst.clone(), 12288).unwrap();
+        let a: Vec<f32> = (0..k * m).map(|i| (i % 7) as f32 - 3.0).collect();
+        let b: Vec<f32> = (0..k * n * 2).map(|i| (i % 5) as f32 - 2.0).collect();
+        buffer.write(lhs, &mut encode_f32(&a), 0, a.len() * 4).unwrap();
+        buffer.write(rhs, &mut encode_f32(&b), 0, b.len() * 4).unwrap();
+
+        let mut expected = Vec::new();
+        for row in b.chunks(k) {
+            for col in a.chunks(k) {
+                expected.push(col.iter().zip(row).map(|(x, y)| x * y).sum::<f32>());
+            }
+        }
+
+        let blockings = [GemmBlocking::default(), GemmBlocking { mc: 4, kc: 8, nc: 3 }];
+        for (blocking, n_threads) in blockings.into_iter().zip([1, 3]) {
+            backend.set_gemm_blocking(blocking).unwrap();
+            backend.set_n_threads(n_threads).unwrap();
+            let mut graph = ComputeGraph::new();
+            graph.build_forward(&ctx, dst.tensor_id(), false).unwrap();
+            backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+
+            let mut output = vec![0; dst.nbytes()];
+            buffer.read(dst.clone(), &mut output, 0, dst.nbytes()).unwrap();
+            assert_eq!(decode_f32(&output), expected);
+        }
+    }
+
+    #[test]
+    fn cpu_compute_plan_disk_cache() {
+        use feml::backend::Backend;
+        use feml::cache::{CacheKind, DiskCache};
+        use feml::cpu::backend::CpuBackend;
+        use feml::cpu::kernels::Isa;
+
+        let dir = std::env::temp_dir().join(format!("feml-plan-cache-{}", std::process::id()));
+        let cache = DiskCache::new(&dir).unwrap();
+        let mut backend = CpuBackend::init().expect("CPU backend should open");
+        backend.set_n_threads(2).unwrap();
+
+        let build = || {
+            let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+            let (input, output) = map_chain(&mut ctx, 3);
+            let buffer = backend.create_buffer(256, BackendBufferUsage::Any).unwrap();
+            let graph = ComputeGraph::new();
+            graph.build_forward(&ctx, output.tensor_id(), false).unwrap();
+            buffer.init_tensor(input.clone(), 0).unwrap();
+            for (i, id) in graph.nodes().iter().enumerate() {
+                let tensor = ctx.get_tensor(*id).unwrap();
+                buffer.init_tensor(tensor, 16 * (i + 1)).unwrap();
+            }
+            buffer.write(input, &mut encode_f32(&[1.0, 2.0, 3.0, 4.0]), 0, 16).unwrap();
+            (ctx, graph, buffer, output)
+        };
+
+        let (ctx, graph, _buffer, _) = build();
+        let plan = backend.plan_create_cached(&ctx, &graph, &cache).unwrap();
+        let isa = if backend.reference_kernels() { Isa::Scalar } else { Isa::Avx512 };
+        let key = format!("{:016x}-{}", graph.fingerprint(&ctx).unwrap(), isa.name());
+        let stored = cache.load(CacheKind::Plan, "cpu", &key).unwrap().expect("plan is stored");
+        assert_eq!(stored.iter().filter(|&&byte| byte == b'\n').count(), plan.n_nodes());
+
+        assert_eq!(stored, b"map_unary scalar\n".repeat(3));
+
+        // A graph built the same way in another context hits the stored entry.
+        let (ctx, graph, buffer, output) = build();
+        let plan = backend.plan_create_cached(&ctx, &graph, &cache).unwrap();
+        assert_eq!(plan.kernel(0), Some(("map_unary", Isa::Scalar)));
+        backend.plan_compute(&plan).expect("cached plan should compute");
+        let mut values = vec![0; 16];
+        buffer.read(output, &mut values, 0, 16).unwrap();
+        assert_eq!(decode_f32(&values), vec![4.0, 5.0, 6.0, 7.0]);
+
+        let wrong_kernels = b"mul scalar\n".repeat(3);
+        for garbage in [&b"garbage"[..], b"map_unary scalar\n", &wrong_kernels] {
+            cache.store(CacheKind::Plan, "cpu", &key, garbage).unwrap();
+            let plan = backend.plan_create_cached(&ctx, &graph, &cache).unwrap();
+            assert_eq!(plan.kernel(1), Some(("map_unary", Isa::Scalar)));
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn serialize_tensors_round_trip() {
+        use feml::serialize::{load_tensors, save_tensors, Compression};
+
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let buffer = backend.create_buffer(256, BackendBufferUsage::Any).unwrap();
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let weight = ctx.new_tensor(DataType::F32, &shape![2, 2]).unwrap();
+        let bias = ctx.new_tensor(DataType::F32, &shape![2]).unwrap();
+        weight.set_name("weight");
+        bias.set_name("bias");
+        buffer.init_tensor(weight.clone(), 0).unwrap();
+        buffer.init_tensor(bias.clone(), 64).unwrap();
+        buffer.write(weight.clone(), &mut encode_f32(&[1.0, 2.0, 3.0, 4.0]), 0, 16).unwrap();
+        buffer.write(bias.clone(), &mut encode_f32(&[0.5, -0.5]), 0, 8).unwrap();
+
+        let compressions = [
+            Compression::None,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(3),
+        ];
+        let path = std::env::temp_dir().join(format!("feml-tensors-{}", std::process::id()));
+        for compression in compressions {
+            save_tensors(&path, &[weight.clone(), bias.clone()], compression).unwrap();
+            let loaded = load_tensors(&path).unwrap();
+            assert_eq!(
+                loaded.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(),
+                ["weight", "bias"]
+            );
+
+            let mut other = Context::builder().tensor_pool_capacity(8).build();
+            let restored = loaded[0].new_tensor(&mut other).unwrap();
+            assert_eq!(&*restored.shape(), &shape![2, 2]);
+            buffer.init_tensor(restored.clone(), 128).unwrap();
+            loaded[0].upload(&restored).unwrap();
+            assert!(loaded[1].upload(&restored).is_err());
+
+            let mut values = vec![0; 16];
+            buffer.read(restored, &mut values, 0, 16).unwrap();
+            assert_eq!(decode_f32(&values), vec![1.0, 2.0, 3.0, 4.0]);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn streaming_loader_uploads_layer_by_layer() {
+        use feml::loader::StreamingLoader;
+        use feml::serialize::{Compression, TensorData, TensorReader, TensorWriter};
+
+        let names = ["token_embd.weight", "blk.0.q", "blk.0.k", "blk.1.q", "output.weight"];
+        let mut writer = TensorWriter::new(Vec::new(), Compression::None).unwrap();
+        for (i, name) in names.iter().enumerate() {
+            let values = [i as f32, i as f32 + 0.5];
+            let record = TensorData {
+                name: name.to_string(),
+                dtype: DataType::F32,
+                shape: shape![2],
+                data: encode_f32(&values),
+            };
+            writer.write(&record).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut progress = Vec::new();
+        let layers = StreamingLoader::new(TensorReader::new(bytes.as_slice()).unwrap(), &*backend)
+            .on_progress(|p| progress.push((p.layer.clone(), p.tensors_loaded, p.bytes_loaded)))
+            .load_all(&mut ctx)
+            .expect("layers should load");
+
+        let layer_names: Vec<&str> = layers.iter().map(|layer| layer.name.as_str()).collect();
+        assert_eq!(layer_names, ["token_embd.weight", "blk.0", "blk.1", "output.weight"]);
+        assert_eq!(layers[1].tensors.len(), 2);
+        assert_eq!(progress[1], ("blk.0".to_string(), 3, 24));
+        assert_eq!(progress.last().unwrap().1, 5);
+
+        let key = layers[1].tensors[1].clone();
+        assert_eq!(key.name(), "blk.0.k");
+        let mut values = vec![0; 8];
+        layers[1].buffer.read(key, &mut values, 0, 8).unwrap();
+        assert_eq!(decode_f32(&values), vec![2.0, 2.5]);
+    }
+
+    #[cfg(feature = "safetensors")]
+    #[test]
+    fn load_safetensors_into_cpu_buffer() {
+        use feml::loader::load_safetensors;
+
+        let mut header = String::from(concat!(
+            r#"{"w":{"dtype":"F32","shape":[2,3],"data_offsets":[0,24]},"#,
+            r#""b":{"dtype":"F32","shape":[2],"data_offsets":[24,32]}}"#
+        ));
+        while header.len() % 8 != 0 {
+            header.push(' ');
+        }
+        let mut file = (header.len() as u64).to_le_bytes().to_vec();
+        file.extend_from_slice(header.as_bytes());
+        file.extend(encode_f32(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, -1.0, -2.0]));
+        let path = std::env::temp_dir().join(format!("feml-{}.safetensors", std::process::id()));
+        std::fs::write(&path, file).unwrap();
+
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let model = load_safetensors(&path, &mut ctx, &*backend).expect("file should load");
+        assert_eq!(model.zero_copy, cfg!(feature = "mmap"));
+        assert!(model.buffer.is_host());
+
+        let [w, b] = [&model.tensors[0], &model.tensors[1]];
+        assert_eq!((w.name(), b.name()), ("w".to_string(), "b".to_string()));
+        assert_eq!(&*w.shape(), &shape![3, 2]);
+        assert_eq!(w.get_f32_nd(&[2, 1]).unwrap(), 6.0);
+        let mut values = vec![0; 8];
+        model.buffer.read(b.clone(), &mut values, 0, 8).unwrap();
+        assert_eq!(decode_f32(&values), vec![-1.0, -2.0]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn staging_pool_chunks_large_transfers() {
+        use feml::staging::StagingPool;
+
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(256, BackendBufferUsage::Any)
+            .expect("CPU buffer should be created");
+        let mut ctx = Context::builder().tensor_pool_capacity(4).build();
+        let tensor = ctx.new_tensor(DataType::F32, &shape![50]).expect("tensor should be created");
+        buffer.init_tensor(tensor.clone(), 0).expect("tensor should bind to CPU buffer");
+
+        // 200 bytes in 48 byte chunks: four full chunks and a partial one.
+        let pool = StagingPool::new(48).unwrap();
+        let values: Vec<f32> = (0..50).map(|i| i as f32).collect();
+        let mut input = encode_f32(&values);
+        pool.upload(&*backend, &tensor, &mut input, 0).expect("upload should succeed");
+        assert_eq!(pool.available(), 2);
+
+        let mut output = vec![0; input.len()];
+        buffer.read(tensor.clone(), &mut output, 0, 200).unwrap();
+        assert_eq!(decode_f32(&output), values);
+
+        let mut tail = vec![0; 120];
+        pool.download(&*backend, &tensor, &mut tail, 80).expect("download should succeed");
+        assert_eq!(decode_f32(&tail), values[20..]);
+
+        let mut small = encode_f32(&[-1.0, -2.0]);
+        pool.upload(&*backend, &tensor, &mut small, 8).expect("small upload should succeed");
+        assert_eq!(tensor.get_f32_nd(&[2]).unwrap(), -1.0);
+    }
+
+    #[test]
+    fn buffer_pool_reuses_compute_buffers() {
+        use feml::buffer_pool::BufferPool;
+
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let pool = BufferPool::new(&*backend).with_max_buffers(2);
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+
+        for step in 0..3 {
+            let buffer = pool.acquire(64, BackendBufferUsage::Compute).expect("acquire");
+            let tensor = ctx.new_tensor(DataType::F32, &shape![4]).unwrap();
+            buffer.init_tensor(tensor.clone(), 0).expect("tensor should bind to pooled buffer");
+            tensor.set_f32_nd(&[0], step as f32).unwrap();
+            assert_eq!(tensor.get_f32_nd(&[0]).unwrap(), step as f32);
+        }
+        let stats = pool.stats();
+        assert_eq!((stats.hits, stats.misses, stats.cached_bytes), (2, 1, 64));
+
+        // Same usage and a similar size reuse the buffer, others allocate.
+        let reused = pool.acquire(40, BackendBufferUsage::Compute).unwrap();
+        assert_eq!(reused.size(), 64);
+        let small = pool.acquire(16, BackendBufferUsage::Compute).unwrap();
+        let weights = pool.acquire(64, BackendBufferUsage::Weights).unwrap();
+        assert_eq!((small.size(), pool.stats().misses), (16, 3));
+        drop((reused, small, weights));
+        assert_eq!(pool.stats().cached_buffers, 2);
+
+        pool.clear();
+        assert_eq!(pool.stats().cached_bytes, 0);
+    }
+
+    #[test]
+    fn backend_facade_allocates_computes_and_transfers() {
+        let backend = feml::Backend::cpu().with_threads(2).build().expect("CPU backend");
+        assert_eq!(backend.name(), "cpu");
+        assert!(feml::Backend::by_name("cpu:0").build().is_ok());
+        assert!(feml::Backend::by_name("cpu:7").build().is_err());
+        assert!(feml::Backend::by_name("nope").build().is_err());
+
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut a = ctx.new_tensor(DataType::F32, &shape![4]).unwrap();
+        let b = ctx.new_tensor(DataType::F32, &shape![4]).unwrap();
+        mark_as_leaf(&a);
+        mark_as_leaf(&b);
+        let out = a.mul(b.clone()).unwrap();
+        let copy = ctx.new_tensor(DataType::F32, &shape![4]).unwrap();
+        let _buffer = backend.alloc(&[a.clone(), b.clone(), out.clone(), copy.clone()]).unwrap();
+
+        backend.write(&a, &encode_f32(&[1.0, 2.0, 3.0, 4.0])).unwrap();
+        backend.write(&b, &encode_f32(&[2.0, 2.0, 2.0, 2.0])).unwrap();
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, out.tensor_id(), false).unwrap();
+        backend.compute(&ctx, &mut graph).expect("graph should compute");
+        backend.transfer(&out, &copy).unwrap();
+        assert_eq!(decode_f32(&backend.read(&copy).unwrap()), vec![2.0, 4.0, 6.0, 8.0]);
+    }
+
+    #[test]
+    fn registry_discovers_registered_backends() {
+        use feml::backend::{BackendDevice, BackendRegister};
+        use feml::error::{Error, Result};
+        use feml::registry::register_backend;
+        use std::any::Any;
+
+        struct EmptyRegister;
+
+        impl BackendRegister for EmptyRegister {
+            fn name(&self) -> &str {
+                "Empty"
+            }
+
+            fn device_count(&self) -> usize {
+                0
+            }
+
+            fn device(&self, _index: usize) -> Result<Box<dyn BackendDevice>> {
+                Err(Error::msg("no devices"))
+            }
+
+            fn probe_devices(&self) -> Result<()> {
+                Ok(())
+            }
+
+            fn init_devices(&self) -> Result<()> {
+                Ok(())
+            }
+
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+
+            fn as_any_mut(&mut self) -> &mut dyn Any {
+                self
+            }
+        }
+
+        assert_eq!(Registry::builtin_backends()[0], "cpu");
+        register_backend("empty", || Ok(Box::new(EmptyRegister)));
+        let registry = Registry::discover().expect("registry discover should succeed");
+        assert!(registry.find("cpu").is_some());
+        assert_eq!(registry.find("empty").map(|reg| reg.device_count()), Some(0));
+        assert!(registry.open_backend("empty", 0).is_err());
+    }
+
+    #[test]
+    fn observer_sees_every_node() {
+        use feml::backend::GraphObserver;
+        use feml::cpu::backend::CpuBackend;
+        use std::sync::{Arc, Mutex};
+
+        struct Recorder {
+            events: Arc<Mutex<Vec<String>>>,
+            stop_after: Option<String>,
+        }
+
+        impl GraphObserver for Recorder {
+            fn on_graph_begin(&mut self, graph: &ComputeGraph) {
+                self.events.lock().unwrap().push(format!("graph {}", graph.nodes().len()));
+            }
+
+            fn on_node_begin(&mut self, tensor: &Tensor) {
+                self.events.lock().unwrap().push(format!("begin {}", tensor.name()));
+            }
+
+            fn on_node_end(&mut self, tensor: &Tensor) -> bool {
+                let first = tensor.get_f32_nd(&[0]).unwrap();
+                self.events.lock().unwrap().push(format!("end {} {first}", tensor.name()));
+                self.stop_after.as_deref() != Some(tensor.name().as_str())
+            }
+        }
+
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let mut backend = feml::Backend::cpu().build().unwrap();
+        let mut a = ctx.new_tensor(DataType::F32, &shape![2]).unwrap();
+        let b = ctx.new_tensor(DataType::F32, &shape![2]).unwrap();
+        mark_as_leaf(&a);
+        mark_as_leaf(&b);
+        let mut ab = a.mul(b.clone()).unwrap();
+        ab.set_name("ab");
+        let out = ab.mul(b.clone()).unwrap();
+        out.set_name("out");
+        let _buffer = backend.alloc(&[a.clone(), b.clone(), ab.clone(), out.clone()]).unwrap();
+        backend.write(&a, &encode_f32(&[1.0, 2.0])).unwrap();
+        backend.write(&b, &encode_f32(&[3.0, 3.0])).unwrap();
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, out.tensor_id(), false).unwrap();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Recorder { events: events.clone(), stop_after: None };
+        backend.inner_mut().set_observer(Some(Box::new(recorder))).unwrap();
+        backend.compute(&ctx, &mut graph).expect("graph should compute");
+        assert_eq!(
+            *events.lock().unwrap(),
+            ["graph 2", "begin ab", "end ab 3", "begin out", "end out 9"]
+        );
+
+        // plans run their kernels under the same hooks
+        events.lock().unwrap().clear();
+        let cpu = backend.inner().as_any().downcast_ref::<CpuBackend>().unwrap();
+        let plan = cpu.plan_create(&ctx, &graph).unwrap();
+        cpu.plan_compute(&plan).expect("plan should compute");
+        assert_eq!(
+            *events.lock().unwrap(),
+            ["graph 2", "begin ab", "end ab 3", "begin out", "end out 9"]
+        );
+
+        events.lock().unwrap().clear();
+        let recorder = Recorder { events: events.clone(), stop_after: Some("ab".to_string()) };
+        backend.inner_mut().set_observer(Some(Box::new(recorder))).unwrap();
+        assert!(backend.compute(&ctx, &mut graph).is_err());
+        assert_eq!(*events.lock().unwrap(), ["graph 2", "begin ab", "end ab 3"]);
+    }
+
+    #[test]
+    fn debug_dumps_tensors_as_npy() {
+        use feml::debug::{dump_graph, dump_tensor, TensorStats};
+
+        let dir = std::env::temp_dir().join(format!("feml-dump-{}", std::process::id()));
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let mut backend = feml::Backend::cpu().build().unwrap();
+        let mut a = ctx.new_tensor(DataType::F32, &shape![3, 2]).unwrap();
+        let b = ctx.new_tensor(DataType::F32, &shape![3, 2]).unwrap();
+        mark_as_leaf(&a);
+        mark_as_leaf(&b);
+        let mut out = a.mul(b.clone()).unwrap();
+        out.set_name("out");
+        let _buffer = backend.alloc(&[a.clone(), b.clone(), out.clone()]).unwrap();
+        backend.write(&a, &encode_f32(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0])).unwrap();
+        backend.write(&b, &encode_f32(&[1.0, 1.0, 1.0, 2.0, 2.0, 2.0])).unwrap();
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, out.tensor_id(), false).unwrap();
+        dump_graph(backend.inner_mut(), &ctx, &mut graph, &dir).expect("graph should dump");
+
+        let bytes = std::fs::read(dir.join("0000_out.npy")).unwrap();
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+        assert_eq!((10 + header_len) % 64, 0);
+        assert!(header.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }"));
+        let values = [1.0, 2.0, 3.0, 8.0, 10.0, 12.0];
+        assert_eq!(decode_f32(&bytes[10 + header_len..]), values);
+        assert!(std::fs::read_to_string(dir.join("index.txt")).unwrap().contains("mean=6"));
+
+        let transposed = out.transpose().unwrap();
+        let path = dir.join("transposed.npy");
+        let stats = dump_tensor(&transposed, &path).unwrap();
+        assert_eq!(stats, TensorStats { min: 1.0, max: 12.0, mean: 6.0 });
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(std::str::from_utf8(&bytes[10..128]).unwrap().contains("'shape': (3, 2)"));
+        assert_eq!(decode_f32(&bytes[128..]), [1.0, 8.0, 2.0, 10.0, 3.0, 12.0]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn debug_dump_graph_reports_write_errors() {
+        use feml::debug::dump_graph;
+
+        let dir = std::env::temp_dir().join(format!("feml-dump-err-{}", std::process::id()));
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let mut backend = feml::Backend::cpu().build().unwrap();
+        let mut a = ctx.new_tensor(DataType::F32, &shape![4]).unwrap();
+        let b = ctx.new_tensor(DataType::F32, &shape![4]).unwrap();
+        mark_as_leaf(&a);
+        mark_as_leaf(&b);
+        let out = a.mul(b.clone()).unwrap();
+        out.set_name("out");
+        let _buffer = backend.alloc(&[a.clone(), b.clone(), out.clone()]).unwrap();
+        backend.write(&a, &encode_f32(&[1.0, 2.0, 3.0, 4.0])).unwrap();
+        backend.write(&b, &encode_f32(&[2.0; 4])).unwrap();
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, out.tensor_id(), false).unwrap();
+
+        // A directory in place of a file makes the write fail.
+        std::fs::create_dir_all(dir.join("0000_out.npy")).unwrap();
+        let err = dump_graph(backend.inner_mut(), &ctx, &mut graph, &dir).unwrap_err();
+        assert!(err.to_string().contains("0000_out.npy"), "{err}");
+
+        std::fs::remove_dir(dir.join("0000_out.npy")).unwrap();
+        std::fs::create_dir(dir.join("index.txt")).unwrap();
+        let err = dump_graph(backend.inner_mut(), &ctx, &mut graph, &dir).unwrap_err();
+        assert!(err.to_string().contains("index.txt"), "{err}");
+        assert!(dir.join("0000_out.npy").is_file());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn gradcheck_compares_numeric_and_analytic_gradients() {
+        use feml::gradcheck::{check_gradients, GradCheckOptions};
+
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let backend = feml::Backend::cpu().build().unwrap();
+        let mut a = ctx.new_tensor(DataType::F32, &shape![3, 1]).unwrap();
+        let b = ctx.new_tensor(DataType::F32, &shape![3, 1]).unwrap();
+        mark_as_leaf(&a);
+        mark_as_leaf(&b);
+        a.set_name("a");
+        b.set_name("b");
+        // The gradient of the dot product a·b is b with respect to a and a with respect to b.
+        let dot = a.mul_mat(b.clone()).unwrap();
+        let grad_a = ctx.new_tensor(DataType::F32, &shape![3, 1]).unwrap();
+        let grad_b = ctx.new_tensor(DataType::F32, &shape![3, 1]).unwrap();
+        let tensors = [a.clone(), b.clone(), dot.clone(), grad_a.clone(), grad_b.clone()];
+        let _buffer = backend.alloc(&tensors).unwrap();
+        backend.write(&a, &encode_f32(&[1.0, 2.0, 3.0])).unwrap();
+        backend.write(&b, &encode_f32(&[0.5, -1.0, 4.0])).unwrap();
+        backend.write(&grad_a, &encode_f32(&[0.5, -1.0, 4.0])).unwrap();
+        backend.write(&grad_b, &encode_f32(&[1.0, 2.5, 3.0])).unwrap();
+
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, dot.tensor_id(), false).unwrap();
+        let params = [(a.clone(), grad_a), (b.clone(), grad_b)];
+        let options = GradCheckOptions::default();
+        let results =
+            check_gradients(backend.inner(), &ctx, &mut graph, &dot, &params, &options).unwrap();
+
+        assert_eq!(results[0].name, "a");
+        assert!(results[0].passed, "{:?}", results[0]);
+        assert!(!results[1].passed);
+        assert_eq!(results[1].worst_index, 1);
+        assert!((results[1].max_abs_error - 0.5).abs() < 1e-2);
+        assert_eq!(decode_f32(&backend.read(&a).unwrap()), vec![1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn cpu_reference_kernels_match_fast_paths() {
+        use feml::backend::Backend;
+        use feml::cpu::autotune::GemmBlocking;
+        use feml::cpu::backend::CpuBackend;
+
+        let (k, m, n) = (45, 13, 29);
+        let mut backend = CpuBackend::init().expect("CPU backend should open");
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut lhs = ctx.new_tensor(DataType::F32, &shape![k, m]).unwrap();
+        let rhs = ctx.new_tensor(DataType::F32, &shape![k, n]).unwrap();
+        mark_as_leaf(&lhs);
+        mark_as_leaf(&rhs);
+        let mut product = lhs.mul_mat(rhs.clone()).unwrap();
+        let out = product.diag_mask_inf(2).unwrap();
+
+        let buffer = backend.create_buffer(16384, BackendBufferUsage::Any).unwrap();
+        buffer.init_tensor(lhs.clone(), 0).unwrap();
+        buffer.init_tensor(rhs.clone(), 4096).unwrap();
+        buffer.init_tensor(product.clone(), 10240).unwrap();
+        buffer.init_tensor(out.clone(), 12288).unwrap();
+        let a: Vec<f32> = (0..k * m).map(|i| ((i * 37) % 101) as f32 / 17.0 - 3.0).collect();
+        let b: Vec<f32> = (0..k * n).map(|i| ((i * 53) % 89) as f32 / 13.0 - 3.0).collect();
+        buffer.write(lhs, &mut encode_f32(&a), 0, a.len() * 4).unwrap();
+        buffer.write(rhs, &mut encode_f32(&b), 0, b.len() * 4).unwrap();
+
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, out.tensor_id(), false).unwrap();
+        let mut run = |backend: &CpuBackend| {
+            backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+            let mut output = vec![0; out.nbytes()];
+            buffer.read(out.clone(), &mut output, 0, out.nbytes()).unwrap();
+            decode_f32(&output)
+        };
+
+        backend.set_gemm_blocking(GemmBlocking { mc: 4, kc: 8, nc: 3 }).unwrap();
+        backend.set_n_threads(3).unwrap();
+        let fast = run(&backend);
+        backend.set_reference_kernels(true);
+        assert!(backend.reference_kernels());
+        assert_eq!(backend.n_threads(), 1);
+        let reference = run(&backend);
+
+        for (fast, reference) in fast.iter().zip(&reference) {
+            assert!(
+                fast == reference || (fast - reference).abs() <= 1e-4 * reference.abs().max(1.0),
+                "{fast} != {reference}"
+            );
+        }
+        backend.set_reference_kernels(false);
+        assert_eq!(backend.n_threads(), 3);
+    }
+
+    #[test]
+    fn cpu_backend_reports_op_support() {
+        use feml::ops::supported_dtypes;
+
+        let backend = feml::Backend::cpu().build().unwrap();
+        let backend = backend.inner();
+        assert!(backend.supports(TensorOpType::TensorOpMulMat, &[DataType::F32, DataType::F16]));
+        assert!(!backend.supports(TensorOpType::TensorOpMul, &[DataType::I32]));
+        assert!(backend.supports(TensorOpType::TensorOpCont, &[DataType::I32]));
+        assert!(!backend.supports(TensorOpType::UNKNOWN, &[]));
+        assert_eq!(
+            backend.supported_dtypes(TensorOpType::TensorOpMul),
+            supported_dtypes(TensorOpType::TensorOpMul)
+        );
+
+        let registry = Registry::discover().unwrap();
+        let cpu = registry.find("cpu").unwrap();
+        let device = cpu.device(0).unwrap();
+        assert!(device.supports_op(TensorOpType::TensorOpSoftMaxBack).unwrap());
+        assert!(!device.supports_op(TensorOpType::UNKNOWN).unwrap());
+    }
+
+    #[test]
+    fn feml_graph_macro_builds_and_computes() -> feml::error::Result<()> {
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        feml::feml_graph! { ctx;
+            input x: F32[2, 2];
+            weight w: F32[2, 3];
+            weight scale: F32[3, 2];
+            let h = w @ x;
+            let y = h * scale;
+            let t = transpose(y);
+            output y, t => graph;
+        }
+        assert_eq!((y.name(), y.shape().dims[..2].to_vec()), ("y".to_string(), vec![3, 2]));
+        assert_eq!(t.shape().dims[..2], [2, 3]);
+        assert_eq!(graph.leaf_count(), 3);
+
+        let backend = feml::Backend::cpu().build()?;
+        let _buffer = backend.alloc(&[x.clone(), w.clone(), scale.clone(), h, y.clone()])?;
+        backend.write(&x, &encode_f32(&[1.0, 0.0, 0.0, 1.0]))?;
+        backend.write(&w, &encode_f32(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]))?;
+        backend.write(&scale, &encode_f32(&[1.0, 1.0, 1.0, 2.0, 2.0, 2.0]))?;
+        backend.compute(&ctx, &mut graph)?;
+        assert_eq!(decode_f32(&backend.read(&y)?), vec![1.0, 3.0, 5.0, 4.0, 8.0, 12.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn memory_report_splits_weights_activations_and_kv_cache() -> feml::error::Result<()> {
+        use feml::memory::{MemoryCategory, MemoryReport};
+
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let w = ctx.new_tensor(DataType::F32, &shape![4, 3])?;
+        let x = ctx.new_tensor(DataType::F32, &shape![4, 2])?;
+        mark_as_leaf(&w);
+        mark_as_leaf(&x);
+        x.set_memory_category(MemoryCategory::Other);
+        let y = w.clone().mul_mat(x.clone())?;
+        let cache = KvCache::new(&mut ctx, DataType::F32, 1, 8, 1, 4)?;
+        let graph = ctx.new_graph(8)?;
+        graph.build_forward(&ctx, y.tensor_id(), true)?;
+        assert_eq!(ctx.memory_report(), MemoryReport::default());
+
+        let backend = feml::Backend::cpu().build()?;
+        let mut tensors = vec![w, x, y.clone()];
+        tensors.extend(cache.tensors().cloned());
+        let _buffer = backend.alloc(&tensors)?;
+        let _view = y.clone().transpose()?;
+
+        let report = ctx.memory_report();
+        assert_eq!(report.weights, 4 * 3 * 4);
+        assert_eq!(report.other, 4 * 2 * 4);
+        assert_eq!(report.activations, 3 * 2 * 4);
+        assert_eq!(report.kv_cache, 2 * 4 * 8 * 4);
+        assert_eq!(report.n_tensors, 5);
+        assert_eq!(graph.memory_report(&ctx)?.total(), (12 + 8 + 6) * 4);
+        Ok(())
+    }
+
+    #[test]
+    fn cpu_buffer_rejects_access_outside_the_tensor() -> feml::error::Result<()> {
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let first = ctx.new_tensor(DataType::F32, &shape![4])?;
+        let second = ctx.new_tensor(DataType::F32, &shape![4])?;
+        let backend = feml::Backend::cpu().build()?;
+        let buffer = backend.alloc(&[first.clone(), second.clone()])?;
+        backend.write(&second, &encode_f32(&[5.0; 4]))?;
+
+        let mut data = vec![0u8; 20];
+        assert!(buffer.write(first.clone(), &mut data, 0, 20).is_err());
+        assert!(buffer.write(first.clone(), &mut data, 4, 16).is_err());
+        assert!(buffer.read(first.clone(), &mut data, 12, 8).is_err());
+        assert!(buffer.fill(first.clone(), 0xff, 16, 1).is_err());
+        assert!(buffer.fill(first.clone(), 0xff, usize::MAX, 2).is_err());
+        buffer.fill(first.clone(), 0, 4, 12)?;
+        assert_eq!(decode_f32(&backend.read(&second)?), vec![5.0; 4]);
+        Ok(())
+    }
+
+    #[test]
+    fn cpu_buffer_typed_views_are_aligned() -> feml::error::Result<()> {
+        use feml::backend::HOST_BUFFER_ALIGNMENT;
+
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let tensor = ctx.new_tensor(DataType::F32, &shape![3])?;
+        let backend = feml::Backend::cpu().build()?;
+        let buffer = backend.alloc(std::slice::from_ref(&tensor))?;
+        backend.write(&tensor, &encode_f32(&[1.0, 2.0, 3.0]))?;
+
+        let memory = buffer.host_memory()?;
+        assert_eq!(memory.as_ptr() as usize % HOST_BUFFER_ALIGNMENT, 0);
+        drop(memory);
+        assert_eq!(buffer.as_slice::<f32>()?[..3], [1.0, 2.0, 3.0]);
+        buffer.as_mut_slice::<f64>()?[0] = 0.0;
+        assert_eq!(decode_f32(&backend.read(&tensor)?), vec![0.0, 0.0, 3.0]);
+
+        let _slice = buffer.as_slice::<u8>()?;
+        assert!(buffer.as_mut_slice::<u8>().is_err());
+        assert!(backend.write(&tensor, &encode_f32(&[1.0; 3])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn graphs_use_weights_shared_from_another_context() -> feml::error::Result<()> {
+        let backend = feml::Backend::cpu().build()?;
+        let mut weights = Context::builder().tensor_pool_capacity(8).build();
+        let w = weights.new_tensor(DataType::F32, &shape![2, 2])?;
+        mark_as_leaf(&w);
+        w.set_name("w");
+        let weights_buffer = backend.alloc(std::slice::from_ref(&w))?;
+        backend.write(&w, &encode_f32(&[1.0, 2.0, 3.0, 4.0]))?;
+
+        let run = |x_data: [f32; 2]| -> feml::error::Result<Vec<f32>> {
+            let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+            let mut shared = ctx.share_tensor(&w)?;
+            assert_eq!(shared.name(), "w");
+            assert!(!weights.contain_tensor(shared.tensor_id()));
+            let x = ctx.new_tensor(DataType::F32, &shape![2, 1])?;
+            mark_as_leaf(&x);
+            let y = shared.mul_mat(x.clone())?;
+            let mut graph = ctx.new_graph(8)?;
+            graph.build_forward(&ctx, y.tensor_id(), true)?;
+            let _buffer = backend.alloc(&[x.clone(), y.clone()])?;
+            backend.write(&x, &encode_f32(&x_data))?;
+            backend.compute(&ctx, &mut graph)?;
+            assert_eq!(ctx.memory_report().total(), (2 + 2) * 4);
+            Ok(decode_f32(&backend.read(&y)?))
+        };
+        assert_eq!(run([1.0, 0.0])?, vec![1.0, 3.0]);
+        assert_eq!(run([0.0, 1.0])?, vec![2.0, 4.0]);
+
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let shared = ctx.share_tensor(&w)?;
+        drop((weights, weights_buffer, w));
+        assert_eq!(decode_f32(&backend.read(&shared)?), vec![1.0, 2.0, 3.0, 4.0]);
+
+        let mut other = Context::builder().tensor_pool_capacity(8).build();
+        let unbound = other.new_tensor(DataType::F32, &shape![2])?;
+        assert!(ctx.share_tensor(&unbound).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn graph_binds_dynamic_sequence_length() -> feml::error::Result<()> {
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let mut w = ctx.new_tensor(DataType::F32, &shape![2, 3])?;
+        let x = ctx.new_tensor(DataType::F32, &shape![2, 4])?;
+        mark_as_leaf(&w);
+        mark_as_leaf(&x);
+        let mut y = w.mul_mat(x.clone())?;
+        let mut yt = y.transpose()?;
+        let z = yt.cont()?;
+        let mut graph = ctx.new_graph(8)?;
+        graph.build_forward(&ctx, z.tensor_id(), true)?;
+
+        let backend = feml::Backend::cpu().build()?;
+        let _buffer = backend.alloc(&[w.clone(), x.clone(), y.clone(), yt, z.clone()])?;
+        backend.write(&w, &encode_f32(&[1.0, 0.0, 0.0, 1.0, 1.0, 1.0]))?;
+
+        for len in [4, 2, 3] {
+            graph.bind_dim(&ctx, &[(x.clone(), 1)], len)?;
+            assert_eq!(
+                (y.shape().dims[..2].to_vec(), z.shape().dims[..2].to_vec()),
+                (vec![3, len], vec![len, 3])
+            );
+            let input: Vec<f32> = (0..2 * len).map(|i| i as f32).collect();
+            backend.write(&x, &encode_f32(&input))?;
+            backend.compute(&ctx, &mut graph)?;
+            let expected: Vec<f32> = (0..len)
+                .flat_map(|t| {
+                    let (a, b) = (input[2 * t], input[2 * t + 1]);
+                    [a, b, a + b]
+                })
+                .collect();
+            assert_eq!(decode_f32(&backend.read(&y)?), expected);
+        }
+        assert!(graph.bind_dim(&ctx, &[(x.clone(), 1)], 5).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn kv_cache_state_round_trips_between_sessions() -> feml::error::Result<()> {
+        let backend = feml::Backend::cpu().build()?;
+        let session = |n_ctx: usize| -> feml::error::Result<_> {
+            let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+            let cache = KvCache::new(&mut ctx, DataType::F32, 2, n_ctx, 2, 2)?;
+            let logits = ctx.new_tensor(DataType::F32, &shape![3])?;
+            let mut tensors: Vec<Tensor> = cache.tensors().cloned().collect();
+            tensors.push(logits.clone());
+            let buffer = backend.alloc(&tensors)?;
+            Ok((ctx, cache, logits, buffer))
+        };
+
+        let (_ctx, mut cache, logits, _buffer) = session(4)?;
+        for (i, tensor) in cache.tensors().enumerate() {
+            let data: Vec<f32> = (0..16).map(|j| (100 * i + j) as f32).collect();
+            backend.write(tensor, &encode_f32(&data))?;
+        }
+        backend.write(&logits, &encode_f32(&[0.5, 1.5, 2.5]))?;
+        cache.advance(3)?;
+        let state = cache.save_state(Some(&logits))?;
+
+        // A longer cache of the same layout takes the state, position by position.
+        let (_ctx, mut restored, restored_logits, _buffer) = session(6)?;
+        assert!(restored.load_state(&state, Some(&restored_logits))?);
+        assert_eq!(restored.len(), 3);
+        assert_eq!(decode_f32(&backend.read(&restored_logits)?), vec![0.5, 1.5, 2.5]);
+        let values = decode_f32(&backend.read(restored.values(1)?)?);
+        // Layer 1 values are tensor 3: head 0 at 0..6, head 1 at 12..18 of 6 positions.
+        assert_eq!(values[..6], [300.0, 301.0, 302.0, 303.0, 304.0, 305.0]);
+        assert_eq!(values[12..18], [308.0, 309.0, 310.0, 311.0, 312.0, 313.0]);
+        assert_eq!(values[6..12], [0.0; 6]);
+
+        let (_ctx, mut small, _, _buffer) = session(2)?;
+        assert!(small.load_state(&state, None).is_err());
+        assert!(!cache.load_state(&cache.save_state(None)?, Some(&logits))?);
+        Ok(())
+    }
+
+    #[test]
+    fn graph_compute_repeat_and_repeat_back() -> feml::error::Result<()> {
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut x = ctx.new_tensor(DataType::F32, &shape![2, 1])?;
+        mark_as_leaf(&x);
+        assert!(x.repeat(&shape![3, 2]).is_err());
+        let mut tiled = x.repeat(&shape![4, 3])?;
+        assert!(tiled.repeat_back(&shape![3, 1]).is_err());
+        let summed = tiled.repeat_back(&shape![2, 1])?;
+        let mut graph = ctx.new_graph(8)?;
+        graph.build_forward(&ctx, summed.tensor_id(), true)?;
+
+        let backend = feml::Backend::cpu().build()?;
+        let _buffer = backend.alloc(&[x.clone(), tiled.clone(), summed.clone()])?;
+        backend.write(&x, &encode_f32(&[1.0, 2.0]))?;
+        backend.compute(&ctx, &mut graph)?;
+        assert_eq!(decode_f32(&backend.read(&tiled)?), [1.0, 2.0].repeat(6));
+        assert_eq!(decode_f32(&backend.read(&summed)?), vec![6.0, 12.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn graph_compute_set_writes_into_a_view() -> feml::error::Result<()> {
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut cache = ctx.new_tensor(DataType::F32, &shape![4, 3])?;
+        let row = ctx.new_tensor(DataType::F32, &shape![2, 2])?;
+        mark_as_leaf(&cache);
+        mark_as_leaf(&row);
+        let nb1 = cache.stride()[1];
+        assert!(cache.set(row.clone(), nb1, nb1 * 3, nb1 * 3, nb1 * 2 + 12).is_err());
+        // A 2x2 block at column 1 of rows 1 and 2.
+        let copy = cache.set(row.clone(), nb1, nb1 * 3, nb1 * 3, nb1 + 4)?;
+        let updated = cache.set_inplace(row.clone(), nb1, nb1 * 3, nb1 * 3, 0)?;
+        let mut graph = ctx.new_graph(8)?;
+        graph.build_forward(&ctx, copy.tensor_id(), true)?;
+        graph.build_forward(&ctx, updated.tensor_id(), true)?;
+
+        let backend = feml::Backend::cpu().build()?;
+        let tensors = [cache.clone(), row.clone(), copy.clone(), updated.clone()];
+        let _buffer = backend.alloc(&tensors)?;
+        backend.write(&cache, &encode_f32(&[0.0; 12]))?;
+        backend.write(&row, &encode_f32(&[1.0, 2.0, 3.0, 4.0]))?;
+        backend.compute(&ctx, &mut graph)?;
+
+        let copy = decode_f32(&backend.read(&copy)?);
+        assert_eq!(copy, [0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 0.0, 0.0, 3.0, 4.0, 0.0]);
+        let cache = decode_f32(&backend.read(&cache)?);
+        assert_eq!(cache, [1.0, 2.0, 0.0, 0.0, 3.0, 4.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn graph_compute_sum_rows_and_mean() -> feml::error::Result<()> {
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut x = ctx.new_tensor(DataType::F32, &shape![10, 3])?;
+        mark_as_leaf(&x);
+        let sums = x.sum_rows()?;
+        let means = x.mean()?;
+        assert_eq!(*sums.shape(), shape![1, 3]);
+        let mut graph = ctx.new_graph(8)?;
+        graph.build_forward(&ctx, sums.tensor_id(), true)?;
+        graph.build_forward(&ctx, means.tensor_id(), true)?;
+
+        let backend = feml::Backend::cpu().with_threads(2).build()?;
+        let _buffer = backend.alloc(&[x.clone(), sums.clone(), means.clone()])?;
+        let data: Vec<f32> = (0..30).map(|i| i as f32).collect();
+        backend.write(&x, &encode_f32(&data))?;
+        backend.compute(&ctx, &mut graph)?;
+        assert_eq!(decode_f32(&backend.read(&sums)?), vec![45.0, 145.0, 245.0]);
+        assert_eq!(decode_f32(&backend.read(&means)?), vec![4.5, 14.5, 24.5]);
+        Ok(())
+    }
+
+    #[test]
+    fn graph_compute_attention_alibi_and_head_bias_f32() {
+        let registry = Registry::discover().expect("registry discover should succeed");
+        let backend = registry.open_backend("CPU", 0).expect("CPU backend should open");
+        let buffer = backend
+            .create_buffer(512, BackendBufferUsage::Any)
+            .expect("CPU buffer should be created");
+
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let k = ctx.new_tensor(DataType::F32, &shape![2, 2, 1, 1]).unwrap();
+        let v = ctx.new_tensor(DataType::F32, &shape![2, 2, 1, 1]).unwrap();
+        let mut q = ctx.new_tensor(DataType::F32, &shape![2, 1, 2, 1]).unwrap();
+        let bias = ctx.new_tensor(DataType::F32, &shape![2, 1, 2]).unwrap();
+
+        let mut offset = 0;
+        for tensor in [&k, &v, &q, &bias] {
+            mark_as_leaf(tensor);
+            buffer.init_tensor(tensor.clone(), offset).unwrap();
+            offset += 64;
+        }
+        buffer.write(k.clone(), &mut encode_f32(&[1.0, 0.0, 0.0, 1.0]), 0, 16).unwrap();
+        buffer.write(v.clone(), &mut encode_f32(&[1.0, 2.0, 3.0, 4.0]), 0, 16).unwrap();
+        buffer.write(q.clone(), &mut encode_f32(&[0.0; 4]), 0, 16).unwrap();
+        let mut head_bias = encode_f32(&[0.0, f32::NEG_INFINITY, f32::NEG_INFINITY, 0.0]);
+        buffer.write(bias.clone(), &mut head_bias, 0, 16).unwrap();
+
+        let alibi = q.attention_alibi(k.clone(), v.clone(), None, 1.0, 8.0).unwrap();
+        let biased = q.attention(k.clone(), v.clone(), Some(bias), 1.0).unwrap();
+        assert!(q.attention_alibi(k, v, None, 1.0, -1.0).is_err());
+
+        // the query is the second position, so key 0 is one position away
+        let [w0, w1] = [1.0f32 / 16.0, 1.0 / 256.0].map(|slope| 1.0 / (1.0 + slope.exp()));
+        assert_eq!(feml::ops::alibi_slopes(2, 8.0), [1.0 / 16.0, 1.0 / 256.0]);
+        let expected = [
+            (&alibi, [3.0 - 2.0 * w0, 4.0 - 2.0 * w0, 3.0 - 2.0 * w1, 4.0 - 2.0 * w1]),
+            (&biased, [1.0, 2.0, 3.0, 4.0]),
+        ];
+        for (tensor, values) in expected {
+            buffer.init_tensor(tensor.clone(), offset).unwrap();
+            offset += 64;
+
+            let mut graph = ComputeGraph::new();
+            graph.build_forward(&ctx, tensor.tensor_id(), false).unwrap();
+            backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+
+            let mut output = vec![0; tensor.nbytes()];
+            buffer.read(tensor.clone(), &mut output, 0, 16).unwrap();
+            for (got, expected) in decode_f32(&output).iter().zip(values) {
+                assert!((got - expected).abs() < 1e-6, "{got} != {expected}");
+            }
+        }
+    }
+
+    #[test]
+    fn graph_compute_sliding_window_attention_f32() -> feml::error::Result<()> {
+        use feml::tensor::AttentionParams;
+
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let k = ctx.new_tensor(DataType::F32, &shape![1, 3, 1, 1])?;
+        let v = ctx.new_tensor(DataType::F32, &shape![1, 3, 1, 1])?;
+        let mut q = ctx.new_tensor(DataType::F32, &shape![1, 3, 1, 1])?;
+        let mut cache = KvCache::new(&mut ctx, DataType::F32, 1, 3, 1, 1)?.with_window(2)?;
+        let mut next = ctx.new_tensor(DataType::F32, &shape![1, 1, 1, 1])?;
+        let mask = ctx.new_tensor(DataType::F32, &shape![3, 1])?;
+        for tensor in [&k, &v, &q, &next, &mask].into_iter().chain(cache.tensors()) {
+            mark_as_leaf(tensor);
+        }
+
+        // all scores are equal, so every query averages the values it attends to
+        let params = AttentionParams { window: Some(2), ..Default::default() };
+        let windowed = q.attention_ext(k.clone(), v.clone(), None, params)?;
+        let empty = AttentionParams { window: Some(0), ..params };
+        assert!(q.attention_ext(k.clone(), v.clone(), None, empty).is_err());
+
+        assert!(cache.advance(3).is_err());
+        cache.advance(2)?;
+        cache.advance(1)?;
+        assert_eq!(cache.next_slots(1)?, [(0, 1)]);
+        cache.advance(1)?;
+        let (cache_k, cache_v) = cache.history(0)?;
+        let ring = next.attention(cache_k.clone(), cache_v.clone(), Some(mask.clone()), 1.0)?;
+
+        let backend = feml::Backend::cpu().build()?;
+        let mut tensors = vec![k, v.clone(), q, next, mask.clone(), windowed.clone(), ring.clone()];
+        tensors.extend(cache.tensors().cloned().chain([cache_k, cache_v]));
+        let _buffer = backend.alloc(&tensors)?;
+        backend.write(&v, &encode_f32(&[1.0, 2.0, 3.0]))?;
+        // position 3 replaced position 0 in slot 0
+        backend.write(cache.values(0)?, &encode_f32(&[4.0, 2.0, 3.0]))?;
+        backend.write(&mask, &encode_f32(&cache.attention_mask(1)?))?;
+
+        for tensor in [&windowed, &ring] {
+            let mut graph = ComputeGraph::new();
+            graph.build_forward(&ctx, tensor.tensor_id(), false)?;
+            backend.compute(&ctx, &mut graph)?;
+        }
+        assert_eq!(decode_f32(&backend.read(&windowed)?), vec![2.0, 2.0, 2.5]);
+        assert_eq!(decode_f32(&backend.read(&ring)?), vec![3.5]);
+        Ok(())
+    }
+
+    #[test]
+    fn graph_compute_mul_mat_rowwise_q8() -> feml::error::Result<()> {
+        use feml::quant::quantize_rows_q8;
+
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let mut w = ctx.new_tensor(DataType::I8, &shape![4, 3])?;
+        let scales = ctx.new_tensor(DataType::F32, &shape![3])?;
+        let x = ctx.new_tensor(DataType::F32, &shape![4, 2])?;
+        for tensor in [&w, &scales, &x] {
+            mark_as_leaf(tensor);
+        }
+        let y = w.mul_mat_rowwise(scales.clone(), x.clone())?;
+        assert_eq!(&*y.shape(), &shape![3, 2]);
+        assert!(w.mul_mat_rowwise(x.clone(), x.clone()).is_err());
+        assert!(x.clone().mul_mat_rowwise(scales.clone(), x.clone()).is_err());
+
+        // the middle row is 100 times larger and keeps its own scale
+        let weights = [1.0, 0.5, -0.25, 0.0, 100.0, -50.0, 25.0, 0.0, 0.1, 0.2, 0.3, 0.4];
+        let input = [1.0, 2.0, 3.0, 4.0, -1.0, 0.0, 0.5, 0.25];
+        let quantized = quantize_rows_q8(&weights, 4)?;
+
+        let backend = feml::Backend::cpu().with_threads(2).build()?;
+        let _buffer = backend.alloc(&[w.clone(), scales.clone(), x.clone(), y.clone()])?;
+        backend.write(&w, &quantized.value_bytes())?;
+        backend.write(&scales, &encode_f32(&quantized.scales))?;
+        backend.write(&x, &encode_f32(&input))?;
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, y.tensor_id(), false)?;
+        backend.compute(&ctx, &mut graph)?;
+
+        // the kernel is exact on the quantized values of both sides
+        let (w_q, x_q) = (quantized.dequantize(), quantize_rows_q8(&input, 4)?.dequantize());
+        let got = decode_f32(&backend.read(&y)?);
+        for (i, got) in got.iter().enumerate() {
+            let (m, n) = (i % 3, i / 3);
+            let expected: f32 = (0..4).map(|k| w_q[m * 4 + k] * x_q[n * 4 + k]).sum();
+            let exact: f32 = (0..4).map(|k| weights[m * 4 + k] * input[n * 4 + k]).sum();
+            assert!((got - expected).abs() <= 1e-5 * expected.abs().max(1.0));
+            assert!((got - exact).abs() <= 0.05 * exact.abs().max(1.0), "{got} != {exact}");
+        }
+        assert!(backend.inner().supports(TensorOpType::TensorOpMulMatRowwise, &[DataType::I8]));
+        Ok(())
+    }
+
+    #[test]
+    fn streaming_loader_dequantizes_selected_tensors() -> feml::error::Result<()> {
+        use feml::loader::StreamingLoader;
+        use feml::quant::quantize_rows_q8;
+        use feml::serialize::{Compression, TensorData, TensorReader, TensorWriter};
+
+        let weights = [1.0, -0.5, 0.25, 0.0, 4.0, 2.0, -1.0, 0.5];
+        let quantized = quantize_rows_q8(&weights, 4)?;
+        let mut writer = TensorWriter::new(Vec::new(), Compression::None)?;
+        for name in ["blk.0.q", "output.weight"] {
+            writer.write(&TensorData {
+                name: name.to_string(),
+                dtype: DataType::I8,
+                shape: shape![4, 2],
+                data: quantized.value_bytes(),
+            })?;
+            writer.write(&TensorData {
+                name: format!("{name}.scales"),
+                dtype: DataType::F32,
+                shape: shape![2],
+                data: encode_f32(&quantized.scales),
+            })?;
+        }
+        let bytes = writer.finish()?;
+
+        let backend = feml::Backend::cpu().build()?;
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let layers = StreamingLoader::new(TensorReader::new(bytes.as_slice())?, backend.inner())
+            .dequantize("output.*", DataType::F32)
+            .load_all(&mut ctx)?;
+
+        let names: Vec<Vec<String>> = layers
+            .iter()
+            .map(|layer| layer.tensors.iter().map(|tensor| tensor.name()).collect())
+            .collect();
+        assert_eq!(names, [vec!["blk.0.q", "blk.0.q.scales"], vec!["output.weight"]]);
+        assert_eq!(layers[0].tensors[0].dtype(), DataType::I8);
+
+        let output = &layers[1].tensors[0];
+        assert_eq!((output.dtype(), *output.shape()), (DataType::F32, shape![4, 2]));
+        let mut values = vec![0; 32];
+        layers[1].buffer.read(output.clone(), &mut values, 0, 32)?;
+        assert_eq!(decode_f32(&values), quantized.dequantize());
+
+        let missing_scales = {
+            let mut writer = TensorWriter::new(Vec::new(), Compression::None)?;
+            writer.write(&TensorData {
+                name: "output.weight".to_string(),
+                dtype: DataType::I8,
+                shape: shape![4, 2],
+                data: quantized.value_bytes(),
+            })?;
+            writer.finish()?
+        };
+        let reader = TensorReader::new(missing_scales.as_slice())?;
+        let loader =
+            StreamingLoader::new(reader, backend.inner()).dequantize("output.*", DataType::F32);
+        assert!(loader.load_all(&mut ctx).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn streaming_loader_filters_and_overrides_by_pattern() -> feml::error::Result<()> {
+        use feml::data_type::f16_to_f32;
+        use feml::loader::StreamingLoader;
+        use feml::serialize::{Compression, TensorData, TensorReader, TensorWriter};
+
+        let names = ["token_embd.weight", "blk.0.attn_q", "blk.0.ffn_up", "blk.1.ffn_up"];
+        let mut writer = TensorWriter::new(Vec::new(), Compression::None)?;
+        for (i, name) in names.iter().enumerate() {
+            writer.write(&TensorData {
+                name: name.to_string(),
+                dtype: DataType::F32,
+                shape: shape![2],
+                data: encode_f32(&[i as f32, 0.5]),
+            })?;
+        }
+        let bytes = writer.finish()?;
+
+        let backend = feml::Backend::cpu().build()?;
+        let offload = feml::Backend::cpu().build()?;
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut progress = Vec::new();
+        let layers = StreamingLoader::new(TensorReader::new(bytes.as_slice())?, backend.inner())
+            .include("blk.*")
+            .exclude("blk.1.*")
+            .override_dtype("*.attn_*", DataType::F16)
+            .place("blk.0", offload.inner())
+            .on_progress(|p| progress.push((p.layers_loaded, p.tensors_loaded, p.bytes_loaded)))
+            .load_all(&mut ctx)?;
+
+        // the embeddings and all of blk.1 are filtered out
+        assert_eq!(layers.len(), 1);
+        let names: Vec<String> = layers[0].tensors.iter().map(|tensor| tensor.name()).collect();
+        assert_eq!(names, ["blk.0.attn_q", "blk.0.ffn_up"]);
+        assert_eq!(progress, [(1, 2, 4 + 8)]);
+
+        let query = &layers[0].tensors[0];
+        assert_eq!(query.dtype(), DataType::F16);
+        let mut values = vec![0; 4];
+        layers[0].buffer.read(query.clone(), &mut values, 0, 4)?;
+        let values: Vec<f32> = values
+            .chunks_exact(2)
+            .map(|bits| f16_to_f32(u16::from_ne_bytes([bits[0], bits[1]])))
+            .collect();
+        assert_eq!(values, [1.0, 0.5]);
+        assert_eq!(layers[0].tensors[1].dtype(), DataType::F32);
+        Ok(())
+    }
+
+    #[cfg(feature = "safetensors")]
+    #[test]
+    fn load_safetensors_reads_model_meta() -> feml::error::Result<()> {
+        use feml::loader::load_safetensors;
+
+        let mut header = String::from(concat!(
+            r#"{"__metadata__":{"general.architecture":"llama","llama.block_count":"2","#,
+            r#""llama.attention.head_count":"4","llama.vocab_size":"32000"},"#,
+            r#""w":{"dtype":"F32","shape":[2],"data_offsets":[0,8]}}"#
+        ));
+        while header.len() % 8 != 0 {
+            header.push(' ');
+        }
+        let mut file = (header.len() as u64).to_le_bytes().to_vec();
+        file.extend_from_slice(header.as_bytes());
+        file.extend(encode_f32(&[1.0, 2.0]));
+        let path =
+            std::env::temp_dir().join(format!("feml-meta-{}.safetensors", std::process::id()));
+        std::fs::write(&path, file)?;
+
+        let backend = feml::Backend::cpu().build()?;
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let model = load_safetensors(&path, &mut ctx, backend.inner());
+        std::fs::remove_file(path)?;
+        let model = model?;
+
+        assert_eq!(model.metadata[0], ("general.architecture".to_string(), "llama".to_string()));
+        let meta = model.meta()?;
+        assert_eq!((meta.architecture.as_str(), meta.n_layers), ("llama", 2));
+        assert_eq!((meta.n_heads, meta.n_kv_heads, meta.vocab_size), (4, 4, Some(32000)));
+        assert_eq!(meta.rope.freq_base, 10000.0);
+        Ok(())
+    }
+
+    #[test]
+    fn graph_outputs_survive_buffer_reuse() {
+        let mut ctx = Context::builder().tensor_pool_capacity(32).build();
+        let backend = feml::Backend::cpu().build().unwrap();
+        // out = a * b^4 through x1 = a * b, x2 and x3
+        let mut chain = || {
+            let mut a = ctx.new_tensor(DataType::F32, &shape![4]).unwrap();
+            let b = ctx.new_tensor(DataType::F32, &shape![4]).unwrap();
+            mark_as_leaf(&a);
+            mark_as_leaf(&b);
+            let mut x1 = a.mul(b.clone()).unwrap();
+            x1.set_name("x1");
+            let mut x2 = x1.mul(b.clone()).unwrap();
+            let mut x3 = x2.mul(b.clone()).unwrap();
+            let out = x3.mul(b.clone()).unwrap();
+            out.set_name("out");
+            (a, b, x1, out)
+        };
+        let (a, b, x1, out) = chain();
+        let (kept_a, kept_b, kept_x1, kept_out) = chain();
+
+        let run = |graph: &mut ComputeGraph, a: &Tensor, b: &Tensor| {
+            let buffer = backend.alloc_graph(&ctx, graph).unwrap();
+            backend.write(a, &encode_f32(&[1.0, 2.0, 3.0, 4.0])).unwrap();
+            backend.write(b, &encode_f32(&[2.0; 4])).unwrap();
+            backend.compute(&ctx, graph).unwrap();
+            buffer
+        };
+
+        // x1 is dead after x2 is computed, so x3 takes its place
+        let mut graph = ComputeGraph::new();
+        graph.add_output(&ctx, &out).unwrap();
+        let _buffer = run(&mut graph, &a, &b);
+        assert_eq!(decode_f32(&backend.read(&out).unwrap()), [16.0, 32.0, 48.0, 64.0]);
+        assert_eq!(decode_f32(&backend.read(&x1).unwrap()), [8.0, 16.0, 24.0, 32.0]);
+
+        let mut graph = ComputeGraph::new();
+        graph.add_output(&ctx, &kept_out).unwrap();
+        graph.add_output(&ctx, &kept_x1).unwrap();
+        let _kept_buffer = run(&mut graph, &kept_a, &kept_b);
+        let x1 = graph.output_by_name(&ctx, "x1").unwrap();
+        assert_eq!(x1.tensor_id(), kept_x1.tensor_id());
+        assert_eq!(decode_f32(&backend.read(&x1).unwrap()), [2.0, 4.0, 6.0, 8.0]);
+        let out = graph.output(&ctx, graph.outputs()[0]).unwrap();
+        assert_eq!(decode_f32(&backend.read(&out).unwrap()), [16.0, 32.0, 48.0, 64.0]);
+        assert!(graph.output(&ctx, kept_a.tensor_id()).is_err());
+    }
+
+    #[test]
+    fn exec_stats_aggregate_across_computes() {
+        use feml::exec_stats::{ExecStats, StatsObserver};
+        use std::sync::{Arc, Mutex};
+
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let mut backend = feml::Backend::cpu().build().unwrap();
+        let mut a = ctx.new_tensor(DataType::F32, &shape![4, 4]).unwrap();
+        let b = ctx.new_tensor(DataType::F32, &shape![4, 4]).unwrap();
+        mark_as_leaf(&a);
+        mark_as_leaf(&b);
+        let mut ab = a.mul(b.clone()).unwrap();
+        let out = ab.mul_mat(b.clone()).unwrap();
+        let _buffer = backend.alloc(&[a.clone(), b.clone(), ab.clone(), out.clone()]).unwrap();
+        backend.write(&a, &encode_f32(&[1.0; 16])).unwrap();
+        backend.write(&b, &encode_f32(&[2.0; 16])).unwrap();
+        let mut graph = ComputeGraph::new();
+        graph.add_output(&ctx, &out).unwrap();
+
+        let stats = Arc::new(Mutex::new(ExecStats::default()));
+        let observer = StatsObserver::new(stats.clone());
+        backend.inner_mut().set_observer(Some(Box::new(observer))).unwrap();
+        for _ in 0..3 {
+            backend.compute(&ctx, &mut graph).unwrap();
+        }
+
+        let stats = stats.lock().unwrap();
+        assert_eq!(stats.n_graphs(), 3);
+        assert_eq!(stats.ranked().len(), 2);
+        for op in [TensorOpType::TensorOpMul, TensorOpType::TensorOpMulMat] {
+            let op = stats.op(op).unwrap();
+            assert_eq!(op.count, 3);
+            assert!(op.min <= op.avg() && op.avg() <= op.max);
+        }
+        assert!(stats.to_string().starts_with("3 graphs"));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn graph_compute_emits_tracing_spans() {
+        use std::sync::{Arc, Mutex};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata};
+
+        /// Records the name and fields of every new span.
+        struct Spans(Arc<Mutex<Vec<String>>>);
+
+        impl tracing::Subscriber for Spans {
+            fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut spans = self.0.lock().unwrap();
+                spans.push(format!("{} {:?}", span.metadata().name(), span.values()));
+                Id::from_u64(spans.len() as u64)
+            }
+            fn record(&self, _span: &Id, _values: &Record<'_>) {}
+            fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+            fn event(&self, _event: &Event<'_>) {}
+            fn enter(&self, _span: &Id) {}
+            fn exit(&self, _span: &Id) {}
+        }
+
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let backend = feml::Backend::cpu().build().unwrap();
+        let mut a = ctx.new_tensor(DataType::F32, &shape![4, 2]).unwrap();
+        let b = ctx.new_tensor(DataType::F32, &shape![4, 2]).unwrap();
+        mark_as_leaf(&a);
+        mark_as_leaf(&b);
+        let out = a.mul(b.clone()).unwrap();
+        out.set_name("out");
+        let _buffer = backend.alloc(&[a.clone(), b.clone(), out.clone()]).unwrap();
+        let mut graph = ComputeGraph::new();
+        graph.add_output(&ctx, &out).unwrap();
+
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        tracing::subscriber::with_default(Spans(spans.clone()), || {
+            backend.compute(&ctx, &mut graph).unwrap();
+        });
+        let spans = spans.lock().unwrap();
+        assert_eq!(spans.len(), 2);
+        assert!(spans[0].starts_with("graph_compute"), "{}", spans[0]);
+        assert!(spans[0].contains("nodes: 1"), "{}", spans[0]);
+        for field in ["node", "TensorOpMul", "name: out", "shape: 4x2", "F32", "bytes: 32"] {
+            assert!(spans[1].contains(field), "{field} not in {}", spans[1]);
+        }
+    }
+
+    #[test]
+    fn alloc_failure_hook_runs_before_one_retry() {
+        use feml::buffer_pool::BufferPool;
+        use feml::memory::{set_alloc_failure_hook, AllocFailure};
+        use std::sync::{Arc, Mutex};
+
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let backend = feml::Backend::cpu().build().unwrap();
+        let small = ctx.new_tensor(DataType::F32, &shape![16]).unwrap();
+        let _small_buffer = backend.alloc(std::slice::from_ref(&small)).unwrap();
+        let huge = ctx.new_tensor(DataType::F32, &shape![1usize << 58]).unwrap();
+
+        let failures = Arc::new(Mutex::new(Vec::new()));
+        let seen = failures.clone();
+        set_alloc_failure_hook(Some(Box::new(move |failure: &AllocFailure<'_>| {
+            seen.lock().unwrap().push((failure.size, failure.report));
+            true
+        })));
+        // the retry fails as well, and there is only one
+        assert!(backend.alloc(std::slice::from_ref(&huge)).is_err());
+
+        let pool = BufferPool::new(backend.inner());
+        drop(pool.acquire(1024, BackendBufferUsage::Any).unwrap());
+        assert_eq!(pool.stats().cached_buffers, 1);
+        assert!(pool.acquire(usize::MAX / 2, BackendBufferUsage::Any).is_err());
+        // the pool gives back its own buffers before the hook runs
+        assert_eq!(pool.stats().cached_buffers, 0);
+        set_alloc_failure_hook(None);
+
+        let failures = failures.lock().unwrap();
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].0, 1usize << 60);
+        let report = failures[0].1.expect("alloc reports the context of its tensors");
+        assert_eq!((report.n_tensors, report.total()), (1, 64));
+        assert_eq!(failures[1], (usize::MAX / 2, None));
+    }
+
+    #[test]
+    fn graph_allocator_reserves_worst_case_once() {
+        let mut ctx = Context::builder().tensor_pool_capacity(32).build();
+        let backend = feml::Backend::cpu().build().unwrap();
+        // (a * b) * b over `n` rows of 16 values
+        let mut build = |n: usize| {
+            let mut a = ctx.new_tensor(DataType::F32, &shape![16, n]).unwrap();
+            let b = ctx.new_tensor(DataType::F32, &shape![16, n]).unwrap();
+            mark_as_leaf(&a);
+            mark_as_leaf(&b);
+            let mut ab = a.mul(b.clone()).unwrap();
+            let out = ab.mul(b.clone()).unwrap();
+            let graph = ComputeGraph::new();
+            graph.add_output(&ctx, &out).unwrap();
+            (graph, a, b, out)
+        };
+        let (mut prompt, prompt_a, prompt_b, prompt_out) = build(8);
+        let (mut decode, decode_a, decode_b, decode_out) = build(1);
+
+        let mut allocator = backend.graph_allocator();
+        let prompt_size = backend.measure_graph(&ctx, &prompt).unwrap();
+        assert!(backend.measure_graph(&ctx, &decode).unwrap() < prompt_size);
+        allocator.reserve(&ctx, &decode).unwrap();
+        assert_eq!(allocator.reserve(&ctx, &prompt).unwrap(), prompt_size);
+        assert_eq!(allocator.buffer_size(), 0);
+
+        for step in 0..3 {
+            let (graph, a, b, out, n) = if step == 0 {
+                (&mut prompt, &prompt_a, &prompt_b, &prompt_out, 8)
+            } else {
+                (&mut decode, &decode_a, &decode_b, &decode_out, 1)
+            };
+            allocator.alloc_graph(&ctx, graph).unwrap();
+            assert_eq!(allocator.buffer_size(), prompt_size);
+            backend.write(a, &encode_f32(&vec![step as f32; 16 * n])).unwrap();
+            backend.write(b, &encode_f32(&vec![2.0; 16 * n])).unwrap();
+            backend.compute(&ctx, graph).unwrap();
+            let expected = vec![4.0 * step as f32; 16 * n];
+            assert_eq!(decode_f32(&backend.read(out).unwrap()), expected);
+        }
+    }
+
+    #[test]
+    fn registered_host_memory_is_tracked_and_used_for_transfers() {
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let backend = feml::Backend::cpu().build().unwrap();
+        let tensor = ctx.new_tensor(DataType::F32, &shape![4]).unwrap();
+        let _buffer = backend.alloc(std::slice::from_ref(&tensor)).unwrap();
+        backend.write(&tensor, &encode_f32(&[1.0, 2.0, 3.0, 4.0])).unwrap();
+
+        let mut staging = vec![0u8; 32];
+        let ptr = staging.as_ptr();
+        {
+            let mut registered = backend.register_host_memory(&mut staging).unwrap();
+            assert!(backend.inner().is_host_registered(ptr, 32));
+            assert!(backend.inner().is_host_registered(ptr.wrapping_add(16), 16));
+            assert!(!backend.inner().is_host_registered(ptr.wrapping_add(16), 32));
+
+            backend.read_into(&tensor, &mut registered[16..]).unwrap();
+            assert!(backend.read_into(&tensor, &mut registered[..8]).is_err());
+            registered[..16].copy_from_slice(&encode_f32(&[5.0, 6.0, 7.0, 8.0]));
+            backend.write_from(&tensor, &mut registered[..16]).unwrap();
+            // SAFETY: the registration fails, as the range is already registered.
+            let overlap = unsafe { backend.inner().register_host_memory(ptr.cast_mut(), 8) };
+            assert!(overlap.is_err());
+            let mut again = [0u8; 8];
+            let again_ptr = again.as_mut_ptr();
+            // SAFETY: `again` outlives the registration, which is undone right away.
+            unsafe { backend.inner().register_host_memory(again_ptr, 8) }.unwrap();
+            backend.inner().unregister_host_memory(again_ptr).unwrap();
+        }
+        assert!(!backend.inner().is_host_registered(ptr, 32));
+        assert_eq!(decode_f32(&staging[16..]), [1.0, 2.0, 3.0, 4.0]);
+
+        // unregistered memory is staged
+        backend.read_into(&tensor, &mut staging[16..]).unwrap();
+        assert_eq!(decode_f32(&staging[16..]), [5.0, 6.0, 7.0, 8.0]);
+        backend.write_from(&tensor, &mut staging[..16]).unwrap();
+        assert_eq!(decode_f32(&backend.read(&tensor).unwrap()), [5.0, 6.0, 7.0, 8.0]);
+        assert!(backend.write_from(&tensor, &mut staging[..8]).is_err());
+    }
+
+    #[test]
+    fn copy_on_write_view_keeps_weights_unmodified() {
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let backend = feml::Backend::cpu().build().unwrap();
+        let mut w = ctx.new_tensor(DataType::F32, &shape![4]).unwrap();
+        let x = ctx.new_tensor(DataType::F32, &shape![2]).unwrap();
+        mark_as_leaf(&w);
+        mark_as_leaf(&x);
+        w.set_copy_on_write(true);
+
+        let mut half = w.view(&shape![2], 8).unwrap();
+        assert!(half.is_copy_on_write());
+        let mut scaled = half.mul_inplace(x.clone()).unwrap();
+        // the first in-place op materializes a private copy, later ones work in place on it
+        assert!(!scaled.is_copy_on_write());
+        let twice = scaled.mul_inplace(x.clone()).unwrap();
+        assert!(twice.src_tensor().contains(&scaled.tensor_id()));
+
+        let mut graph = ComputeGraph::new();
+        graph.add_output(&ctx, &twice).unwrap();
+        let _buffer = backend.alloc_graph(&ctx, &graph).unwrap();
+        backend.write(&w, &encode_f32(&[1.0, 2.0, 3.0, 4.0])).unwrap();
+        backend.write(&x, &encode_f32(&[10.0, 100.0])).unwrap();
+        backend.compute(&ctx, &mut graph).unwrap();
+
+        assert_eq!(decode_f32(&backend.read(&twice).unwrap()), [300.0, 40000.0]);
+        assert_eq!(decode_f32(&backend.read(&w).unwrap()), [1.0, 2.0, 3.0, 4.0]);
+
+        let mut other = Context::builder().tensor_pool_capacity(4).build();
+        assert!(other.share_tensor(&x).unwrap().is_copy_on_write());
+    }
+
+    #[test]
+    fn dropout_replays_from_saved_rng_state() {
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let backend = feml::Backend::cpu().build().unwrap();
+        let mut x = ctx.new_tensor(DataType::F32, &shape![256]).unwrap();
+        mark_as_leaf(&x);
+        let mut graph = ComputeGraph::new();
+        let rng = graph.rng(&mut ctx).unwrap();
+        let out = x.dropout(&rng, 0.25).unwrap();
+        graph.add_output(&ctx, &out).unwrap();
+        assert!(x.dropout(&rng, 1.0).is_err());
+        assert!(x.dropout(&x.clone(), 0.5).is_err());
+
+        let _buffer = backend.alloc_graph(&ctx, &graph).unwrap();
+        backend.write(&x, &encode_f32(&[1.0; 256])).unwrap();
+        backend.set_rng_state(&ctx, &graph, RngState::new(7)).unwrap();
+        let saved = backend.rng_state(&ctx, &graph).unwrap();
+        let run = |graph: &mut ComputeGraph| {
+            backend.compute(&ctx, graph).unwrap();
+            decode_f32(&backend.read(&out).unwrap())
+        };
+
+        let first = run(&mut graph);
+        let kept: Vec<f32> = first.iter().copied().filter(|&v| v != 0.0).collect();
+        assert!(kept.iter().all(|&v| v == 1.0 / 0.75));
+        assert!((160..224).contains(&kept.len()), "{}", kept.len());
+        // every compute draws anew, and restoring the state replays the first one
+        let second = run(&mut graph);
+        assert_ne!(first, second);
+        assert_eq!(backend.rng_state(&ctx, &graph).unwrap().counter, 2);
+        backend.set_rng_state(&ctx, &graph, saved).unwrap();
+        assert_eq!(run(&mut graph), first);
+    }
+
+    #[test]
+    fn rope_store_kv_writes_rotated_keys_into_cache_slots() -> feml::error::Result<()> {
+        use feml::model_meta::RopeParams;
+
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let cache = KvCache::new(&mut ctx, DataType::F32, 1, 3, 2, 4)?.with_window(2)?;
+        let mut k = ctx.new_tensor(DataType::F32, &shape![4, 2, 2])?;
+        let v = ctx.new_tensor(DataType::F32, &shape![4, 2, 2])?;
+        let positions = ctx.new_tensor(DataType::I32, &shape![2])?;
+        for tensor in [&k, &v, &positions].into_iter().chain(cache.tensors()) {
+            mark_as_leaf(tensor);
+        }
+        let rope = RopeParams::default();
+        assert!(cache.store_rope(1, &mut k, &v, &positions, rope).is_err());
+        let odd = RopeParams { n_dims: Some(3), ..rope };
+        assert!(cache.store_rope(0, &mut k, &v, &positions, odd).is_err());
+        assert!(cache.store_rope(0, &mut k, &v, &v, rope).is_err());
+        let stored = cache.store_rope(0, &mut k, &v, &positions, rope)?;
+        let mut graph = ctx.new_graph(8)?;
+        graph.build_forward(&ctx, stored.tensor_id(), true)?;
+
+        let backend = feml::Backend::cpu().build()?;
+        let mut tensors = vec![k.clone(), v.clone(), positions.clone()];
+        tensors.extend(cache.tensors().cloned());
+        tensors.push(stored.clone());
+        let _buffer = backend.alloc(&tensors)?;
+        let keys: Vec<f32> = (0..16).map(|i| i as f32 + 1.0).collect();
+        backend.write(&k, &encode_f32(&keys))?;
+        backend.write(&v, &encode_f32(&keys.iter().map(|x| -x).collect::<Vec<_>>()))?;
+        backend.write(&positions, &[1i32, 3].map(i32::to_le_bytes).concat())?;
+        for tensor in cache.tensors() {
+            backend.write(tensor, &encode_f32(&[0.5; 24]))?;
+        }
+        backend.compute(&ctx, &mut graph)?;
+
+        // token t of head h goes to slot position % 3 of that head, rotated by its position
+        let cache_k = decode_f32(&backend.read(cache.keys(0)?)?);
+        let cache_v = decode_f32(&backend.read(cache.values(0)?)?);
+        for (t, (position, slot)) in [(1.0f32, 1), (3.0, 0)].into_iter().enumerate() {
+            for h in 0..2 {
+                let row = &keys[8 * t + 4 * h..][..4];
+                let at = 4 * slot + 12 * h;
+                for (i, theta) in [position, position * 0.01].into_iter().enumerate() {
+                    let (sin, cos) = theta.sin_cos();
+                    let (x0, x1) = (row[2 * i], row[2 * i + 1]);
+                    assert!((cache_k[at + 2 * i] - (x0 * cos - x1 * sin)).abs() < 1e-5);
+                    assert!((cache_k[at + 2 * i + 1] - (x0 * sin + x1 * cos)).abs() < 1e-5);
+                }
+                assert_eq!(cache_v[at..at + 4], row.iter().map(|x| -x).collect::<Vec<_>>());
+            }
+        }
+        assert_eq!(cache_k[8..12], [0.5; 4]);
+        assert_eq!(cache_v[20..24], [0.5; 4]);
+        Ok(())
+    }
+
+    #[test]
+    fn mul_mat_rowwise_q8_requantizes_the_output() -> feml::error::Result<()> {
+        use feml::quant::quantize_rows_q8;
+
+        // rows of 64 values take the vector dot products where the CPU has them
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let mut w = ctx.new_tensor(DataType::I8, &shape![64, 5])?;
+        let scales = ctx.new_tensor(DataType::F32, &shape![5])?;
+        let x = ctx.new_tensor(DataType::F32, &shape![64, 3])?;
+        for tensor in [&w, &scales, &x] {
+            mark_as_leaf(tensor);
+        }
+        assert!(w.mul_mat_rowwise_q8(scales.clone(), x.clone(), 0.0).is_err());
+        let y = w.mul_mat_rowwise(scales.clone(), x.clone())?;
+        let y_q = w.mul_mat_rowwise_q8(scales.clone(), x.clone(), 0.05)?;
+        assert_eq!(y_q.dtype(), DataType::I8);
+
+        let weights: Vec<f32> = (0..320).map(|i| ((i * 29 % 41) as f32 - 20.0) / 40.0).collect();
+        let input: Vec<f32> = (0..192).map(|i| ((i * 13 % 17) as f32 - 8.0) / 8.0).collect();
+        let quantized = quantize_rows_q8(&weights, 64)?;
+        let backend = feml::Backend::cpu().with_threads(2).build()?;
+        let tensors = [w.clone(), scales.clone(), x.clone(), y.clone(), y_q.clone()];
+        let _buffer = backend.alloc(&tensors)?;
+        backend.write(&w, &quantized.value_bytes())?;
+        backend.write(&scales, &encode_f32(&quantized.scales))?;
+        backend.write(&x, &encode_f32(&input))?;
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, y.tensor_id(), true)?;
+        graph.build_forward(&ctx, y_q.tensor_id(), true)?;
+        backend.compute(&ctx, &mut graph)?;
+
+        let y = decode_f32(&backend.read(&y)?);
+        let y_q = backend.read(&y_q)?;
+        for (m, (y, q)) in y.iter().zip(&y_q).enumerate() {
+            let (w_row, x_row) = (&weights[m % 5 * 64..][..64], &input[m / 5 * 64..][..64]);
+            let exact: f32 = w_row.iter().zip(x_row).map(|(w, x)| w * x).sum();
+            assert!((y - exact).abs() <= 0.05 * exact.abs().max(1.0), "{y} != {exact}");
+            assert_eq!(*q as i8, (y / 0.05).round().clamp(-127.0, 127.0) as i8);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn native_f16_precision_matches_f32_mul_mat() {
+        use feml::backend::Backend;
+        use feml::cpu::backend::{CpuBackend, Precision};
+        use feml::data_type::{f16_to_f32, f32_to_f16};
+
+        let (k, m, n) = (40, 5, 3);
+        let mut backend = CpuBackend::init().expect("CPU backend should open");
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut lhs = ctx.new_tensor(DataType::F16, &shape![k, m]).unwrap();
+        let rhs = ctx.new_tensor(DataType::F16, &shape![k, n]).unwrap();
+        mark_as_leaf(&lhs);
+        mark_as_leaf(&rhs);
+        let out = lhs.mul_mat(rhs.clone()).unwrap();
+
+        let buffer = backend.create_buffer(4096, BackendBufferUsage::Any).unwrap();
+        buffer.init_tensor(lhs.clone(), 0).unwrap();
+        buffer.init_tensor(rhs.clone(), 1024).unwrap();
+        buffer.init_tensor(out.clone(), 2048).unwrap();
+        let encode = |count: usize, step: usize| -> Vec<u8> {
+            let value = |i: usize| ((i * step) % 13) as f32 / 8.0 - 0.75;
+            (0..count).flat_map(|i| f32_to_f16(value(i)).to_ne_bytes()).collect()
+        };
+        buffer.write(lhs, &mut encode(k * m, 7), 0, k * m * 2).unwrap();
+        buffer.write(rhs, &mut encode(k * n, 5), 0, k * n * 2).unwrap();
+
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, out.tensor_id(), false).unwrap();
+        let mut run = |backend: &CpuBackend| {
+            backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+            let mut output = vec![0; out.nbytes()];
+            buffer.read(out.clone(), &mut output, 0, out.nbytes()).unwrap();
+            let halves = output.chunks_exact(2).map(|b| u16::from_ne_bytes([b[0], b[1]]));
+            halves.map(f16_to_f32).collect::<Vec<_>>()
+        };
+
+        assert_eq!(backend.precision(), Precision::F32);
+        let widened = run(&backend);
+        backend.set_precision(Precision::NativeF16);
+        let native = run(&backend);
+        // inputs in steps of 1/8 keep every product and partial sum exact in F16
+        assert_eq!(native, widened, "native F16 path: {:?}", backend.native_f16());
+    }
+
+    #[test]
+    fn streaming_loader_repacks_weights_for_mul_mat() -> feml::error::Result<()> {
+        use feml::backend::Backend;
+        use feml::cpu::backend::CpuBackend;
+        use feml::loader::StreamingLoader;
+        use feml::repack::deinterleave_rows;
+        use feml::serialize::{Compression, TensorData, TensorReader, TensorWriter};
+
+        let (k, m, n) = (6, 16, 3);
+        let weights: Vec<f32> = (0..k * m).map(|i| (i % 7) as f32 - 3.0).collect();
+        let mut writer = TensorWriter::new(Vec::new(), Compression::None)?;
+        for (name, shape, data) in [
+            ("blk.0.ffn_up", shape![k, m], encode_f32(&weights)),
+            ("blk.0.ffn_norm", shape![k], encode_f32(&[1.0; 6])),
+        ] {
+            let name = name.to_string();
+            writer.write(&TensorData { name, dtype: DataType::F32, shape, data })?;
+        }
+        let bytes = writer.finish()?;
+
+        let backend = CpuBackend::init()?;
+        let panel = backend.blk_size_interleave(TensorOpType::TensorOpMulMat, DataType::F32);
+        assert_eq!(panel, Some(8));
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let layers = StreamingLoader::new(TensorReader::new(bytes.as_slice())?, &backend)
+            .repack("blk.*")
+            .load_all(&mut ctx)?;
+        let (up, norm) = (layers[0].tensors[0].clone(), layers[0].tensors[1].clone());
+        assert_eq!((up.interleaved_rows(), norm.interleaved_rows()), (Some(8), None));
+        let mut stored = vec![0; up.nbytes()];
+        layers[0].buffer.read(up.clone(), &mut stored, 0, up.nbytes())?;
+        assert_ne!(decode_f32(&stored), weights);
+        assert_eq!(decode_f32(&deinterleave_rows(&stored, 4, k, 8)?), weights);
+
+        let input: Vec<f32> = (0..k * n).map(|i| (i % 5) as f32 - 2.0).collect();
+        let x = ctx.new_tensor(DataType::F32, &shape![k, n])?;
+        mark_as_leaf(&x);
+        let y = up.clone().mul_mat(x.clone())?;
+        let buffer = backend.create_buffer(1024, BackendBufferUsage::Any)?;
+        buffer.init_tensor(x.clone(), 0)?;
+        buffer.init_tensor(y.clone(), 512)?;
+        buffer.write(x, &mut encode_f32(&input), 0, k * n * 4)?;
+
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, y.tensor_id(), false)?;
+        backend.graph_compute(&ctx, &mut graph)?;
+        let mut output = vec![0; y.nbytes()];
+        buffer.read(y, &mut output, 0, m * n * 4)?;
+        let expected: Vec<f32> = (0..m * n)
+            .map(|i| {
+                let (w_row, x_row) = (&weights[i % m * k..][..k], &input[i / m * k..][..k]);
+                w_row.iter().zip(x_row).map(|(w, x)| w * x).sum()
+            })
+            .collect();
+        assert_eq!(decode_f32(&output), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn first_touch_leaves_compute_buffers_zeroed() -> feml::error::Result<()> {
+        use feml::backend::Backend;
+        use feml::cpu::backend::{CpuBackend, FIRST_TOUCH_BYTES_PER_THREAD};
+
+        let mut backend = CpuBackend::init()?;
+        backend.set_n_threads(4)?;
+        assert!(!backend.first_touch());
+        backend.set_first_touch(true);
+
+        // F32 elements, so the buffer holds the threshold for each of the four threads
+        let n = FIRST_TOUCH_BYTES_PER_THREAD + 3;
+        let buffer = backend.create_buffer(n * 4, BackendBufferUsage::Compute)?;
+        let mut ctx = Context::builder().tensor_pool_capacity(2).build();
+        let tensor = ctx.new_tensor(DataType::F32, &shape![n])?;
+        buffer.init_tensor(tensor.clone(), 0)?;
+        let mut data = vec![1; n * 4];
+        buffer.read(tensor.clone(), &mut data, 0, n * 4)?;
+        assert!(data.iter().all(|&b| b == 0));
+
+        buffer.write(tensor.clone(), &mut encode_f32(&[2.5; 3]), (n - 3) * 4, 12)?;
+        let mut tail = vec![0; 12];
+        buffer.read(tensor, &mut tail, (n - 3) * 4, 12)?;
+        assert_eq!(decode_f32(&tail), [2.5; 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn backend_features_report_the_selected_code_paths() -> feml::error::Result<()> {
+        let backend = feml::Backend::cpu().with_threads(3).build()?;
+        let features = backend.features();
+        let value = |name: &str| {
+            let feature = features.iter().find(|feature| feature.name == name);
+            feature.map(|feature| feature.value.clone())
+        };
+        assert_eq!(value("threads").as_deref(), Some("3"));
+        assert_eq!(value("reference_kernels").as_deref(), Some("0"));
+        assert_eq!(value("precision").as_deref(), Some("f32"));
+        assert!(value("dot_q8").is_some() && value("gemm_blocking").is_some());
+        #[cfg(target_arch = "x86_64")]
+        assert!(value("avx2").is_some());
+
+        let threads = features.iter().find(|feature| feature.name == "threads").unwrap();
+        assert_eq!(threads.to_string(), "threads=3");
+        Ok(())
+    }
+
+    #[test]
+    fn eager_trace_replays_on_new_inputs() -> feml::error::Result<()> {
+        use feml::eager::Eager;
+
+        let backend = feml::Backend::cpu().build()?;
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let mut eager = Eager::new(&backend);
+        let x = eager.input(&mut ctx, DataType::F32, &shape![3], &encode_f32(&[1.0, 2.0, 3.0]))?;
+        let w = eager.input(&mut ctx, DataType::F32, &shape![3], &encode_f32(&[2.0; 3]))?;
+
+        let square = x.clone().mul(x.clone())?;
+        eager.eval(&ctx, &square)?;
+        assert_eq!(decode_f32(&eager.read(&square)?), [1.0, 4.0, 9.0]);
+
+        let mut steps = 0;
+        let mut graph = eager.trace(&mut ctx, |eager, ctx| {
+            let scaled = x.clone().mul(w.clone())?;
+            eager.eval(ctx, &scaled)?;
+            steps += 1;
+            Ok(vec![scaled.clone().mul(x.clone())?])
+        })?;
+        let output = graph.output(&ctx, graph.outputs()[0])?;
+        assert_eq!(decode_f32(&eager.read(&output)?), [2.0, 8.0, 18.0]);
+        assert_eq!(graph.node_count(), 2);
+
+        backend.write(&x, &encode_f32(&[-1.0, 0.5, 4.0]))?;
+        backend.compute(&ctx, &mut graph)?;
+        assert_eq!(decode_f32(&backend.read(&output)?), [2.0, 0.5, 32.0]);
+        assert_eq!(steps, 1);
+
+        let unbound = ctx.new_tensor(DataType::F32, &shape![3])?;
+        mark_as_leaf(&unbound);
+        let product = unbound.clone().mul(x.clone())?;
+        assert!(eager.eval(&ctx, &product).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn binary_ops_broadcast_and_chain_in_one_graph() -> feml::error::Result<()> {
+        use feml::data_type::{f16_to_f32, f32_to_f16};
+
+        let backend = feml::Backend::cpu().with_threads(2).build()?;
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let mut x = ctx.new_tensor(DataType::F32, &shape![3, 2])?;
+        let bias = ctx.new_tensor(DataType::F32, &shape![3])?;
+        let scale = ctx.new_tensor(DataType::F32, &shape![1, 2])?;
+        for tensor in [&x, &bias, &scale] {
+            mark_as_leaf(tensor);
+        }
+        let sum = x.add(bias.clone())?;
+        let out = sum.clone().div(scale.clone())?;
+        let diff = out.clone().sub(x.clone())?;
+
+        let mut h = ctx.new_tensor(DataType::F16, &shape![2])?;
+        mark_as_leaf(&h);
+        let product = h.mul(h.clone())?;
+
+        let tensors = [&x, &bias, &scale, &sum, &out, &diff, &h, &product].map(Tensor::clone);
+        let _buffer = backend.alloc(&tensors)?;
+        backend.write(&x, &encode_f32(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]))?;
+        backend.write(&bias, &encode_f32(&[1.0, 0.0, -1.0]))?;
+        backend.write(&scale, &encode_f32(&[2.0, 4.0]))?;
+        let halves = [1.5f32, -3.0].map(|v| f32_to_f16(v).to_ne_bytes());
+        backend.write(&h, halves.as_flattened())?;
+
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, diff.tensor_id(), true)?;
+        graph.build_forward(&ctx, product.tensor_id(), true)?;
+        backend.compute(&ctx, &mut graph)?;
+
+        assert_eq!(decode_f32(&backend.read(&out)?), [1.0, 1.0, 1.0, 1.25, 1.25, 1.25]);
+        assert_eq!(decode_f32(&backend.read(&diff)?), [0.0, -1.0, -2.0, -2.75, -3.75, -4.75]);
+        let product = backend.read(&product)?;
+        let product: Vec<f32> =
+            product.chunks_exact(2).map(|b| f16_to_f32(u16::from_ne_bytes([b[0], b[1]]))).collect();
+        assert_eq!(product, [2.25, 9.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn op_fixtures_pass_on_the_cpu_backend() -> feml::error::Result<()> {
+        let backend = feml::Backend::cpu().with_threads(2).build()?;
+        let reports = feml::testing::run_op_fixtures(&backend)?;
+        assert_eq!(reports.len(), feml::testing::op_fixtures().len());
+        for report in &reports {
+            assert!(report.passed, "{report:?}");
+        }
+
+        let fixture = &feml::testing::op_fixtures()[0];
+        let mut wrong = fixture.expected.values.clone();
+        wrong[1] += 0.5;
+        let report = fixture.check(&wrong)?;
+        assert!(!report.passed);
+        assert_eq!((report.max_abs_error, report.worst_index), (0.5, 1));
+        assert!(fixture.check(&wrong[1..]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn soft_max_normalizes_masked_rows() -> feml::error::Result<()> {
+        let backend = feml::Backend::cpu().with_threads(2).build()?;
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let mut scores = ctx.new_tensor(DataType::F32, &shape![3, 3])?;
+        mark_as_leaf(&scores);
+        let mut masked = scores.diag_mask_inf(0)?;
+        let probs = masked.soft_max()?;
+
+        let _buffer = backend.alloc(&[scores.clone(), masked, probs.clone()])?;
+        let values = [1000.0, 0.0, 5.0, 2.0, 2.0, -1.0, -3.0, -3.0, -3.0];
+        backend.write(&scores, &encode_f32(&values))?;
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, probs.tensor_id(), true)?;
+        backend.compute(&ctx, &mut graph)?;
+
+        let probs = decode_f32(&backend.read(&probs)?);
+        let expected = [1.0, 0.0, 0.0, 0.5, 0.5, 0.0, 1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0];
+        for (actual, expected) in probs.iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-6, "{probs:?}");
+        }
+
+        let mut empty = ctx.new_tensor(DataType::F32, &shape![2])?;
+        mark_as_leaf(&empty);
+        let empty_probs = empty.soft_max()?;
+        let _buffer = backend.alloc(&[empty.clone(), empty_probs.clone()])?;
+        backend.write(&empty, &encode_f32(&[f32::NEG_INFINITY; 2]))?;
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, empty_probs.tensor_id(), true)?;
+        backend.compute(&ctx, &mut graph)?;
+        assert_eq!(decode_f32(&backend.read(&empty_probs)?), [0.0, 0.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn rms_norm_scales_rows_to_unit_mean_square() -> feml::error::Result<()> {
+        let (ne0, rows, eps) = (19, 3, 1e-5);
+        let backend = feml::Backend::cpu().with_threads(2).build()?;
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let mut x = ctx.new_tensor(DataType::F32, &shape![ne0, rows])?;
+        let weight = ctx.new_tensor(DataType::F32, &shape![ne0])?;
+        mark_as_leaf(&x);
+        mark_as_leaf(&weight);
+        let mut norm = x.rms_norm(eps)?;
+        let out = norm.mul(weight.clone())?;
+
+        let _buffer = backend.alloc(&[x.clone(), weight.clone(), norm, out.clone()])?;
+        let values: Vec<f32> = (0..ne0 * rows).map(|i| ((i * 7) % 23) as f32 - 11.0).collect();
+        let weights: Vec<f32> = (0..ne0).map(|i| 0.5 + i as f32 / 10.0).collect();
+        backend.write(&x, &encode_f32(&values))?;
+        backend.write(&weight, &encode_f32(&weights))?;
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, out.tensor_id(), true)?;
+        backend.compute(&ctx, &mut graph)?;
+
+        let out = decode_f32(&backend.read(&out)?);
+        for (row, (x, out)) in values.chunks(ne0).zip(out.chunks(ne0)).enumerate() {
+            let mean_square = x.iter().map(|&v| f64::from(v * v)).sum::<f64>() / ne0 as f64;
+            let scale = 1.0 / (mean_square + f64::from(eps)).sqrt();
+            for ((&x, &w), &actual) in x.iter().zip(&weights).zip(out) {
+                let expected = f64::from(x) * scale * f64::from(w);
+                assert!((f64::from(actual) - expected).abs() < 1e-5, "row {row}: {out:?}");
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn provenance_points_errors_at_the_building_line() -> feml::error::Result<()> {
+        let backend = feml::Backend::cpu().build()?;
+        let mut ctx = Context::builder().tensor_pool_capacity(16).provenance(true).build();
+        let mut a = ctx.new_tensor(DataType::I32, &shape![4])?;
+        let b = ctx.new_tensor(DataType::I32, &shape![4])?;
+        mark_as_leaf(&a);
+        mark_as_leaf(&b);
+        let (sum, line) = (a.add(b.clone())?, line!());
+        sum.set_name("sum");
+        let origin = sum.origin().expect("provenance is on");
+        assert_eq!((origin.file(), origin.line()), (file!(), line));
+
+        let _buffer = backend.alloc(&[a, b.clone(), sum.clone()])?;
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, sum.tensor_id(), true)?;
+        let message = backend.compute(&ctx, &mut graph).unwrap_err().to_string();
+        let node = format!("'sum' (TensorOpAdd) built at {}:{line}:", file!());
+        assert!(message.contains(&node), "{message}");
+
+        let x = ctx.new_tensor(DataType::F32, &shape![3])?;
+        let graph = ComputeGraph::new();
+        let (_, line) =
+            (graph.build_node("bad", &[&x, &b], || x.clone().mul_mat(b.clone()))?, line!());
+        let errors = graph.finish(&ctx, &[]).unwrap_err();
+        let message = errors[0].to_string();
+        assert!(message.contains(&format!("built at {}:{line}:", file!())), "{message}");
+
+        let mut plain = Context::builder().tensor_pool_capacity(4).build();
+        let mut c = plain.new_tensor(DataType::F32, &shape![2])?;
+        assert!(c.add(c.clone())?.origin().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn layer_norm_normalizes_then_scales_and_shifts_rows() -> feml::error::Result<()> {
+        let (ne0, rows, eps) = (21, 4, 1e-5);
+        let backend = feml::Backend::cpu().with_threads(3).build()?;
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let mut x = ctx.new_tensor(DataType::F32, &shape![ne0, rows])?;
+        let gamma = ctx.new_tensor(DataType::F32, &shape![ne0])?;
+        let beta = ctx.new_tensor(DataType::F32, &shape![ne0])?;
+        for tensor in [&x, &gamma, &beta] {
+            mark_as_leaf(tensor);
+        }
+        let out = x.layer_norm(gamma.clone(), beta.clone(), eps)?;
+        assert!(x.layer_norm(beta.clone(), x.clone(), eps).is_err());
+
+        let _buffer = backend.alloc(&[x.clone(), gamma.clone(), beta.clone(), out.clone()])?;
+        let values: Vec<f32> =
+            (0..ne0 * rows).map(|i| ((i * 5) % 17) as f32 * 0.5 + 100.0).collect();
+        let weights: Vec<f32> = (0..ne0).map(|i| 1.0 + i as f32 / 8.0).collect();
+        let biases: Vec<f32> = (0..ne0).map(|i| i as f32 - 10.0).collect();
+        backend.write(&x, &encode_f32(&values))?;
+        backend.write(&gamma, &encode_f32(&weights))?;
+        backend.write(&beta, &encode_f32(&biases))?;
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, out.tensor_id(), true)?;
+        backend.compute(&ctx, &mut graph)?;
+
+        let out = decode_f32(&backend.read(&out)?);
+        for (x, out) in values.chunks(ne0).zip(out.chunks(ne0)) {
+            let x: Vec<f64> = x.iter().map(|&v| f64::from(v)).collect();
+            let mean = x.iter().sum::<f64>() / ne0 as f64;
+            let var = x.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / ne0 as f64;
+            for (i, (&x, &actual)) in x.iter().zip(out).enumerate() {
+                let norm = (x - mean) / (var + f64::from(eps)).sqrt();
+                let expected = norm * f64::from(weights[i]) + f64::from(biases[i]);
+                assert!((f64::from(actual) - expected).abs() < 1e-4, "{out:?}");
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn oversized_allocations_fail_instead_of_aborting() -> feml::error::Result<()> {
+        let backend = feml::Backend::cpu().build()?;
+        assert_eq!(backend.inner().max_buffer_size(), isize::MAX as usize);
+        let mut ctx = Context::builder().tensor_pool_capacity(4).build();
+        let small = ctx.new_tensor(DataType::F32, &shape![5])?;
+        assert_eq!(backend.inner().alloc_size(&small), 64);
+
+        let huge = ctx.new_tensor(DataType::F32, &shape![1usize << 61])?;
+        let error = backend.alloc(&[huge]).err().expect("the allocation must fail");
+        assert!(error.to_string().contains("exceed"), "{error}");
+        Ok(())
+    }
+
+    #[test]
+    fn gelu_f16_table_matches_the_f32_formulas() {
+        use feml::backend::Backend;
+        use feml::cpu::backend::CpuBackend;
+        use feml::data_type::{f16_to_f32, f32_to_f16};
+        use feml::tensor::GeluMode;
+
+        let n = 97;
+        let mut backend = CpuBackend::init().expect("CPU backend should open");
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut x = ctx.new_tensor(DataType::F16, &shape![n]).unwrap();
+        mark_as_leaf(&x);
+        let tanh = x.gelu(GeluMode::Tanh).unwrap();
+        let exact = x.gelu(GeluMode::Exact).unwrap();
+
+        let buffer = backend.create_buffer(1024, BackendBufferUsage::Any).unwrap();
+        buffer.init_tensor(x.clone(), 0).unwrap();
+        buffer.init_tensor(tanh.clone(), 256).unwrap();
+        buffer.init_tensor(exact.clone(), 512).unwrap();
+        let values: Vec<f32> = (0..n).map(|i| (i as f32 - 48.0) / 8.0).collect();
+        let mut bytes: Vec<u8> = values.iter().flat_map(|&v| f32_to_f16(v).to_ne_bytes()).collect();
+        buffer.write(x.clone(), &mut bytes, 0, n * 2).unwrap();
+
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, tanh.tensor_id(), false).unwrap();
+        graph.build_forward(&ctx, exact.tensor_id(), true).unwrap();
+        let mut run = |backend: &CpuBackend| {
+            backend.graph_compute(&ctx, &mut graph).expect("CPU graph compute should succeed");
+            [&tanh, &exact].map(|out| {
+                let mut output = vec![0; out.nbytes()];
+                buffer.read(out.clone(), &mut output, 0, out.nbytes()).unwrap();
+                output.chunks_exact(2).map(|b| u16::from_ne_bytes([b[0], b[1]])).collect::<Vec<_>>()
+            })
+        };
+
+        let table = run(&backend);
+        backend.set_reference_kernels(true);
+        let reference = run(&backend);
+        assert_eq!(table, reference);
+        for (i, &x) in values.iter().enumerate() {
+            let x = f64::from(x);
+            let inner = (2.0 / std::f64::consts::PI).sqrt() * (x + 0.044715 * x * x * x);
+            let expected = 0.5 * x * (1.0 + inner.tanh());
+            let actual = f64::from(f16_to_f32(table[0][i]));
+            assert!((actual - expected).abs() <= 2e-3 * expected.abs().max(1.0), "{x}: {actual}");
+        }
+        // at -2 the formulas differ by 1e-4, well above the F16 resolution there
+        let differs = |i: usize| table[0][i] != table[1][i];
+        assert!(differs(32) && !differs(48), "{:?}", table);
+    }
+
+    #[test]
+    fn mul_mat_computes_weights_of_a_registered_data_type() -> feml::error::Result<()> {
+        use feml::data_type::{register_data_type, CustomDataType};
+
+        // Blocks of 8 int8 values sharing an f32 scale.
+        fn to_f32(src: &[u8], dst: &mut [f32]) {
+            for (block, dst) in src.chunks_exact(12).zip(dst.chunks_exact_mut(8)) {
+                let scale = f32::from_ne_bytes(block[..4].try_into().unwrap());
+                for (&q, value) in block[4..].iter().zip(dst) {
+                    *value = scale * f32::from(q as i8);
+                }
+            }
+        }
+        fn from_f32(src: &[f32], dst: &mut [u8]) {
+            for (values, block) in src.chunks_exact(8).zip(dst.chunks_exact_mut(12)) {
+                let max = values.iter().fold(0.0f32, |max, value| max.max(value.abs()));
+                let scale = max / 127.0;
+                block[..4].copy_from_slice(&scale.to_ne_bytes());
+                for (value, q) in values.iter().zip(&mut block[4..]) {
+                    *q = if scale == 0.0 { 0 } else { (value / scale).round() as i8 as u8 };
+                }
+            }
+        }
+        fn vec_dot(x: &[u8], y: &[f32]) -> f32 {
+            let mut sum = 0.0;
+            for (block, y) in x.chunks_exact(12).zip(y.chunks_exact(8)) {
+                let scale = f32::from_ne_bytes(block[..4].try_into().unwrap());
+                let dot: f32 = block[4..].iter().zip(y).map(|(&q, y)| f32::from(q as i8) * y).sum();
+                sum += scale * dot;
+            }
+            sum
+        }
+        let q8 = CustomDataType {
+            name: "TEST_Q8_BLOCK8",
+            block_size: 8,
+            type_size: 12,
+            to_f32,
+            from_f32,
+            vec_dot: Some(vec_dot),
+        };
+        let dtype = register_data_type(q8)?;
+
+        let (k, m, n) = (24, 3, 2);
+        let backend = feml::Backend::cpu().build()?;
+        assert!(backend.inner().supports(TensorOpType::TensorOpMulMat, &[dtype, DataType::F32]));
+        assert!(!backend.inner().supports(TensorOpType::TensorOpAdd, &[dtype, DataType::F32]));
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut w = ctx.new_tensor(dtype, &shape![k, m])?;
+        let x = ctx.new_tensor(DataType::F32, &shape![k, n])?;
+        mark_as_leaf(&w);
+        mark_as_leaf(&x);
+        let out = w.mul_mat(x.clone())?;
+        assert_eq!(w.nbytes(), m * 3 * 12);
+        assert!(ctx.new_tensor(dtype, &shape![k + 4]).is_err());
+
+        let _buffer = backend.alloc(&[w.clone(), x.clone(), out.clone()])?;
+        let weights: Vec<f32> = (0..k * m).map(|i| ((i * 7) % 19) as f32 / 4.0 - 2.0).collect();
+        let mut bytes = vec![0; w.nbytes()];
+        from_f32(&weights, &mut bytes);
+        let inputs: Vec<f32> = (0..k * n).map(|i| ((i * 5) % 11) as f32 / 8.0 - 0.5).collect();
+        backend.write(&w, &bytes)?;
+        backend.write(&x, &encode_f32(&inputs))?;
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, out.tensor_id(), false)?;
+        backend.compute(&ctx, &mut graph)?;
+
+        let mut dequantized = vec![0.0; k * m];
+        to_f32(&bytes, &mut dequantized);
+        let out = decode_f32(&backend.read(&out)?);
+        for (j, x) in inputs.chunks(k).enumerate() {
+            for (i, w) in dequantized.chunks(k).enumerate() {
+                let expected: f32 = w.iter().zip(x).map(|(w, x)| w * x).sum();
+                assert!((out[j * m + i] - expected).abs() < 1e-4, "{out:?}");
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn swiglu_matches_silu_then_mul_in_one_node() -> feml::error::Result<()> {
+        let (ne0, rows) = (37, 5);
+        let backend = feml::Backend::cpu().with_threads(3).build()?;
+        let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+        let mut gate = ctx.new_tensor(DataType::F32, &shape![ne0, rows])?;
+        let up = ctx.new_tensor(DataType::F32, &shape![ne0, rows])?;
+        mark_as_leaf(&gate);
+        mark_as_leaf(&up);
+        let fused = gate.swiglu(up.clone())?;
+        let mut silu = gate.silu()?;
+        let unfused = silu.mul(up.clone())?;
+        let row = ctx.new_tensor(DataType::F32, &shape![ne0])?;
+        assert!(gate.swiglu(row).is_err());
+
+        let tensors = [gate.clone(), up.clone(), fused.clone(), silu, unfused.clone()];
+        let _buffer = backend.alloc(&tensors)?;
+        let a: Vec<f32> = (0..ne0 * rows).map(|i| ((i * 13) % 29) as f32 / 3.0 - 5.0).collect();
+        let b: Vec<f32> = (0..ne0 * rows).map(|i| ((i * 7) % 17) as f32 / 4.0 - 2.0).collect();
+        backend.write(&gate, &encode_f32(&a))?;
+        backend.write(&up, &encode_f32(&b))?;
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, fused.tensor_id(), false)?;
+        assert_eq!(graph.nodes().len(), 1);
+        graph.build_forward(&ctx, unfused.tensor_id(), true)?;
+        backend.compute(&ctx, &mut graph)?;
+
+        let fused = decode_f32(&backend.read(&fused)?);
+        let unfused = decode_f32(&backend.read(&unfused)?);
+        for ((&x, &y), (&fused, &unfused)) in a.iter().zip(&b).zip(fused.iter().zip(&unfused)) {
+            let expected = f64::from(x) / (1.0 + (-f64::from(x)).exp()) * f64::from(y);
+            assert!((f64::from(fused) - expected).abs() < 1e-5, "{x} {y}: {fused}");
+            assert_eq!(fused, unfused);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn streaming_loader_converts_layers_on_a_threadpool() -> feml::error::Result<()> {
+        use feml::loader::{LoadedLayer, StreamingLoader};
+        use feml::quant::quantize_rows_q8;
+        use feml::serialize::{Compression, TensorData, TensorReader, TensorWriter};
+        use feml::threadpool::{ThreadPool, ThreadPoolParams};
+        use std::sync::Arc;
+
+        let weights: Vec<f32> = (0..64).map(|i| i as f32 / 8.0 - 4.0).collect();
+        let quantized = quantize_rows_q8(&weights, 16)?;
+        let mut writer = TensorWriter::new(Vec::new(), Compression::None)?;
+        for layer in 0..2 {
+            for name in ["attn_q", "attn_k", "ffn_up", "ffn_down"] {
+                writer.write(&TensorData {
+                    name: format!("blk.{layer}.{name}"),
+                    dtype: DataType::F32,
+                    shape: shape![16, 4],
+                    data: encode_f32(&weights),
+                })?;
+            }
+            writer.write(&TensorData {
+                name: format!("blk.{layer}.ffn_gate"),
+                dtype: DataType::I8,
+                shape: shape![16, 4],
+                data: quantized.value_bytes(),
+            })?;
+            writer.write(&TensorData {
+                name: format!("blk.{layer}.ffn_gate.scales"),
+                dtype: DataType::F32,
+                shape: shape![4],
+                data: encode_f32(&quantized.scales),
+            })?;
+        }
+        let bytes = writer.finish()?;
+
+        let backend = feml::Backend::cpu().build()?;
+        type Contents = Vec<(String, DataType, Vec<u8>)>;
+        let load = |pool: Option<Arc<ThreadPool>>| -> feml::error::Result<Contents> {
+            let mut ctx = Context::builder().tensor_pool_capacity(16).build();
+            let mut loader =
+                StreamingLoader::new(TensorReader::new(bytes.as_slice())?, backend.inner())
+                    .override_dtype("*", DataType::F16)
+                    .dequantize("*.ffn_gate", DataType::F32);
+            if let Some(pool) = pool {
+                loader = loader.threadpool(pool);
+            }
+            let layers: Vec<LoadedLayer> = loader.load_all(&mut ctx)?;
+            let mut contents = Vec::new();
+            for layer in &layers {
+                for tensor in &layer.tensors {
+                    let mut data = vec![0; tensor.nbytes()];
+                    layer.buffer.read(tensor.clone(), &mut data, 0, tensor.nbytes())?;
+                    contents.push((tensor.name(), tensor.dtype(), data));
+                }
+            }
+            Ok(contents)
+        };
+
+        let pool = Arc::new(ThreadPool::new(ThreadPoolParams::new(4))?);
+        let sequential = load(None)?;
+        assert_eq!(sequential.len(), 2 * 5);
+        assert!(sequential.iter().all(|(_, dtype, _)| *dtype != DataType::I8));
+        assert_eq!(load(Some(pool))?, sequential);
+        Ok(())
+    }
+
+    #[test]
+    fn relu_and_leaky_relu_split_rows_across_threads() -> feml::error::Result<()> {
+        let (ne0, rows) = (23, 6);
+        let backend = feml::Backend::cpu().with_threads(4).build()?;
+        let mut ctx = Context::builder().tensor_pool_capacity(8).build();
+        let mut input = ctx.new_tensor(DataType::F32, &shape![ne0, rows])?;
+        mark_as_leaf(&input);
+        let relu = input.relu()?;
+        let leaky = input.leaky_relu(0.2)?;
+
+        let _buffer = backend.alloc(&[input.clone(), relu.clone(), leaky.clone()])?;
+        let x: Vec<f32> = (0..ne0 * rows).map(|i| ((i * 11) % 19) as f32 / 2.0 - 4.5).collect();
+        backend.write(&input, &encode_f32(&x))?;
+        let mut graph = ComputeGraph::new();
+        graph.build_forward(&ctx, relu.tensor_id(), false)?;
+        graph.build_forward(&ctx, leaky.tensor_id(), true)?;
+        backend.compute(&ctx, &mut graph)?;
+
+        let relu = decode_f32(&backend.read(&relu)?);
+        let leaky = decode_f32(&backend.read(&leaky)?);
+        for ((&x, &relu), &leaky) in x.iter().zip(&relu).zip(&leaky) {
+            assert_eq!(relu.to_bits(), x.max(0.0).to_bits(), "{x}");
+            assert_eq!(leaky, if x < 0.0 { x * 0.2 } else { x }, "{x}");
+        }
+        Ok(())
+    }
 }